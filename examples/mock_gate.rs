@@ -0,0 +1,38 @@
+//! Имитация panel.gate.cx и API device token для ручной отладки без живой панели.
+//!
+//! cargo run --example mock_gate -- [fixture.json] [page_size]
+//!
+//! Затем запустите приложение с `base_url` на напечатанный адрес панели.
+
+use std::{path::PathBuf, time::Duration};
+
+use p2p_app::mock::{load_fixture, MockPanel, MockPanelConfig, MockTokenApi};
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    let fixture = PathBuf::from(args.next().unwrap_or_else(|| "idex_history.json".to_string()));
+    let page_size = args.next().and_then(|s| s.parse().ok()).unwrap_or(10);
+
+    let payouts = match load_fixture(&fixture) {
+        Ok(payouts) => payouts,
+        Err(e) => {
+            eprintln!("Failed to load fixture {}: {}", fixture.display(), e);
+            std::process::exit(1);
+        }
+    };
+    println!("Loaded {} payouts from {}", payouts.len(), fixture.display());
+
+    let config = MockPanelConfig {
+        page_size,
+        ..Default::default()
+    };
+    let panel = MockPanel::start(config, payouts);
+    let token_api = MockTokenApi::start(vec!["mock-device-token".to_string()]);
+    println!("Mock panel: {}", panel.base_url());
+    println!("Mock token API: {} (valid token: mock-device-token)", token_api.url());
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+    }
+}
@@ -0,0 +1,427 @@
+//! Напоминания об истекающих выплатах.
+//!
+//! Планировщик следит за взятыми, но ещё не оплаченными выплатами и по мере
+//! приближения `expired_at` поднимает всё более громкие оповещения: на каждом
+//! пороге из config.json — свой набор каналов (уведомление на рабочем столе,
+//! звук, сообщение в Telegram). Просроченные выплаты записываются в
+//! `missed_deadlines.jsonl`, чтобы их можно было разобрать позже. Просрочкой
+//! считается только выплата, которую планировщик застал активной до срока.
+//! Выплаты без статуса или с неизвестным статусом не отслеживаются.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, OpenOptions},
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::{
+    idex::{Transaction, STATUS_IN_PROGRESS},
+    metrics::metrics,
+    money::RUB,
+    notify::{play_sound, Notifier, NotifierSink, BEEP},
+    profiles::DEFAULT_PROFILE,
+    proxy::ProxyState,
+};
+
+const MISSED_FILE: &str = "missed_deadlines.jsonl";
+
+/// Канал доставки оповещения.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Desktop,
+    Sound,
+    Telegram,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Desktop => "desktop",
+            Channel::Sound => "sound",
+            Channel::Telegram => "telegram",
+        }
+    }
+}
+
+/// Порог: за сколько секунд до `expired_at` и по каким каналам предупреждать.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Threshold {
+    pub seconds_before: i64,
+    pub channels: Vec<Channel>,
+}
+
+/// Бот, через которого отправляются сообщения в Telegram.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+    /// Адрес Bot API; переопределяется для тестов.
+    #[serde(default = "default_telegram_api")]
+    pub api_url: String,
+}
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+/// Настройки оповещений (секция `alerts` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// По умолчанию выключено.
+    pub enabled: bool,
+    /// Пороги в любом порядке; срабатывает каждый не больше одного раза на выплату.
+    pub thresholds: Vec<Threshold>,
+    /// Каналы для оповещения о просроченной выплате.
+    pub missed_channels: Vec<Channel>,
+    /// Статусы взятой, но ещё не оплаченной выплаты; только такие отслеживаются.
+    pub active_statuses: Vec<String>,
+    /// Звуковой файл; без него подаётся системный сигнал.
+    pub sound_file: Option<String>,
+    pub telegram: Option<TelegramConfig>,
+    pub check_interval_secs: u64,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            thresholds: vec![
+                Threshold {
+                    seconds_before: 600,
+                    channels: vec![Channel::Desktop],
+                },
+                Threshold {
+                    seconds_before: 300,
+                    channels: vec![Channel::Desktop, Channel::Sound],
+                },
+                Threshold {
+                    seconds_before: 120,
+                    channels: vec![Channel::Desktop, Channel::Sound, Channel::Telegram],
+                },
+            ],
+            missed_channels: vec![Channel::Desktop, Channel::Sound, Channel::Telegram],
+            active_statuses: vec![STATUS_IN_PROGRESS.to_string()],
+            sound_file: None,
+            telegram: None,
+            check_interval_secs: 5,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertKind {
+    /// До истечения осталось `remaining_secs` секунд.
+    Expiring { remaining_secs: i64 },
+    Missed,
+    /// Сессия панели истечёт через `remaining_secs` секунд; см. `session`.
+    SessionExpiring { remaining_secs: i64 },
+}
+
+/// Оповещение по одной выплате. У оповещений о сессии `transaction_id` пуст,
+/// а `expired_at` — срок куки сессии.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub transaction_id: String,
+    pub expired_at: DateTime<Utc>,
+    pub kind: AlertKind,
+    pub channels: Vec<Channel>,
+    pub title: String,
+    pub message: String,
+}
+
+pub type SinkFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// Способ доставки оповещения.
+pub trait AlertSink: Send + Sync {
+    fn deliver<'a>(&'a self, alert: &'a Alert) -> SinkFuture<'a>;
+}
+
+/// Звуковой сигнал: файл из настроек или системный звонок терминала.
+pub struct SoundSink {
+    pub file: Option<String>,
+}
+
+impl AlertSink for SoundSink {
+    fn deliver<'a>(&'a self, _alert: &'a Alert) -> SinkFuture<'a> {
+        Box::pin(play_sound(self.file.as_deref().unwrap_or(BEEP)))
+    }
+}
+
+/// Сообщение в Telegram через Bot API.
+pub struct TelegramSink {
+    client: Client,
+    config: TelegramConfig,
+}
+
+impl TelegramSink {
+    pub fn new(config: TelegramConfig) -> Self {
+        Self {
+            client: Client::new(),
+            config,
+        }
+    }
+}
+
+impl AlertSink for TelegramSink {
+    fn deliver<'a>(&'a self, alert: &'a Alert) -> SinkFuture<'a> {
+        Box::pin(async move {
+            let url = format!(
+                "{}/bot{}/sendMessage",
+                self.config.api_url.trim_end_matches('/'),
+                self.config.bot_token
+            );
+            let response = self
+                .client
+                .post(url)
+                .json(&json!({
+                    "chat_id": self.config.chat_id,
+                    "text": format!("{}\n{}", alert.title, alert.message),
+                }))
+                .send()
+                .await
+                // Адрес содержит токен бота, поэтому в ошибку его не выводим.
+                .map_err(|e| e.without_url().to_string())?;
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(format!("Telegram API responded with {}", response.status()))
+            }
+        })
+    }
+}
+
+/// Планировщик оповещений. Сработавшие пороги хранятся в памяти,
+/// просроченные выплаты — в `missed_deadlines.jsonl`.
+pub struct ExpiryScheduler {
+    config: AlertsConfig,
+    sinks: BTreeMap<Channel, Arc<dyn AlertSink>>,
+    /// Наименьший сработавший порог (в секундах) по выплате.
+    fired: HashMap<String, i64>,
+    missed: HashSet<String>,
+    /// Выплаты, которые планировщик застал активными до их срока.
+    seen: HashSet<String>,
+    missed_path: PathBuf,
+    /// Метка `profile` метрик.
+    profile: String,
+}
+
+impl ExpiryScheduler {
+    /// Планировщик с каналами по умолчанию (см. `default_sinks`).
+    pub fn new(config: &AlertsConfig, data_dir: &Path) -> Self {
+        let missed_path = data_dir.join(MISSED_FILE);
+        let missed = fs::read_to_string(&missed_path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .filter_map(|v| v.get("transaction_id")?.as_str().map(String::from))
+            .collect();
+        Self {
+            config: config.clone(),
+            sinks: default_sinks(config),
+            fired: HashMap::new(),
+            missed,
+            seen: HashSet::new(),
+            missed_path,
+            profile: DEFAULT_PROFILE.to_string(),
+        }
+    }
+
+    /// Заменяет способ доставки для канала.
+    pub fn with_sink(mut self, channel: Channel, sink: Arc<dyn AlertSink>) -> Self {
+        self.sinks.insert(channel, sink);
+        self
+    }
+
+    /// Задаёт профиль, которым помечаются метрики планировщика.
+    pub fn with_profile(mut self, profile: &str) -> Self {
+        self.profile = profile.to_string();
+        self
+    }
+
+    fn is_active(&self, tx: &Transaction) -> bool {
+        tx.approved_at.is_none()
+            && tx.status.as_deref().is_some_and(|status| {
+                self.config
+                    .active_statuses
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(status))
+            })
+    }
+
+    /// Оповещения, которые пора поднять в момент `now`. Если за время между
+    /// проверками пройдено несколько порогов, поднимается только самый поздний.
+    pub fn due(&mut self, transactions: &[Transaction], now: DateTime<Utc>) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for tx in transactions {
+            if !self.is_active(tx) {
+                continue;
+            }
+            let Some(expired_at) = tx
+                .expired_at
+                .as_deref()
+                .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+                .map(|e| e.with_timezone(&Utc))
+            else {
+                continue;
+            };
+            let remaining = (expired_at - now).num_seconds();
+            let amount = tx
+                .trader_amount(RUB)
+                .map(|m| format!(" на {}", m))
+                .unwrap_or_default();
+
+            if remaining <= 0 {
+                // Срок, прошедший до того, как выплата попала под наблюдение,
+                // просрочкой не считается: это старые записи истории.
+                if self.seen.contains(&tx.transaction_id)
+                    && self.missed.insert(tx.transaction_id.clone())
+                {
+                    alerts.push(Alert {
+                        transaction_id: tx.transaction_id.clone(),
+                        expired_at,
+                        kind: AlertKind::Missed,
+                        channels: self.config.missed_channels.clone(),
+                        title: "Выплата просрочена".to_string(),
+                        message: format!("Выплата {}{} не оплачена вовремя.", tx.transaction_id, amount),
+                    });
+                }
+                continue;
+            }
+
+            self.seen.insert(tx.transaction_id.clone());
+            let fired = self.fired.get(&tx.transaction_id).copied();
+            let Some(threshold) = self
+                .config
+                .thresholds
+                .iter()
+                .filter(|t| remaining <= t.seconds_before)
+                .filter(|t| fired.is_none_or(|f| t.seconds_before < f))
+                .min_by_key(|t| t.seconds_before)
+            else {
+                continue;
+            };
+            self.fired
+                .insert(tx.transaction_id.clone(), threshold.seconds_before);
+            alerts.push(Alert {
+                transaction_id: tx.transaction_id.clone(),
+                expired_at,
+                kind: AlertKind::Expiring {
+                    remaining_secs: remaining,
+                },
+                channels: threshold.channels.clone(),
+                title: "Выплата скоро истечёт".to_string(),
+                message: format!(
+                    "Выплата {}{} истекает через {} мин {} с.",
+                    tx.transaction_id,
+                    amount,
+                    remaining / 60,
+                    remaining % 60
+                ),
+            });
+        }
+        alerts
+    }
+
+    /// Находит и доставляет оповещения, записывает просроченные выплаты.
+    pub async fn check(&mut self, transactions: &[Transaction], now: DateTime<Utc>) -> Vec<Alert> {
+        let alerts = self.due(transactions, now);
+        for alert in &alerts {
+            if alert.kind == AlertKind::Missed {
+                warn!(transaction = %alert.transaction_id, expired_at = %alert.expired_at, "Payout deadline missed");
+                metrics()
+                    .missed_deadlines
+                    .with_label_values(&[&self.profile])
+                    .inc();
+                if let Err(e) = self.record_missed(alert, now) {
+                    warn!(error = %e, "Failed to record missed deadline");
+                }
+            } else {
+                info!(transaction = %alert.transaction_id, channels = ?alert.channels, "Payout is about to expire");
+            }
+            deliver(&self.sinks, alert, &self.profile).await;
+        }
+        alerts
+    }
+
+    fn record_missed(&self, alert: &Alert, now: DateTime<Utc>) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.missed_path)?;
+        let line = json!({
+            "transaction_id": alert.transaction_id,
+            "expired_at": alert.expired_at,
+            "recorded_at": now,
+        });
+        writeln!(file, "{}", line)
+    }
+}
+
+/// Каналы по умолчанию: рабочий стол (системные уведомления, см. `notify`),
+/// звук и, если настроен бот, Telegram.
+pub fn default_sinks(config: &AlertsConfig) -> BTreeMap<Channel, Arc<dyn AlertSink>> {
+    let mut sinks: BTreeMap<Channel, Arc<dyn AlertSink>> = BTreeMap::new();
+    sinks.insert(Channel::Desktop, Arc::new(NotifierSink::new(Notifier::system())));
+    sinks.insert(
+        Channel::Sound,
+        Arc::new(SoundSink {
+            file: config.sound_file.clone(),
+        }),
+    );
+    if let Some(telegram) = &config.telegram {
+        sinks.insert(Channel::Telegram, Arc::new(TelegramSink::new(telegram.clone())));
+    }
+    sinks
+}
+
+/// Доставляет оповещение по всем его каналам, у которых есть способ доставки;
+/// результаты учитываются в метриках профиля `profile`.
+pub async fn deliver(sinks: &BTreeMap<Channel, Arc<dyn AlertSink>>, alert: &Alert, profile: &str) {
+    for channel in &alert.channels {
+        let Some(sink) = sinks.get(channel) else {
+            debug!(channel = channel.as_str(), "Alert channel is not configured");
+            continue;
+        };
+        let result = sink.deliver(alert).await;
+        let label = if result.is_ok() { "sent" } else { "failed" };
+        metrics()
+            .alerts
+            .with_label_values(&[profile, channel.as_str(), label])
+            .inc();
+        if let Err(e) = result {
+            warn!(channel = channel.as_str(), error = %e, "Failed to deliver alert");
+        }
+    }
+}
+
+/// Проверяет историю каждые `check_interval_secs`, пока не сработает `shutdown`.
+pub async fn run_alerts_until(state: ProxyState, shutdown: CancellationToken) {
+    let config = state.config.alerts.clone();
+    let mut scheduler = ExpiryScheduler::new(&config, &state.data_dir)
+        .with_sink(Channel::Desktop, Arc::new(NotifierSink::new(state.notifier.clone())))
+        .with_profile(&state.profile);
+    let interval = Duration::from_secs(config.check_interval_secs.max(1));
+    while !shutdown.is_cancelled() {
+        let transactions = state.transactions.lock().unwrap().clone();
+        scheduler.check(&transactions, Utc::now()).await;
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Аналитика оборота по собранным выплатам: разбивка по дням, сменам и банкам,
+//! наша комиссия (разница `total` и `amount`), средний курс и динамика
+//! процента успешных выплат. Суммы трейдера считаются по каждой валюте отдельно.
+//! В оборот входят только завершённые выплаты; остальные считаются по статусам.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    idex::{Transaction, STATUS_COMPLETED},
+    money::{Currency, Decimal, TRADER},
+};
+
+/// Знаков после запятой в расчётных курсах.
+const COURSE_DP: u32 = 6;
+
+/// Смена оператора; границы в местном времени `HH:MM`, конец не включается.
+/// Смена может переходить через полночь (`20:00`–`08:00`).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShiftConfig {
+    pub name: String,
+    pub start: String,
+    pub end: String,
+}
+
+/// Настройки аналитики (секция `analytics` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AnalyticsConfig {
+    /// Смещение местного времени от UTC в часах; по нему считаются дни и смены.
+    pub utc_offset_hours: i32,
+    pub shifts: Vec<ShiftConfig>,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            utc_offset_hours: 3,
+            shifts: vec![
+                ShiftConfig {
+                    name: "day".to_string(),
+                    start: "08:00".to_string(),
+                    end: "20:00".to_string(),
+                },
+                ShiftConfig {
+                    name: "night".to_string(),
+                    start: "20:00".to_string(),
+                    end: "08:00".to_string(),
+                },
+            ],
+        }
+    }
+}
+
+/// Сводка по группе транзакций.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub key: String,
+    pub count: u64,
+    /// Суммы выплат трейдера по буквенным кодам валют.
+    pub amount: BTreeMap<String, Decimal>,
+    pub total: BTreeMap<String, Decimal>,
+    /// Комиссия `total - amount` по выплатам, где известны обе суммы.
+    pub spread: BTreeMap<String, Decimal>,
+    /// Курс фиата к криптовалюте, взвешенный по объёму, например `RUB/USDT`.
+    pub effective_course: BTreeMap<String, Decimal>,
+    /// Среднее значение `course` из панели.
+    pub avg_course: Option<Decimal>,
+    pub avg_success_rate: Option<f64>,
+    /// Изменение `avg_success_rate` к предыдущему дню (только в разбивке по дням).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success_rate_change: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub total: Stats,
+    pub by_day: Vec<Stats>,
+    pub by_shift: Vec<Stats>,
+    pub by_bank: Vec<Stats>,
+    /// Незавершённые выплаты за период по статусам; в оборот не входят.
+    pub not_completed: BTreeMap<String, u64>,
+}
+
+#[derive(Default)]
+struct Acc {
+    stats: Stats,
+    amount: BTreeMap<Currency, Decimal>,
+    total: BTreeMap<Currency, Decimal>,
+    spread: BTreeMap<Currency, Decimal>,
+    /// Суммы фиата и криптовалюты по выплатам, где есть обе.
+    pairs: BTreeMap<(Currency, Currency), (Decimal, Decimal)>,
+    course_sum: Decimal,
+    course_count: u32,
+    rate_sum: f64,
+    rate_count: u32,
+}
+
+impl Acc {
+    fn add(&mut self, tx: &Transaction) {
+        self.stats.count += 1;
+        let amount = tx.amount.get(TRADER);
+        let total = tx.total.get(TRADER);
+        for (code, value) in amount.into_iter().flatten() {
+            *self.amount.entry(Currency::new(code)).or_default() += value;
+            if let Some(total) = total.and_then(|t| t.get(code)) {
+                *self.spread.entry(Currency::new(code)).or_default() += total - value;
+            }
+        }
+        for (code, value) in total.into_iter().flatten() {
+            *self.total.entry(Currency::new(code)).or_default() += value;
+        }
+        let currencies: Vec<(Currency, Decimal)> = amount
+            .into_iter()
+            .flatten()
+            .map(|(code, value)| (Currency::new(code), *value))
+            .collect();
+        for (fiat, fiat_value) in currencies.iter().filter(|(c, _)| !c.is_crypto()) {
+            for (crypto, crypto_value) in currencies.iter().filter(|(c, _)| c.is_crypto()) {
+                let pair = self.pairs.entry((fiat.clone(), crypto.clone())).or_default();
+                pair.0 += fiat_value;
+                pair.1 += crypto_value;
+            }
+        }
+        if let Some(course) = tx.course {
+            self.course_sum += course;
+            self.course_count += 1;
+        }
+        if let Some(rate) = tx.success_rate {
+            self.rate_sum += rate;
+            self.rate_count += 1;
+        }
+    }
+
+    fn finish(mut self, key: String) -> Stats {
+        let by_alpha = |map: BTreeMap<Currency, Decimal>| {
+            map.into_iter()
+                .map(|(c, v)| (c.alpha().to_string(), v))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let s = &mut self.stats;
+        s.key = key;
+        s.amount = by_alpha(self.amount);
+        s.total = by_alpha(self.total);
+        s.spread = by_alpha(self.spread);
+        s.effective_course = self
+            .pairs
+            .into_iter()
+            .filter(|(_, (_, crypto))| *crypto > Decimal::ZERO)
+            .map(|((fiat, crypto), (fiat_sum, crypto_sum))| {
+                (
+                    format!("{}/{}", fiat, crypto),
+                    (fiat_sum / crypto_sum).round_dp(COURSE_DP),
+                )
+            })
+            .collect();
+        s.avg_course = (self.course_count > 0)
+            .then(|| (self.course_sum / Decimal::from(self.course_count)).round_dp(COURSE_DP));
+        s.avg_success_rate = (self.rate_count > 0).then(|| self.rate_sum / self.rate_count as f64);
+        self.stats
+    }
+}
+
+fn parse_hhmm(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()
+}
+
+impl ShiftConfig {
+    fn contains(&self, time: NaiveTime) -> bool {
+        let (Some(start), Some(end)) = (parse_hhmm(&self.start), parse_hhmm(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            time >= start && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+impl AnalyticsConfig {
+    /// Смещение местного времени от UTC.
+    pub fn offset(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset_hours * 3600)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap())
+    }
+
+    /// Местная дата в момент `now`.
+    pub fn today(&self, now: DateTime<Utc>) -> NaiveDate {
+        now.with_timezone(&self.offset()).date_naive()
+    }
+
+    /// Местное время создания транзакции.
+    fn local_time(&self, tx: &Transaction) -> Option<NaiveDateTime> {
+        DateTime::parse_from_rfc3339(&tx.created_at)
+            .ok()
+            .map(|dt| dt.with_timezone(&self.offset()).naive_local())
+    }
+
+    fn shift_name(&self, time: NaiveTime) -> &str {
+        self.shifts
+            .iter()
+            .find(|s| s.contains(time))
+            .map(|s| s.name.as_str())
+            .unwrap_or("other")
+    }
+}
+
+/// Строит отчёт по транзакциям, созданным в диапазоне местных дат (включительно).
+pub fn build_report(
+    transactions: &[Transaction],
+    config: &AnalyticsConfig,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Report {
+    let mut total = Acc::default();
+    let mut by_day: BTreeMap<NaiveDate, Acc> = BTreeMap::new();
+    let mut by_shift: BTreeMap<String, Acc> = BTreeMap::new();
+    let mut by_bank: BTreeMap<String, Acc> = BTreeMap::new();
+    let mut not_completed: BTreeMap<String, u64> = BTreeMap::new();
+
+    for tx in transactions {
+        let Some(local) = config.local_time(tx) else {
+            continue;
+        };
+        let date = local.date();
+        if from.is_some_and(|from| date < from) || to.is_some_and(|to| date > to) {
+            continue;
+        }
+        if tx.status.as_deref() != Some(STATUS_COMPLETED) {
+            let status = tx.status.clone().unwrap_or_else(|| "unknown".to_string());
+            *not_completed.entry(status).or_default() += 1;
+            continue;
+        }
+        total.add(tx);
+        by_day.entry(date).or_default().add(tx);
+        by_shift
+            .entry(config.shift_name(local.time()).to_string())
+            .or_default()
+            .add(tx);
+        let bank = tx
+            .bank_label
+            .clone()
+            .or_else(|| tx.bank_name.clone())
+            .unwrap_or_else(|| "unknown".to_string());
+        by_bank.entry(bank).or_default().add(tx);
+    }
+
+    let mut days: Vec<Stats> = by_day
+        .into_iter()
+        .map(|(date, acc)| acc.finish(date.to_string()))
+        .collect();
+    let mut previous_rate = None;
+    for day in &mut days {
+        if let (Some(prev), Some(rate)) = (previous_rate, day.avg_success_rate) {
+            day.success_rate_change = Some(rate - prev);
+        }
+        previous_rate = day.avg_success_rate.or(previous_rate);
+    }
+
+    // Банки — по убыванию числа выплат.
+    let mut banks: Vec<Stats> = by_bank
+        .into_iter()
+        .map(|(bank, acc)| acc.finish(bank))
+        .collect();
+    banks.sort_by_key(|b| std::cmp::Reverse(b.count));
+
+    Report {
+        from,
+        to,
+        total: total.finish("total".to_string()),
+        by_day: days,
+        by_shift: by_shift
+            .into_iter()
+            .map(|(shift, acc)| acc.finish(shift))
+            .collect(),
+        by_bank: banks,
+        not_completed,
+    }
+}
+
+fn optional<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_string())
+}
+
+fn amounts(map: &BTreeMap<String, Decimal>) -> String {
+    if map.is_empty() {
+        return "-".to_string();
+    }
+    map.iter()
+        .map(|(currency, v)| format!("{:.2} {}", v, currency))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn render_section(out: &mut String, title: &str, rows: &[Stats]) {
+    let _ = writeln!(out, "\n{}", title);
+    for s in rows {
+        let courses = s
+            .effective_course
+            .iter()
+            .map(|(pair, v)| format!("{} {}", pair, v))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(
+            out,
+            "  {:<24} count {:>5} | amount {} | spread {} | course {} (avg {}) | success {}",
+            s.key,
+            s.count,
+            amounts(&s.amount),
+            amounts(&s.spread),
+            if courses.is_empty() { "-".to_string() } else { courses },
+            optional(s.avg_course),
+            optional(s.avg_success_rate),
+        );
+    }
+}
+
+/// Текстовый отчёт для командной строки.
+pub fn render_text(report: &Report) -> String {
+    let mut out = String::new();
+    let range = |d: Option<NaiveDate>| d.map(|d| d.to_string()).unwrap_or_else(|| "…".to_string());
+    let _ = writeln!(out, "Turnover report {} – {}", range(report.from), range(report.to));
+    render_section(&mut out, "Total", std::slice::from_ref(&report.total));
+    render_section(&mut out, "By day", &report.by_day);
+    render_section(&mut out, "By shift", &report.by_shift);
+    render_section(&mut out, "By bank", &report.by_bank);
+
+    let trend: Vec<String> = report
+        .by_day
+        .iter()
+        .filter_map(|d| d.success_rate_change.map(|c| format!("{} {:+.2}", d.key, c)))
+        .collect();
+    if !trend.is_empty() {
+        let _ = writeln!(out, "\nSuccess rate change: {}", trend.join(", "));
+    }
+    if !report.not_completed.is_empty() {
+        let statuses: Vec<String> = report
+            .not_completed
+            .iter()
+            .map(|(status, count)| format!("{} {}", status, count))
+            .collect();
+        let _ = writeln!(out, "\nNot completed (excluded): {}", statuses.join(", "));
+    }
+    out
+}
@@ -0,0 +1,194 @@
+//! Локальный API приложения. Обслуживается тем же сервером, что и прокси,
+//! по префиксу `/__p2p/`, чтобы не пересекаться с путями панели. Куки сессии
+//! через API не принимаются: их передаёт `p2p_app cookies import` (см. `instance`).
+//!
+//! Каждый запрос должен нести заголовок `X-P2P-Token` с токеном, который прокси
+//! при запуске пишет в файл `api_token` каталога профиля (права 0600). Запросы
+//! с нелокальным `Origin` и с телом не в JSON отклоняются.
+
+use std::{collections::HashMap, fs, io, io::Write, path::Path};
+
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    analytics::build_report,
+    export::{export, parse_date, ExportFormat, ExportOptions},
+    proxy::ProxyState,
+};
+
+/// Префикс путей локального API; остальные запросы уходят в панель.
+pub const API_PREFIX: &str = "/__p2p/";
+
+/// Заголовок с токеном доступа к API.
+pub const API_TOKEN_HEADER: &str = "x-p2p-token";
+
+/// Файл в каталоге профиля, в который прокси пишет токен API.
+pub const API_TOKEN_FILE: &str = "api_token";
+
+pub fn is_api_path(path: &str) -> bool {
+    path.starts_with(API_PREFIX)
+}
+
+/// Обрабатывает запрос к локальному API; вызывается вместо проксирования.
+pub async fn handle_api(req: Request<Body>, state: &ProxyState) -> Response<Body> {
+    if let Some(response) = reject(&req, &state.api_token) {
+        return response;
+    }
+    let route = req
+        .uri()
+        .path()
+        .trim_start_matches(API_PREFIX)
+        .trim_end_matches('/')
+        .to_string();
+    let query: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|q| url::form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+        .unwrap_or_default();
+
+    match (req.method(), route.as_str()) {
+        (&Method::GET, "export") => export_transactions(state, &query),
+        (&Method::GET, "analytics") => analytics_report(state, &query),
+        (&Method::GET, "session") => session_status(state),
+        (&Method::GET, "health") => task_health(state),
+        _ => error_response(StatusCode::NOT_FOUND, "unknown API endpoint"),
+    }
+}
+
+/// Пишет токен API в `api_token` каталога `data_dir`, доступный только владельцу.
+pub fn publish_token(data_dir: &Path, token: &str) -> io::Result<()> {
+    let path = data_dir.join(API_TOKEN_FILE);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // Файл прошлого запуска мог остаться с другими правами.
+        if path.exists() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(&path)?.write_all(token.as_bytes())
+}
+
+/// Проверяет токен, `Origin` и `Content-Type`; при отказе возвращает ответ с ошибкой.
+fn reject(req: &Request<Body>, token: &str) -> Option<Response<Body>> {
+    let value = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+    if token.is_empty() || value(API_TOKEN_HEADER) != Some(token) {
+        warn!(path = %req.uri().path(), "API request without a valid token");
+        return Some(error_response(StatusCode::UNAUTHORIZED, "missing or invalid API token"));
+    }
+    if let Some(origin) = value(header::ORIGIN.as_str()) {
+        if !is_local_origin(origin) {
+            warn!(%origin, "API request from a foreign origin");
+            return Some(error_response(StatusCode::FORBIDDEN, "foreign origin"));
+        }
+    }
+    let content_type = value(header::CONTENT_TYPE.as_str());
+    let json = content_type.is_some_and(|v| {
+        v.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("application/json")
+    });
+    if (content_type.is_some() || req.method() != Method::GET) && !json {
+        return Some(error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "request body must be application/json",
+        ));
+    }
+    None
+}
+
+/// Страница открыта с локального адреса: 127.0.0.1, localhost или [::1].
+fn is_local_origin(origin: &str) -> bool {
+    url::Url::parse(origin).is_ok_and(|url| {
+        matches!(url.scheme(), "http" | "https")
+            && matches!(url.host_str(), Some("127.0.0.1" | "localhost" | "[::1]"))
+    })
+}
+
+/// `GET /__p2p/export?format=xlsx&from=2025-02-01&to=2025-02-28&fields=...`
+fn export_transactions(state: &ProxyState, query: &HashMap<String, String>) -> Response<Body> {
+    let mut options = match ExportOptions::from_config(&state.config.export, ExportFormat::Csv) {
+        Ok(options) => options,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    };
+    for (key, value) in query {
+        if let Err(e) = options.set(key, value) {
+            return error_response(StatusCode::BAD_REQUEST, &e.to_string());
+        }
+    }
+
+    let transactions = state.transactions.lock().unwrap().clone();
+    match export(&transactions, &options) {
+        Ok(bytes) => Response::builder()
+            .header(header::CONTENT_TYPE, options.format.content_type())
+            .header(
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"idex_export.{}\"",
+                    options.format.extension()
+                ),
+            )
+            .body(Body::from(bytes))
+            .unwrap(),
+        Err(e) => {
+            warn!(error = %e, "Export failed");
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
+
+/// `GET /__p2p/analytics?from=2025-02-01&to=2025-02-28` — отчёт в JSON.
+fn analytics_report(state: &ProxyState, query: &HashMap<String, String>) -> Response<Body> {
+    let date = |key: &str| query.get(key).map(|v| parse_date(v)).transpose();
+    let (from, to) = match (date("from"), date("to")) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let report = {
+        let transactions = state.transactions.lock().unwrap();
+        build_report(&transactions, &state.config.analytics, from, to)
+    };
+    json_response(&report)
+}
+
+/// `GET /__p2p/session` — есть ли куки, чем закончился последний опрос и
+/// когда истекает сессия.
+/// Значения кук не отдаются, только имена.
+fn session_status(state: &ProxyState) -> Response<Body> {
+    let names: Vec<String> = state
+        .cookies
+        .lock()
+        .unwrap()
+        .cookies
+        .iter()
+        .map(|c| c.name.clone())
+        .collect();
+    let poll = state.poll_status.lock().unwrap().clone();
+    let session = state.session.lock().unwrap().clone();
+    json_response(&json!({ "cookies": names, "poll": poll, "session": session }))
+}
+
+/// `GET /__p2p/health` — состояние фоновых задач: работает ли задача, сколько
+/// раз перезапускалась и с какой ошибкой упала последний раз.
+fn task_health(state: &ProxyState) -> Response<Body> {
+    let tasks = state.tasks.lock().unwrap().clone();
+    json_response(&json!({ "tasks": tasks }))
+}
+
+fn json_response(value: &impl serde::Serialize) -> Response<Body> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(value).unwrap_or_default()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "error": message }).to_string()))
+        .unwrap()
+}
@@ -0,0 +1,570 @@
+//! Фоновая загрузка вложений выплат (PDF-чеков) через авторизованную сессию панели.
+//!
+//! Файлы хранятся в каталоге данных по SHA-256 содержимого
+//! (`attachments/ab/abcdef….pdf`), поэтому одинаковые чеки лежат на диске один раз.
+//! Состояние загрузок ведётся в `attachments/index.json` по `original_url`:
+//! недокачанный файл остаётся в `partial/` и докачивается запросом с `Range`,
+//! неудачные попытки повторяются с нарастающей паузой, в том числе после перезапуска.
+//! Путь к сохранённому файлу и его хеш дописываются в запись вложения транзакции
+//! (`local_path`, `sha256`), туда же попадает результат сверки чека (`receipt_check`).
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    header::{COOKIE, RANGE, USER_AGENT},
+    Client, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::{
+    idex::{save_transactions, Transaction},
+    metrics::metrics,
+    proxy::ProxyState,
+    receipts::{parse_pdf, verify_receipt, Receipt, ReceiptIssue},
+};
+
+const INDEX_FILE: &str = "index.json";
+const PARTIAL_DIR: &str = "partial";
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/119.0.0.0 Safari/537.36";
+
+/// Настройки загрузки вложений (секция `attachments` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AttachmentsConfig {
+    pub enabled: bool,
+    /// Каталог для файлов внутри каталога данных.
+    pub directory: String,
+    /// Путь на панели, от которого отсчитывается относительный `original_url`.
+    pub url_prefix: String,
+    /// Пауза между проходами по истории, в секундах.
+    pub interval_secs: u64,
+    /// Пауза после первой неудачной попытки; дальше удваивается (до часа).
+    pub retry_delay_secs: u64,
+    /// После стольких неудачных попыток вложение больше не запрашивается.
+    pub max_attempts: u32,
+}
+
+impl Default for AttachmentsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: "attachments".to_string(),
+            url_prefix: "storage/".to_string(),
+            interval_secs: 30,
+            retry_delay_secs: 30,
+            max_attempts: 8,
+        }
+    }
+}
+
+/// Вложение из `Transaction::attachments`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachmentRef {
+    pub transaction_id: String,
+    pub original_url: String,
+    pub file_name: Option<String>,
+    pub extension: Option<String>,
+    /// Размер в байтах по данным панели.
+    pub size: Option<u64>,
+    /// Панель пометила чек как поддельный (`custom_properties.fake`).
+    pub fake: bool,
+}
+
+/// Состояние загрузки одного вложения в индексе.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AttachmentRecord {
+    pub transaction_id: String,
+    pub file_name: Option<String>,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    /// Путь к файлу относительно каталога данных, когда загрузка завершена.
+    pub local_path: Option<String>,
+    pub downloaded_at: Option<DateTime<Utc>>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub retry_after: Option<DateTime<Utc>>,
+    /// Поля, извлечённые из PDF-чека.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+    /// Почему не удалось разобрать PDF-чек.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_error: Option<String>,
+}
+
+/// Итог одного прохода загрузчика.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncStats {
+    pub downloaded: usize,
+    /// Загружены, но такой файл уже был в хранилище.
+    pub deduplicated: usize,
+    pub failed: usize,
+    /// Транзакции, в которые дописаны пути к файлам или результаты сверки.
+    pub linked: usize,
+}
+
+/// Вложения транзакции. Панель отдаёт их массивом или объектом по id.
+pub fn attachment_refs(tx: &Transaction) -> Vec<AttachmentRef> {
+    let items: Vec<&Value> = match &tx.attachments {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(Value::Object(items)) => items.values().collect(),
+        _ => Vec::new(),
+    };
+    items
+        .into_iter()
+        .filter_map(|item| {
+            let original_url = item.get("original_url")?.as_str()?.to_string();
+            let text = |key| item.get(key).and_then(|v: &Value| v.as_str()).map(String::from);
+            Some(AttachmentRef {
+                transaction_id: tx.transaction_id.clone(),
+                file_name: text("file_name"),
+                extension: text("extension").or_else(|| {
+                    Path::new(&original_url)
+                        .extension()
+                        .and_then(|e| e.to_str())
+                        .map(String::from)
+                }),
+                size: item.get("size").and_then(|v| v.as_u64()),
+                fake: item
+                    .get("custom_properties")
+                    .and_then(|p| p.get("fake"))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false),
+                original_url,
+            })
+        })
+        .collect()
+}
+
+/// Адрес файла: абсолютный `original_url` как есть, относительный — от `url_prefix` панели.
+pub fn attachment_url(base_url: &str, url_prefix: &str, original_url: &str) -> Option<url::Url> {
+    if let Ok(url) = url::Url::parse(original_url) {
+        return Some(url);
+    }
+    let base = url::Url::parse(base_url).ok()?;
+    let prefix = format!("{}/", url_prefix.trim_matches('/'));
+    let prefix = if prefix == "/" { String::new() } else { prefix };
+    base.join(&prefix)
+        .ok()?
+        .join(original_url.trim_start_matches('/'))
+        .ok()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Хранилище вложений с индексом загрузок.
+pub struct AttachmentStore {
+    data_dir: PathBuf,
+    directory: String,
+    config: AttachmentsConfig,
+    index: BTreeMap<String, AttachmentRecord>,
+}
+
+impl AttachmentStore {
+    /// Открывает хранилище и читает индекс; повреждённый индекс начинается заново.
+    pub fn open(data_dir: &Path, config: &AttachmentsConfig) -> Self {
+        let directory = config.directory.trim_matches('/').to_string();
+        let path = data_dir.join(&directory).join(INDEX_FILE);
+        let index = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!(error = %e, path = %path.display(), "Failed to parse attachment index");
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            data_dir: data_dir.to_path_buf(),
+            directory,
+            config: config.clone(),
+            index,
+        }
+    }
+
+    /// Записи индекса по `original_url`.
+    pub fn records(&self) -> &BTreeMap<String, AttachmentRecord> {
+        &self.index
+    }
+
+    pub fn record(&self, original_url: &str) -> Option<&AttachmentRecord> {
+        self.index.get(original_url)
+    }
+
+    fn root(&self) -> PathBuf {
+        self.data_dir.join(&self.directory)
+    }
+
+    fn save_index(&self) -> io::Result<()> {
+        fs::create_dir_all(self.root())?;
+        let json = serde_json::to_string_pretty(&self.index)?;
+        let path = self.root().join(INDEX_FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Путь файла относительно каталога данных.
+    fn object_path(&self, sha256: &str, extension: Option<&str>) -> String {
+        let name = match extension {
+            Some(ext) if !ext.is_empty() => format!("{}.{}", sha256, ext.to_ascii_lowercase()),
+            _ => sha256.to_string(),
+        };
+        format!("{}/{}/{}", self.directory, &sha256[..2], name)
+    }
+
+    fn partial_path(&self, original_url: &str) -> PathBuf {
+        self.root()
+            .join(PARTIAL_DIR)
+            .join(format!("{}.part", sha256_hex(original_url.as_bytes())))
+    }
+
+    /// Загрузка завершена и файл на месте нужного размера.
+    fn is_stored(&self, record: &AttachmentRecord) -> bool {
+        let Some(local_path) = &record.local_path else {
+            return false;
+        };
+        match fs::metadata(self.data_dir.join(local_path)) {
+            Ok(meta) => record.size.is_none_or(|size| meta.len() == size),
+            Err(_) => false,
+        }
+    }
+
+    /// Один проход: докачивает недостающие вложения всех транзакций и
+    /// дописывает пути к файлам в историю.
+    pub async fn sync(&mut self, state: &ProxyState, client: &Client) -> SyncStats {
+        let mut stats = SyncStats::default();
+        let refs: Vec<AttachmentRef> = {
+            let store = state.transactions.lock().unwrap();
+            store.iter().flat_map(attachment_refs).collect()
+        };
+
+        for attachment in refs {
+            let now = Utc::now();
+            let record = self
+                .index
+                .entry(attachment.original_url.clone())
+                .or_insert_with(|| AttachmentRecord {
+                    transaction_id: attachment.transaction_id.clone(),
+                    file_name: attachment.file_name.clone(),
+                    size: attachment.size,
+                    ..Default::default()
+                })
+                .clone();
+            if record.local_path.is_some() {
+                if self.is_stored(&record) {
+                    self.parse_receipt(&attachment);
+                    continue;
+                }
+                warn!(url = %attachment.original_url, "Stored attachment is missing or damaged, downloading again");
+                self.reset(&attachment.original_url);
+            } else if record.attempts >= self.config.max_attempts
+                || record.retry_after.is_some_and(|t| t > now)
+            {
+                continue;
+            }
+
+            match self.download(state, client, &attachment).await {
+                Ok((sha256, local_path, deduplicated)) => {
+                    info!(
+                        transaction = %attachment.transaction_id,
+                        path = %local_path,
+                        deduplicated,
+                        "Attachment downloaded"
+                    );
+                    if deduplicated {
+                        stats.deduplicated += 1;
+                        metrics().attachment_downloads.with_label_values(&[&state.profile, "deduplicated"]).inc();
+                    } else {
+                        stats.downloaded += 1;
+                        metrics().attachment_downloads.with_label_values(&[&state.profile, "downloaded"]).inc();
+                    }
+                    let record = self.index.get_mut(&attachment.original_url).unwrap();
+                    record.size = attachment.size.or(record.size);
+                    record.sha256 = Some(sha256);
+                    record.local_path = Some(local_path);
+                    record.downloaded_at = Some(Utc::now());
+                    record.last_error = None;
+                    record.retry_after = None;
+                    self.parse_receipt(&attachment);
+                }
+                Err(e) => {
+                    stats.failed += 1;
+                    metrics().attachment_downloads.with_label_values(&[&state.profile, "failed"]).inc();
+                    let record = self.index.get_mut(&attachment.original_url).unwrap();
+                    record.attempts += 1;
+                    let delay = Duration::from_secs(self.config.retry_delay_secs)
+                        .saturating_mul(1 << (record.attempts - 1).min(16))
+                        .min(MAX_RETRY_DELAY);
+                    warn!(
+                        transaction = %attachment.transaction_id,
+                        url = %attachment.original_url,
+                        attempt = record.attempts,
+                        retry_in = ?delay,
+                        error = %e,
+                        "Failed to download attachment"
+                    );
+                    record.last_error = Some(e);
+                    record.retry_after = chrono::Duration::from_std(delay).ok().map(|d| Utc::now() + d);
+                }
+            }
+            // Индекс сохраняется после каждого файла, чтобы после сбоя продолжить с того же места.
+            if let Err(e) = self.save_index() {
+                warn!(error = %e, "Failed to save attachment index");
+            }
+        }
+
+        stats.linked = self.link(state);
+        stats
+    }
+
+    /// Разбирает сохранённый PDF-чек, если это ещё не сделано.
+    fn parse_receipt(&mut self, attachment: &AttachmentRef) {
+        let is_pdf = attachment
+            .extension
+            .as_deref()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+        let Some(record) = self.index.get_mut(&attachment.original_url) else {
+            return;
+        };
+        if !is_pdf || record.receipt.is_some() || record.parse_error.is_some() {
+            return;
+        }
+        let Some(local_path) = &record.local_path else {
+            return;
+        };
+        let parsed = fs::read(self.data_dir.join(local_path))
+            .map_err(|e| e.to_string())
+            .and_then(|data| parse_pdf(&data));
+        match parsed {
+            Ok(receipt) => record.receipt = Some(receipt),
+            Err(e) => {
+                warn!(url = %attachment.original_url, error = %e, "Failed to parse receipt");
+                record.parse_error = Some(e);
+            }
+        }
+    }
+
+    fn reset(&mut self, original_url: &str) {
+        if let Some(record) = self.index.get_mut(original_url) {
+            record.receipt = None;
+            record.parse_error = None;
+            record.sha256 = None;
+            record.local_path = None;
+            record.downloaded_at = None;
+            record.attempts = 0;
+            record.retry_after = None;
+        }
+    }
+
+    /// Скачивает вложение с докачкой и проверками. Возвращает хеш, путь и признак
+    /// того, что такой файл уже был в хранилище.
+    async fn download(
+        &self,
+        state: &ProxyState,
+        client: &Client,
+        attachment: &AttachmentRef,
+    ) -> Result<(String, String, bool), String> {
+        let url = attachment_url(&state.base_url, &self.config.url_prefix, &attachment.original_url)
+            .ok_or_else(|| format!("invalid attachment url {}", attachment.original_url))?;
+        let partial = self.partial_path(&attachment.original_url);
+        if let Some(dir) = partial.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+
+        let mut offset = fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+        if attachment.size.is_some_and(|size| offset > size) {
+            let _ = fs::remove_file(&partial);
+            offset = 0;
+        }
+        let complete = offset > 0 && attachment.size == Some(offset);
+        if !complete {
+            let mut request = client
+                .get(url.clone())
+                .header(COOKIE, state.cookie_header())
+                .header(USER_AGENT, BROWSER_USER_AGENT);
+            if offset > 0 {
+                debug!(url = %url, offset, "Resuming attachment download");
+                request = request.header(RANGE, format!("bytes={}-", offset));
+            }
+            let mut response = request.send().await.map_err(|e| e.to_string())?;
+            let status = response.status();
+            let append = match status {
+                StatusCode::PARTIAL_CONTENT if offset > 0 => true,
+                s if s.is_success() => false,
+                s => {
+                    if s == StatusCode::RANGE_NOT_SATISFIABLE {
+                        let _ = fs::remove_file(&partial);
+                    }
+                    return Err(format!("HTTP {}", s));
+                }
+            };
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(&partial)
+                .await
+                .map_err(|e| e.to_string())?;
+            // Оборванная передача оставляет часть файла для следующей попытки.
+            while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+                file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            }
+            file.flush().await.map_err(|e| e.to_string())?;
+        }
+
+        let data = tokio::fs::read(&partial).await.map_err(|e| e.to_string())?;
+        let invalid = |reason: String| {
+            let _ = fs::remove_file(&partial);
+            Err(reason)
+        };
+        if let Some(size) = attachment.size {
+            if data.len() as u64 != size {
+                return invalid(format!("size mismatch: expected {}, got {}", size, data.len()));
+            }
+        }
+        let is_pdf = attachment
+            .extension
+            .as_deref()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+        if is_pdf && !data.starts_with(b"%PDF") {
+            return invalid("response is not a PDF document".to_string());
+        }
+
+        let sha256 = sha256_hex(&data);
+        let local_path = self.object_path(&sha256, attachment.extension.as_deref());
+        let target = self.data_dir.join(&local_path);
+        let deduplicated = target.exists();
+        if deduplicated {
+            let _ = fs::remove_file(&partial);
+        } else {
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            fs::rename(&partial, &target).map_err(|e| e.to_string())?;
+        }
+        Ok((sha256, local_path, deduplicated))
+    }
+
+    /// Дописывает `local_path`, `sha256` и `receipt_check` в записи вложений и
+    /// сохраняет историю, если что-то изменилось. Возвращает число изменённых транзакций.
+    fn link(&self, state: &ProxyState) -> usize {
+        let mut store = state.transactions.lock().unwrap();
+        let mut linked = 0;
+        for tx in store.iter_mut() {
+            let mut attachments = tx.attachments.take();
+            let items: Vec<&mut Value> = match &mut attachments {
+                Some(Value::Array(items)) => items.iter_mut().collect(),
+                Some(Value::Object(items)) => items.values_mut().collect(),
+                _ => Vec::new(),
+            };
+            let mut changed = false;
+            for item in items {
+                let Some(record) = item
+                    .get("original_url")
+                    .and_then(|u| u.as_str())
+                    .and_then(|u| self.index.get(u))
+                else {
+                    continue;
+                };
+                let (Some(local_path), Some(sha256)) = (&record.local_path, &record.sha256) else {
+                    continue;
+                };
+                let fake = item
+                    .get("custom_properties")
+                    .and_then(|p| p.get("fake"))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let issues = match (&record.receipt, &record.parse_error) {
+                    (Some(receipt), _) => verify_receipt(tx, receipt, fake, &state.config.receipts),
+                    (None, error) => {
+                        let mut issues = Vec::new();
+                        if let Some(error) = error {
+                            issues.push(ReceiptIssue::Unreadable {
+                                error: error.clone(),
+                            });
+                        }
+                        if fake {
+                            issues.push(ReceiptIssue::MarkedFake);
+                        }
+                        issues
+                    }
+                };
+                let check = json!({ "ok": issues.is_empty(), "issues": issues });
+                let Some(fields) = item.as_object_mut() else {
+                    continue;
+                };
+                let updates = [
+                    ("local_path", Value::from(local_path.as_str())),
+                    ("sha256", Value::from(sha256.as_str())),
+                    ("receipt_check", check),
+                ];
+                for (key, value) in updates {
+                    if fields.get(key) == Some(&value) {
+                        continue;
+                    }
+                    if key == "receipt_check" && !issues.is_empty() {
+                        warn!(
+                            transaction = %tx.transaction_id,
+                            issues = %value["issues"],
+                            "Receipt does not match the payout"
+                        );
+                    }
+                    fields.insert(key.to_string(), value);
+                    changed = true;
+                }
+            }
+            tx.attachments = attachments;
+            if changed {
+                linked += 1;
+            }
+        }
+        if linked > 0 {
+            if let Err(e) = save_transactions(&state.data_dir, &store) {
+                warn!(error = %e, "Failed to save transactions with attachment paths");
+            }
+        }
+        linked
+    }
+}
+
+/// Периодически догружает вложения, пока не сработает `shutdown`.
+/// Прерванная на середине загрузка продолжится со следующего запуска.
+pub async fn run_attachments_until(state: ProxyState, shutdown: CancellationToken) {
+    let config = state.config.attachments.clone();
+    let mut store = AttachmentStore::open(&state.data_dir, &config);
+    let client = Client::new();
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+
+    while !shutdown.is_cancelled() {
+        if !state.cookie_header().is_empty() {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                stats = store.sync(&state, &client) => {
+                    if stats != SyncStats::default() {
+                        info!(?stats, "Attachment sync finished");
+                    }
+                }
+            }
+        }
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! Подкоманды командной строки, которые выполняются без запуска окна, и
+//! параметры запуска самого приложения.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use chrono::{NaiveDate, Utc};
+
+use crate::{
+    analytics::{build_report, render_text, AnalyticsConfig},
+    config::AppConfig,
+    cookie_io::{export_cookies, filter_importable, parse_cookies, CookieFormat},
+    cookie_sync::apply_cookies,
+    export::{export, parse_date, ExportFormat, ExportOptions},
+    idex::load_transactions,
+    instance::{find_running, HandOff},
+    profiles::{resolve_profiles, Profile, ProfileConfig},
+    proxy::{host_of, load_cookies, save_cookies},
+};
+
+pub const USAGE: &str = "\
+Usage:
+  p2p_app [options]            start the application
+  p2p_app export [options]     export a profile's payout history
+  p2p_app report [options]     turnover and margin report of a profile
+  p2p_app cookies import FILE  merge cookies into a profile (FILE \"-\" reads stdin)
+  p2p_app cookies export       print or save a profile's cookies
+
+Options:
+  --headless                   run without a window (proxy, poller, uploads, alerts)
+  --pid-file PATH              write the process id to PATH while running
+
+Only one instance runs per data directory: launching again shows the IDEX
+window of the running one, and `cookies import` hands the cookies over to it.
+Headless mode reloads config.json on SIGHUP and never prompts for the device
+token: it is read from P2P_DEVICE_TOKEN or device.token in the data directory.
+Session cookies are read from the profile's cookies.json or handed over with
+`cookies import`; the local API does not accept cookies.
+The local API under http://127.0.0.1:<proxy port>/__p2p/ requires the
+X-P2P-Token header with the token from the profile's api_token file;
+GET /__p2p/health reports the state of background tasks.
+
+Export options:
+  --format csv|xlsx|parquet    output format (default: csv)
+  --from YYYY-MM-DD            first day by created_at, inclusive
+  --to YYYY-MM-DD              last day by created_at, inclusive
+  --fields a,b,c               columns to export (default: all)
+  --delimiter ;                CSV column delimiter (\"tab\" for TAB)
+  --decimal-comma              write CSV numbers as 1234,56
+  --output PATH                output file (default: idex_export.<format>)
+  --profile NAME               profile from config.json (default: the first one)
+
+Report options:
+  --from YYYY-MM-DD            first local day, inclusive
+  --to YYYY-MM-DD              last local day, inclusive
+  --json                       print the report as JSON
+  --profile NAME               profile from config.json (default: the first one)
+
+Cookie options:
+  --format json|netscape|header
+                               browser extension JSON, cookies.txt or a Cookie
+                               header (import: detected by default; export: json)
+  --profile NAME               profile from config.json (default: the first one)
+  --output PATH                export to a file instead of stdout
+
+Cookies of other domains than the profile's panel are skipped. While the
+application is running, `cookies import` hands the cookies over to it instead
+of writing cookies.json.";
+
+/// Параметры обычного запуска.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LaunchOptions {
+    /// Без окна: только фоновые задачи.
+    pub headless: bool,
+    pub pid_file: Option<PathBuf>,
+}
+
+/// Что делать после разбора аргументов.
+pub enum Invocation {
+    Launch(LaunchOptions),
+    Command(CliCommand),
+}
+
+pub enum CliCommand {
+    Export {
+        options: ExportOptions,
+        output: Option<PathBuf>,
+        profiles: Vec<ProfileConfig>,
+        profile: Option<String>,
+    },
+    Report {
+        config: AnalyticsConfig,
+        profiles: Vec<ProfileConfig>,
+        profile: Option<String>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        json: bool,
+    },
+    ImportCookies {
+        profiles: Vec<ProfileConfig>,
+        profile: Option<String>,
+        input: PathBuf,
+        format: Option<CookieFormat>,
+    },
+    ExportCookies {
+        profiles: Vec<ProfileConfig>,
+        profile: Option<String>,
+        output: Option<PathBuf>,
+        format: CookieFormat,
+    },
+}
+
+/// Разбирает аргументы без имени программы.
+pub fn parse_args(args: &[String], config: &AppConfig) -> Result<Invocation, String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(Invocation::Launch(LaunchOptions::default()));
+    };
+    match command.as_str() {
+        "export" => parse_export(rest, config).map(Invocation::Command),
+        "report" => parse_report(rest, config).map(Invocation::Command),
+        "cookies" => parse_cookies_command(rest, config).map(Invocation::Command),
+        "help" | "--help" | "-h" => Err(String::new()),
+        flag if flag.starts_with("--") => parse_launch(args).map(Invocation::Launch),
+        other => Err(format!("unknown command: {}", other)),
+    }
+}
+
+fn parse_launch(args: &[String]) -> Result<LaunchOptions, String> {
+    let mut options = LaunchOptions::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--headless" => options.headless = true,
+            "--pid-file" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| "missing value for --pid-file".to_string())?;
+                options.pid_file = Some(PathBuf::from(value));
+            }
+            "--help" | "-h" => return Err(String::new()),
+            other => return Err(format!("unexpected argument: {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_export(args: &[String], config: &AppConfig) -> Result<CliCommand, String> {
+    let mut options = ExportOptions::from_config(&config.export, ExportFormat::Csv)
+        .map_err(|e| e.to_string())?;
+    let (mut output, mut profile) = (None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let Some(key) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument: {}", arg));
+        };
+        // Флаги без значения.
+        if key == "decimal-comma" {
+            options.decimal_comma = true;
+            continue;
+        }
+        let value = iter
+            .next()
+            .ok_or_else(|| format!("missing value for --{}", key))?;
+        if key == "output" {
+            output = Some(PathBuf::from(value));
+        } else if key == "profile" {
+            profile = Some(value.clone());
+        } else {
+            options.set(key, value).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(CliCommand::Export {
+        options,
+        output,
+        profiles: config.profiles.clone(),
+        profile,
+    })
+}
+
+fn parse_report(args: &[String], config: &AppConfig) -> Result<CliCommand, String> {
+    let (mut from, mut to, mut json, mut profile) = (None, None, false, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--profile" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("missing value for {}", arg))?;
+                profile = Some(value.clone());
+            }
+            "--from" | "--to" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("missing value for {}", arg))?;
+                let date = parse_date(value).map_err(|e| e.to_string())?;
+                if arg == "--from" {
+                    from = Some(date);
+                } else {
+                    to = Some(date);
+                }
+            }
+            other => return Err(format!("unexpected argument: {}", other)),
+        }
+    }
+    Ok(CliCommand::Report {
+        config: config.analytics.clone(),
+        profiles: config.profiles.clone(),
+        profile,
+        from,
+        to,
+        json,
+    })
+}
+
+fn parse_cookies_command(args: &[String], config: &AppConfig) -> Result<CliCommand, String> {
+    let (action, rest) = args
+        .split_first()
+        .ok_or_else(|| "missing cookies action: import or export".to_string())?;
+    let (mut profile, mut output, mut input, mut format) = (None, None, None, None);
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--profile" => profile = Some(value()?),
+            "--output" => output = Some(PathBuf::from(value()?)),
+            "--format" => format = Some(CookieFormat::parse(&value()?).map_err(|e| e.to_string())?),
+            path if input.is_none() && action == "import" && (path == "-" || !path.starts_with("--")) => {
+                input = Some(PathBuf::from(path))
+            }
+            other => return Err(format!("unexpected argument: {}", other)),
+        }
+    }
+    let profiles = config.profiles.clone();
+    match action.as_str() {
+        "import" => Ok(CliCommand::ImportCookies {
+            profiles,
+            profile,
+            input: input.ok_or_else(|| "missing file to import".to_string())?,
+            format,
+        }),
+        "export" => Ok(CliCommand::ExportCookies {
+            profiles,
+            profile,
+            output,
+            format: format.unwrap_or(CookieFormat::Json),
+        }),
+        other => Err(format!("unknown cookies action: {}", other)),
+    }
+}
+
+/// Профиль по имени или первый из настроек.
+fn find_profile(
+    profiles: Vec<ProfileConfig>,
+    data_dir: &Path,
+    name: Option<&str>,
+) -> Result<Profile, String> {
+    let config = AppConfig {
+        profiles,
+        ..Default::default()
+    };
+    let profiles = resolve_profiles(&config, data_dir).map_err(|e| format!("config.json: {}", e))?;
+    match name {
+        Some(name) => profiles
+            .into_iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| format!("unknown profile: {}", name)),
+        None => Ok(profiles.into_iter().next().expect("at least one profile")),
+    }
+}
+
+/// Выполняет подкоманду над данными из `data_dir`.
+pub fn run(command: CliCommand, data_dir: &Path) -> Result<(), String> {
+    match command {
+        CliCommand::Export {
+            options,
+            output,
+            profiles,
+            profile,
+        } => {
+            let profile = find_profile(profiles, data_dir, profile.as_deref())?;
+            let transactions = load_transactions(&profile.data_dir);
+            let bytes = export(&transactions, &options).map_err(|e| e.to_string())?;
+            let output = output.unwrap_or_else(|| {
+                PathBuf::from(format!("idex_export.{}", options.format.extension()))
+            });
+            fs::write(&output, bytes)
+                .map_err(|e| format!("failed to write {}: {}", output.display(), e))?;
+            println!("Exported to {}", output.display());
+            Ok(())
+        }
+        CliCommand::Report {
+            config,
+            profiles,
+            profile,
+            from,
+            to,
+            json,
+        } => {
+            let profile = find_profile(profiles, data_dir, profile.as_deref())?;
+            let report = build_report(&load_transactions(&profile.data_dir), &config, from, to);
+            if json {
+                let text = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+                println!("{}", text);
+            } else {
+                print!("{}", render_text(&report));
+            }
+            Ok(())
+        }
+        CliCommand::ImportCookies {
+            profiles,
+            profile,
+            input,
+            format,
+        } => {
+            let profile = find_profile(profiles, data_dir, profile.as_deref())?;
+            let text = if input.as_os_str() == "-" {
+                let mut text = String::new();
+                io::stdin()
+                    .read_to_string(&mut text)
+                    .map_err(|e| format!("failed to read stdin: {}", e))?;
+                text
+            } else {
+                fs::read_to_string(&input)
+                    .map_err(|e| format!("failed to read {}: {}", input.display(), e))?
+            };
+            // Работающий экземпляр перезаписал бы cookies.json своей копией.
+            if let Some(running) = find_running(data_dir) {
+                let reply = running
+                    .send(HandOff::ImportCookies {
+                        profile: profile.name,
+                        text,
+                        format,
+                    })
+                    .map_err(|e| e.to_string())?;
+                println!("{}", reply);
+                return Ok(());
+            }
+            let host = host_of(&profile.base_url);
+            let cookies = parse_cookies(&text, format, &host).map_err(|e| e.to_string())?;
+            let (cookies, report) = filter_importable(cookies, &host, Utc::now());
+
+            fs::create_dir_all(&profile.data_dir)
+                .map_err(|e| format!("failed to create {}: {}", profile.data_dir.display(), e))?;
+            let mut store = load_cookies(&profile.data_dir)
+                .map_err(|e| format!("failed to read cookies.json: {}", e))?;
+            apply_cookies(&mut store, cookies, Utc::now());
+            save_cookies(&profile.data_dir, &store)
+                .map_err(|e| format!("failed to write cookies.json: {}", e))?;
+
+            println!("{}", report.describe(&profile.name, &host));
+            Ok(())
+        }
+        CliCommand::ExportCookies {
+            profiles,
+            profile,
+            output,
+            format,
+        } => {
+            let profile = find_profile(profiles, data_dir, profile.as_deref())?;
+            let store = load_cookies(&profile.data_dir)
+                .map_err(|e| format!("failed to read cookies.json: {}", e))?;
+            let text = export_cookies(&store.cookies, format, Utc::now());
+            match output {
+                Some(output) => {
+                    fs::write(&output, text)
+                        .map_err(|e| format!("failed to write {}: {}", output.display(), e))?;
+                    println!("Exported cookies of profile {} to {}", profile.name, output.display());
+                }
+                None => println!("{}", text),
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alerts::AlertsConfig, analytics::AnalyticsConfig, attachments::AttachmentsConfig, export::ExportConfig,
+    logging::LoggingConfig, notify::NotifyConfig, profiles::ProfileConfig, receipts::ReceiptsConfig, recorder::RecorderConfig,
+    rules::AutoClaimConfig, session::SessionConfig,
+};
+
+const CONFIG_FILE: &str = "config.json";
+
+/// Переменная окружения, переопределяющая каталог данных.
+pub const DATA_DIR_ENV: &str = "P2P_DATA_DIR";
+
+/// Каталог данных приложения: `P2P_DATA_DIR` или текущий каталог.
+pub fn data_dir() -> PathBuf {
+    env::var_os(DATA_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Настройки приложения из config.json. Отсутствующие поля получают значения
+/// по умолчанию, поэтому файл можно не создавать вовсе.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct AppConfig {
+    pub logging: LoggingConfig,
+    pub recorder: RecorderConfig,
+    pub export: ExportConfig,
+    pub analytics: AnalyticsConfig,
+    pub attachments: AttachmentsConfig,
+    pub receipts: ReceiptsConfig,
+    pub auto_claim: AutoClaimConfig,
+    pub alerts: AlertsConfig,
+    pub session: SessionConfig,
+    pub notifications: NotifyConfig,
+    pub profiles: Vec<ProfileConfig>,
+}
+
+/// Загружает config.json; при ошибке чтения или разбора возвращает настройки по умолчанию.
+pub fn load_config(data_dir: &Path) -> AppConfig {
+    try_load_config(data_dir).unwrap_or_else(|e| {
+        // Логирование настраивается по этому же файлу, поэтому пишем напрямую.
+        eprintln!("{}", e);
+        AppConfig::default()
+    })
+}
+
+/// Загружает config.json, сообщая об ошибке вместо перехода на настройки по
+/// умолчанию. Отсутствующий файл ошибкой не считается.
+pub fn try_load_config(data_dir: &Path) -> Result<AppConfig, String> {
+    let path = data_dir.join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(AppConfig::default());
+    }
+    let content =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", CONFIG_FILE, e))?;
+    serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", CONFIG_FILE, e))
+}
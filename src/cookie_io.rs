@@ -0,0 +1,336 @@
+//! Импорт и экспорт кук в форматах других инструментов: JSON расширений
+//! браузера (EditThisCookie, Cookie-Editor), Netscape `cookies.txt` и строка
+//! заголовка `Cookie:`.
+//!
+//! При импорте остаются только куки, которые браузер отправил бы панели:
+//! домен совпадает с хостом панели или является его родителем.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    cookie_sync::{is_expired, parse_cookie_pairs},
+    proxy::{Cookie, ProxyState},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CookieFormat {
+    /// Массив кук расширения браузера или содержимое cookies.json.
+    Json,
+    /// Netscape `cookies.txt` (curl, wget, yt-dlp).
+    Netscape,
+    /// Строка `name=value; name2=value2`, можно с префиксом `Cookie:`.
+    Header,
+}
+
+impl CookieFormat {
+    pub fn parse(value: &str) -> Result<Self, CookieIoError> {
+        match value.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "netscape" | "txt" | "cookies.txt" => Ok(Self::Netscape),
+            "header" => Ok(Self::Header),
+            _ => Err(CookieIoError::UnknownFormat(value.to_string())),
+        }
+    }
+
+    /// Угадывает формат по содержимому.
+    pub fn detect(text: &str) -> Self {
+        let text = text.trim_start();
+        if text.starts_with('[') || text.starts_with('{') {
+            Self::Json
+        } else if text.starts_with('#') || text.lines().any(|l| l.split('\t').count() >= 7) {
+            Self::Netscape
+        } else {
+            Self::Header
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CookieIoError {
+    UnknownFormat(String),
+    Json(String),
+    Netscape { line: usize, message: String },
+    Empty,
+}
+
+impl fmt::Display for CookieIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CookieIoError::UnknownFormat(v) => {
+                write!(f, "unknown cookie format: {} (expected json, netscape or header)", v)
+            }
+            CookieIoError::Json(e) => write!(f, "invalid cookie JSON: {}", e),
+            CookieIoError::Netscape { line, message } => {
+                write!(f, "invalid cookies.txt line {}: {}", line, message)
+            }
+            CookieIoError::Empty => write!(f, "no cookies found"),
+        }
+    }
+}
+
+impl std::error::Error for CookieIoError {}
+
+/// Кука в JSON расширения: поля в camelCase, как экспортирует Chrome,
+/// или в snake_case, как в cookies.json.
+#[derive(Deserialize)]
+struct ImportedCookie {
+    name: String,
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    domain: Option<String>,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default, alias = "expirationDate")]
+    expiration_date: Option<f64>,
+    #[serde(default, alias = "hostOnly")]
+    host_only: Option<bool>,
+    #[serde(default, alias = "httpOnly")]
+    http_only: Option<bool>,
+    #[serde(default, alias = "sameSite")]
+    same_site: Option<String>,
+    #[serde(default)]
+    secure: Option<bool>,
+    #[serde(default)]
+    session: Option<bool>,
+    #[serde(default, alias = "storeId")]
+    store_id: Option<String>,
+}
+
+/// Разбирает куки; `format` угадывается, если не задан. Куки без домена
+/// (строка заголовка, JSON без `domain`) относятся к `host`.
+pub fn parse_cookies(
+    text: &str,
+    format: Option<CookieFormat>,
+    host: &str,
+) -> Result<Vec<Cookie>, CookieIoError> {
+    let cookies = match format.unwrap_or_else(|| CookieFormat::detect(text)) {
+        CookieFormat::Json => parse_json(text, host)?,
+        CookieFormat::Netscape => parse_netscape(text)?,
+        CookieFormat::Header => parse_header(text, host),
+    };
+    if cookies.is_empty() {
+        return Err(CookieIoError::Empty);
+    }
+    Ok(cookies)
+}
+
+fn parse_json(text: &str, host: &str) -> Result<Vec<Cookie>, CookieIoError> {
+    // Без `#[serde(untagged)]`: с arbitrary_precision он не разбирает дробные числа.
+    let mut json: Value = serde_json::from_str(text).map_err(|e| CookieIoError::Json(e.to_string()))?;
+    if let Some(store) = json.get_mut("cookies") {
+        json = store.take();
+    }
+    let cookies: Vec<ImportedCookie> =
+        serde_json::from_value(json).map_err(|e| CookieIoError::Json(e.to_string()))?;
+    Ok(cookies
+        .into_iter()
+        .map(|c| {
+            let domain = c.domain.filter(|d| !d.is_empty());
+            Cookie {
+                host_only: c.host_only.or(Some(domain.as_deref().is_none_or(|d| !d.starts_with('.')))),
+                domain: domain.unwrap_or_else(|| host.to_string()),
+                name: c.name,
+                value: c.value,
+                path: c.path.unwrap_or_else(|| "/".to_string()),
+                session: c.session.or(Some(c.expiration_date.is_none())),
+                expiration_date: c.expiration_date,
+                http_only: c.http_only,
+                same_site: c.same_site,
+                secure: c.secure,
+                store_id: c.store_id,
+            }
+        })
+        .collect())
+}
+
+/// Префикс строки `cookies.txt` для HttpOnly-кук (curl).
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+fn parse_netscape(text: &str) -> Result<Vec<Cookie>, CookieIoError> {
+    let mut cookies = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| CookieIoError::Netscape {
+            line: index + 1,
+            message: message.to_string(),
+        };
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, _subdomains, path, secure, expires, name, value] = fields[..] else {
+            return Err(error("expected 7 tab-separated fields"));
+        };
+        let expires: i64 = expires.trim().parse().map_err(|_| error("invalid expiry"))?;
+        cookies.push(Cookie {
+            name: name.to_string(),
+            value: value.trim_end_matches('\r').to_string(),
+            host_only: Some(!domain.starts_with('.')),
+            domain: domain.to_string(),
+            path: path.to_string(),
+            // Ноль в `cookies.txt` означает сессионную куку.
+            expiration_date: (expires > 0).then_some(expires as f64),
+            http_only: Some(http_only),
+            same_site: None,
+            secure: Some(secure.eq_ignore_ascii_case("TRUE")),
+            session: Some(expires <= 0),
+            store_id: None,
+        });
+    }
+    Ok(cookies)
+}
+
+fn parse_header(text: &str, host: &str) -> Vec<Cookie> {
+    let text = text.trim();
+    let text = text
+        .get(..7)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("cookie:"))
+        .map_or(text, |_| &text[7..]);
+    parse_cookie_pairs(text)
+        .into_iter()
+        .map(|(name, value)| Cookie {
+            name,
+            value,
+            domain: host.to_string(),
+            path: "/".to_string(),
+            expiration_date: None,
+            host_only: Some(true),
+            // Из строки заголовка не понять, HttpOnly ли кука; в webview она
+            // попадёт, но прокси всё равно подставляет значение из хранилища.
+            http_only: Some(false),
+            same_site: None,
+            secure: None,
+            session: Some(true),
+            store_id: None,
+        })
+        .collect()
+}
+
+/// Отправил бы браузер куку с доменом `domain` на `host`.
+pub fn domain_matches(domain: &str, host: &str) -> bool {
+    let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    !domain.is_empty()
+        && (host == domain || host.strip_suffix(&domain).is_some_and(|rest| rest.ends_with('.')))
+}
+
+/// Итог импорта.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Куки с истёкшим сроком; в хранилище они удаляют одноимённые.
+    pub expired: usize,
+    /// `name (domain)` кук, которые к панели не относятся.
+    pub rejected: Vec<String>,
+}
+
+impl ImportReport {
+    /// Итог для пользователя, как его печатает `cookies import`.
+    pub fn describe(&self, profile: &str, host: &str) -> String {
+        let mut text = format!(
+            "Imported {} cookies into profile {} ({} expired removed)",
+            self.imported, profile, self.expired
+        );
+        if !self.rejected.is_empty() {
+            text.push_str(&format!("\nSkipped, not for {}: {}", host, self.rejected.join(", ")));
+        }
+        text
+    }
+}
+
+/// Оставляет куки, которые относятся к `host`, и считает итог импорта.
+pub fn filter_importable(
+    cookies: Vec<Cookie>,
+    host: &str,
+    now: DateTime<Utc>,
+) -> (Vec<Cookie>, ImportReport) {
+    let mut report = ImportReport::default();
+    let mut accepted = Vec::new();
+    for cookie in cookies {
+        if !domain_matches(&cookie.domain, host) {
+            report.rejected.push(format!("{} ({})", cookie.name, cookie.domain));
+            continue;
+        }
+        if is_expired(&cookie, now) {
+            report.expired += 1;
+        } else {
+            report.imported += 1;
+        }
+        accepted.push(cookie);
+    }
+    (accepted, report)
+}
+
+/// Импортирует куки в хранилище работающего профиля и сохраняет cookies.json.
+pub fn import_into(
+    state: &ProxyState,
+    text: &str,
+    format: Option<CookieFormat>,
+    now: DateTime<Utc>,
+) -> Result<ImportReport, CookieIoError> {
+    let host = state.upstream_host();
+    let cookies = parse_cookies(text, format, &host)?;
+    let (cookies, report) = filter_importable(cookies, &host, now);
+    state.merge_cookies(cookies);
+    Ok(report)
+}
+
+/// Выгружает куки в указанном формате; истёкшие пропускаются.
+pub fn export_cookies(cookies: &[Cookie], format: CookieFormat, now: DateTime<Utc>) -> String {
+    let live = cookies.iter().filter(|c| !is_expired(c, now));
+    match format {
+        CookieFormat::Json => {
+            let list: Vec<Value> = live
+                .enumerate()
+                .map(|(index, c)| {
+                    json!({
+                        "domain": c.domain,
+                        "expirationDate": c.expiration_date,
+                        "hostOnly": c.host_only.unwrap_or(!c.domain.starts_with('.')),
+                        "httpOnly": c.http_only.unwrap_or(false),
+                        "name": c.name,
+                        "path": c.path,
+                        "sameSite": c.same_site.as_deref().unwrap_or("unspecified"),
+                        "secure": c.secure.unwrap_or(false),
+                        "session": c.expiration_date.is_none(),
+                        "storeId": c.store_id.as_deref().unwrap_or("0"),
+                        "value": c.value,
+                        "id": index + 1,
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&list).expect("cookies serialize to JSON")
+        }
+        CookieFormat::Netscape => {
+            let mut out = String::from("# Netscape HTTP Cookie File\n");
+            for c in live {
+                let subdomains = c.host_only == Some(false) || c.domain.starts_with('.');
+                out.push_str(&format!(
+                    "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    if c.http_only == Some(true) { HTTP_ONLY_PREFIX } else { "" },
+                    c.domain,
+                    if subdomains { "TRUE" } else { "FALSE" },
+                    c.path,
+                    if c.secure == Some(true) { "TRUE" } else { "FALSE" },
+                    c.expiration_date.map_or(0, |e| e as i64),
+                    c.name,
+                    c.value
+                ));
+            }
+            out
+        }
+        CookieFormat::Header => live
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; "),
+    }
+}
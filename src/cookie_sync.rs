@@ -0,0 +1,281 @@
+//! Синхронизация кук между webview и `CookieStore`.
+//!
+//! wry 0.28 не даёт доступа к хранилищу кук webview, поэтому главным хранилищем
+//! служит `CookieStore` прокси. HttpOnly-куки живут только в нём и
+//! подставляются прокси в запросы к панели, а в webview не попадают вовсе.
+//! Остальные куки (например, `XSRF-TOKEN`, который читает фронтенд) webview
+//! получает из переписанных `Set-Cookie` и из скрипта при открытии окна, а
+//! изменения, сделанные страницей, возвращаются через IPC-сообщение
+//! `ipc::PageMessage::Cookies`.
+//!
+//! Мост работает через `document.cookie` из JS, а не через нативное API
+//! хранилища кук, поэтому HttpOnly-куки webview им не поддерживаются: они не
+//! кладутся в страницу, не читаются из неё и не удаляются при выходе. Если
+//! сайт ставит HttpOnly-куки прямо в webview (как в окне Telegram), они живут
+//! только в хранилище самого webview.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::proxy::{Cookie, CookieStore};
+
+/// Скрипт инициализации: сообщает приложению о каждом изменении `document.cookie`.
+/// Требует `ipc::BRIDGE_SCRIPT`.
+pub const CAPTURE_SCRIPT: &str = r#"
+(function () {
+    var last = null;
+    function report() {
+        var current = document.cookie;
+        if (current !== last) {
+            last = current;
+            window.__p2p.send({ type: "cookies", cookies: current });
+        }
+    }
+    document.addEventListener("DOMContentLoaded", report);
+    setInterval(report, 2000);
+})();
+"#;
+
+fn now_secs(now: DateTime<Utc>) -> f64 {
+    now.timestamp() as f64
+}
+
+/// Истёк ли срок куки к моменту `now`.
+pub fn is_expired(cookie: &Cookie, now: DateTime<Utc>) -> bool {
+    cookie
+        .expiration_date
+        .is_some_and(|expires| expires <= now_secs(now))
+}
+
+/// Может ли кука жить в `document.cookie`: не HttpOnly и без символов,
+/// которые ломают строку `name=value`.
+pub fn is_seedable(cookie: &Cookie) -> bool {
+    cookie.http_only != Some(true)
+        && !cookie.name.is_empty()
+        && !cookie.name.contains(['=', ';', ',', ' '])
+        && !cookie.value.contains([';', ',', '\r', '\n'])
+}
+
+fn parse_expires(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    DateTime::parse_from_rfc2822(value)
+        .map(|d| d.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            // Устаревший формат с дефисами: `Wed, 21-Oct-2015 07:28:00 GMT`.
+            NaiveDateTime::parse_from_str(value, "%a, %d-%b-%Y %H:%M:%S GMT")
+                .ok()
+                .map(|d| d.and_utc())
+        })
+}
+
+/// Разбирает заголовок `Set-Cookie`. Куки, удаляемые сервером (`Max-Age=0`
+/// или `Expires` в прошлом), возвращаются с прошедшим `expiration_date`.
+pub fn parse_set_cookie(header: &str, domain: &str, now: DateTime<Utc>) -> Option<Cookie> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: value.trim().trim_matches('"').to_string(),
+        domain: domain.to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: Some(true),
+        http_only: Some(false),
+        same_site: None,
+        secure: Some(false),
+        session: Some(true),
+        store_id: None,
+    };
+    let mut max_age = None;
+    let mut expires = None;
+    for attribute in parts {
+        let (key, value) = attribute
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .unwrap_or((attribute.trim(), ""));
+        match key.to_ascii_lowercase().as_str() {
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "domain" if !value.is_empty() => {
+                cookie.domain = value.trim_start_matches('.').to_string();
+                cookie.host_only = Some(false);
+            }
+            "httponly" => cookie.http_only = Some(true),
+            "secure" => cookie.secure = Some(true),
+            "samesite" => cookie.same_site = Some(value.to_ascii_lowercase()),
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" => expires = parse_expires(value),
+            _ => {}
+        }
+    }
+    // Max-Age важнее Expires.
+    let expiration = match max_age {
+        Some(seconds) if seconds <= 0 => Some(now_secs(now) - 1.0),
+        Some(seconds) => Some(now_secs(now) + seconds as f64),
+        None => expires.map(|e| e.timestamp() as f64),
+    };
+    if let Some(expiration) = expiration {
+        cookie.expiration_date = Some(expiration);
+        cookie.session = Some(false);
+    }
+    Some(cookie)
+}
+
+/// Применяет куки из ответа к хранилищу: заменяет одноимённые, удаляет
+/// истёкшие. Возвращает, изменилось ли хранилище.
+pub fn apply_cookies(store: &mut CookieStore, cookies: Vec<Cookie>, now: DateTime<Utc>) -> bool {
+    let mut changed = false;
+    for cookie in cookies {
+        let position = store.cookies.iter().position(|c| c.name == cookie.name);
+        if is_expired(&cookie, now) {
+            if let Some(pos) = position {
+                store.cookies.remove(pos);
+                changed = true;
+            }
+            continue;
+        }
+        match position {
+            Some(pos) => store.cookies[pos] = cookie,
+            None => store.cookies.push(cookie),
+        }
+        changed = true;
+    }
+    changed
+}
+
+/// Пары `name=value` из заголовка `Cookie` или строки `document.cookie`.
+pub fn parse_cookie_pairs(header: &str) -> Vec<(String, String)> {
+    header
+        .split(';')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim();
+            (!name.is_empty()).then(|| (name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Заголовок `Cookie` для панели: все куки хранилища (включая HttpOnly),
+/// плюс куки из браузера, которых в хранилище нет.
+pub fn merge_cookie_header(store: &CookieStore, browser: Option<&str>, now: DateTime<Utc>) -> String {
+    let mut pairs: Vec<String> = store
+        .cookies
+        .iter()
+        .filter(|c| !is_expired(c, now))
+        .map(|c| format!("{}={}", c.name, c.value))
+        .collect();
+    for (name, value) in browser.map(parse_cookie_pairs).unwrap_or_default() {
+        if !store.cookies.iter().any(|c| c.name == name) {
+            pairs.push(format!("{}={}", name, value));
+        }
+    }
+    pairs.join("; ")
+}
+
+/// Переписывает `Set-Cookie` панели для webview, открытого на адресе прокси:
+/// HttpOnly-куки остаются только в хранилище, а `Domain` и `Secure`
+/// убираются, иначе браузер отвергнет куку для http://127.0.0.1.
+pub fn webview_set_cookie(header: &str) -> Option<String> {
+    let mut parts = header.split(';');
+    let mut out = vec![parts.next()?.trim().to_string()];
+    for attribute in parts {
+        let attribute = attribute.trim();
+        let key = attribute
+            .split('=')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        match key.as_str() {
+            "httponly" => return None,
+            "domain" | "secure" | "partitioned" => {}
+            // SameSite=None без Secure браузер не примет.
+            "samesite" if attribute.to_ascii_lowercase().ends_with("none") => {
+                out.push("SameSite=Lax".to_string())
+            }
+            "" => {}
+            _ => out.push(attribute.to_string()),
+        }
+    }
+    Some(out.join("; "))
+}
+
+/// Скрипт, кладущий в `document.cookie` куки хранилища, доступные странице.
+/// Строки экранируются как JSON, поэтому кавычки и обратные слэши в
+/// значениях не ломают скрипт.
+pub fn seed_script(cookies: &[Cookie], now: DateTime<Utc>) -> String {
+    cookies
+        .iter()
+        .filter(|c| is_seedable(c) && !is_expired(c, now))
+        .map(|c| {
+            let mut cookie = format!("{}={}; path={}", c.name, c.value, c.path);
+            if let Some(expires) = c
+                .expiration_date
+                .and_then(|e| DateTime::from_timestamp(e as i64, 0))
+            {
+                cookie.push_str(&format!(
+                    "; expires={}",
+                    expires.format("%a, %d %b %Y %H:%M:%S GMT")
+                ));
+            }
+            let literal = serde_json::to_string(&cookie).expect("string serializes to JSON");
+            format!("document.cookie = {};", literal)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Переносит в хранилище `document.cookie`, присланный страницей. `seen` —
+/// имена кук из прошлых сообщений страницы: из хранилища удаляются только
+/// куки, которые страница уже показывала и которых теперь нет (например,
+/// после выхода). Куки, так и не попавшие в страницу, не трогаются, как и
+/// HttpOnly-куки.
+pub fn apply_document_cookies(
+    store: &mut CookieStore,
+    seen: &mut HashSet<String>,
+    document_cookie: &str,
+    domain: &str,
+) -> bool {
+    let pairs = parse_cookie_pairs(document_cookie);
+    let current: HashSet<String> = pairs.iter().map(|(name, _)| name.clone()).collect();
+    let mut changed = false;
+    let before = store.cookies.len();
+    store.cookies.retain(|c| {
+        c.http_only == Some(true) || !seen.contains(&c.name) || current.contains(&c.name)
+    });
+    changed |= store.cookies.len() != before;
+    *seen = current;
+    for (name, value) in pairs {
+        match store.cookies.iter_mut().find(|c| c.name == name) {
+            Some(cookie) if cookie.http_only == Some(true) => {}
+            Some(cookie) => {
+                if cookie.value != value {
+                    cookie.value = value;
+                    changed = true;
+                }
+            }
+            None => {
+                store.cookies.push(Cookie {
+                    name,
+                    value,
+                    domain: domain.to_string(),
+                    path: "/".to_string(),
+                    expiration_date: None,
+                    host_only: Some(true),
+                    http_only: Some(false),
+                    same_site: None,
+                    secure: Some(false),
+                    session: Some(true),
+                    store_id: None,
+                });
+                changed = true;
+            }
+        }
+    }
+    changed
+}
@@ -0,0 +1,183 @@
+//! Режим `--headless`: прокси, опрос выплат, загрузка чеков и оповещения
+//! работают без окна, например на сервере под systemd.
+//!
+//! Куки сессии кладутся в cookies.json профиля заранее или передаются
+//! работающему демону повторным запуском `cookies import` (см. `instance`);
+//! других способов нет, локальный API куки не принимает. Device token демон не
+//! спрашивает: он берётся из `P2P_DEVICE_TOKEN` или device.token.
+//! SIGTERM и Ctrl+C останавливают демон, SIGHUP перечитывает config.json и
+//! перезапускает задачи профилей. Настройки логирования при перечитывании
+//! не меняются.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{try_load_config, AppConfig},
+    instance::{import_cookies, HandOff, InstanceLock},
+    notify::Notifier,
+    profiles::{profile_states, resolve_profiles, stop_profiles, Profile, ProfileError},
+    proxy::ProxyState,
+    supervisor::Supervisor,
+};
+
+/// Управляющие события демона.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonSignal {
+    Shutdown,
+    /// Перечитать config.json.
+    Reload,
+}
+
+/// PID-файл; удаляется при выходе, если в нём всё ещё наш PID.
+pub struct PidFile {
+    path: PathBuf,
+    pid: u32,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let pid = std::process::id();
+        fs::write(path, format!("{}\n", pid))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            pid,
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let ours = fs::read_to_string(&self.path)
+            .map(|content| content.trim() == self.pid.to_string())
+            .unwrap_or(false);
+        if ours {
+            if let Err(e) = fs::remove_file(&self.path) {
+                warn!(path = %self.path.display(), error = %e, "Failed to remove PID file");
+            }
+        }
+    }
+}
+
+/// Пересылает сигналы ОС в `signals`, пока канал открыт.
+pub async fn forward_signals(signals: UnboundedSender<DaemonSignal>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let (mut term, mut hup) = match (signal(SignalKind::terminate()), signal(SignalKind::hangup())) {
+            (Ok(term), Ok(hup)) => (term, hup),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!(error = %e, "Failed to listen for SIGTERM/SIGHUP");
+                let _ = tokio::signal::ctrl_c().await;
+                let _ = signals.send(DaemonSignal::Shutdown);
+                return;
+            }
+        };
+        loop {
+            let signal = tokio::select! {
+                _ = tokio::signal::ctrl_c() => DaemonSignal::Shutdown,
+                _ = term.recv() => DaemonSignal::Shutdown,
+                _ = hup.recv() => DaemonSignal::Reload,
+            };
+            if signals.send(signal).is_err() {
+                return;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        let _ = signals.send(DaemonSignal::Shutdown);
+    }
+}
+
+/// Работает до `DaemonSignal::Shutdown` (или закрытия канала). Ошибка
+/// возвращается только если профили из исходных настроек некорректны;
+/// некорректный config.json при перечитывании оставляет прежние настройки.
+/// С `instance` демон принимает команды повторных запусков.
+pub async fn run_daemon(
+    data_dir: &Path,
+    config: AppConfig,
+    mut signals: UnboundedReceiver<DaemonSignal>,
+    instance: Option<&InstanceLock>,
+) -> Result<(), ProfileError> {
+    let mut config = config;
+    let mut profiles = resolve_profiles(&config, data_dir)?;
+    // Профили текущего поколения: после SIGHUP команды идут уже к новым.
+    let current: Arc<Mutex<HashMap<String, ProxyState>>> = Arc::default();
+    let server = instance.map(|instance| {
+        let current = current.clone();
+        tokio::spawn(instance.serve(Arc::new(move |hand_off| match hand_off {
+            HandOff::Show => Err("the running instance is headless and has no window".to_string()),
+            HandOff::ImportCookies {
+                profile,
+                text,
+                format,
+            } => import_cookies(&current.lock().unwrap(), &profile, &text, format),
+        })))
+    });
+    let result = run_generations(data_dir, &mut config, &mut profiles, &mut signals, &current).await;
+    if let Some(server) = server {
+        server.abort();
+    }
+    result
+}
+
+async fn run_generations(
+    data_dir: &Path,
+    config: &mut AppConfig,
+    profiles: &mut Vec<Profile>,
+    signals: &mut UnboundedReceiver<DaemonSignal>,
+    current: &Mutex<HashMap<String, ProxyState>>,
+) -> Result<(), ProfileError> {
+    loop {
+        let supervisor = Supervisor::new();
+        // Нажатия ведут к окну IDEX, которого у демона нет.
+        let notifier = Notifier::from_config(config, None);
+        let states = profile_states(profiles, config, &notifier);
+        *current.lock().unwrap() = states.clone();
+        for profile in profiles.iter() {
+            if let Some(state) = states.get(&profile.name) {
+                // Ошибка порта уже в логе; остальные задачи профиля работают.
+                let _ = profile.spawn_tasks(&supervisor, state);
+            }
+        }
+        info!(profiles = profiles.len(), "Headless mode started");
+
+        let reloaded = loop {
+            match signals.recv().await {
+                Some(DaemonSignal::Reload) => match reload(data_dir) {
+                    Ok(next) => break Some(next),
+                    Err(e) => error!(error = %e, "Config reload failed, keeping current settings"),
+                },
+                Some(DaemonSignal::Shutdown) | None => break None,
+            }
+        };
+
+        stop_profiles(&supervisor, &states).await;
+        match reloaded {
+            Some((next_config, next_profiles)) => {
+                info!("Config reloaded, restarting profiles");
+                *config = next_config;
+                *profiles = next_profiles;
+            }
+            None => {
+                info!("Headless mode stopped");
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn reload(data_dir: &Path) -> Result<(AppConfig, Vec<Profile>), String> {
+    let config = try_load_config(data_dir)?;
+    let profiles = resolve_profiles(&config, data_dir).map_err(|e| e.to_string())?;
+    Ok((config, profiles))
+}
@@ -0,0 +1,679 @@
+//! Выгрузка истории транзакций в CSV, XLSX и Parquet с фильтром по дате
+//! создания и выбором полей.
+
+use std::{collections::BTreeSet, fmt, str::FromStr, sync::Arc};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use parquet::{
+    column::writer::ColumnWriter,
+    data_type::{ByteArray, FixedLenByteArray},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use rust_decimal::prelude::ToPrimitive;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    idex::Transaction,
+    money::{lookup, Currency, Decimal, TRADER},
+};
+
+/// Масштаб колонок DECIMAL в Parquet: 8 знаков покрывают и копейки, и USDT.
+const PARQUET_DECIMAL_SCALE: u32 = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "xlsx" => Ok(ExportFormat::Xlsx),
+            "parquet" => Ok(ExportFormat::Parquet),
+            other => Err(ExportError::InvalidOption(format!("unknown format: {}", other))),
+        }
+    }
+}
+
+/// Настройки выгрузки по умолчанию (секция `export` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// Разделитель колонок CSV; для Excel с русской локалью обычно `;`.
+    pub delimiter: char,
+    /// Писать дробные числа в CSV через запятую (`1234,56`).
+    pub decimal_comma: bool,
+    /// Поля по умолчанию; пустой список означает «все поля».
+    pub fields: Vec<String>,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            decimal_comma: false,
+            fields: Vec::new(),
+        }
+    }
+}
+
+/// Параметры одной выгрузки.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Первый день диапазона по `created_at` (UTC), включительно.
+    pub from: Option<NaiveDate>,
+    /// Последний день диапазона, включительно.
+    pub to: Option<NaiveDate>,
+    pub fields: Vec<Field>,
+    pub delimiter: u8,
+    pub decimal_comma: bool,
+}
+
+impl ExportOptions {
+    /// Параметры из настроек; поля из config.json проверяются здесь же.
+    pub fn from_config(config: &ExportConfig, format: ExportFormat) -> Result<Self, ExportError> {
+        if !config.delimiter.is_ascii() {
+            return Err(ExportError::InvalidOption(format!(
+                "delimiter must be an ASCII character: {:?}",
+                config.delimiter
+            )));
+        }
+        Ok(Self {
+            format,
+            from: None,
+            to: None,
+            fields: parse_fields(&config.fields.join(","))?,
+            delimiter: config.delimiter as u8,
+            decimal_comma: config.decimal_comma,
+        })
+    }
+
+    /// Применяет один параметр из командной строки или строки запроса API.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ExportError> {
+        match key {
+            "format" => self.format = value.parse()?,
+            "from" => self.from = Some(parse_date(value)?),
+            "to" => self.to = Some(parse_date(value)?),
+            "fields" => self.fields = parse_fields(value)?,
+            "delimiter" => {
+                self.delimiter = match value {
+                    "tab" | "\\t" => b'\t',
+                    v if v.len() == 1 && v.is_ascii() => v.as_bytes()[0],
+                    v => {
+                        return Err(ExportError::InvalidOption(format!(
+                            "delimiter must be a single ASCII character: {:?}",
+                            v
+                        )))
+                    }
+                }
+            }
+            "decimal_comma" | "decimal-comma" => {
+                self.decimal_comma = matches!(value, "" | "1" | "true" | "yes")
+            }
+            other => {
+                return Err(ExportError::InvalidOption(format!(
+                    "unknown export option: {}",
+                    other
+                )))
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    InvalidOption(String),
+    Csv(csv::Error),
+    Xlsx(rust_xlsxwriter::XlsxError),
+    Parquet(parquet::errors::ParquetError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::InvalidOption(msg) => write!(f, "{}", msg),
+            ExportError::Csv(e) => write!(f, "CSV export failed: {}", e),
+            ExportError::Xlsx(e) => write!(f, "XLSX export failed: {}", e),
+            ExportError::Parquet(e) => write!(f, "Parquet export failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<csv::Error> for ExportError {
+    fn from(e: csv::Error) -> Self {
+        ExportError::Csv(e)
+    }
+}
+
+impl From<rust_xlsxwriter::XlsxError> for ExportError {
+    fn from(e: rust_xlsxwriter::XlsxError) -> Self {
+        ExportError::Xlsx(e)
+    }
+}
+
+impl From<parquet::errors::ParquetError> for ExportError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        ExportError::Parquet(e)
+    }
+}
+
+/// Тип колонки, определяющий её представление в XLSX и Parquet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Decimal,
+    Number,
+    Integer,
+    DateTime,
+}
+
+enum Cell {
+    Text(Option<String>),
+    Decimal(Option<Decimal>),
+    Number(Option<f64>),
+    Integer(Option<i64>),
+    DateTime(Option<NaiveDateTime>, Option<String>),
+}
+
+/// Колонка выгрузки с фиксированным полем `Transaction`.
+struct Column {
+    name: &'static str,
+    kind: Kind,
+    get: fn(&Transaction) -> Cell,
+}
+
+/// Сторона суммы: `amount` или `total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Side {
+    Amount,
+    Total,
+}
+
+impl Side {
+    fn name(self) -> &'static str {
+        match self {
+            Side::Amount => "amount",
+            Side::Total => "total",
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Source {
+    Column(&'static Column),
+    Money {
+        side: Side,
+        party: String,
+        currency: Currency,
+    },
+}
+
+/// Колонка выгрузки. Имена фиксированных колонок совпадают с полями
+/// `Transaction` в JSON, суммы называются `amount_rub`, `total_usdt`
+/// (трейдер) или `amount_merchant_rub` (другие стороны сделки).
+#[derive(Clone)]
+pub struct Field {
+    pub name: String,
+    source: Source,
+}
+
+impl fmt::Debug for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl Field {
+    fn column(column: &'static Column) -> Self {
+        Self {
+            name: column.name.to_string(),
+            source: Source::Column(column),
+        }
+    }
+
+    fn money(side: Side, party: &str, currency: Currency) -> Self {
+        let alpha = currency.alpha().to_ascii_lowercase();
+        let name = if party == TRADER {
+            format!("{}_{}", side.name(), alpha)
+        } else {
+            format!("{}_{}_{}", side.name(), party, alpha)
+        };
+        Self {
+            name,
+            source: Source::Money {
+                side,
+                party: party.to_string(),
+                currency,
+            },
+        }
+    }
+
+    fn kind(&self) -> Kind {
+        match &self.source {
+            Source::Column(c) => c.kind,
+            Source::Money { .. } => Kind::Decimal,
+        }
+    }
+
+    fn get(&self, tx: &Transaction) -> Cell {
+        match &self.source {
+            Source::Column(c) => (c.get)(tx),
+            Source::Money {
+                side,
+                party,
+                currency,
+            } => {
+                let amounts = match side {
+                    Side::Amount => &tx.amount,
+                    Side::Total => &tx.total,
+                };
+                Cell::Decimal(lookup(amounts, party, currency.code()).map(|m| m.amount))
+            }
+        }
+    }
+
+    /// Колонка суммы по имени вида `amount_rub` или `total_merchant_usdt`.
+    fn parse_money(name: &str) -> Option<Self> {
+        let (side, rest) = if let Some(rest) = name.strip_prefix("amount_") {
+            (Side::Amount, rest)
+        } else {
+            (Side::Total, name.strip_prefix("total_")?)
+        };
+        let (party, currency) = match rest.rsplit_once('_') {
+            Some((party, currency)) => (party, currency),
+            None => (TRADER, rest),
+        };
+        Some(Self::money(side, party, Currency::parse(currency)?))
+    }
+}
+
+macro_rules! text {
+    ($name:ident) => {
+        Column {
+            name: stringify!($name),
+            kind: Kind::Text,
+            get: |tx| Cell::Text(tx.$name.clone()),
+        }
+    };
+}
+
+macro_rules! datetime {
+    ($name:ident, $tx:ident => $raw:expr) => {
+        Column {
+            name: stringify!($name),
+            kind: Kind::DateTime,
+            get: |$tx| datetime_cell($raw),
+        }
+    };
+}
+
+fn datetime_cell(raw: Option<&str>) -> Cell {
+    Cell::DateTime(raw.and_then(parse_datetime), raw.map(String::from))
+}
+
+/// Фиксированные колонки в порядке объявления `Transaction`.
+/// Колонки сумм добавляются после `wallet` по валютам, найденным в данных.
+const COLUMNS: &[Column] = &[
+    text!(user_id),
+    Column {
+        name: "transaction_id",
+        kind: Kind::Text,
+        get: |tx| Cell::Text(Some(tx.transaction_id.clone())),
+    },
+    text!(payment_method_id),
+    text!(wallet),
+    text!(status),
+    text!(bank_name),
+    text!(bank_code),
+    text!(bank_label),
+    text!(payment_method),
+    Column {
+        name: "course",
+        kind: Kind::Decimal,
+        get: |tx| Cell::Decimal(tx.course),
+    },
+    Column {
+        name: "success_count",
+        kind: Kind::Integer,
+        get: |tx| Cell::Integer(tx.success_count.map(i64::from)),
+    },
+    Column {
+        name: "success_rate",
+        kind: Kind::Number,
+        get: |tx| Cell::Number(tx.success_rate),
+    },
+    datetime!(approved_at, tx => tx.approved_at.as_deref()),
+    datetime!(expired_at, tx => tx.expired_at.as_deref()),
+    datetime!(created_at, tx => Some(tx.created_at.as_str())),
+    datetime!(updated_at, tx => Some(tx.updated_at.as_str())),
+    text!(trader_id),
+    text!(trader_name),
+    Column {
+        name: "attachments",
+        kind: Kind::Text,
+        get: |tx| Cell::Text(tx.attachments.as_ref().map(|v| v.to_string())),
+    },
+    text!(idex_id),
+];
+
+/// Все колонки для выгрузки этих транзакций: фиксированные и по каждой
+/// встретившейся паре «сторона сделки — валюта».
+pub fn default_fields(transactions: &[&Transaction]) -> Vec<Field> {
+    let mut money = BTreeSet::new();
+    for tx in transactions {
+        for (side, amounts) in [(Side::Amount, &tx.amount), (Side::Total, &tx.total)] {
+            for (party, by_currency) in amounts {
+                for code in by_currency.keys() {
+                    // Трейдер — первым, остальные стороны по алфавиту.
+                    money.insert((side, party != TRADER, party.clone(), code.clone()));
+                }
+            }
+        }
+    }
+    let mut fields = Vec::with_capacity(COLUMNS.len() + money.len());
+    for column in COLUMNS {
+        fields.push(Field::column(column));
+        if column.name == "wallet" {
+            fields.extend(
+                money
+                    .iter()
+                    .map(|(side, _, party, code)| Field::money(*side, party, Currency::new(code))),
+            );
+        }
+    }
+    fields
+}
+
+/// Разбирает список полей через запятую; пустая строка означает «все поля».
+pub fn parse_fields(list: &str) -> Result<Vec<Field>, ExportError> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            COLUMNS
+                .iter()
+                .find(|c| c.name == name)
+                .map(Field::column)
+                .or_else(|| Field::parse_money(name))
+                .ok_or_else(|| ExportError::InvalidOption(format!("unknown field: {}", name)))
+        })
+        .collect()
+}
+
+/// Разбирает дату диапазона в формате `YYYY-MM-DD`.
+pub fn parse_date(value: &str) -> Result<NaiveDate, ExportError> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|e| ExportError::InvalidOption(format!("invalid date {}: {}", value, e)))
+}
+
+fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|dt| dt.naive_utc())
+}
+
+/// Транзакции, созданные в заданном диапазоне дат.
+pub fn select(
+    transactions: &[Transaction],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Vec<&Transaction> {
+    transactions
+        .iter()
+        .filter(|tx| {
+            if from.is_none() && to.is_none() {
+                return true;
+            }
+            let Some(date) = parse_datetime(&tx.created_at).map(|dt| dt.date()) else {
+                return false;
+            };
+            from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+        })
+        .collect()
+}
+
+/// Выгружает транзакции в выбранном формате и возвращает содержимое файла.
+pub fn export(transactions: &[Transaction], options: &ExportOptions) -> Result<Vec<u8>, ExportError> {
+    let rows = select(transactions, options.from, options.to);
+    let fields = if options.fields.is_empty() {
+        default_fields(&rows)
+    } else {
+        options.fields.clone()
+    };
+    match options.format {
+        ExportFormat::Csv => export_csv(&rows, &fields, options),
+        ExportFormat::Xlsx => export_xlsx(&rows, &fields),
+        ExportFormat::Parquet => export_parquet(&rows, &fields),
+    }
+}
+
+fn export_csv(
+    rows: &[&Transaction],
+    fields: &[Field],
+    options: &ExportOptions,
+) -> Result<Vec<u8>, ExportError> {
+    let number = |v: String| {
+        if options.decimal_comma {
+            v.replace('.', ",")
+        } else {
+            v
+        }
+    };
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .from_writer(Vec::new());
+    writer.write_record(fields.iter().map(|f| f.name.as_str()))?;
+    for tx in rows {
+        writer.write_record(fields.iter().map(|f| match f.get(tx) {
+            Cell::Text(v) => v.unwrap_or_default(),
+            Cell::Decimal(v) => v.map(|v| number(v.to_string())).unwrap_or_default(),
+            Cell::Number(v) => v.map(|v| number(v.to_string())).unwrap_or_default(),
+            Cell::Integer(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+            // Исходная строка панели, чтобы не терять точность и часовой пояс.
+            Cell::DateTime(_, raw) => raw.unwrap_or_default(),
+        }))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| ExportError::Csv(e.into_error().into()))
+}
+
+fn export_xlsx(rows: &[&Transaction], fields: &[Field]) -> Result<Vec<u8>, ExportError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Transactions")?;
+
+    let header = Format::new().set_bold();
+    let money = Format::new().set_num_format("#,##0.00");
+    let datetime = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+    for (col, field) in fields.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, &field.name, &header)?;
+    }
+    for (i, tx) in rows.iter().enumerate() {
+        let row = i as u32 + 1;
+        for (col, field) in fields.iter().enumerate() {
+            let col = col as u16;
+            match field.get(tx) {
+                Cell::Text(Some(v)) => {
+                    sheet.write_string(row, col, v)?;
+                }
+                // В Excel число всё равно хранится как double.
+                Cell::Decimal(Some(v)) => {
+                    sheet.write_number_with_format(row, col, v.to_f64().unwrap_or_default(), &money)?;
+                }
+                Cell::Number(Some(v)) => {
+                    sheet.write_number(row, col, v)?;
+                }
+                Cell::Integer(Some(v)) => {
+                    sheet.write_number(row, col, v as f64)?;
+                }
+                Cell::DateTime(Some(dt), _) => {
+                    sheet.write_datetime_with_format(row, col, dt, &datetime)?;
+                }
+                // Нераспознанную дату оставляем строкой, а не теряем.
+                Cell::DateTime(None, Some(raw)) => {
+                    sheet.write_string(row, col, raw)?;
+                }
+                _ => {}
+            }
+        }
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofit();
+    Ok(workbook.save_to_buffer()?)
+}
+
+/// Поля для схемы Parquet: повторы убираются, а имя, которое парсер схемы
+/// не примет (пробелы, пунктуация в имени стороны), даёт понятную ошибку.
+fn parquet_fields(fields: &[Field]) -> Result<Vec<&Field>, ExportError> {
+    let mut seen = BTreeSet::new();
+    let mut unique = Vec::with_capacity(fields.len());
+    for field in fields {
+        let valid = field.name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && field.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(ExportError::InvalidOption(format!(
+                "field {:?} cannot be a Parquet column: use only latin letters, digits and '_'",
+                field.name
+            )));
+        }
+        if seen.insert(field.name.as_str()) {
+            unique.push(field);
+        }
+    }
+    Ok(unique)
+}
+
+fn export_parquet(rows: &[&Transaction], fields: &[Field]) -> Result<Vec<u8>, ExportError> {
+    let fields = parquet_fields(fields)?;
+    let columns: Vec<String> = fields
+        .iter()
+        .map(|f| match f.kind() {
+            Kind::Text => format!("OPTIONAL BYTE_ARRAY {} (UTF8);", f.name),
+            Kind::Decimal => format!(
+                "OPTIONAL FIXED_LEN_BYTE_ARRAY (16) {} (DECIMAL(38,{}));",
+                f.name, PARQUET_DECIMAL_SCALE
+            ),
+            Kind::Number => format!("OPTIONAL DOUBLE {};", f.name),
+            Kind::Integer => format!("OPTIONAL INT64 {};", f.name),
+            Kind::DateTime => format!("OPTIONAL INT64 {} (TIMESTAMP(MILLIS,true));", f.name),
+        })
+        .collect();
+    let schema = parse_message_type(&format!("message transaction {{ {} }}", columns.join(" ")))?;
+    let props = Arc::new(WriterProperties::builder().build());
+
+    let mut buffer = Vec::new();
+    let mut writer = SerializedFileWriter::new(&mut buffer, Arc::new(schema), props)?;
+    let mut row_group = writer.next_row_group()?;
+    for field in &fields {
+        let Some(mut column) = row_group.next_column()? else {
+            break;
+        };
+        let cells: Vec<Cell> = rows.iter().map(|tx| field.get(tx)).collect();
+        // Уровень определения 1 — значение есть, 0 — NULL.
+        let mut levels = Vec::with_capacity(cells.len());
+        match column.untyped() {
+            ColumnWriter::ByteArrayColumnWriter(w) => {
+                let values: Vec<ByteArray> = cells
+                    .into_iter()
+                    .filter_map(|c| {
+                        let v = match c {
+                            Cell::Text(v) => v,
+                            _ => None,
+                        };
+                        levels.push(v.is_some() as i16);
+                        v.map(|s| ByteArray::from(s.into_bytes()))
+                    })
+                    .collect();
+                w.write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnWriter::FixedLenByteArrayColumnWriter(w) => {
+                let values: Vec<FixedLenByteArray> = cells
+                    .into_iter()
+                    .filter_map(|c| {
+                        let v = match c {
+                            Cell::Decimal(v) => v,
+                            _ => None,
+                        };
+                        levels.push(v.is_some() as i16);
+                        v.map(|mut d| {
+                            d.rescale(PARQUET_DECIMAL_SCALE);
+                            let bytes = d.mantissa().to_be_bytes().to_vec();
+                            FixedLenByteArray::from(ByteArray::from(bytes))
+                        })
+                    })
+                    .collect();
+                w.write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnWriter::DoubleColumnWriter(w) => {
+                let values: Vec<f64> = cells
+                    .into_iter()
+                    .filter_map(|c| {
+                        let v = match c {
+                            Cell::Number(v) => v,
+                            _ => None,
+                        };
+                        levels.push(v.is_some() as i16);
+                        v
+                    })
+                    .collect();
+                w.write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnWriter::Int64ColumnWriter(w) => {
+                let values: Vec<i64> = cells
+                    .into_iter()
+                    .filter_map(|c| {
+                        let v = match c {
+                            Cell::Integer(v) => v,
+                            Cell::DateTime(dt, _) => dt.map(|dt| dt.and_utc().timestamp_millis()),
+                            _ => None,
+                        };
+                        levels.push(v.is_some() as i16);
+                        v
+                    })
+                    .collect();
+                w.write_batch(&values, Some(&levels), None)?;
+            }
+            _ => unreachable!("schema only has BYTE_ARRAY, FIXED_LEN_BYTE_ARRAY, DOUBLE and INT64 columns"),
+        }
+        column.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(buffer)
+}
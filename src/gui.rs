@@ -0,0 +1,499 @@
+//! Окна приложения: IDEX каждого профиля, переключатель аккаунтов, оверлей
+//! и Telegram.
+//! Собирается только с фичей `gui`.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use tokio::runtime::Runtime;
+use tracing::{debug, error, info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use wry::{
+    application::{
+        dpi::LogicalSize,
+        event::{Event, WindowEvent},
+        event_loop::{ControlFlow, EventLoop},
+        window::WindowBuilder,
+    },
+    webview::{WebContext, WebView, WebViewBuilder},
+};
+
+use p2p_app::{
+    config::AppConfig,
+    cookie_sync::{seed_script, CAPTURE_SCRIPT},
+    instance::{import_cookies, HandOff, InstanceLock},
+    notify::Notifier,
+    ipc::{
+        overlay_state, parse_page_message, payout_id_from_path, to_script, AppMessage,
+        PageMessage, BRIDGE_SCRIPT, OVERLAY_SCRIPT,
+    },
+    profiles::{profile_states, stop_profiles, Profile},
+    proxy::ProxyState,
+    supervisor::{shutdown_signal, Supervisor},
+};
+
+use crate::telegram::show_telegram;
+
+/// Как часто обновляется оверлей в окнах IDEX.
+const OVERLAY_REFRESH: Duration = Duration::from_secs(5);
+
+// -----------------------------
+// Команды для event loop.
+// -----------------------------
+#[derive(Debug)]
+pub enum Command {
+    /// Открыть окно IDEX профиля с указанным именем.
+    ShowIdex(String),
+    ShowSwitcher,
+    /// Отправить свежее состояние в оверлей окна профиля.
+    PushOverlay(String),
+    RefreshOverlays,
+    ShowTelegram,
+    Exit,
+}
+
+// -----------------------------
+// Глобальное состояние окон. 
+// -----------------------------
+struct IdexWindow {
+    webview: WebView,
+    /// Выплата, открытая на странице (по пути SPA).
+    selected: Arc<Mutex<Option<String>>>,
+    // Хранилище webview профиля должно жить не меньше самого webview.
+    _context: WebContext,
+}
+
+struct AppState {
+    idex_windows: HashMap<String, IdexWindow>,
+    /// Окна с ошибкой запуска прокси вместо IDEX, по профилю.
+    error_windows: HashMap<String, WebView>,
+    switcher: Option<WebView>,
+    telegram: Option<WebView>,
+}
+
+impl AppState {
+    fn new() -> Self {
+        Self {
+            idex_windows: HashMap::new(),
+            error_windows: HashMap::new(),
+            switcher: None,
+            telegram: None,
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Страница переключателя аккаунтов: кнопка на профиль, выбор уходит через IPC.
+fn switcher_html(profiles: &[Profile], states: &HashMap<String, ProxyState>) -> String {
+    let buttons = profiles
+        .iter()
+        .map(|p| {
+            let addr = states
+                .get(&p.name)
+                .and_then(|s| *s.proxy_addr.lock().unwrap())
+                .map_or_else(|| "прокси не запущен".to_string(), |a| a.to_string());
+            format!(
+                "<button onclick=\"window.ipc.postMessage('{name}')\">{name}<small>{addr}</small></button>",
+                name = p.name,
+                addr = addr
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><style>
+body {{ font-family: sans-serif; margin: 12px; }}
+button {{ display: block; width: 100%; margin-bottom: 8px; padding: 10px; font-size: 15px; text-align: left; }}
+small {{ float: right; color: #888; }}
+</style></head>
+<body>{}</body></html>"#,
+        buttons
+    )
+}
+
+/// Страница вместо окна IDEX, когда прокси профиля не запустился.
+fn bind_error_html(profile: &str, error: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><style>
+body {{ font-family: sans-serif; margin: 24px; }}
+code {{ display: block; margin: 12px 0; padding: 8px; background: #f3f3f3; }}
+</style></head>
+<body><h3>Не удалось запустить прокси профиля {}</h3><code>{}</code>
+<p>Закройте программу, которая занимает порт, или укажите другой <b>proxy_port</b>
+(либо <b>0</b> — любой свободный) в config.json и перезапустите приложение.</p></body></html>"#,
+        escape_html(profile),
+        escape_html(error)
+    )
+}
+
+/// Отправляет в оверлей окна текущую статистику, сессию и состояние опроса.
+fn push_overlay(window: &IdexWindow, profile: &Profile, state: &ProxyState, supervisor: &Supervisor) {
+    let health = supervisor.health();
+    let selected = window.selected.lock().unwrap().clone();
+    let overlay = overlay_state(
+        &profile.name,
+        state,
+        health.get(&profile.task_name("idex")),
+        selected.as_deref(),
+        chrono::Utc::now(),
+    );
+    if let Err(e) = window
+        .webview
+        .evaluate_script(&to_script(&AppMessage::Overlay(overlay)))
+    {
+        debug!(profile = %profile.name, error = %e, "Не удалось обновить оверлей");
+    }
+}
+
+/// Открывает окна профилей и работает до закрытия приложения; фоновые
+/// задачи выполняются на `rt` в отдельном потоке.
+pub fn run(
+    profiles: Vec<Profile>,
+    config: &AppConfig,
+    data_dir: &Path,
+    rt: Arc<Runtime>,
+    mut log_guard: Option<WorkerGuard>,
+    instance: InstanceLock,
+) -> ! {
+    // У каждого профиля свои куки, история, прокси и фоновые задачи.
+    // Нажатие на уведомление открывает окно IDEX его профиля.
+    let (clicks, mut click_events) = tokio::sync::mpsc::unbounded_channel();
+    let notifier = Notifier::from_config(config, Some(clicks));
+    let states = profile_states(&profiles, config, &notifier);
+
+    // Создаем event loop для пользовательских команд.
+    let event_loop = EventLoop::<Command>::with_user_event();
+    let proxy_event = event_loop.create_proxy();
+
+    // Повторный запуск открывает окно первого профиля или передаёт куки.
+    let hand_off = {
+        let proxy_event = Mutex::new(proxy_event.clone());
+        let first = profiles[0].name.clone();
+        let states = states.clone();
+        instance.serve(Arc::new(move |hand_off| match hand_off {
+            HandOff::Show => proxy_event
+                .lock()
+                .unwrap()
+                .send_event(Command::ShowIdex(first.clone()))
+                .map(|()| format!("Showing IDEX window of profile {}", first))
+                .map_err(|_| "the application is shutting down".to_string()),
+            HandOff::ImportCookies {
+                profile,
+                text,
+                format,
+            } => import_cookies(&states, &profile, &text, format),
+        }))
+    };
+    let mut instance = Some(instance);
+
+    // Запуск Tokio runtime для асинхронных задач под надзором супервизора.
+    let supervisor = Arc::new(Supervisor::new());
+    let shutdown = supervisor.shutdown_token();
+    // Ошибки запуска прокси по профилю: их окно показывает вместо IDEX.
+    let bind_errors: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+    let mut background = {
+        let rt_clone = rt.clone();
+        let profiles = profiles.clone();
+        let states = states.clone();
+        let supervisor = supervisor.clone();
+        let proxy_event = proxy_event.clone();
+        let bind_errors = bind_errors.clone();
+        Some(thread::spawn(move || {
+            rt_clone.block_on(async {
+                let hand_off = tokio::spawn(hand_off);
+                let click_proxy = proxy_event.clone();
+                let clicks = tokio::spawn(async move {
+                    while let Some(click) = click_events.recv().await {
+                        debug!(profile = %click.profile, "Нажатие на уведомление");
+                        if click_proxy.send_event(Command::ShowIdex(click.profile)).is_err() {
+                            break;
+                        }
+                    }
+                });
+                for profile in &profiles {
+                    if let Some(proxy_state) = states.get(&profile.name) {
+                        if let Err(e) = profile.spawn_tasks(&supervisor, proxy_state) {
+                            bind_errors.lock().unwrap().insert(profile.name.clone(), e.to_string());
+                        }
+                    }
+                }
+
+                // Окна открываются, когда адреса прокси уже известны: IDEX
+                // первого профиля и, если профилей несколько, переключатель.
+                if profiles.len() > 1 {
+                    let _ = proxy_event.send_event(Command::ShowSwitcher);
+                }
+                let _ = proxy_event.send_event(Command::ShowIdex(profiles[0].name.clone()));
+
+                // Работаем до команды Exit из event loop или сигнала ОС.
+                let stop = supervisor.shutdown_token();
+                tokio::select! {
+                    _ = stop.cancelled() => {}
+                    _ = shutdown_signal() => {
+                        info!("Получен сигнал завершения.");
+                        let _ = proxy_event.send_event(Command::Exit);
+                    }
+                }
+
+                hand_off.abort();
+                clicks.abort();
+                stop_profiles(&supervisor, &states).await;
+            });
+        }))
+    };
+
+    // Куки окна Telegram общие для всех профилей.
+    let data_dir = data_dir.to_path_buf();
+
+    // Глобальное состояние для окон.
+    let app_state = Arc::new(Mutex::new(AppState::new()));
+
+    // Периодически обновляем оверлей: статистика и опрос меняются без участия страницы.
+    {
+        let proxy_clone = proxy_event.clone();
+        thread::spawn(move || loop {
+            thread::sleep(OVERLAY_REFRESH);
+            if proxy_clone.send_event(Command::RefreshOverlays).is_err() {
+                break;
+            }
+        });
+    }
+
+    // Основной event loop.
+    event_loop.run(move |event, target, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        match event {
+            Event::UserEvent(cmd) => match cmd {
+                Command::ShowIdex(name) => {
+                    let mut state = app_state.lock().unwrap();
+                    if let Some(existing) = state.idex_windows.get(&name) {
+                        existing.webview.window().set_focus();
+                        return;
+                    }
+                    if let Some(existing) = state.error_windows.get(&name) {
+                        existing.window().set_focus();
+                        return;
+                    }
+                    let (Some(profile), Some(proxy_state)) = (
+                        profiles.iter().find(|p| p.name == name),
+                        states.get(&name).cloned(),
+                    ) else {
+                        warn!(profile = %name, "Неизвестный профиль");
+                        return;
+                    };
+                    let title = if profiles.len() > 1 {
+                        format!("IDEX — {}", profile.name)
+                    } else {
+                        "IDEX".to_string()
+                    };
+
+                    // Без прокси окно IDEX не загрузится: показываем причину.
+                    let Some(url) = proxy_state.webview_url() else {
+                        let error = bind_errors
+                            .lock()
+                            .unwrap()
+                            .get(&name)
+                            .cloned()
+                            .unwrap_or_else(|| "прокси не запущен".to_string());
+                        let window = WindowBuilder::new()
+                            .with_title(format!("{} — ошибка", title))
+                            .with_inner_size(LogicalSize::new(560.0, 260.0))
+                            .build(target)
+                            .expect("Не удалось создать окно");
+                        let webview = WebViewBuilder::new(window)
+                            .expect("Ошибка создания webview")
+                            .with_html(bind_error_html(&profile.name, &error))
+                            .expect("Не удалось загрузить страницу")
+                            .build()
+                            .expect("Ошибка сборки webview");
+                        state.error_windows.insert(profile.name.clone(), webview);
+                        error!(profile = %profile.name, %error, "Окно IDEX не открыто: прокси не запущен");
+                        return;
+                    };
+                    let window = WindowBuilder::new()
+                        .with_title(title)
+                        .with_inner_size(LogicalSize::new(1024.0, 768.0))
+                        .build(target)
+                        .expect("Не удалось создать окно IDEX");
+
+                    // HttpOnly-куки подставляет прокси, странице достаются только остальные.
+                    let cookie_script = seed_script(
+                        &proxy_state.cookies.lock().unwrap().cookies,
+                        chrono::Utc::now(),
+                    );
+
+                    let selected = Arc::new(Mutex::new(None));
+                    let ipc_selected = selected.clone();
+                    let ipc_event = proxy_event.clone();
+                    let ipc_profile = profile.name.clone();
+
+                    // Отдельное хранилище webview, чтобы куки профилей не смешивались.
+                    let mut context = WebContext::new(profile.webview_dir.clone());
+                    let webview = WebViewBuilder::new(window)
+                        .expect("Ошибка создания webview")
+                        .with_web_context(&mut context)
+                        .with_url(&url)
+                        .expect("Не удалось загрузить URL")
+                        .with_initialization_script(BRIDGE_SCRIPT)
+                        .with_initialization_script(&cookie_script)
+                        .with_initialization_script(CAPTURE_SCRIPT)
+                        .with_initialization_script(OVERLAY_SCRIPT)
+                        .with_initialization_script(
+                            r#"
+                            document.addEventListener('contextmenu', event => {
+                                event.preventDefault();
+                            });
+                            "#,
+                        )
+                        .with_ipc_handler(move |_, message| match parse_page_message(&message) {
+                            Ok(PageMessage::Cookies { cookies }) => {
+                                proxy_state.apply_document_cookies(&cookies)
+                            }
+                            Ok(PageMessage::Navigated { path }) => {
+                                *ipc_selected.lock().unwrap() = payout_id_from_path(&path);
+                                let _ = ipc_event.send_event(Command::PushOverlay(ipc_profile.clone()));
+                            }
+                            Ok(PageMessage::Copied { field }) => {
+                                debug!(profile = %ipc_profile, %field, "Скопировано из оверлея");
+                            }
+                            Ok(PageMessage::OpenTelegram) => {
+                                let _ = ipc_event.send_event(Command::ShowTelegram);
+                            }
+                            Err(e) => warn!(error = %e, "Invalid IPC message"),
+                        })
+                        .build()
+                        .expect("Ошибка сборки webview");
+
+                    state.idex_windows.insert(
+                        profile.name.clone(),
+                        IdexWindow {
+                            webview,
+                            selected,
+                            _context: context,
+                        },
+                    );
+                    info!(profile = %profile.name, "Открыт IDEX");
+                }
+                Command::PushOverlay(name) => {
+                    let state = app_state.lock().unwrap();
+                    if let (Some(window), Some(profile), Some(proxy_state)) = (
+                        state.idex_windows.get(&name),
+                        profiles.iter().find(|p| p.name == name),
+                        states.get(&name),
+                    ) {
+                        push_overlay(window, profile, proxy_state, &supervisor);
+                    }
+                }
+                Command::RefreshOverlays => {
+                    let state = app_state.lock().unwrap();
+                    for profile in &profiles {
+                        if let (Some(window), Some(proxy_state)) =
+                            (state.idex_windows.get(&profile.name), states.get(&profile.name))
+                        {
+                            push_overlay(window, profile, proxy_state, &supervisor);
+                        }
+                    }
+                }
+                Command::ShowSwitcher => {
+                    let mut state = app_state.lock().unwrap();
+                    if state.switcher.is_some() {
+                        return;
+                    }
+                    let window = WindowBuilder::new()
+                        .with_title("Аккаунты")
+                        .with_inner_size(LogicalSize::new(320.0, 60.0 + 50.0 * profiles.len() as f64))
+                        .build(target)
+                        .expect("Не удалось создать окно аккаунтов");
+                    let ipc_event = proxy_event.clone();
+                    let webview = WebViewBuilder::new(window)
+                        .expect("Ошибка создания webview")
+                        .with_html(switcher_html(&profiles, &states))
+                        .expect("Не удалось загрузить страницу")
+                        .with_ipc_handler(move |_, name| {
+                            let _ = ipc_event.send_event(Command::ShowIdex(name));
+                        })
+                        .build()
+                        .expect("Ошибка сборки webview");
+                    state.switcher = Some(webview);
+                }
+                Command::ShowTelegram => {
+                    let mut state = app_state.lock().unwrap();
+                    if let Some(existing) = &state.telegram {
+                        existing.window().set_focus();
+                        return;
+                    }
+                    match show_telegram(target, &data_dir) {
+                        Ok(webview) => {
+                            state.telegram = Some(webview);
+                            info!("Открыт Telegram");
+                        }
+                        Err(e) => error!(error = %e, "Не удалось открыть окно Telegram"),
+                    }
+                }
+                Command::Exit => {
+                    info!("Завершение работы приложения...");
+                    {
+                        let mut state = app_state.lock().unwrap();
+                        state.idex_windows.clear();
+                        state.error_windows.clear();
+                        state.switcher = None;
+                        state.telegram = None;
+                    }
+                    // Дожидаемся остановки прокси, текущего опроса и сохранения данных.
+                    shutdown.cancel();
+                    if let Some(handle) = background.take() {
+                        if handle.join().is_err() {
+                            error!("Фоновый поток завершился с паникой.");
+                        }
+                    }
+                    drop(instance.take());
+                    drop(log_guard.take());
+                    process::exit(0);
+                }
+            },
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                window_id,
+                ..
+            } => {
+                info!(?window_id, "Окно закрыто");
+                let mut state = app_state.lock().unwrap();
+                // Закрытие переключателя или последнего окна IDEX завершает приложение.
+                let closes_switcher = state
+                    .switcher
+                    .as_ref()
+                    .is_some_and(|w| w.window().id() == window_id);
+                state
+                    .idex_windows
+                    .retain(|_, w| w.webview.window().id() != window_id);
+                state.error_windows.retain(|_, w| w.window().id() != window_id);
+                // Окно Telegram закрывается само по себе и не завершает приложение.
+                if state.telegram.as_ref().is_some_and(|w| w.window().id() == window_id) {
+                    state.telegram = None;
+                }
+                if closes_switcher
+                    || (state.switcher.is_none()
+                        && state.idex_windows.is_empty()
+                        && state.error_windows.is_empty())
+                {
+                    let _ = proxy_event.send_event(Command::Exit);
+                }
+            }
+            _ => {}
+        }
+    });
+}
@@ -1,15 +1,24 @@
 use std::{
     collections::HashSet,
     fs,
-    path::PathBuf,
-    time::Duration,
+    path::Path,
+    time::{Duration, Instant},
 };
 
-use reqwest::Client;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use tokio::time;
-use crate::ProxyState;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, trace, warn};
+use crate::metrics::{metrics, SOURCE_POLL, SOURCE_PROXY};
+use crate::money::{
+    self, lookup, parse_decimal, parse_party_amounts, Decimal, Money, PartyAmounts, RUB, TRADER,
+    USDT,
+};
+use crate::panel::PanelError;
+use crate::proxy::ProxyState;
+use crate::rules::RuleEngine;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
@@ -17,16 +26,19 @@ pub struct Transaction {
     pub transaction_id: String,
     pub payment_method_id: Option<String>,
     pub wallet: Option<String>,
-    pub amount_rub: f64,
-    pub amount_usdt: f64,
-    pub total_rub: f64,
-    pub total_usdt: f64,
+    /// Суммы выплаты по сторонам сделки и кодам валют.
+    #[serde(default)]
+    pub amount: PartyAmounts,
+    /// Суммы к списанию с учётом комиссии по сторонам сделки и кодам валют.
+    #[serde(default)]
+    pub total: PartyAmounts,
     pub status: Option<String>,
     pub bank_name: Option<String>,
     pub bank_code: Option<String>,
     pub bank_label: Option<String>,
     pub payment_method: Option<String>,
-    pub course: Option<f64>,
+    #[serde(default, with = "money::decimal")]
+    pub course: Option<Decimal>,
     pub success_count: Option<u32>,
     pub success_rate: Option<f64>,
     pub approved_at: Option<String>,
@@ -39,29 +51,152 @@ pub struct Transaction {
     pub idex_id: Option<String>,
 }
 
-/// Загружает сохранённые транзакции из файла idex_history.json
-pub fn load_transactions() -> Vec<Transaction> {
-    let path = PathBuf::from("idex_history.json");
-    if path.exists() {
-        match fs::read_to_string(&path) {
-            Ok(content) => match serde_json::from_str::<Vec<Transaction>>(&content) {
-                Ok(tx) => {
-                    println!("Loaded {} transactions from file.", tx.len());
-                    return tx;
-                }
-                Err(e) => {
-                    println!("Failed to deserialize transactions from file: {}", e);
+impl Transaction {
+    /// Сумма выплаты трейдера в валюте с кодом `code`.
+    pub fn trader_amount(&self, code: &str) -> Option<Money> {
+        lookup(&self.amount, TRADER, code)
+    }
+
+    /// Сумма к списанию трейдера в валюте с кодом `code`.
+    pub fn trader_total(&self, code: &str) -> Option<Money> {
+        lookup(&self.total, TRADER, code)
+    }
+}
+
+const HISTORY_FILE: &str = "idex_history.json";
+
+/// Поля сумм трейдера в прежних форматах истории: поле, объект сумм, код валюты.
+const LEGACY_AMOUNT_FIELDS: [(&str, &str, &str); 4] = [
+    ("amount_rub", "amount", RUB),
+    ("amount_usdt", "amount", USDT),
+    ("total_rub", "total", RUB),
+    ("total_usdt", "total", USDT),
+];
+
+/// Переводит запись истории из прежних форматов (v1 — суммы `f64`,
+/// v2 — строки в полях `amount_rub` и т.п.) в суммы по сторонам и валютам.
+/// Возвращает версию исходного формата, если запись пришлось менять.
+///
+/// Старые версии не сохраняли статус выплаты; запись с `approved_at`
+/// считается завершённой.
+pub fn upgrade_record(record: &mut Value) -> Option<u32> {
+    let obj = record.as_object_mut()?;
+    let approved = obj.get("approved_at").is_some_and(|v| !v.is_null());
+    if approved && obj.get("status").is_none_or(Value::is_null) {
+        obj.insert("status".to_string(), json!(STATUS_COMPLETED));
+    }
+    let mut version = None;
+    if let Some(course) = obj.get_mut("course").filter(|c| c.is_number()) {
+        *course = v1_decimal(course).map_or(Value::Null, |c| Value::String(c.to_string()));
+        version = Some(1);
+    }
+    for (field, side, code) in LEGACY_AMOUNT_FIELDS {
+        let Some(value) = obj.remove(field) else {
+            continue;
+        };
+        let found = if value.is_number() { 1 } else { 2 };
+        version = Some(version.map_or(found, |v: u32| v.min(found)));
+        if let Some(amount) = v1_decimal(&value) {
+            let side = obj.entry(side).or_insert_with(|| json!({}));
+            if let Some(side) = side.as_object_mut() {
+                let trader = side.entry(TRADER).or_insert_with(|| json!({}));
+                if let Some(trader) = trader.as_object_mut() {
+                    trader.insert(code.to_string(), Value::String(amount.to_string()));
                 }
-            },
-            Err(e) => println!("Failed to read idex_history.json file: {}", e),
+            }
+        }
+    }
+    version
+}
+
+/// Значение поля прежнего формата: в v1 (`f64`) вместо отсутствующей суммы
+/// записывался 0.0, в строковом v2 ноль — настоящий ноль.
+fn v1_decimal(value: &Value) -> Option<Decimal> {
+    parse_decimal(value).filter(|d| !(value.is_number() && d.is_zero()))
+}
+
+/// Загружает сохранённые транзакции из файла idex_history.json.
+/// История в прежних форматах переводится в текущий только в памяти; файл
+/// переписывает `migrate_history`.
+pub fn load_transactions(data_dir: &Path) -> Vec<Transaction> {
+    match read_history(data_dir) {
+        Some((_, tx, _)) => {
+            info!("Loaded {} transactions from file.", tx.len());
+            tx
         }
+        None => Vec::new(),
     }
-    Vec::new()
+}
+
+/// Переводит idex_history.json из прежнего формата в текущий и перезаписывает
+/// его; исходный файл сохраняется рядом как `idex_history.v<N>.json`.
+/// Вызывается только процессом, который занял каталог данных (см. `instance`),
+/// чтобы команды вроде `export` ничего не меняли. Возвращает версию исходного
+/// формата, если файл был перезаписан.
+pub fn migrate_history(data_dir: &Path) -> Option<u32> {
+    let (content, tx, version) = read_history(data_dir)?;
+    let version = version?;
+    let backup = data_dir.join(format!("idex_history.v{}.json", version));
+    if !backup.exists() {
+        if let Err(e) = fs::write(&backup, &content) {
+            warn!(error = %e, "Failed to back up legacy history, migration postponed");
+            return None;
+        }
+    }
+    match save_transactions(data_dir, &tx) {
+        Ok(()) => {
+            info!(
+                backup = %backup.display(),
+                from_version = version,
+                "Migrated transaction history to the current format"
+            );
+            Some(version)
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to save migrated history");
+            None
+        }
+    }
+}
+
+/// Читает историю: исходный текст, транзакции и самую старую версию формата
+/// среди записей, если какие-то пришлось переводить.
+fn read_history(data_dir: &Path) -> Option<(String, Vec<Transaction>, Option<u32>)> {
+    let path = data_dir.join(HISTORY_FILE);
+    if !path.exists() {
+        return None;
+    }
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!(error = %e, "Failed to read idex_history.json file");
+            return None;
+        }
+    };
+    let mut records = match serde_json::from_str::<Vec<Value>>(&content) {
+        Ok(records) => records,
+        Err(e) => {
+            warn!(error = %e, "Failed to deserialize transactions from file");
+            return None;
+        }
+    };
+    let legacy = records.iter_mut().filter_map(upgrade_record).min();
+    let tx = records
+        .into_iter()
+        .filter_map(|record| match serde_json::from_value(record) {
+            Ok(tx) => Some(tx),
+            Err(e) => {
+                warn!(error = %e, "Skipping malformed transaction in history");
+                None
+            }
+        })
+        .collect();
+    Some((content, tx, legacy))
 }
 
 /// Сохраняет транзакции в файл idex_history.json
-pub fn save_transactions(tx: &[Transaction]) -> std::io::Result<()> {
-    let path = PathBuf::from("idex_history.json");
+pub fn save_transactions(data_dir: &Path, tx: &[Transaction]) -> std::io::Result<()> {
+    let path = data_dir.join(HISTORY_FILE);
     let json = serde_json::to_string_pretty(tx)?;
     fs::write(path, json)?;
     Ok(())
@@ -71,17 +206,156 @@ pub fn save_transactions(tx: &[Transaction]) -> std::io::Result<()> {
 fn extract_id(val: &Value) -> Option<String> {
     if let Some(s) = val.as_str() {
         Some(s.to_string())
-    } else if let Some(n) = val.as_i64() {
-        Some(n.to_string())
     } else {
-        None
+        val.as_i64().map(|n| n.to_string())
+    }
+}
+
+/// Путь API выплат, ответы на который разбирает пассивный перехват в прокси.
+pub const PAYOUTS_PATH: &str = "/api/v1/payments/payouts";
+
+/// Пока пользователь листает выплаты в IDEX, активный опрос откладывается.
+const BROWSING_WINDOW: Duration = Duration::from_secs(30);
+
+/// Даже при активном просмотре опрашиваем API не реже, чем раз в эту паузу.
+const MAX_POLL_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Итог последнего прохода опроса.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PollStatus {
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Страницы, которые не удалось получить.
+    pub failed_pages: u32,
+    /// Панель ответила 401: сессия истекла.
+    pub unauthorized: bool,
+}
+
+/// Результат слияния транзакции с хранилищем.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeOutcome {
+    New,
+    Updated,
+    Unchanged,
+}
+
+/// Добавляет транзакцию в хранилище или заменяет сохранённую, если она изменилась
+/// на стороне панели (по `updated_at` или статусу).
+pub fn merge_transaction(store: &mut Vec<Transaction>, tx: Transaction) -> MergeOutcome {
+    match store
+        .iter_mut()
+        .find(|saved| saved.transaction_id == tx.transaction_id)
+    {
+        Some(saved) => {
+            if saved.updated_at == tx.updated_at && saved.status == tx.status {
+                MergeOutcome::Unchanged
+            } else {
+                *saved = tx;
+                MergeOutcome::Updated
+            }
+        }
+        None => {
+            store.push(tx);
+            MergeOutcome::New
+        }
+    }
+}
+
+/// Проверяет, относится ли путь запроса к списку или карточке выплаты.
+pub fn is_payouts_path(path: &str) -> bool {
+    path == PAYOUTS_PATH
+        || path
+            .strip_prefix(PAYOUTS_PATH)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Находит выплаты в ответе API: массив списка (оба варианта расположения)
+/// или одиночный объект из карточки выплаты (`response.payout`). Другие
+/// ответы с `data` выплатами не считаются.
+pub fn extract_payouts(json: &Value) -> Vec<&Value> {
+    let list = json
+        .get("data")
+        .and_then(|d| d.get("transactions"))
+        .or_else(|| {
+            json.get("response")
+                .and_then(|r| r.get("payouts"))
+                .and_then(|p| p.get("data"))
+        })
+        .and_then(|t| t.as_array());
+    if let Some(list) = list {
+        return list.iter().collect();
+    }
+
+    json.get("response")
+        .and_then(|r| r.get("payout"))
+        .filter(|p| p.get("id").is_some())
+        .into_iter()
+        .collect()
+}
+
+/// Пассивный перехват: разбирает ответ API выплат, прошедший через прокси,
+/// и сразу сливает найденные транзакции в общее хранилище.
+pub fn capture_payouts(proxy_state: &ProxyState, body: &[u8]) {
+    proxy_state.mark_panel_activity();
+
+    let json: Value = match serde_json::from_slice(body) {
+        Ok(val) => val,
+        Err(e) => {
+            debug!(error = %e, "Captured payouts response is not JSON");
+            return;
+        }
+    };
+
+    let payouts = proxy_state.source.extract_payouts(&json);
+    if payouts.is_empty() {
+        return;
+    }
+
+    let mut store = proxy_state.transactions.lock().unwrap();
+    let (mut new_count, mut updated_count) = (0, 0);
+    for tx in payouts {
+        match merge_transaction(&mut store, tx) {
+            MergeOutcome::New => new_count += 1,
+            MergeOutcome::Updated => updated_count += 1,
+            MergeOutcome::Unchanged => {}
+        }
+    }
+
+    metrics().record_transactions(&proxy_state.profile, SOURCE_PROXY, new_count, updated_count);
+    if new_count + updated_count > 0 {
+        info!(
+            new = new_count,
+            updated = updated_count,
+            "Captured transactions from proxy"
+        );
+        if let Err(e) = save_transactions(&proxy_state.data_dir, &store) {
+            warn!(error = %e, "Failed to save transactions");
+        }
+    }
+}
+
+/// Статус свободной выплаты, которую ещё можно взять.
+pub const STATUS_PENDING: &str = "pending";
+/// Статус взятой, но ещё не оплаченной выплаты.
+pub const STATUS_IN_PROGRESS: &str = "in_progress";
+pub const STATUS_COMPLETED: &str = "completed";
+
+/// Статус выплаты как есть: строка без изменений, числовой код — своим номером.
+/// Значения кодов панели не задокументированы, поэтому коды не переводятся в
+/// имена: по выплате с кодом вместо имени ничего не берётся, не оповещается и
+/// не считается в оборот.
+pub fn status_name(value: &Value) -> Option<String> {
+    match value {
+        Value::String(name) => Some(name.clone()),
+        Value::Number(code) => Some(code.to_string()),
+        _ => None,
     }
 }
 
 /// Функция маппинга транзакции из JSON (Value) в Transaction.
 /// Адаптируйте её под реальную структуру ответа API.
-fn map_transaction(json: &Value) -> Option<Transaction> {
-    println!("Mapping transaction: {:?}", json);
+pub fn map_transaction(json: &Value) -> Option<Transaction> {
+    // Полезную нагрузку не логируем: в ней кошельки и данные клиентов.
+    trace!(id = ?json.get("id"), "Mapping transaction");
     // Пытаемся извлечь id транзакции, используя extract_id
     let id = json.get("id").and_then(extract_id)?;
     Some(Transaction {
@@ -98,31 +372,9 @@ fn map_transaction(json: &Value) -> Option<Transaction> {
             .get("wallet")
             .and_then(|v| v.as_str())
             .map(String::from),
-        amount_rub: json
-            .get("amount")
-            .and_then(|amt| amt.get("trader"))
-            .and_then(|tr| tr.get("643"))
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.0),
-        amount_usdt: json
-            .get("amount")
-            .and_then(|amt| amt.get("trader"))
-            .and_then(|tr| tr.get("000001"))
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.0),
-        total_rub: json
-            .get("total")
-            .and_then(|tot| tot.get("trader"))
-            .and_then(|tr| tr.get("643"))
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.0),
-        total_usdt: json
-            .get("total")
-            .and_then(|tot| tot.get("trader"))
-            .and_then(|tr| tr.get("000001"))
-            .and_then(|v| v.as_f64())
-            .unwrap_or(0.0),
-        status: json.get("status").and_then(|v| v.as_str()).map(String::from),
+        amount: json.get("amount").map(parse_party_amounts).unwrap_or_default(),
+        total: json.get("total").map(parse_party_amounts).unwrap_or_default(),
+        status: json.get("status").and_then(status_name),
         bank_name: json
             .get("bank")
             .and_then(|b| b.get("name"))
@@ -147,7 +399,7 @@ fn map_transaction(json: &Value) -> Option<Transaction> {
             .get("meta")
             .and_then(|m| m.get("courses"))
             .and_then(|c| c.get("trader"))
-            .and_then(|v| v.as_f64()),
+            .and_then(parse_decimal),
         success_count: json
             .get("tooltip")
             .and_then(|t| t.get("payments"))
@@ -194,127 +446,152 @@ fn map_transaction(json: &Value) -> Option<Transaction> {
     })
 }
 
-/// Каждые 5 секунд опрашивает API выплат, выводит полный ответ сервера и,
-/// если найдены новые транзакции, сразу сохраняет обновленный список в файл.
+/// Каждые 5 секунд просматривает список выплат источника `proxy_state.source`
+/// и, если найдены новые транзакции, сразу сохраняет обновленный список в файл.
+/// Пока пользователь сам просматривает выплаты через прокси, опрос откладывается
+/// (но не дольше `MAX_POLL_BACKOFF`).
 pub async fn run_idex(proxy_state: ProxyState) {
-    let gate_api_url = "https://panel.gate.cx/api/v1/payments/payouts?filters%5Bstatus%5D%5B%5D=2&filters%5Bstatus%5D%5B%5D=3&filters%5Bstatus%5D%5B%5D=7&filters%5Bstatus%5D%5B%5D=8&filters%5Bstatus%5D%5B%5D=9&page=";
-    let user_agent = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/119.0.0.0 Safari/537.36";
-    let client = Client::new();
-
-    loop {
-        // Проверяем наличие кук
-        let has_cookies = {
-            let store = proxy_state.cookies.lock().unwrap();
-            !store.cookies.is_empty()
-        };
+    run_idex_until(proxy_state, CancellationToken::new()).await
+}
+
+/// То же, что `run_idex`, но завершается по `shutdown`. Начатый опрос всегда
+/// доводится до конца и сохраняется, прерываются только паузы между опросами.
+pub async fn run_idex_until(proxy_state: ProxyState, shutdown: CancellationToken) {
+    let source = proxy_state.source.clone();
+    let mut last_poll: Option<Instant> = None;
+    let auto_claim = &proxy_state.config.auto_claim;
+    let mut rules = (auto_claim.enabled && !auto_claim.rules.is_empty())
+        .then(|| RuleEngine::new(auto_claim, &proxy_state.data_dir));
 
-        if !has_cookies {
-            println!("No cookies found, waiting for cookies to be set...");
-            time::sleep(Duration::from_secs(5)).await;
+    while !shutdown.is_cancelled() {
+        if !source.has_session(&proxy_state) {
+            debug!("No cookies found, waiting for cookies to be set...");
+            pause(&shutdown, Duration::from_secs(5)).await;
             continue;
         }
 
-        println!("Checking transactions...");
-        let mut saved_tx = load_transactions();
-        let mut saved_ids: HashSet<String> =
-            saved_tx.iter().map(|tx| tx.transaction_id.clone()).collect();
-        let mut new_transactions = Vec::new();
-
-        // Проходим по страницам 1..=10
-        for page in 1..=10 {
-            let url = format!("{}{}", gate_api_url, page);
-            let mut req_builder = client.get(&url).header("User-Agent", user_agent);
-
-            {
-                let store = proxy_state.cookies.lock().unwrap();
-                let cookie_str = store
-                    .cookies
-                    .iter()
-                    .map(|c| format!("{}={}", c.name, c.value))
-                    .collect::<Vec<_>>()
-                    .join("; ");
-                req_builder = req_builder.header("Cookie", cookie_str);
+        let poll_overdue = last_poll.is_none_or(|t| t.elapsed() >= MAX_POLL_BACKOFF);
+        if proxy_state.panel_active_within(BROWSING_WINDOW) && !poll_overdue {
+            debug!("User is browsing payouts, active poll postponed.");
+            // Выплаты, пришедшие через прокси, правила видят и без опроса.
+            if let Some(rules) = rules.as_mut().filter(|_| last_poll.is_some()) {
+                let candidates = rule_candidates(&proxy_state, rules);
+                rules.process(&proxy_state, &candidates).await;
             }
+            pause(&shutdown, Duration::from_secs(5)).await;
+            continue;
+        }
+        let poll_started = Instant::now();
+        let first_poll = last_poll.is_none();
+        last_poll = Some(poll_started);
 
-            match req_builder.send().await {
-                Ok(resp) => {
-                    let status = resp.status();
-                    let text = resp
-                        .text()
-                        .await
-                        .unwrap_or_else(|e| format!("Error reading text: {}", e));
-                    // println!("Response status: {}", status);
-                    // println!("Response body: {}", text);
-
-                    if status.is_success() {
-                        let json: Value = match serde_json::from_str(&text) {
-                            Ok(val) => val,
-                            Err(e) => {
-                                println!("Failed to parse JSON: {}", e);
-                                continue;
-                            }
-                        };
-
-                        // Определяем массив транзакций (учитываются оба варианта расположения)
-                        let trans_arr = json
-                            .get("data")
-                            .and_then(|d| d.get("transactions"))
-                            .or_else(|| {
-                                json.get("response")
-                                    .and_then(|r| r.get("payouts"))
-                                    .and_then(|p| p.get("data"))
-                            })
-                            .and_then(|t| t.as_array());
-
-                        if let Some(trans_arr) = trans_arr {
-                            println!(
-                                "Found {} transactions on page {}.",
-                                trans_arr.len(),
-                                page
-                            );
-                            for transaction in trans_arr {
-                                // Извлекаем id транзакции с учетом разных типов значений
-                                if let Some(id_value) = transaction.get("id") {
-                                    if let Some(id) = extract_id(id_value) {
-                                        if saved_ids.contains(&id) {
-                                            continue;
-                                        }
-                                        if let Some(new_tx) = map_transaction(transaction) {
-                                            println!(
-                                                "New transaction found: {}",
-                                                new_tx.transaction_id
-                                            );
-                                            saved_ids.insert(new_tx.transaction_id.clone());
-                                            new_transactions.push(new_tx);
-                                        }
-                                    } else {
-                                        println!("Unable to extract transaction id.");
-                                    }
-                                }
-                            }
-                        } else {
-                            println!("No transactions array in response on page {}.", page);
+        debug!("Checking transactions...");
+        let mut saved_ids: HashSet<String> = {
+            let store = proxy_state.transactions.lock().unwrap();
+            store.iter().map(|tx| tx.transaction_id.clone()).collect()
+        };
+        let mut new_transactions = Vec::new();
+        let mut status = PollStatus::default();
+
+        for page in 1..=source.max_pages() {
+            match source.fetch_page(&proxy_state, page).await {
+                Ok(found) => {
+                    debug!(page, count = found.transactions.len(), "Found transactions on page");
+                    for tx in found.transactions {
+                        if saved_ids.insert(tx.transaction_id.clone()) {
+                            info!(id = %tx.transaction_id, "New transaction found");
+                            new_transactions.push(tx);
                         }
-                    } else {
-                        println!("Failed to fetch page {}: HTTP {}", page, status);
+                    }
+                    if found.last_page.is_some_and(|last| page >= last) {
+                        break;
                     }
                 }
                 Err(e) => {
-                    eprintln!("Error fetching page {}: {}", page, e);
+                    warn!(page, error = %e, "Failed to fetch page");
+                    status.failed_pages += 1;
+                    status.unauthorized |= matches!(e, PanelError::Unauthorized);
+                    page_failed(&proxy_state.profile, page);
                 }
             }
         }
+        status.finished_at = Some(Utc::now());
+        *proxy_state.poll_status.lock().unwrap() = status;
 
         if !new_transactions.is_empty() {
-            println!("Found {} new transactions.", new_transactions.len());
-            saved_tx.extend(new_transactions);
-            match save_transactions(&saved_tx) {
-                Ok(_) => println!("Transactions saved successfully."),
-                Err(e) => eprintln!("Failed to save transactions: {}", e),
+            info!("Found {} new transactions.", new_transactions.len());
+            // Пока шёл опрос, часть транзакций могла уже прийти через прокси:
+            // о них не оповещаем.
+            let announced = {
+                let mut store = proxy_state.transactions.lock().unwrap();
+                let mut announced = Vec::new();
+                let mut updated_count = 0;
+                for tx in new_transactions {
+                    match merge_transaction(&mut store, tx.clone()) {
+                        MergeOutcome::New => announced.push(tx),
+                        MergeOutcome::Updated => updated_count += 1,
+                        MergeOutcome::Unchanged => {}
+                    }
+                }
+                metrics().record_transactions(
+                    &proxy_state.profile,
+                    SOURCE_POLL,
+                    announced.len() as u64,
+                    updated_count,
+                );
+                match save_transactions(&proxy_state.data_dir, &store) {
+                    Ok(_) => debug!("Transactions saved successfully."),
+                    Err(e) => warn!(error = %e, "Failed to save transactions"),
+                }
+                announced
+            };
+            if !announced.is_empty() {
+                proxy_state.notifier.new_payouts(&announced, Utc::now()).await;
             }
         } else {
-            println!("No new transactions on this check.");
+            debug!("No new transactions on this check.");
+        }
+
+        // Выплаты, видимые при первом опросе, не берутся: они могли быть
+        // свободны задолго до запуска.
+        if let Some(rules) = rules.as_mut() {
+            let candidates = rule_candidates(&proxy_state, rules);
+            if first_poll {
+                rules.skip(&candidates);
+            } else {
+                rules.process(&proxy_state, &candidates).await;
+            }
         }
-        time::sleep(Duration::from_secs(5)).await;
+        metrics()
+            .poll_duration
+            .with_label_values(&[&proxy_state.profile])
+            .observe(poll_started.elapsed().as_secs_f64());
+        pause(&shutdown, Duration::from_secs(5)).await;
+    }
+    debug!("IDEX poller stopped.");
+}
+
+/// Выплаты из истории, которых движок правил ещё не рассматривал.
+fn rule_candidates(proxy_state: &ProxyState, rules: &RuleEngine) -> Vec<Transaction> {
+    let store = proxy_state.transactions.lock().unwrap();
+    store
+        .iter()
+        .filter(|tx| !rules.has_seen(&tx.transaction_id))
+        .cloned()
+        .collect()
+}
+
+fn page_failed(profile: &str, page: u32) {
+    metrics()
+        .page_failures
+        .with_label_values(&[profile, &page.to_string()])
+        .inc();
+}
+
+/// Пауза между опросами, прерываемая остановкой приложения.
+async fn pause(shutdown: &CancellationToken, duration: Duration) {
+    tokio::select! {
+        _ = shutdown.cancelled() => {}
+        _ = time::sleep(duration) => {}
     }
 }
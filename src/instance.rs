@@ -0,0 +1,416 @@
+//! Один экземпляр приложения на каталог данных.
+//!
+//! Запущенный экземпляр держит `instance.json` (PID, порт и секрет локального
+//! канала) и принимает команды на 127.0.0.1. Повторный запуск находит файл,
+//! передаёт запущенному экземпляру своё намерение — показать окно IDEX или
+//! импортировать куки — и завершается, не трогая cookies.json и историю.
+//! Файл упавшего процесса распознаётся по тому, что процесса с его PID нет,
+//! порт не отвечает или на порту чужой процесс, не знающий секрета.
+//!
+//! Протокол: одна строка JSON с запросом `{"v", "secret", "action", ...}`
+//! и одна строка JSON с ответом `{"ok", "message"}`. Действие `ping` —
+//! проверка из `alive`: экземпляр отвечает своим PID, не вызывая обработчик.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    future::Future,
+    io::{self, BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tracing::{debug, info, warn};
+
+use crate::{
+    cookie_io::{import_into, CookieFormat},
+    proxy::ProxyState,
+};
+
+pub const INSTANCE_FILE: &str = "instance.json";
+
+/// Версия протокола; запрос другой версии отклоняется.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Предел размера запроса: куки плюс запас на обёртку.
+const MAX_REQUEST: u64 = 2 * 1024 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Сколько ждём ответа на `ping`; экземпляр, который ещё не начал принимать
+/// команды, за это время не ответит.
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Намерение повторного запуска.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum HandOff {
+    /// Показать окно IDEX первого профиля.
+    Show,
+    ImportCookies {
+        profile: String,
+        text: String,
+        format: Option<CookieFormat>,
+    },
+}
+
+/// Обработчик команд в запущенном экземпляре: текст ответа или ошибки.
+pub type HandOffHandler = Arc<dyn Fn(HandOff) -> Result<String, String> + Send + Sync>;
+
+#[derive(Serialize, Deserialize)]
+struct InstanceInfo {
+    pid: u32,
+    port: u16,
+    secret: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    v: u32,
+    secret: String,
+    #[serde(flatten)]
+    hand_off: HandOff,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PingAction {
+    Ping,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Ping {
+    v: u32,
+    secret: String,
+    action: PingAction,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Reply {
+    ok: bool,
+    message: String,
+}
+
+#[derive(Debug)]
+pub enum InstanceError {
+    Io(io::Error),
+    /// Ответ не разбирается: другая версия приложения или чужой процесс на порту.
+    Protocol(String),
+    /// Запущенный экземпляр отказался выполнить команду.
+    Rejected(String),
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::Io(e) => write!(f, "failed to reach the running instance: {}", e),
+            InstanceError::Protocol(e) => write!(f, "unexpected reply from the running instance: {}", e),
+            InstanceError::Rejected(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
+
+impl From<io::Error> for InstanceError {
+    fn from(e: io::Error) -> Self {
+        InstanceError::Io(e)
+    }
+}
+
+/// Итог попытки занять каталог данных.
+pub enum Acquired {
+    /// Мы — единственный экземпляр.
+    Primary(InstanceLock),
+    Running(RunningInstance),
+}
+
+/// Каталог данных занят этим процессом; `instance.json` удаляется при
+/// освобождении, если в нём всё ещё наш PID.
+pub struct InstanceLock {
+    path: PathBuf,
+    info: InstanceInfo,
+    listener: TcpListener,
+}
+
+/// Уже запущенный экземпляр.
+pub struct RunningInstance {
+    info: InstanceInfo,
+}
+
+/// Занимает `data_dir` или находит экземпляр, который уже работает с ним.
+pub fn acquire(data_dir: &Path) -> io::Result<Acquired> {
+    let path = data_dir.join(INSTANCE_FILE);
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))?;
+    let info = InstanceInfo {
+        pid: std::process::id(),
+        port: listener.local_addr()?.port(),
+        secret: new_secret()?,
+    };
+    // Вторая попытка — после удаления файла упавшего процесса.
+    for _ in 0..2 {
+        match publish(&path, &info) {
+            Ok(()) => {
+                debug!(port = info.port, "Instance lock acquired");
+                return Ok(Acquired::Primary(InstanceLock {
+                    path,
+                    info,
+                    listener,
+                }));
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        if let Some(running) = alive(&existing) {
+            return Ok(Acquired::Running(running));
+        }
+        // Удаляем, только если файл не сменился с проверки: его мог занять
+        // параллельный запуск.
+        if fs::read_to_string(&path).unwrap_or_default() == existing {
+            warn!(path = %path.display(), "Removing stale instance file");
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} is held by another process", path.display()),
+    ))
+}
+
+/// Работающий экземпляр для `data_dir`, если он есть.
+pub fn find_running(data_dir: &Path) -> Option<RunningInstance> {
+    alive(&fs::read_to_string(data_dir.join(INSTANCE_FILE)).ok()?)
+}
+
+fn alive(content: &str) -> Option<RunningInstance> {
+    let info: InstanceInfo = serde_json::from_str(content).ok()?;
+    if !process_exists(info.pid) {
+        return None;
+    }
+    let running = RunningInstance { info };
+    let ping = Ping {
+        v: PROTOCOL_VERSION,
+        secret: running.info.secret.clone(),
+        action: PingAction::Ping,
+    };
+    match running.exchange(&ping, PING_TIMEOUT) {
+        Ok(reply) => (reply.ok && reply.message == running.info.pid.to_string()).then_some(running),
+        // Порт занят, но ответа нет: экземпляр ещё не начал принимать команды.
+        Err(InstanceError::Io(e))
+            if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) =>
+        {
+            Some(running)
+        }
+        Err(_) => None,
+    }
+}
+
+/// Есть ли процесс с таким PID. Без системных вызовов это видно только в
+/// Linux (`/proc`); на других системах считается, что есть.
+fn process_exists(pid: u32) -> bool {
+    !cfg!(target_os = "linux") || Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Записывает файл целиком во временный и ссылается на него под именем
+/// `instance.json`: создание ссылки атомарно и не заменяет чужой файл, а
+/// читатель не увидит его недописанным.
+fn publish(path: &Path, info: &InstanceInfo) -> io::Result<()> {
+    let tmp = path.with_extension(format!("{}.tmp", info.pid));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        // Секрет не должен читаться другими пользователями машины.
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let result = options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(serde_json::to_string(info)?.as_bytes()))
+        .and_then(|()| fs::hard_link(&tmp, path));
+    let _ = fs::remove_file(&tmp);
+    result
+}
+
+/// Случайный секрет из 128 бит от генератора ОС, в шестнадцатеричном виде.
+pub(crate) fn new_secret() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+impl InstanceInfo {
+    fn addr(&self) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, self.port))
+    }
+}
+
+impl RunningInstance {
+    pub fn pid(&self) -> u32 {
+        self.info.pid
+    }
+
+    /// Передаёт команду и ждёт ответа.
+    pub fn send(&self, hand_off: HandOff) -> Result<String, InstanceError> {
+        let request = Request {
+            v: PROTOCOL_VERSION,
+            secret: self.info.secret.clone(),
+            hand_off,
+        };
+        let reply = self.exchange(&request, REPLY_TIMEOUT)?;
+        if reply.ok {
+            Ok(reply.message)
+        } else {
+            Err(InstanceError::Rejected(reply.message))
+        }
+    }
+
+    fn exchange(&self, request: &impl Serialize, timeout: Duration) -> Result<Reply, InstanceError> {
+        let mut stream = TcpStream::connect_timeout(&self.info.addr(), CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(timeout))?;
+        let mut line = serde_json::to_string(request).map_err(|e| InstanceError::Protocol(e.to_string()))?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        serde_json::from_str(&reply).map_err(|e| InstanceError::Protocol(e.to_string()))
+    }
+}
+
+impl InstanceLock {
+    pub fn port(&self) -> u16 {
+        self.info.port
+    }
+
+    /// Принимает команды повторных запусков, пока future не отменят. Должна
+    /// выполняться внутри Tokio runtime.
+    pub fn serve(&self, handler: HandOffHandler) -> impl Future<Output = ()> + Send + 'static {
+        let listener = self.listener.try_clone();
+        let secret = self.info.secret.clone();
+        async move {
+            let listener = match listener
+                .and_then(|l| l.set_nonblocking(true).map(|()| l))
+                .and_then(tokio::net::TcpListener::from_std)
+            {
+                Ok(listener) => listener,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept commands from other launches");
+                    return;
+                }
+            };
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        // Например, кончились дескрипторы: ждём и принимаем дальше.
+                        warn!(error = %e, "Failed to accept instance connection");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let handler = handler.clone();
+                let secret = secret.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &secret, handler).await {
+                        debug!(error = %e, "Instance connection failed");
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        let ours = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<InstanceInfo>(&content).ok())
+            .is_some_and(|info| info.pid == self.info.pid && info.port == self.info.port);
+        if ours {
+            if let Err(e) = fs::remove_file(&self.path) {
+                warn!(path = %self.path.display(), error = %e, "Failed to remove instance file");
+            }
+        }
+    }
+}
+
+async fn handle_connection(
+    stream: tokio::net::TcpStream,
+    secret: &str,
+    handler: HandOffHandler,
+) -> io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut line = String::new();
+    tokio::io::BufReader::new(read.take(MAX_REQUEST))
+        .read_line(&mut line)
+        .await?;
+    if line.is_empty() {
+        return Ok(());
+    }
+    let result = match serde_json::from_str::<Request>(&line) {
+        // `ping` из `alive` обработчику не передаётся.
+        Err(_) if is_ping(&line, secret) => Ok(std::process::id().to_string()),
+        Err(e) => Err(format!("malformed request: {}", e)),
+        Ok(request) if request.v != PROTOCOL_VERSION => Err(format!(
+            "unsupported protocol version {} (expected {})",
+            request.v, PROTOCOL_VERSION
+        )),
+        Ok(request) if request.secret != secret => Err("invalid instance secret".to_string()),
+        Ok(request) => {
+            info!(action = action_name(&request.hand_off), "Command from another launch");
+            // Обработчик может блокироваться (запись cookies.json), поэтому не на потоке runtime.
+            tokio::task::spawn_blocking(move || handler(request.hand_off))
+                .await
+                .unwrap_or_else(|e| Err(format!("handler failed: {}", e)))
+        }
+    };
+    let reply = match result {
+        Ok(message) => Reply { ok: true, message },
+        Err(message) => Reply { ok: false, message },
+    };
+    let mut text = serde_json::to_string(&reply).unwrap_or_default();
+    text.push('\n');
+    write.write_all(text.as_bytes()).await
+}
+
+fn is_ping(line: &str, secret: &str) -> bool {
+    serde_json::from_str::<Ping>(line).is_ok_and(|ping| ping.v == PROTOCOL_VERSION && ping.secret == secret)
+}
+
+fn action_name(hand_off: &HandOff) -> &'static str {
+    match hand_off {
+        HandOff::Show => "show",
+        HandOff::ImportCookies { .. } => "import_cookies",
+    }
+}
+
+/// Выполняет `HandOff::ImportCookies` над состояниями профилей.
+pub fn import_cookies(
+    states: &HashMap<String, ProxyState>,
+    profile: &str,
+    text: &str,
+    format: Option<CookieFormat>,
+) -> Result<String, String> {
+    let state = states
+        .get(profile)
+        .ok_or_else(|| format!("unknown profile: {}", profile))?;
+    let report = import_into(state, text, format, Utc::now()).map_err(|e| e.to_string())?;
+    info!(
+        %profile,
+        imported = report.imported,
+        expired = report.expired,
+        rejected = report.rejected.len(),
+        "Cookies imported from another launch"
+    );
+    Ok(report.describe(profile, &state.upstream_host()))
+}
@@ -0,0 +1,321 @@
+//! Мост между Rust и страницей в окне IDEX.
+//!
+//! Страница шлёт сообщения через `window.__p2p.send(...)`, которое добавляет
+//! номер протокола `v` и вызывает `window.ipc.postMessage`. Приложение отвечает
+//! через `evaluate_script` вызовом `window.__p2p.receive(...)`. Сообщение с
+//! другим `v` отвергается целиком, поэтому несовместимые скрипты и приложение
+//! не поймут друг друга молча неправильно.
+
+use std::{collections::BTreeMap, fmt};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    analytics::build_report,
+    money::{Decimal, RUB},
+    proxy::ProxyState,
+    supervisor::{TaskHealth, TaskStatus},
+};
+
+/// Версия протокола сообщений.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// Сообщения страницы.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PageMessage {
+    /// Текущее значение `document.cookie`.
+    Cookies { cookies: String },
+    /// SPA перешла на другой путь (например, карточку выплаты).
+    Navigated { path: String },
+    /// Пользователь скопировал поле выбранной выплаты из оверлея.
+    Copied { field: String },
+    /// Кнопка оверлея «Telegram»: открыть окно веб-версии Telegram.
+    OpenTelegram,
+}
+
+/// Сообщения приложения странице.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppMessage {
+    Overlay(OverlayState),
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    v: u64,
+    #[serde(flatten)]
+    message: &'a AppMessage,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IpcError {
+    Malformed(String),
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpcError::Malformed(e) => write!(f, "malformed IPC message: {}", e),
+            IpcError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported IPC protocol version {} (expected {})",
+                v, PROTOCOL_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+/// Разбирает сообщение страницы, проверяя версию протокола.
+pub fn parse_page_message(raw: &str) -> Result<PageMessage, IpcError> {
+    let value: Value = serde_json::from_str(raw).map_err(|e| IpcError::Malformed(e.to_string()))?;
+    let version = value
+        .get("v")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| IpcError::Malformed("missing protocol version".to_string()))?;
+    if version != PROTOCOL_VERSION {
+        return Err(IpcError::UnsupportedVersion(version));
+    }
+    serde_json::from_value(value).map_err(|e| IpcError::Malformed(e.to_string()))
+}
+
+/// Скрипт, доставляющий сообщение странице. JSON подставляется как литерал
+/// объекта, поэтому строки из данных не могут выйти за его пределы.
+pub fn to_script(message: &AppMessage) -> String {
+    let json = serde_json::to_string(&Envelope {
+        v: PROTOCOL_VERSION,
+        message,
+    })
+    .expect("IPC message serializes to JSON");
+    format!("window.__p2p && window.__p2p.receive({});", json)
+}
+
+/// Скрипт инициализации моста; должен идти первым среди скриптов окна.
+pub const BRIDGE_SCRIPT: &str = r#"
+(function () {
+    if (window.__p2p) { return; }
+    var handlers = {};
+    window.__p2p = {
+        version: 1,
+        send: function (message) {
+            message.v = 1;
+            window.ipc.postMessage(JSON.stringify(message));
+        },
+        receive: function (message) {
+            if (!message || message.v !== 1) { return; }
+            (handlers[message.type] || []).forEach(function (h) { h(message); });
+        },
+        on: function (type, handler) {
+            (handlers[type] = handlers[type] || []).push(handler);
+        }
+    };
+})();
+"#;
+
+/// Оверлей со статистикой за день, состоянием сессии и опроса и кнопками
+/// копирования реквизитов выбранной выплаты. Данные выводятся только через
+/// `textContent`.
+pub const OVERLAY_SCRIPT: &str = r#"
+(function () {
+    var root, lastPath = null, state = null;
+
+    function copy(field, text) {
+        function done() { window.__p2p.send({ type: "copied", field: field }); }
+        if (navigator.clipboard && navigator.clipboard.writeText) {
+            navigator.clipboard.writeText(text).then(done, fallback);
+        } else {
+            fallback();
+        }
+        function fallback() {
+            var area = document.createElement("textarea");
+            area.value = text;
+            document.body.appendChild(area);
+            area.select();
+            document.execCommand("copy");
+            area.remove();
+            done();
+        }
+    }
+
+    function line(text) {
+        var div = document.createElement("div");
+        div.textContent = text;
+        root.appendChild(div);
+        return div;
+    }
+
+    function button(label, field, value) {
+        var b = document.createElement("button");
+        b.textContent = label;
+        b.style.cssText = "margin:4px 4px 0 0;font-size:12px;cursor:pointer;";
+        b.onclick = function () { copy(field, value); };
+        root.appendChild(b);
+    }
+
+    function render() {
+        if (!state || !document.body) { return; }
+        if (!root) {
+            root = document.createElement("div");
+            root.style.cssText = "position:fixed;right:12px;bottom:12px;z-index:2147483647;" +
+                "background:rgba(20,20,20,.85);color:#fff;font:12px sans-serif;" +
+                "padding:8px 10px;border-radius:6px;max-width:260px;";
+        }
+        if (!root.isConnected) { document.body.appendChild(root); }
+        root.textContent = "";
+        var amounts = Object.keys(state.today.amount).map(function (c) {
+            return state.today.amount[c] + " " + c;
+        }).join(", ");
+        line(state.profile + " · сегодня: " + state.today.count + (amounts ? " · " + amounts : ""));
+        var session = { active: "активна", expired: "истекла", missing: "нет входа" }[state.session];
+        if (state.session_expires_at && state.session === "active") {
+            session += " до " + new Date(state.session_expires_at).toLocaleString();
+        }
+        line("Сессия: " + session);
+        var poller = state.poller.status || "не запущен";
+        if (state.poller.last_poll) {
+            poller += " · " + new Date(state.poller.last_poll).toLocaleTimeString();
+        }
+        if (state.poller.failed_pages) { poller += " · ошибок: " + state.poller.failed_pages; }
+        if (state.poller.restarts) { poller += " · перезапусков: " + state.poller.restarts; }
+        line("Опрос: " + poller);
+        var selected = state.selected;
+        if (selected) {
+            line("Выплата " + selected.id + (selected.bank ? " · " + selected.bank : ""));
+            if (selected.wallet) { button("Реквизиты", "wallet", selected.wallet); }
+            if (selected.amount) { button("Сумма", "amount", selected.amount); }
+        }
+        var telegram = document.createElement("button");
+        telegram.textContent = "Telegram";
+        telegram.style.cssText = "display:block;margin-top:6px;font-size:12px;cursor:pointer;";
+        telegram.onclick = function () { window.__p2p.send({ type: "open_telegram" }); };
+        root.appendChild(telegram);
+    }
+
+    function checkPath() {
+        if (location.pathname !== lastPath) {
+            lastPath = location.pathname;
+            window.__p2p.send({ type: "navigated", path: lastPath });
+        }
+    }
+
+    window.__p2p.on("overlay", function (message) {
+        state = message;
+        render();
+    });
+    document.addEventListener("DOMContentLoaded", function () { checkPath(); render(); });
+    setInterval(checkPath, 500);
+})();
+"#;
+
+/// Выплаты за сегодня (по местной дате из настроек аналитики).
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct TodayStats {
+    pub count: u64,
+    /// Сумма выплат трейдера по буквенному коду валюты.
+    pub amount: BTreeMap<String, Decimal>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    /// Кук нет: пользователь ещё не входил.
+    Missing,
+    Active,
+    /// Последний опрос или запрос поддержания сессии получил 401.
+    Expired,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PollerState {
+    /// Состояние задачи опроса у супервизора.
+    pub status: Option<TaskStatus>,
+    pub restarts: u32,
+    pub last_poll: Option<DateTime<Utc>>,
+    pub failed_pages: u32,
+}
+
+/// Выплата, открытая на странице.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SelectedPayout {
+    pub id: String,
+    pub wallet: Option<String>,
+    /// Сумма в рублях без валюты — в таком виде её вставляют в банк.
+    pub amount: Option<String>,
+    pub bank: Option<String>,
+}
+
+/// Всё, что показывает оверлей.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OverlayState {
+    pub profile: String,
+    pub today: TodayStats,
+    pub session: SessionStatus,
+    /// Срок куки сессии, если он известен.
+    pub session_expires_at: Option<DateTime<Utc>>,
+    pub poller: PollerState,
+    pub selected: Option<SelectedPayout>,
+}
+
+/// Номер выплаты из пути SPA: числовой сегмент после сегмента с `payout`
+/// (`/payouts/123`, `/payments/payout/123/details`).
+pub fn payout_id_from_path(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    segments.windows(2).find_map(|pair| {
+        let is_id = !pair[1].is_empty() && pair[1].chars().all(|c| c.is_ascii_digit());
+        (pair[0].contains("payout") && is_id).then(|| pair[1].to_string())
+    })
+}
+
+/// Собирает состояние оверлея профиля.
+pub fn overlay_state(
+    profile: &str,
+    state: &ProxyState,
+    poller: Option<&TaskHealth>,
+    selected: Option<&str>,
+    now: DateTime<Utc>,
+) -> OverlayState {
+    let transactions = state.transactions.lock().unwrap();
+    let today = state.config.analytics.today(now);
+    let report = build_report(&transactions, &state.config.analytics, Some(today), Some(today));
+    let selected = selected.and_then(|id| {
+        let tx = transactions.iter().find(|tx| tx.transaction_id == id)?;
+        Some(SelectedPayout {
+            id: tx.transaction_id.clone(),
+            wallet: tx.wallet.clone(),
+            amount: tx.trader_amount(RUB).map(|m| m.amount.to_string()),
+            bank: tx.bank_label.clone().or_else(|| tx.bank_name.clone()),
+        })
+    });
+
+    let poll = state.poll_status.lock().unwrap().clone();
+    let health = state.session.lock().unwrap().clone();
+    let session = if state.cookies.lock().unwrap().cookies.is_empty() {
+        SessionStatus::Missing
+    } else if poll.unauthorized || health.unauthorized {
+        SessionStatus::Expired
+    } else {
+        SessionStatus::Active
+    };
+
+    OverlayState {
+        profile: profile.to_string(),
+        today: TodayStats {
+            count: report.total.count,
+            amount: report.total.amount,
+        },
+        session,
+        session_expires_at: health.expires_at,
+        poller: PollerState {
+            status: poller.map(|h| h.status),
+            restarts: poller.map_or(0, |h| h.restarts),
+            last_poll: poll.finished_at,
+            failed_pages: poll.failed_pages,
+        },
+        selected,
+    }
+}
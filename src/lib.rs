@@ -0,0 +1,29 @@
+pub mod alerts;
+pub mod analytics;
+pub mod api;
+pub mod attachments;
+pub mod cli;
+pub mod config;
+pub mod cookie_io;
+pub mod cookie_sync;
+pub mod daemon;
+pub mod export;
+pub mod idex;
+pub mod instance;
+pub mod ipc;
+pub mod logging;
+pub mod metrics;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod money;
+pub mod notify;
+pub mod panel;
+pub mod profiles;
+pub mod proxy;
+pub mod receipts;
+pub mod recorder;
+pub mod rules;
+pub mod session;
+pub mod source;
+pub mod supervisor;
+pub mod token;
@@ -0,0 +1,239 @@
+//! Логирование через `tracing`: уровни и фильтры по модулям, ротация файлов
+//! в каталоге данных, JSON-формат и обязательное маскирование секретов.
+
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::OnceLock,
+};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+const REDACTED: &str = "[redacted]";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+/// Настройки логирования (секция `logging` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Директивы `EnvFilter`, например `info,p2p_app::proxy=debug`.
+    /// Переменная окружения `RUST_LOG` имеет приоритет.
+    pub filter: String,
+    /// Писать события в JSON вместо человекочитаемого текста.
+    pub json: bool,
+    /// Писать ли лог в файлы помимо консоли.
+    pub file: bool,
+    /// Каталог логов относительно каталога данных.
+    pub directory: String,
+    pub rotation: LogRotation,
+    /// Сколько файлов хранить после ротации.
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            json: false,
+            file: true,
+            directory: "logs".to_string(),
+            rotation: LogRotation::Daily,
+            max_files: 14,
+        }
+    }
+}
+
+/// Настраивает глобальный подписчик. Возвращённый guard нужно держать до выхода,
+/// иначе хвост файлового лога потеряется.
+pub fn init_logging(config: &LoggingConfig, data_dir: &Path) -> Option<WorkerGuard> {
+    let filter = || {
+        EnvFilter::try_from_default_env()
+            .or_else(|_| EnvFilter::try_new(&config.filter))
+            .unwrap_or_else(|_| EnvFilter::new("info"))
+    };
+
+    let console = RedactingMakeWriter(io::stdout);
+    let console_layer = if config.json {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(console)
+            .with_filter(filter())
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::layer()
+            .with_writer(console)
+            .with_filter(filter())
+            .boxed()
+    };
+
+    let mut guard = None;
+    let file_layer = if config.file {
+        // tracing-appender не создаёт каталог сам и без него не запустится.
+        let directory = data_dir.join(&config.directory);
+        if let Err(e) = fs::create_dir_all(&directory) {
+            eprintln!("Failed to create log directory {}: {}", directory.display(), e);
+        }
+        let rotation = match config.rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        };
+        match RollingFileAppender::builder()
+            .rotation(rotation)
+            .filename_prefix("p2p_app")
+            .filename_suffix("log")
+            .max_log_files(config.max_files.max(1))
+            .build(&directory)
+        {
+            Ok(appender) => {
+                let (writer, worker) = tracing_appender::non_blocking(appender);
+                guard = Some(worker);
+                let writer = RedactingMakeWriter(writer);
+                Some(if config.json {
+                    tracing_subscriber::fmt::layer()
+                        .json()
+                        .with_ansi(false)
+                        .with_writer(writer)
+                        .with_filter(filter())
+                        .boxed()
+                } else {
+                    tracing_subscriber::fmt::layer()
+                        .with_ansi(false)
+                        .with_writer(writer)
+                        .with_filter(filter())
+                        .boxed()
+                })
+            }
+            Err(e) => {
+                eprintln!("Failed to open log directory {}: {}", config.directory, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Err(e) = tracing_subscriber::registry()
+        .with(console_layer)
+        .with(file_layer)
+        .try_init()
+    {
+        eprintln!("Logging is already initialised: {}", e);
+    }
+    guard
+}
+
+/// Маскирует номер кошелька/карты/телефона, оставляя последние 4 цифры.
+pub fn mask_wallet(wallet: &str) -> String {
+    let digits = wallet.chars().filter(|c| c.is_ascii_digit()).count();
+    if digits <= 4 {
+        return "*".repeat(wallet.chars().count());
+    }
+    let mut seen = 0;
+    wallet
+        .chars()
+        .map(|c| {
+            if c.is_ascii_digit() {
+                seen += 1;
+                if seen <= digits - 4 {
+                    return '*';
+                }
+            }
+            c
+        })
+        .collect()
+}
+
+fn secret_pairs() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r#"(?i)\b(sid|rsid|xsrf-token|x-xsrf-token|laravel_session|device_?token|token|authorization|cookie|set-cookie)("?\s*[=:]\s*"?)([^;,\s"'\]}]+)"#,
+        )
+        .unwrap()
+    })
+}
+
+fn encrypted_payloads() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // Зашифрованные куки Laravel и JWT начинаются с base64 от `{"`.
+    RE.get_or_init(|| Regex::new(r"eyJ[A-Za-z0-9+/=_\-.%]{16,}").unwrap())
+}
+
+fn wallet_numbers() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    // Телефоны РФ (11 цифр с 7/8, опционально с +) и номера карт (16–19 цифр),
+    // в том числе с пробелами, дефисами и скобками, как их понимает `mask_wallet`.
+    RE.get_or_init(|| {
+        Regex::new(r"(\+?\b[78](?:[ \-()]{0,2}\d){10}\b|\b\d{4}(?:[ \-]?\d{4}){3}\d{0,3}\b)")
+            .unwrap()
+    })
+}
+
+/// Вычищает из строки значения кук, токены и номера кошельков.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut out = Cow::Borrowed(text);
+    if secret_pairs().is_match(&out) {
+        out = Cow::Owned(
+            secret_pairs()
+                .replace_all(&out, |c: &Captures| format!("{}{}{}", &c[1], &c[2], REDACTED))
+                .into_owned(),
+        );
+    }
+    if encrypted_payloads().is_match(&out) {
+        out = Cow::Owned(encrypted_payloads().replace_all(&out, REDACTED).into_owned());
+    }
+    if wallet_numbers().is_match(&out) {
+        out = Cow::Owned(
+            wallet_numbers()
+                .replace_all(&out, |c: &Captures| mask_wallet(&c[0]))
+                .into_owned(),
+        );
+    }
+    out
+}
+
+/// Обёртка над writer-ом, пропускающая каждое событие через `redact`.
+pub struct RedactingWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.inner.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct RedactingMakeWriter<M>(pub M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.0.make_writer(),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Метрики Prometheus для прокси, опроса IDEX, загрузки вложений, оповещений
+//! и проверки токена.
+//! Реестр общий для процесса, поэтому метрики профиля несут метку `profile`, а
+//! отдаёт их по пути `/metrics` только прокси первого профиля. Очереди выгрузки
+//! на сервер в приложении нет, поэтому нет и метрики её глубины.
+
+use std::sync::OnceLock;
+
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder};
+
+/// Путь, по которому прокси отдаёт метрики вместо проксирования.
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Источник транзакции: активный опрос или пассивный перехват в прокси.
+pub const SOURCE_POLL: &str = "poll";
+pub const SOURCE_PROXY: &str = "proxy";
+
+pub struct Metrics {
+    registry: Registry,
+    /// Проксированные запросы по статусу ответа панели (`error`, если ответа нет).
+    pub proxy_requests: IntCounterVec,
+    /// Время до получения заголовков ответа панели.
+    pub upstream_latency: HistogramVec,
+    /// Длительность полного прохода `run_idex` по страницам.
+    pub poll_duration: HistogramVec,
+    /// Неудачные страницы опроса по номеру страницы.
+    pub page_failures: IntCounterVec,
+    /// Новые и обновлённые транзакции по источнику.
+    pub transactions: IntCounterVec,
+    /// Куки, полученные из `Set-Cookie`.
+    pub cookie_updates: IntCounterVec,
+    /// Проверки device token по результату: `valid`, `invalid`, `error`.
+    pub token_verifications: IntCounterVec,
+    /// Загрузки вложений по результату: `downloaded`, `deduplicated`, `failed`.
+    pub attachment_downloads: IntCounterVec,
+    /// Оповещения по каналу и результату доставки: `sent`, `failed`.
+    pub alerts: IntCounterVec,
+    /// Выплаты, не оплаченные до `expired_at`.
+    pub missed_deadlines: IntCounterVec,
+    /// Запросы поддержания сессии по результату: `ok`, `unauthorized`, `failed`.
+    pub session_keepalives: IntCounterVec,
+    /// Смены значения отслеживаемых кук сессии.
+    pub cookie_rotations: IntCounterVec,
+    /// Уведомления по событию и результату: `shown`, `failed`, `quiet`.
+    pub notifications: IntCounterVec,
+}
+
+/// Общий набор метрик процесса.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("p2p".to_string()), None).unwrap();
+        let latency_buckets = vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+        let proxy_requests = IntCounterVec::new(
+            Opts::new("proxy_requests_total", "Proxied requests by upstream status"),
+            &["profile", "status"],
+        )
+        .unwrap();
+        let upstream_latency = HistogramVec::new(
+            HistogramOpts::new("proxy_upstream_latency_seconds", "Upstream response latency")
+                .buckets(latency_buckets.clone()),
+            &["profile"],
+        )
+        .unwrap();
+        let poll_duration = HistogramVec::new(
+            HistogramOpts::new("idex_poll_duration_seconds", "Duration of a full IDEX poll")
+                .buckets(latency_buckets),
+            &["profile"],
+        )
+        .unwrap();
+        let page_failures = IntCounterVec::new(
+            Opts::new("idex_page_failures_total", "Failed IDEX poll pages"),
+            &["profile", "page"],
+        )
+        .unwrap();
+        let transactions = IntCounterVec::new(
+            Opts::new("transactions_total", "New and updated transactions found"),
+            &["profile", "source", "kind"],
+        )
+        .unwrap();
+        let cookie_updates = IntCounterVec::new(
+            Opts::new("cookie_updates_total", "Cookies received via Set-Cookie"),
+            &["profile"],
+        )
+        .unwrap();
+        let token_verifications = IntCounterVec::new(
+            Opts::new("token_verifications_total", "Device token verifications by result"),
+            &["result"],
+        )
+        .unwrap();
+        let attachment_downloads = IntCounterVec::new(
+            Opts::new("attachment_downloads_total", "Attachment downloads by result"),
+            &["profile", "result"],
+        )
+        .unwrap();
+        let alerts = IntCounterVec::new(
+            Opts::new("alerts_total", "Expiry alerts by channel and delivery result"),
+            &["profile", "channel", "result"],
+        )
+        .unwrap();
+        let missed_deadlines = IntCounterVec::new(
+            Opts::new("missed_deadlines_total", "Payouts not paid before expiry"),
+            &["profile"],
+        )
+        .unwrap();
+        let session_keepalives = IntCounterVec::new(
+            Opts::new("session_keepalives_total", "Session keep-alive requests by result"),
+            &["profile", "result"],
+        )
+        .unwrap();
+        let cookie_rotations = IntCounterVec::new(
+            Opts::new("cookie_rotations_total", "Value changes of tracked session cookies"),
+            &["profile", "cookie"],
+        )
+        .unwrap();
+        let notifications = IntCounterVec::new(
+            Opts::new("notifications_total", "Desktop notifications by event and result"),
+            &["profile", "event", "result"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(proxy_requests.clone())).unwrap();
+        registry.register(Box::new(upstream_latency.clone())).unwrap();
+        registry.register(Box::new(poll_duration.clone())).unwrap();
+        registry.register(Box::new(page_failures.clone())).unwrap();
+        registry.register(Box::new(transactions.clone())).unwrap();
+        registry.register(Box::new(cookie_updates.clone())).unwrap();
+        registry.register(Box::new(token_verifications.clone())).unwrap();
+        registry.register(Box::new(attachment_downloads.clone())).unwrap();
+        registry.register(Box::new(alerts.clone())).unwrap();
+        registry.register(Box::new(missed_deadlines.clone())).unwrap();
+        registry.register(Box::new(session_keepalives.clone())).unwrap();
+        registry.register(Box::new(cookie_rotations.clone())).unwrap();
+        registry.register(Box::new(notifications.clone())).unwrap();
+
+        Self {
+            registry,
+            proxy_requests,
+            upstream_latency,
+            poll_duration,
+            page_failures,
+            transactions,
+            cookie_updates,
+            token_verifications,
+            attachment_downloads,
+            alerts,
+            missed_deadlines,
+            session_keepalives,
+            cookie_rotations,
+            notifications,
+        }
+    }
+
+    /// Учитывает найденные транзакции профиля из указанного источника.
+    pub fn record_transactions(&self, profile: &str, source: &str, new: u64, updated: u64) {
+        self.transactions
+            .with_label_values(&[profile, source, "new"])
+            .inc_by(new);
+        self.transactions
+            .with_label_values(&[profile, source, "updated"])
+            .inc_by(updated);
+    }
+
+    /// Все метрики в текстовом формате Prometheus.
+    pub fn encode(&self) -> String {
+        let mut buf = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            tracing::warn!(error = %e, "Failed to encode metrics");
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
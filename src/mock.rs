@@ -0,0 +1,768 @@
+//! Офлайн-имитация panel.gate.cx, API проверки device token и Telegram Bot API,
+//! а также способ показа уведомлений, который только их запоминает.
+//! Используется интеграционными тестами и примером `mock_gate` для отладки без живой панели.
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    fs,
+    future::Future,
+    io,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use hyper::{
+    header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, COOKIE, RANGE, SET_COOKIE, USER_AGENT},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+use tracing::error;
+
+use crate::idex::{extract_payouts, Transaction, PAYOUTS_PATH};
+use crate::idex::upgrade_record;
+use crate::money::{decimal_to_json, party_amounts_to_json};
+use crate::notify::{Notification, NotifyBackend, NotifyFuture};
+use crate::panel::{BALANCE_PATH, XSRF_COOKIE, XSRF_HEADER};
+
+/// Поведение имитации панели.
+#[derive(Debug, Clone)]
+pub struct MockPanelConfig {
+    /// Сколько выплат отдаётся на одной странице списка.
+    pub page_size: usize,
+    /// Кука (имя, значение), без которой API отвечает 401.
+    pub session_cookie: Option<(String, String)>,
+    /// Сессия истекает после стольких авторизованных запросов.
+    pub session_ttl: Option<usize>,
+    /// Страницы списка, на которых API отвечает 500.
+    pub failing_pages: HashSet<u32>,
+    /// Искусственная задержка перед каждым ответом.
+    pub latency: Duration,
+}
+
+impl Default for MockPanelConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 10,
+            session_cookie: None,
+            session_ttl: None,
+            failing_pages: HashSet::new(),
+            latency: Duration::ZERO,
+        }
+    }
+}
+
+/// Запрос, пришедший в имитацию.
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub cookie: Option<String>,
+    pub user_agent: Option<String>,
+    pub range: Option<String>,
+    pub xsrf_token: Option<String>,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+struct PanelState {
+    config: MockPanelConfig,
+    payouts: Vec<Value>,
+    requests: Vec<MockRequest>,
+    authorized: usize,
+    /// Файлы вложений по пути запроса.
+    files: HashMap<String, Vec<u8>>,
+    /// Столько следующих ответов с файлами оборвутся на середине.
+    interrupted_files: usize,
+    /// Id выплат, взятых в работу.
+    claimed: Vec<String>,
+    /// Id выплат, отмеченных оплаченными, и отменённых трейдером.
+    paid: Vec<String>,
+    cancelled: Vec<String>,
+    /// Баланс трейдера: код валюты → сумма.
+    balance: Value,
+    /// XSRF-токены, выданные раньше этого номера запроса, считаются устаревшими.
+    xsrf_valid_from: usize,
+}
+
+/// Запущенная имитация панели. Останавливается при удалении.
+pub struct MockPanel {
+    addr: SocketAddr,
+    state: Arc<Mutex<PanelState>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockPanel {
+    /// Поднимает сервер на свободном порту 127.0.0.1. Вызывать внутри Tokio runtime.
+    pub fn start(config: MockPanelConfig, payouts: Vec<Value>) -> Self {
+        let state = Arc::new(Mutex::new(PanelState {
+            config,
+            payouts,
+            requests: Vec::new(),
+            authorized: 0,
+            files: HashMap::new(),
+            interrupted_files: 0,
+            claimed: Vec::new(),
+            paid: Vec::new(),
+            cancelled: Vec::new(),
+            balance: json!({}),
+            xsrf_valid_from: 0,
+        }));
+        let (addr, shutdown) = spawn_server(state.clone(), panel_handler);
+        Self {
+            addr,
+            state,
+            _shutdown: shutdown,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Базовый URL в том виде, в каком его ожидает `ProxyState`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn push_payout(&self, payout: Value) {
+        self.state.lock().unwrap().payouts.push(payout);
+    }
+
+    /// Заменяет выплату с тем же `id` (например, чтобы сменить статус).
+    pub fn replace_payout(&self, payout: Value) {
+        let mut state = self.state.lock().unwrap();
+        let id = payout.get("id").cloned();
+        if let Some(saved) = state.payouts.iter_mut().find(|p| p.get("id") == id.as_ref()) {
+            *saved = payout;
+        }
+    }
+
+    pub fn set_failing_pages(&self, pages: HashSet<u32>) {
+        self.state.lock().unwrap().config.failing_pages = pages;
+    }
+
+    /// Отдаёт `content` по пути `path` (например, `/storage/1724608/check.pdf`).
+    /// Поддерживаются запросы с `Range: bytes=N-`.
+    pub fn add_file(&self, path: &str, content: Vec<u8>) {
+        self.state
+            .lock()
+            .unwrap()
+            .files
+            .insert(path.to_string(), content);
+    }
+
+    /// Следующие `count` ответов с файлами передадут половину и оборвут соединение.
+    pub fn interrupt_files(&self, count: usize) {
+        self.state.lock().unwrap().interrupted_files = count;
+    }
+
+    /// Id выплат, взятых в работу, в порядке взятия.
+    pub fn claimed(&self) -> Vec<String> {
+        self.state.lock().unwrap().claimed.clone()
+    }
+
+    pub fn paid(&self) -> Vec<String> {
+        self.state.lock().unwrap().paid.clone()
+    }
+
+    pub fn cancelled(&self) -> Vec<String> {
+        self.state.lock().unwrap().cancelled.clone()
+    }
+
+    /// Баланс, который отдаёт панель, в виде `{"643": "1000.00", ...}`.
+    pub fn set_balance(&self, balance: Value) {
+        self.state.lock().unwrap().balance = balance;
+    }
+
+    /// Все выданные XSRF-токены устаревают: действия с ними получат 419.
+    pub fn expire_xsrf(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.xsrf_valid_from = state.requests.len() + 1;
+    }
+
+    /// Немедленно «протухает» текущая сессия.
+    pub fn expire_session(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.config.session_ttl = Some(0);
+        state.authorized = 0;
+    }
+}
+
+async fn panel_handler(req: Request<Body>, state: Arc<Mutex<PanelState>>) -> Response<Body> {
+    let latency = state.lock().unwrap().config.latency;
+    if !latency.is_zero() {
+        tokio::time::sleep(latency).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let mut state = state.lock().unwrap();
+    let header = |name: &str| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+    };
+    let request = MockRequest {
+        method: parts.method.clone(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(String::from),
+        cookie: header(COOKIE.as_str()),
+        user_agent: header(USER_AGENT.as_str()),
+        range: header(RANGE.as_str()),
+        xsrf_token: header(XSRF_HEADER),
+        content_type: header(CONTENT_TYPE.as_str()),
+        body: body.to_vec(),
+    };
+    state.requests.push(request.clone());
+
+    if let Some((name, value)) = &state.config.session_cookie {
+        let expected = format!("{}={}", name, value);
+        let has_session = request
+            .cookie
+            .as_deref()
+            .is_some_and(|c| c.split(';').any(|pair| pair.trim() == expected));
+        let expired = state
+            .config
+            .session_ttl
+            .is_some_and(|ttl| state.authorized >= ttl);
+        if !has_session || expired {
+            return json_response(
+                StatusCode::UNAUTHORIZED,
+                json!({ "success": false, "message": "Unauthenticated." }),
+            );
+        }
+    }
+    state.authorized += 1;
+
+    let action = request
+        .path
+        .strip_prefix(PAYOUTS_PATH)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .and_then(|rest| rest.split_once('/'));
+    let mut response = if let Some((id, action)) = action.filter(|_| request.method == Method::POST) {
+        let (id, action) = (id.to_string(), action.to_string());
+        action_response(&mut state, &request, &id, &action)
+    } else if request.method != Method::GET {
+        json_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            json!({ "success": false, "message": "Method not allowed." }),
+        )
+    } else if state.files.contains_key(&request.path) {
+        file_response(&mut state, &request)
+    } else if request.path == BALANCE_PATH {
+        json_response(
+            StatusCode::OK,
+            json!({ "success": true, "response": { "balance": state.balance } }),
+        )
+    } else if request.path == PAYOUTS_PATH {
+        let page = query_param(request.query.as_deref(), "page")
+            .and_then(|p| p.parse::<u32>().ok())
+            .unwrap_or(1)
+            .max(1);
+        if state.config.failing_pages.contains(&page) {
+            json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "success": false, "message": "Server Error" }),
+            )
+        } else {
+            json_response(StatusCode::OK, payouts_page(&state, page))
+        }
+    } else if let Some(id) = request
+        .path
+        .strip_prefix(PAYOUTS_PATH)
+        .and_then(|rest| rest.strip_prefix('/'))
+    {
+        match state
+            .payouts
+            .iter()
+            .find(|p| p.get("id").map(id_string).as_deref() == Some(id))
+        {
+            Some(payout) => json_response(
+                StatusCode::OK,
+                json!({ "success": true, "response": { "payout": payout } }),
+            ),
+            None => json_response(
+                StatusCode::NOT_FOUND,
+                json!({ "success": false, "message": "Not found." }),
+            ),
+        }
+    } else {
+        json_response(
+            StatusCode::NOT_FOUND,
+            json!({ "success": false, "message": "Not found." }),
+        )
+    };
+
+    // Панель обновляет XSRF-TOKEN на каждый ответ — имитируем это для проверки кук.
+    let xsrf = format!(
+        "XSRF-TOKEN=mock-xsrf-{}; path=/; samesite=lax",
+        state.requests.len()
+    );
+    response
+        .headers_mut()
+        .append(SET_COOKIE, xsrf.parse().unwrap());
+    response
+}
+
+/// Действие трейдера над выплатой. Как и Laravel, требует `X-XSRF-TOKEN`,
+/// совпадающий с кукой `XSRF-TOKEN`.
+fn action_response(
+    state: &mut PanelState,
+    request: &MockRequest,
+    id: &str,
+    action: &str,
+) -> Response<Body> {
+    let xsrf_cookie = request.cookie.as_deref().and_then(|c| {
+        c.split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == XSRF_COOKIE)
+            .map(|(_, value)| value.to_string())
+    });
+    let issued = xsrf_cookie
+        .as_deref()
+        .and_then(|t| t.strip_prefix("mock-xsrf-"))
+        .and_then(|n| n.parse::<usize>().ok());
+    let stale = issued.is_some_and(|n| n < state.xsrf_valid_from);
+    if xsrf_cookie.is_none() || request.xsrf_token != xsrf_cookie || stale {
+        return json_response(
+            StatusCode::from_u16(419).unwrap(),
+            json!({ "message": "CSRF token mismatch." }),
+        );
+    }
+    let Some(payout) = state
+        .payouts
+        .iter()
+        .find(|p| p.get("id").map(id_string).as_deref() == Some(id))
+        .cloned()
+    else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            json!({ "success": false, "message": "Not found." }),
+        );
+    };
+    let claimed = state.claimed.iter().any(|c| c == id);
+    let rejected = |message: &str| {
+        json_response(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            json!({ "success": false, "message": message }),
+        )
+    };
+    let ok = || {
+        json_response(
+            StatusCode::OK,
+            json!({ "success": true, "response": { "payout": payout } }),
+        )
+    };
+    match action {
+        "take" if claimed => rejected("Payout already taken."),
+        "take" => {
+            state.claimed.push(id.to_string());
+            ok()
+        }
+        "approve" | "cancel" if !claimed => rejected("Payout is not in work."),
+        "approve" => {
+            let multipart = request
+                .content_type
+                .as_deref()
+                .is_some_and(|t| t.starts_with("multipart/form-data"));
+            let marker = br#"name="attachments[]""#;
+            if !multipart || !request.body.windows(marker.len()).any(|w| w == marker) {
+                return rejected("The attachments field is required.");
+            }
+            state.claimed.retain(|c| c != id);
+            state.paid.push(id.to_string());
+            ok()
+        }
+        "cancel" => {
+            state.claimed.retain(|c| c != id);
+            state.cancelled.push(id.to_string());
+            ok()
+        }
+        _ => json_response(
+            StatusCode::NOT_FOUND,
+            json!({ "success": false, "message": "Not found." }),
+        ),
+    }
+}
+
+fn file_response(state: &mut PanelState, request: &MockRequest) -> Response<Body> {
+    let content = &state.files[&request.path];
+    let offset = request
+        .range
+        .as_deref()
+        .and_then(|r| r.strip_prefix("bytes="))
+        .and_then(|r| r.strip_suffix('-'))
+        .and_then(|r| r.parse::<usize>().ok());
+    let (status, body) = match offset {
+        Some(offset) if offset >= content.len() => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .body(Body::empty())
+                .unwrap();
+        }
+        Some(offset) => (StatusCode::PARTIAL_CONTENT, content[offset..].to_vec()),
+        None => (StatusCode::OK, content.clone()),
+    };
+    let mut builder = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/pdf")
+        .header(CONTENT_LENGTH, body.len());
+    if let Some(offset) = offset {
+        builder = builder.header(
+            CONTENT_RANGE,
+            format!("bytes {}-{}/{}", offset, content.len() - 1, content.len()),
+        );
+    }
+    if state.interrupted_files == 0 {
+        return builder.body(Body::from(body)).unwrap();
+    }
+    state.interrupted_files -= 1;
+    let (mut sender, stream) = Body::channel();
+    let half = body[..body.len() / 2].to_vec();
+    tokio::spawn(async move {
+        let _ = sender.send_data(half.into()).await;
+        // abort() сбрасывает ещё не отданные данные, поэтому даём клиенту их прочитать.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        sender.abort();
+    });
+    builder.body(stream).unwrap()
+}
+
+fn payouts_page(state: &PanelState, page: u32) -> Value {
+    let per_page = state.config.page_size.max(1);
+    let total = state.payouts.len();
+    let last_page = total.div_ceil(per_page).max(1);
+    let data: Vec<&Value> = state
+        .payouts
+        .iter()
+        .skip((page as usize - 1) * per_page)
+        .take(per_page)
+        .collect();
+    json!({
+        "success": true,
+        "response": {
+            "payouts": {
+                "current_page": page,
+                "data": data,
+                "last_page": last_page,
+                "per_page": per_page,
+                "total": total,
+            }
+        }
+    })
+}
+
+struct TokenState {
+    valid_tokens: HashSet<String>,
+    checked: Vec<String>,
+}
+
+/// Запущенная имитация API проверки device token. Останавливается при удалении.
+pub struct MockTokenApi {
+    addr: SocketAddr,
+    state: Arc<Mutex<TokenState>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockTokenApi {
+    /// Поднимает сервер на свободном порту 127.0.0.1. Вызывать внутри Tokio runtime.
+    pub fn start(valid_tokens: impl IntoIterator<Item = String>) -> Self {
+        let state = Arc::new(Mutex::new(TokenState {
+            valid_tokens: valid_tokens.into_iter().collect(),
+            checked: Vec::new(),
+        }));
+        let (addr, shutdown) = spawn_server(state.clone(), token_handler);
+        Self {
+            addr,
+            state,
+            _shutdown: shutdown,
+        }
+    }
+
+    /// URL для `verify_device_token`.
+    pub fn url(&self) -> String {
+        format!("http://{}/api/deviceToken", self.addr)
+    }
+
+    /// Токены, которые проверялись, в порядке поступления.
+    pub fn checked_tokens(&self) -> Vec<String> {
+        self.state.lock().unwrap().checked.clone()
+    }
+}
+
+async fn token_handler(req: Request<Body>, state: Arc<Mutex<TokenState>>) -> Response<Body> {
+    if req.method() != Method::POST {
+        return json_response(StatusCode::METHOD_NOT_ALLOWED, json!({ "valid": false }));
+    }
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .unwrap_or_default();
+    let token = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|v| v.get("deviceToken").and_then(|t| t.as_str()).map(String::from));
+    let Some(token) = token else {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            json!({ "valid": false, "error": "deviceToken is required" }),
+        );
+    };
+
+    let mut state = state.lock().unwrap();
+    let valid = state.valid_tokens.contains(&token);
+    state.checked.push(token);
+    json_response(StatusCode::OK, json!({ "valid": valid }))
+}
+
+struct TelegramState {
+    bot_token: String,
+    messages: Vec<(String, String)>,
+}
+
+/// Запущенная имитация Telegram Bot API: принимает `sendMessage` для одного
+/// бота и запоминает сообщения. Останавливается при удалении.
+pub struct MockTelegram {
+    addr: SocketAddr,
+    state: Arc<Mutex<TelegramState>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockTelegram {
+    /// Поднимает сервер на свободном порту 127.0.0.1. Вызывать внутри Tokio runtime.
+    pub fn start(bot_token: &str) -> Self {
+        let state = Arc::new(Mutex::new(TelegramState {
+            bot_token: bot_token.to_string(),
+            messages: Vec::new(),
+        }));
+        let (addr, shutdown) = spawn_server(state.clone(), telegram_handler);
+        Self {
+            addr,
+            state,
+            _shutdown: shutdown,
+        }
+    }
+
+    /// Адрес для `api_url` в настройках Telegram.
+    pub fn api_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Отправленные сообщения: `(chat_id, text)` в порядке поступления.
+    pub fn messages(&self) -> Vec<(String, String)> {
+        self.state.lock().unwrap().messages.clone()
+    }
+}
+
+async fn telegram_handler(req: Request<Body>, state: Arc<Mutex<TelegramState>>) -> Response<Body> {
+    let expected = format!("/bot{}/sendMessage", state.lock().unwrap().bot_token);
+    if req.method() != Method::POST || req.uri().path() != expected {
+        return json_response(
+            StatusCode::UNAUTHORIZED,
+            json!({ "ok": false, "error_code": 401, "description": "Unauthorized" }),
+        );
+    }
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .unwrap_or_default();
+    let message = serde_json::from_slice::<Value>(&body).ok().and_then(|v| {
+        let chat_id = match v.get("chat_id")? {
+            Value::String(id) => id.clone(),
+            id => id.to_string(),
+        };
+        Some((chat_id, v.get("text")?.as_str()?.to_string()))
+    });
+    let Some((chat_id, text)) = message else {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            json!({ "ok": false, "error_code": 400, "description": "Bad Request: message text is empty" }),
+        );
+    };
+
+    let mut state = state.lock().unwrap();
+    state.messages.push((chat_id.clone(), text.clone()));
+    let message_id = state.messages.len();
+    json_response(
+        StatusCode::OK,
+        json!({ "ok": true, "result": { "message_id": message_id, "chat": { "id": chat_id }, "text": text } }),
+    )
+}
+
+/// Способ показа уведомлений для тестов: запоминает уведомления и звуки
+/// вместо показа. Клоны разделяют записи.
+#[derive(Default, Clone)]
+pub struct RecordingNotifications {
+    shown: Arc<Mutex<Vec<Notification>>>,
+    sounds: Arc<Mutex<Vec<String>>>,
+}
+
+impl RecordingNotifications {
+    /// Показанные уведомления в порядке показа.
+    pub fn shown(&self) -> Vec<Notification> {
+        self.shown.lock().unwrap().clone()
+    }
+
+    /// Проигранные звуки: путь к файлу или `beep`.
+    pub fn sounds(&self) -> Vec<String> {
+        self.sounds.lock().unwrap().clone()
+    }
+}
+
+impl NotifyBackend for RecordingNotifications {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn show<'a>(&'a self, notification: &'a Notification) -> NotifyFuture<'a> {
+        self.shown.lock().unwrap().push(notification.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn play<'a>(&'a self, sound: &'a str) -> NotifyFuture<'a> {
+        self.sounds.lock().unwrap().push(sound.to_string());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Загружает выплаты для имитации из файла. Понимает:
+/// массив сырых выплат панели, idex_history.json (массив `Transaction`),
+/// одиночный ответ API, HAR и JSONL из записи трафика прокси.
+pub fn load_fixture(path: &Path) -> io::Result<Vec<Value>> {
+    let content = fs::read_to_string(path)?;
+    match serde_json::from_str::<Value>(&content) {
+        Ok(Value::Array(items)) => Ok(items.into_iter().map(fixture_payout).collect()),
+        Ok(json) => {
+            let har_entries = json
+                .get("log")
+                .and_then(|l| l.get("entries"))
+                .and_then(|e| e.as_array());
+            Ok(match har_entries {
+                Some(entries) => entries
+                    .iter()
+                    .filter_map(|e| e.get("response").and_then(|r| r.get("content")))
+                    .flat_map(payouts_from_body)
+                    .collect(),
+                None => extract_payouts(&json).into_iter().cloned().collect(),
+            })
+        }
+        // Не один JSON-документ — считаем, что это JSONL рекордера.
+        Err(_) => Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .filter_map(|e| e.get("response").and_then(|r| r.get("body")).cloned())
+            .flat_map(|body| payouts_from_body(&body))
+            .collect()),
+    }
+}
+
+/// Запись истории превращаем обратно в выплату панели, сырые выплаты оставляем как есть.
+fn fixture_payout(item: Value) -> Value {
+    if item.get("transaction_id").is_some() {
+        let mut record = item.clone();
+        upgrade_record(&mut record);
+        if let Ok(tx) = serde_json::from_value::<Transaction>(record) {
+            return payout_from_transaction(&tx);
+        }
+    }
+    item
+}
+
+fn payouts_from_body(body: &Value) -> Vec<Value> {
+    body.get("text")
+        .and_then(|t| t.as_str())
+        .and_then(|text| serde_json::from_str::<Value>(text).ok())
+        .map(|json| extract_payouts(&json).into_iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Обратное к `map_transaction` преобразование: собирает выплату в формате API панели.
+pub fn payout_from_transaction(tx: &Transaction) -> Value {
+    let numeric = |s: &str| {
+        s.parse::<u64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::from(s))
+    };
+    json!({
+        "id": numeric(&tx.transaction_id),
+        "userId": tx.user_id,
+        "payment_method_id": tx.payment_method_id,
+        "wallet": tx.wallet,
+        "amount": party_amounts_to_json(&tx.amount),
+        "total": party_amounts_to_json(&tx.total),
+        "status": tx.status.as_deref().map(|s| s.parse::<i64>().map_or_else(|_| Value::from(s), Value::from)),
+        "bank": { "name": tx.bank_name, "code": tx.bank_code, "label": tx.bank_label },
+        "method": { "label": tx.payment_method },
+        "meta": { "courses": { "trader": tx.course.map(decimal_to_json) } },
+        "tooltip": { "payments": { "success": tx.success_count, "percent": tx.success_rate } },
+        "approved_at": tx.approved_at,
+        "expired_at": tx.expired_at,
+        "created_at": tx.created_at,
+        "updated_at": tx.updated_at,
+        "trader": {
+            "id": tx.trader_id.as_deref().map(numeric),
+            "name": tx.trader_name,
+        },
+        "attachments": tx.attachments,
+    })
+}
+
+fn spawn_server<S, F, Fut>(state: Arc<Mutex<S>>, handler: F) -> (SocketAddr, oneshot::Sender<()>)
+where
+    S: Send + 'static,
+    F: Fn(Request<Body>, Arc<Mutex<S>>) -> Fut + Copy + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = handler(req, state.clone());
+                async move { Ok::<_, Infallible>(response.await) }
+            }))
+        }
+    });
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let addr = server.local_addr();
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let graceful = server.with_graceful_shutdown(async {
+            let _ = stopped.await;
+        });
+        if let Err(e) = graceful.await {
+            error!(error = %e, "Mock server error");
+        }
+    });
+    (addr, shutdown)
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn query_param(query: Option<&str>, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(query?.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn id_string(id: &Value) -> String {
+    match id {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,258 @@
+//! Денежные суммы без потерь точности: `Decimal` с привязкой к валюте,
+//! суммы по сторонам сделки и справочник кодов валют панели.
+//!
+//! Панель передаёт суммы объектами `{"trader": {"643": 14438.0, "000001": 145.96}}`:
+//! сторона сделки → код валюты → значение. Коды фиата — числовые ISO 4217,
+//! у криптовалют — внутренние коды панели.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+pub use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Код рубля в API панели.
+pub const RUB: &str = "643";
+/// Код USDT в API панели.
+pub const USDT: &str = "000001";
+
+/// Сторона сделки, от лица которой работает приложение.
+pub const TRADER: &str = "trader";
+
+/// Суммы одной стороны: код валюты → значение.
+pub type CurrencyAmounts = BTreeMap<String, Decimal>;
+/// Суммы по сторонам сделки (`trader`, `merchant`, ...).
+pub type PartyAmounts = BTreeMap<String, CurrencyAmounts>;
+
+/// Сведения о валюте для отображения.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrencyInfo {
+    pub code: &'static str,
+    pub alpha: &'static str,
+    pub name: &'static str,
+    pub minor_units: u32,
+    pub crypto: bool,
+}
+
+macro_rules! fiat {
+    ($code:expr, $alpha:expr, $name:expr, $minor:expr) => {
+        CurrencyInfo {
+            code: $code,
+            alpha: $alpha,
+            name: $name,
+            minor_units: $minor,
+            crypto: false,
+        }
+    };
+}
+
+/// Известные валюты: ISO 4217 для фиата и коды панели для криптовалют.
+pub const CURRENCIES: &[CurrencyInfo] = &[
+    fiat!("643", "RUB", "Российский рубль", 2),
+    fiat!("840", "USD", "Доллар США", 2),
+    fiat!("978", "EUR", "Евро", 2),
+    fiat!("398", "KZT", "Казахстанский тенге", 2),
+    fiat!("933", "BYN", "Белорусский рубль", 2),
+    fiat!("980", "UAH", "Украинская гривна", 2),
+    fiat!("860", "UZS", "Узбекский сум", 2),
+    fiat!("417", "KGS", "Киргизский сом", 2),
+    fiat!("972", "TJS", "Таджикский сомони", 2),
+    fiat!("051", "AMD", "Армянский драм", 2),
+    fiat!("944", "AZN", "Азербайджанский манат", 2),
+    fiat!("981", "GEL", "Грузинский лари", 2),
+    fiat!("498", "MDL", "Молдавский лей", 2),
+    fiat!("156", "CNY", "Китайский юань", 2),
+    fiat!("949", "TRY", "Турецкая лира", 2),
+    fiat!("784", "AED", "Дирхам ОАЭ", 2),
+    fiat!("826", "GBP", "Фунт стерлингов", 2),
+    fiat!("356", "INR", "Индийская рупия", 2),
+    fiat!("764", "THB", "Тайский бат", 2),
+    fiat!("704", "VND", "Вьетнамский донг", 0),
+    CurrencyInfo {
+        code: USDT,
+        alpha: "USDT",
+        name: "Tether",
+        minor_units: 6,
+        crypto: true,
+    },
+];
+
+/// Валюта по коду панели. Неизвестные коды сохраняются как есть.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Currency(String);
+
+impl Currency {
+    pub fn new(code: &str) -> Self {
+        Self(code.to_string())
+    }
+
+    pub fn rub() -> Self {
+        Self::new(RUB)
+    }
+
+    pub fn usdt() -> Self {
+        Self::new(USDT)
+    }
+
+    /// Валюта по буквенному коду (`RUB`, `usdt`) или по коду панели.
+    pub fn parse(value: &str) -> Option<Self> {
+        CURRENCIES
+            .iter()
+            .find(|c| c.alpha.eq_ignore_ascii_case(value) || c.code == value)
+            .map(|c| Self::new(c.code))
+            .or_else(|| {
+                (!value.is_empty() && value.chars().all(|c| c.is_ascii_digit()))
+                    .then(|| Self::new(value))
+            })
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+
+    pub fn info(&self) -> Option<&'static CurrencyInfo> {
+        CURRENCIES.iter().find(|c| c.code == self.0)
+    }
+
+    /// Буквенный код для отображения; для неизвестной валюты — код панели.
+    pub fn alpha(&self) -> &str {
+        self.info().map(|c| c.alpha).unwrap_or(&self.0)
+    }
+
+    pub fn is_crypto(&self) -> bool {
+        self.info().is_some_and(|c| c.crypto)
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.alpha())
+    }
+}
+
+/// Сумма в конкретной валюте. Сложение и вычитание разных валют невозможно.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Money {
+    pub amount: Decimal,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: Decimal, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Option<Money> {
+        (self.currency == other.currency)
+            .then(|| Money::new(self.amount + other.amount, self.currency.clone()))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Option<Money> {
+        (self.currency == other.currency)
+            .then(|| Money::new(self.amount - other.amount, self.currency.clone()))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+/// Сумма стороны в валюте, если она есть.
+pub fn lookup(amounts: &PartyAmounts, party: &str, code: &str) -> Option<Money> {
+    amounts
+        .get(party)
+        .and_then(|by_currency| by_currency.get(code))
+        .map(|amount| Money::new(*amount, Currency::new(code)))
+}
+
+/// Все суммы из объекта панели вида `{"trader": {"643": 14438.0}, ...}`.
+/// Значения, которые не удаётся разобрать как число, пропускаются.
+pub fn parse_party_amounts(value: &Value) -> PartyAmounts {
+    let Some(parties) = value.as_object() else {
+        return PartyAmounts::new();
+    };
+    parties
+        .iter()
+        .filter_map(|(party, by_currency)| {
+            let amounts: CurrencyAmounts = by_currency
+                .as_object()?
+                .iter()
+                .filter_map(|(code, v)| parse_decimal(v).map(|d| (code.clone(), d)))
+                .collect();
+            (!amounts.is_empty()).then(|| (party.clone(), amounts))
+        })
+        .collect()
+}
+
+/// Обратное к `parse_party_amounts`: точные JSON-числа в формате панели.
+pub fn party_amounts_to_json(amounts: &PartyAmounts) -> Value {
+    Value::Object(
+        amounts
+            .iter()
+            .map(|(party, by_currency)| {
+                let inner = by_currency
+                    .iter()
+                    .map(|(code, d)| (code.clone(), decimal_to_json(*d)))
+                    .collect();
+                (party.clone(), Value::Object(inner))
+            })
+            .collect(),
+    )
+}
+
+/// Точное значение из JSON: число (в исходной записи, без округления до `f64`)
+/// или строка, в том числе с десятичной запятой и разделителями разрядов.
+pub fn parse_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Number(n) => parse_decimal_str(&n.to_string()),
+        Value::String(s) => parse_decimal_str(s),
+        _ => None,
+    }
+}
+
+/// Запятая считается десятичной, только если она одна и точки нет; иначе
+/// запятые разделяют разряды (`1,234,567.89`) и отбрасываются.
+fn parse_decimal_str(s: &str) -> Option<Decimal> {
+    let decimal_comma = !s.contains('.') && s.matches(',').count() == 1;
+    let cleaned: String = s
+        .trim()
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\u{a0}')
+        .filter(|c| *c != ',' || decimal_comma)
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect();
+    Decimal::from_str(&cleaned)
+        .or_else(|_| Decimal::from_scientific(&cleaned))
+        .ok()
+}
+
+/// Сериализация `Option<Decimal>` (курс) строкой, чтобы не терять точность.
+pub mod decimal {
+    use super::*;
+
+    pub fn serialize<S: serde::Serializer>(
+        value: &Option<Decimal>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(d) => serializer.serialize_str(&d.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Decimal>, D::Error> {
+        Ok(parse_decimal(&Value::deserialize(deserializer)?))
+    }
+}
+
+/// JSON-число с точной записью `Decimal`, как его присылает панель.
+pub fn decimal_to_json(value: Decimal) -> Value {
+    serde_json::Number::from_str(&value.to_string())
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
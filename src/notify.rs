@@ -0,0 +1,660 @@
+//! Всплывающие уведомления на рабочем столе: новые выплаты и оповещения.
+//!
+//! Уведомление показывает сменный способ показа (`NotifyBackend`): на Linux —
+//! сервис уведомлений по спецификации D-Bus (через `gdbus`), в остальных
+//! системах — системная утилита. Нажатие на уведомление о выплате открывает
+//! окно IDEX её профиля. Для каждого события можно задать свой звук, а в часы
+//! «не беспокоить» уведомления не показываются и звук не играет, кроме
+//! событий из `quiet_hours.allow` (они показываются без звука).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    future::Future,
+    io::Write as _,
+    pin::Pin,
+    process::Stdio,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    sync::mpsc::UnboundedSender,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    alerts::{Alert, AlertKind, AlertSink, SinkFuture},
+    analytics::AnalyticsConfig,
+    config::AppConfig,
+    idex::Transaction,
+    metrics::metrics,
+    money::RUB,
+};
+
+/// Имя приложения в уведомлениях.
+const APP_NAME: &str = "p2p_app";
+/// Звук «системный сигнал» вместо файла.
+pub const BEEP: &str = "beep";
+/// Больше стольких новых выплат за раз — одно общее уведомление.
+const MAX_PAYOUT_NOTIFICATIONS: usize = 3;
+
+/// Событие, о котором уведомляют.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    NewPayout,
+    /// Выплата скоро истечёт.
+    Expiring,
+    Missed,
+    /// Сессия панели скоро истечёт.
+    Session,
+}
+
+impl NotifyEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotifyEvent::NewPayout => "new_payout",
+            NotifyEvent::Expiring => "expiring",
+            NotifyEvent::Missed => "missed",
+            NotifyEvent::Session => "session",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// D-Bus на Linux, системная утилита в остальных системах.
+    Auto,
+    Dbus,
+    /// `notify-send`, `osascript` или `msg`.
+    Command,
+}
+
+/// Часы «не беспокоить» по местному времени (`analytics.utc_offset_hours`).
+/// Интервал может переходить через полночь.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuietHours {
+    /// Начало, `HH:MM`.
+    pub from: String,
+    /// Конец, `HH:MM`, не включая.
+    pub to: String,
+    /// События, которые показываются и в эти часы (без звука).
+    #[serde(default)]
+    pub allow: Vec<NotifyEvent>,
+}
+
+/// Настройки уведомлений (секция `notifications` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NotifyConfig {
+    /// `false` отключает все всплывающие уведомления, в том числе канал
+    /// `desktop` оповещений.
+    pub enabled: bool,
+    pub backend: BackendKind,
+    /// Уведомлять о выплатах, найденных опросом.
+    pub new_payouts: bool,
+    /// Звук по событию: путь к файлу или `beep`. Без записи событие беззвучно;
+    /// у оповещений звук также подаёт канал `sound`.
+    pub sounds: BTreeMap<NotifyEvent, String>,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            backend: BackendKind::Auto,
+            new_payouts: true,
+            sounds: BTreeMap::from([(NotifyEvent::NewPayout, BEEP.to_string())]),
+            quiet_hours: None,
+        }
+    }
+}
+
+/// Куда ведёт нажатие на уведомление.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationClick {
+    pub profile: String,
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub event: NotifyEvent,
+    pub title: String,
+    pub body: String,
+    pub click: Option<NotificationClick>,
+}
+
+/// Что было сделано с уведомлением.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Delivery {
+    pub shown: bool,
+    pub sound: bool,
+}
+
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// Способ показа уведомлений и проигрывания звуков.
+pub trait NotifyBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn show<'a>(&'a self, notification: &'a Notification) -> NotifyFuture<'a>;
+
+    /// `sound` — путь к файлу или `BEEP`.
+    fn play<'a>(&'a self, sound: &'a str) -> NotifyFuture<'a> {
+        Box::pin(play_sound(sound))
+    }
+}
+
+/// Способ показа из настроек. Нажатия передаются в `clicks`, если backend
+/// их поддерживает и канал задан.
+pub fn backend_from_config(
+    config: &NotifyConfig,
+    clicks: Option<UnboundedSender<NotificationClick>>,
+) -> Arc<dyn NotifyBackend> {
+    match config.backend {
+        BackendKind::Dbus => Arc::new(DbusBackend::new(clicks)),
+        BackendKind::Auto if cfg!(target_os = "linux") => Arc::new(DbusBackend::new(clicks)),
+        BackendKind::Auto | BackendKind::Command => Arc::new(CommandBackend),
+    }
+}
+
+/// Уведомления одного профиля; клонируется дёшево, способ показа общий.
+#[derive(Clone)]
+pub struct Notifier {
+    config: Arc<NotifyConfig>,
+    backend: Arc<dyn NotifyBackend>,
+    offset: FixedOffset,
+    profile: String,
+}
+
+impl Notifier {
+    pub fn new(config: &NotifyConfig, analytics: &AnalyticsConfig, backend: Arc<dyn NotifyBackend>) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            backend,
+            offset: analytics.offset(),
+            profile: String::new(),
+        }
+    }
+
+    /// Уведомления по секции `notifications` config.json.
+    pub fn from_config(config: &AppConfig, clicks: Option<UnboundedSender<NotificationClick>>) -> Self {
+        let backend = backend_from_config(&config.notifications, clicks);
+        Self::new(&config.notifications, &config.analytics, backend)
+    }
+
+    /// Ничего не показывает: для состояний без настроенных уведомлений.
+    pub fn disabled() -> Self {
+        let config = NotifyConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        Self::new(&config, &AnalyticsConfig::default(), Arc::new(CommandBackend))
+    }
+
+    /// Системные уведомления с настройками по умолчанию.
+    pub fn system() -> Self {
+        let config = NotifyConfig::default();
+        Self::new(&config, &AnalyticsConfig::default(), backend_from_config(&config, None))
+    }
+
+    /// Тот же способ показа для профиля `profile`: нажатие откроет его окно.
+    pub fn for_profile(&self, profile: &str) -> Self {
+        Self {
+            profile: profile.to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn config(&self) -> &NotifyConfig {
+        &self.config
+    }
+
+    /// Идут ли часы «не беспокоить» в момент `now`.
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        let Some(quiet) = &self.config.quiet_hours else {
+            return false;
+        };
+        let parse = |value: &str| NaiveTime::parse_from_str(value.trim(), "%H:%M").ok();
+        let (Some(from), Some(to)) = (parse(&quiet.from), parse(&quiet.to)) else {
+            return false;
+        };
+        let time = now.with_timezone(&self.offset).time();
+        if from <= to {
+            from <= time && time < to
+        } else {
+            time >= from || time < to
+        }
+    }
+
+    /// Показывает уведомление и проигрывает звук события с учётом часов
+    /// «не беспокоить». Ошибка показа записывается в лог и метрики.
+    pub async fn notify(&self, notification: &Notification, now: DateTime<Utc>) -> Delivery {
+        let event = notification.event;
+        if !self.config.enabled {
+            return Delivery::default();
+        }
+        let quiet = self.is_quiet(now);
+        let allowed = self
+            .config
+            .quiet_hours
+            .as_ref()
+            .is_some_and(|q| q.allow.contains(&event));
+        if quiet && !allowed {
+            debug!(event = event.as_str(), "Notification suppressed by quiet hours");
+            record(&self.profile, event, "quiet");
+            return Delivery::default();
+        }
+
+        let mut delivery = Delivery::default();
+        match self.backend.show(notification).await {
+            Ok(()) => {
+                delivery.shown = true;
+                record(&self.profile, event, "shown");
+            }
+            Err(e) => {
+                warn!(backend = self.backend.name(), event = event.as_str(), error = %e, "Failed to show notification");
+                record(&self.profile, event, "failed");
+            }
+        }
+        if let Some(sound) = self.config.sounds.get(&event).filter(|_| !quiet) {
+            match self.backend.play(sound).await {
+                Ok(()) => delivery.sound = true,
+                Err(e) => warn!(event = event.as_str(), error = %e, "Failed to play notification sound"),
+            }
+        }
+        delivery
+    }
+
+    /// Уведомления о новых выплатах: сумма, банк и реквизиты каждой или одно
+    /// общее, если выплат больше `MAX_PAYOUT_NOTIFICATIONS`.
+    pub fn payout_notifications(&self, transactions: &[Transaction]) -> Vec<Notification> {
+        let click = |transaction_id: Option<String>| {
+            Some(NotificationClick {
+                profile: self.profile.clone(),
+                transaction_id,
+            })
+        };
+        let prefix = if self.profile.is_empty() {
+            String::new()
+        } else {
+            format!("{}: ", self.profile)
+        };
+        if transactions.len() > MAX_PAYOUT_NOTIFICATIONS {
+            let total = transactions
+                .iter()
+                .filter_map(|tx| tx.trader_amount(RUB))
+                .map(|m| m.amount)
+                .sum::<rust_decimal::Decimal>();
+            return vec![Notification {
+                event: NotifyEvent::NewPayout,
+                title: format!("{}новые выплаты: {}", prefix, transactions.len()),
+                body: format!("Всего {} {}", total, RUB),
+                click: click(None),
+            }];
+        }
+        transactions
+            .iter()
+            .map(|tx| {
+                let mut body = String::new();
+                if let Some(amount) = tx.trader_amount(RUB) {
+                    let _ = writeln!(body, "{}", amount);
+                }
+                if let Some(bank) = tx.bank_label.as_ref().or(tx.bank_name.as_ref()) {
+                    let _ = writeln!(body, "{}", bank);
+                }
+                if let Some(wallet) = &tx.wallet {
+                    let _ = writeln!(body, "{}", wallet);
+                }
+                Notification {
+                    event: NotifyEvent::NewPayout,
+                    title: format!("{}новая выплата {}", prefix, tx.transaction_id),
+                    body: body.trim_end().to_string(),
+                    click: click(Some(tx.transaction_id.clone())),
+                }
+            })
+            .collect()
+    }
+
+    /// Уведомляет о выплатах, найденных опросом, если это включено.
+    pub async fn new_payouts(&self, transactions: &[Transaction], now: DateTime<Utc>) {
+        if !self.config.enabled || !self.config.new_payouts {
+            return;
+        }
+        for notification in self.payout_notifications(transactions) {
+            self.notify(&notification, now).await;
+        }
+    }
+}
+
+fn record(profile: &str, event: NotifyEvent, result: &str) {
+    metrics()
+        .notifications
+        .with_label_values(&[profile, event.as_str(), result])
+        .inc();
+}
+
+/// Канал `desktop` оповещений через уведомления профиля.
+pub struct NotifierSink {
+    notifier: Notifier,
+}
+
+impl NotifierSink {
+    pub fn new(notifier: Notifier) -> Self {
+        Self { notifier }
+    }
+}
+
+impl AlertSink for NotifierSink {
+    fn deliver<'a>(&'a self, alert: &'a Alert) -> SinkFuture<'a> {
+        Box::pin(async move {
+            let event = match alert.kind {
+                AlertKind::Expiring { .. } => NotifyEvent::Expiring,
+                AlertKind::Missed => NotifyEvent::Missed,
+                AlertKind::SessionExpiring { .. } => NotifyEvent::Session,
+            };
+            let notification = Notification {
+                event,
+                title: alert.title.clone(),
+                body: alert.message.clone(),
+                click: Some(NotificationClick {
+                    profile: self.notifier.profile.clone(),
+                    transaction_id: Some(alert.transaction_id.clone()).filter(|id| !id.is_empty()),
+                }),
+            };
+            // Пропуск в часы «не беспокоить» ошибкой доставки не считается.
+            self.notifier.notify(&notification, Utc::now()).await;
+            Ok(())
+        })
+    }
+}
+
+/// Уведомление через системную утилиту; нажатия не отслеживаются.
+pub struct CommandBackend;
+
+impl NotifyBackend for CommandBackend {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn show<'a>(&'a self, notification: &'a Notification) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            let (title, body) = (&notification.title, &notification.body);
+            let mut command = if cfg!(target_os = "macos") {
+                let script = format!("display notification {:?} with title {:?}", body, title);
+                let mut c = Command::new("osascript");
+                c.arg("-e").arg(script);
+                c
+            } else if cfg!(windows) {
+                let mut c = Command::new("msg");
+                c.arg("*").arg(format!("{}\n{}", title, body));
+                c
+            } else {
+                let mut c = Command::new("notify-send");
+                c.args(["-u", urgency_name(notification.event), "-a", APP_NAME])
+                    .arg(title)
+                    .arg(body);
+                c
+            };
+            run(&mut command).await
+        })
+    }
+}
+
+fn urgency_name(event: NotifyEvent) -> &'static str {
+    match urgency(event) {
+        2 => "critical",
+        _ => "normal",
+    }
+}
+
+/// Срочность по спецификации: 1 — обычная, 2 — критическая (не скрывается сама).
+fn urgency(event: NotifyEvent) -> u8 {
+    match event {
+        NotifyEvent::NewPayout | NotifyEvent::Session => 1,
+        NotifyEvent::Expiring | NotifyEvent::Missed => 2,
+    }
+}
+
+/// Уведомления по спецификации freedesktop через `gdbus`. Нажатия ловит
+/// `gdbus monitor`, запущенный при первом уведомлении.
+pub struct DbusBackend {
+    clicks: Option<UnboundedSender<NotificationClick>>,
+    /// Показанные уведомления с действием: id сервиса → куда ведёт нажатие.
+    pending: Arc<Mutex<HashMap<u32, NotificationClick>>>,
+    monitor: OnceLock<()>,
+}
+
+const DBUS_DEST: &str = "org.freedesktop.Notifications";
+const DBUS_PATH: &str = "/org/freedesktop/Notifications";
+/// Действие, которое сервис вызывает по нажатию на само уведомление.
+const DEFAULT_ACTION: &str = "default";
+
+impl DbusBackend {
+    pub fn new(clicks: Option<UnboundedSender<NotificationClick>>) -> Self {
+        Self {
+            clicks,
+            pending: Arc::default(),
+            monitor: OnceLock::new(),
+        }
+    }
+
+    /// Следит за сигналами сервиса уведомлений, пока жив процесс.
+    fn start_monitor(&self, clicks: UnboundedSender<NotificationClick>) {
+        let pending = self.pending.clone();
+        tokio::spawn(async move {
+            let child = Command::new("gdbus")
+                .args(["monitor", "--session", "--dest", DBUS_DEST, "--object-path", DBUS_PATH])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .spawn();
+            let mut child = match child {
+                Ok(child) => child,
+                Err(e) => {
+                    warn!(error = %e, "Failed to watch notification clicks");
+                    return;
+                }
+            };
+            let Some(stdout) = child.stdout.take() else {
+                return;
+            };
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                match parse_signal(&line) {
+                    Some(DbusSignal::ActionInvoked { id, action }) if action == DEFAULT_ACTION => {
+                        let click = pending.lock().unwrap().remove(&id);
+                        if let Some(click) = click {
+                            if clicks.send(click).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Some(DbusSignal::Closed { id }) => {
+                        pending.lock().unwrap().remove(&id);
+                    }
+                    _ => {}
+                }
+            }
+            debug!("Notification click monitor stopped");
+        });
+    }
+}
+
+impl NotifyBackend for DbusBackend {
+    fn name(&self) -> &'static str {
+        "dbus"
+    }
+
+    fn show<'a>(&'a self, notification: &'a Notification) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            let clickable = self.clicks.is_some() && notification.click.is_some();
+            if let (true, Some(clicks)) = (clickable, &self.clicks) {
+                self.monitor.get_or_init(|| self.start_monitor(clicks.clone()));
+            }
+            let output = Command::new("gdbus")
+                .args(notify_args(notification, clickable))
+                .stdin(Stdio::null())
+                .output()
+                .await
+                .map_err(|e| format!("failed to run gdbus: {}", e))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "gdbus exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            let id = parse_notify_reply(&String::from_utf8_lossy(&output.stdout))
+                .ok_or_else(|| "unexpected reply from the notification service".to_string())?;
+            if let (true, Some(click)) = (clickable, &notification.click) {
+                self.pending.lock().unwrap().insert(id, click.clone());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Аргументы `gdbus call` для метода `Notify`: строки — в текстовом формате
+/// GVariant, тело — с экранированием разметки, которую поддерживает сервис.
+pub fn notify_args(notification: &Notification, clickable: bool) -> Vec<String> {
+    let actions = if clickable {
+        format!("[{}, {}]", gvariant_string(DEFAULT_ACTION), gvariant_string("Открыть"))
+    } else {
+        "@as []".to_string()
+    };
+    let hints = format!(
+        "{{'urgency': <byte {}>, 'category': <{}>}}",
+        urgency(notification.event),
+        gvariant_string("im.received")
+    );
+    [
+        "call",
+        "--session",
+        "--dest",
+        DBUS_DEST,
+        "--object-path",
+        DBUS_PATH,
+        "--method",
+        "org.freedesktop.Notifications.Notify",
+    ]
+    .into_iter()
+    .map(String::from)
+    .chain([
+        gvariant_string(APP_NAME),
+        "uint32 0".to_string(),
+        gvariant_string(""),
+        gvariant_string(&notification.title),
+        gvariant_string(&escape_markup(&notification.body)),
+        actions,
+        hints,
+        // Срок показа решает сервис.
+        "int32 -1".to_string(),
+    ])
+    .collect()
+}
+
+fn gvariant_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('\'');
+    for c in value.chars() {
+        match c {
+            '\\' | '\'' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Номер уведомления из ответа `gdbus call`: `(uint32 42,)`.
+pub fn parse_notify_reply(reply: &str) -> Option<u32> {
+    reply
+        .trim()
+        .strip_prefix("(uint32 ")?
+        .strip_suffix(",)")?
+        .parse()
+        .ok()
+}
+
+/// Сигнал сервиса уведомлений из вывода `gdbus monitor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbusSignal {
+    ActionInvoked { id: u32, action: String },
+    Closed { id: u32 },
+}
+
+/// Разбирает строку `gdbus monitor`, например
+/// `/org/freedesktop/Notifications: org.freedesktop.Notifications.ActionInvoked (uint32 7, 'default')`.
+pub fn parse_signal(line: &str) -> Option<DbusSignal> {
+    let (_, rest) = line.split_once("org.freedesktop.Notifications.")?;
+    let (name, args) = rest.split_once(' ')?;
+    let args = args.trim().strip_prefix('(')?.strip_suffix(')')?;
+    let mut parts = args.splitn(2, ", ");
+    let id = parts.next()?.strip_prefix("uint32 ")?.parse().ok()?;
+    match name {
+        "ActionInvoked" => {
+            let action = parts.next()?.trim().trim_matches('\'').to_string();
+            Some(DbusSignal::ActionInvoked { id, action })
+        }
+        "NotificationClosed" => Some(DbusSignal::Closed { id }),
+        _ => None,
+    }
+}
+
+/// Проигрывает файл или подаёт системный сигнал (`BEEP`).
+pub async fn play_sound(sound: &str) -> Result<(), String> {
+    if sound == BEEP {
+        print!("\x07");
+        let _ = std::io::stdout().flush();
+        return Ok(());
+    }
+    let mut command = if cfg!(target_os = "macos") {
+        let mut c = Command::new("afplay");
+        c.arg(sound);
+        c
+    } else if cfg!(windows) {
+        let mut c = Command::new("powershell");
+        c.arg("-NoProfile").arg("-Command").arg(format!(
+            "(New-Object Media.SoundPlayer '{}').PlaySync()",
+            sound.replace('\'', "''")
+        ));
+        c
+    } else {
+        let mut c = Command::new("paplay");
+        c.arg(sound);
+        c
+    };
+    run(&mut command).await
+}
+
+pub(crate) async fn run(command: &mut Command) -> Result<(), String> {
+    let status = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("command exited with {}", status))
+    }
+}
@@ -0,0 +1,273 @@
+//! Действия трейдера на панели от имени пользователя: взять выплату, отметить
+//! оплаченной с чеком, отменить, получить подробности и баланс.
+//!
+//! Запросы идут с куками сессии из `ProxyState`, а значение куки `XSRF-TOKEN`
+//! передаётся в заголовке `X-XSRF-TOKEN`, как это делает фронтенд Laravel.
+//! Куки из ответов сохраняются обратно в `ProxyState`. Пути действий повторяют
+//! запросы фронтенда панели и собраны в константах ниже.
+
+use std::fmt;
+
+use reqwest::{
+    header::{ACCEPT, COOKIE, USER_AGENT},
+    multipart::{Form, Part},
+    Client, RequestBuilder, StatusCode,
+};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::{
+    idex::{map_transaction, Transaction, PAYOUTS_PATH},
+    money::{parse_decimal, CurrencyAmounts},
+    proxy::ProxyState,
+};
+
+/// Кука, в которой Laravel отдаёт CSRF-токен.
+pub const XSRF_COOKIE: &str = "XSRF-TOKEN";
+/// Заголовок, в котором панель ждёт CSRF-токен.
+pub const XSRF_HEADER: &str = "X-XSRF-TOKEN";
+
+/// Баланс трейдера по валютам.
+pub const BALANCE_PATH: &str = "/api/v1/trader/balance";
+/// Действия над выплатой: `{PAYOUTS_PATH}/{id}/{action}`.
+const CLAIM_ACTION: &str = "take";
+const MARK_PAID_ACTION: &str = "approve";
+const CANCEL_ACTION: &str = "cancel";
+/// Поле формы, в котором фронтенд отправляет чек.
+const RECEIPT_FIELD: &str = "attachments[]";
+
+pub const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/119.0.0.0 Safari/537.36";
+/// Laravel отвечает этим статусом, когда CSRF-токен устарел.
+const CSRF_MISMATCH: u16 = 419;
+
+#[derive(Debug)]
+pub enum PanelError {
+    /// Сессия истекла или куки не подходят.
+    Unauthorized,
+    NotFound,
+    /// Панель не приняла CSRF-токен даже после его обновления.
+    CsrfMismatch,
+    /// Панель отказала в действии (например, выплату уже забрали).
+    Rejected { status: u16, message: String },
+    Http(reqwest::Error),
+    InvalidResponse(String),
+}
+
+impl fmt::Display for PanelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PanelError::Unauthorized => write!(f, "panel session is not authorized"),
+            PanelError::NotFound => write!(f, "payout not found"),
+            PanelError::CsrfMismatch => write!(f, "panel rejected the CSRF token"),
+            PanelError::Rejected { status, message } => {
+                write!(f, "panel rejected the request ({}): {}", status, message)
+            }
+            PanelError::Http(e) => write!(f, "request to panel failed: {}", e),
+            PanelError::InvalidResponse(msg) => write!(f, "invalid panel response: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PanelError {}
+
+impl From<reqwest::Error> for PanelError {
+    fn from(e: reqwest::Error) -> Self {
+        PanelError::Http(e)
+    }
+}
+
+/// Декодирует `%XX` в значении куки.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Клиент действий трейдера на панели.
+#[derive(Clone)]
+pub struct PanelClient {
+    client: Client,
+    state: ProxyState,
+}
+
+impl PanelClient {
+    pub fn new(state: ProxyState) -> Self {
+        Self {
+            client: Client::new(),
+            state,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.state.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Текущий CSRF-токен из куки `XSRF-TOKEN`.
+    pub fn xsrf_token(&self) -> Option<String> {
+        let store = self.state.cookies.lock().unwrap();
+        store
+            .cookies
+            .iter()
+            .find(|c| c.name == XSRF_COOKIE)
+            .map(|c| percent_decode(&c.value))
+    }
+
+    /// Получает свежую куку `XSRF-TOKEN`: панель выставляет её на любой ответ.
+    async fn refresh_xsrf(&self) -> Result<(), PanelError> {
+        let response = self
+            .client
+            .get(self.url("/"))
+            .header(COOKIE, self.state.cookie_header())
+            .header(USER_AGENT, BROWSER_USER_AGENT)
+            .send()
+            .await?;
+        self.state
+            .update_from_headers(response.headers(), &self.state.upstream_host());
+        Ok(())
+    }
+
+    /// Отправляет запрос с куками и CSRF-токеном. При ответе 419 токен обновляется
+    /// и запрос повторяется один раз.
+    async fn send(&self, build: impl Fn(&Client) -> RequestBuilder) -> Result<Value, PanelError> {
+        if self.xsrf_token().is_none() {
+            self.refresh_xsrf().await?;
+        }
+        for attempt in 0..2 {
+            let mut request = build(&self.client)
+                .header(COOKIE, self.state.cookie_header())
+                .header(USER_AGENT, BROWSER_USER_AGENT)
+                .header(ACCEPT, "application/json")
+                .header("X-Requested-With", "XMLHttpRequest");
+            if let Some(token) = self.xsrf_token() {
+                request = request.header(XSRF_HEADER, token);
+            }
+            let response = request.send().await?;
+            self.state
+                .update_from_headers(response.headers(), &self.state.upstream_host());
+            let status = response.status();
+            if status.as_u16() == CSRF_MISMATCH {
+                debug!(attempt, "Panel rejected CSRF token, refreshing");
+                if attempt == 0 {
+                    self.refresh_xsrf().await?;
+                }
+                continue;
+            }
+            match status {
+                StatusCode::UNAUTHORIZED => return Err(PanelError::Unauthorized),
+                StatusCode::NOT_FOUND => return Err(PanelError::NotFound),
+                _ => {}
+            }
+            let text = response.text().await?;
+            let json: Option<Value> = serde_json::from_str(&text).ok();
+            let message = || {
+                json.as_ref()
+                    .and_then(|j| j.get("message"))
+                    .and_then(|m| m.as_str())
+                    .unwrap_or(status.canonical_reason().unwrap_or(""))
+                    .to_string()
+            };
+            let failed = json
+                .as_ref()
+                .and_then(|j| j.get("success"))
+                .and_then(|s| s.as_bool())
+                == Some(false);
+            if !status.is_success() || failed {
+                return Err(PanelError::Rejected {
+                    status: status.as_u16(),
+                    message: message(),
+                });
+            }
+            return json.ok_or_else(|| PanelError::InvalidResponse("response is not JSON".to_string()));
+        }
+        Err(PanelError::CsrfMismatch)
+    }
+
+    fn action_url(&self, payout_id: &str, action: &str) -> String {
+        self.url(&format!("{}/{}/{}", PAYOUTS_PATH, payout_id, action))
+    }
+
+    /// Берёт выплату в работу.
+    pub async fn claim(&self, payout_id: &str) -> Result<Transaction, PanelError> {
+        let url = self.action_url(payout_id, CLAIM_ACTION);
+        payout(self.send(|client| client.post(url.as_str())).await?)
+    }
+
+    /// Отмечает выплату оплаченной и прикладывает чек.
+    pub async fn mark_paid(
+        &self,
+        payout_id: &str,
+        file_name: &str,
+        receipt: Vec<u8>,
+    ) -> Result<Transaction, PanelError> {
+        let url = self.action_url(payout_id, MARK_PAID_ACTION);
+        let mime = if file_name.to_ascii_lowercase().ends_with(".pdf") {
+            "application/pdf"
+        } else {
+            "application/octet-stream"
+        };
+        // Форма не клонируется, поэтому собирается заново на каждую попытку.
+        let form = || {
+            let part = Part::bytes(receipt.clone())
+                .file_name(file_name.to_string())
+                .mime_str(mime)
+                .expect("static MIME type is valid");
+            Form::new().part(RECEIPT_FIELD, part)
+        };
+        payout(
+            self.send(|client| client.post(url.as_str()).multipart(form()))
+                .await?,
+        )
+    }
+
+    /// Отказывается от выплаты и возвращает её в общий список.
+    pub async fn cancel(&self, payout_id: &str) -> Result<Transaction, PanelError> {
+        let url = self.action_url(payout_id, CANCEL_ACTION);
+        payout(self.send(|client| client.post(url.as_str())).await?)
+    }
+
+    /// Актуальное состояние выплаты.
+    pub async fn details(&self, payout_id: &str) -> Result<Transaction, PanelError> {
+        let url = self.url(&format!("{}/{}", PAYOUTS_PATH, payout_id));
+        payout(self.send(|client| client.get(url.as_str())).await?)
+    }
+
+    /// Баланс трейдера: код валюты → сумма.
+    pub async fn balance(&self) -> Result<CurrencyAmounts, PanelError> {
+        let url = self.url(BALANCE_PATH);
+        let json = self.send(|client| client.get(url.as_str())).await?;
+        let balance = json
+            .get("response")
+            .and_then(|r| r.get("balance"))
+            .and_then(|b| b.as_object())
+            .ok_or_else(|| PanelError::InvalidResponse("no balance in response".to_string()))?;
+        Ok(balance
+            .iter()
+            .filter_map(|(code, value)| parse_decimal(value).map(|d| (code.clone(), d)))
+            .collect())
+    }
+}
+
+/// Выплата из ответа на действие: `{"response": {"payout": {...}}}`.
+fn payout(json: Value) -> Result<Transaction, PanelError> {
+    json.get("response")
+        .and_then(|r| r.get("payout"))
+        .and_then(map_transaction)
+        .ok_or_else(|| PanelError::InvalidResponse("no payout in response".to_string()))
+}
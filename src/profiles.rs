@@ -0,0 +1,292 @@
+//! Профили трейдеров: несколько аккаунтов панели на одной машине.
+//!
+//! Каждый профиль из секции `profiles` config.json получает свой каталог
+//! `profiles/<name>/` с cookies.json, историей и прочими файлами, свой прокси
+//! на отдельном порту, свои фоновые задачи и своё окно IDEX с отдельным
+//! хранилищем webview. Прокси различает профили по порту: фронтенд панели
+//! обращается к API по абсолютным путям, поэтому префикс пути он бы потерял.
+//! Без секции `profiles` работает один профиль в самом каталоге данных, как раньше.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    alerts::run_alerts_until,
+    attachments::run_attachments_until,
+    config::AppConfig,
+    idex::run_idex_until,
+    notify::Notifier,
+    proxy::{bind_proxy, serve_proxy, BindError, ProxyState},
+    recorder::TrafficRecorder,
+    session::run_session_until,
+    source::{source_by_name, GATE_SOURCE},
+    supervisor::{Supervisor, SHUTDOWN_TIMEOUT},
+};
+
+/// Панель, с которой работают профили по умолчанию.
+pub const DEFAULT_BASE_URL: &str = "https://panel.gate.cx/";
+/// Порт прокси первого профиля; следующие профили получают порты по порядку.
+/// Занятый порт по умолчанию заменяется свободным (см. `proxy::bind_proxy`).
+pub const DEFAULT_PROXY_PORT: u16 = 8080;
+/// Имя профиля, когда секция `profiles` не задана.
+pub const DEFAULT_PROFILE: &str = "default";
+
+const PROFILES_DIR: &str = "profiles";
+const WEBVIEW_DIR: &str = "webview";
+
+/// Профиль в config.json.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileConfig {
+    /// Имя профиля; оно же имя каталога, поэтому только латиница, цифры, `-` и `_`.
+    pub name: String,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Платформа профиля (`gate`); по умолчанию gate.cx.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Порт прокси; по умолчанию `8080 + номер профиля`, `0` — любой свободный.
+    /// Заданный порт не подменяется: если он занят, профиль работает без прокси.
+    #[serde(default)]
+    pub proxy_port: Option<u16>,
+}
+
+/// Готовый к запуску профиль.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub base_url: String,
+    /// Имя источника выплат, см. `source_by_name`.
+    pub source: String,
+    /// Предпочтительный адрес прокси; фактический — в `ProxyState::proxy_addr`.
+    pub proxy_addr: SocketAddr,
+    /// Порт задан в config.json и не подменяется свободным.
+    pub fixed_port: bool,
+    pub data_dir: PathBuf,
+    /// Каталог данных webview; `None` — общий каталог по умолчанию.
+    pub webview_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProfileError {
+    InvalidName(String),
+    DuplicateName(String),
+    DuplicatePort(u16),
+    UnknownSource(String),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::InvalidName(name) => write!(
+                f,
+                "invalid profile name {:?}: use latin letters, digits, '-' and '_'",
+                name
+            ),
+            ProfileError::DuplicateName(name) => write!(f, "duplicate profile name {:?}", name),
+            ProfileError::DuplicatePort(port) => {
+                write!(f, "proxy port {} is used by several profiles", port)
+            }
+            ProfileError::UnknownSource(source) => write!(f, "unknown payout source {:?}", source),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn local_addr(port: u16) -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, port))
+}
+
+/// Профили из настроек. Без секции `profiles` — один профиль `default` в `data_dir`.
+pub fn resolve_profiles(config: &AppConfig, data_dir: &Path) -> Result<Vec<Profile>, ProfileError> {
+    if config.profiles.is_empty() {
+        return Ok(vec![Profile {
+            name: DEFAULT_PROFILE.to_string(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            source: GATE_SOURCE.to_string(),
+            proxy_addr: local_addr(DEFAULT_PROXY_PORT),
+            fixed_port: false,
+            data_dir: data_dir.to_path_buf(),
+            webview_dir: None,
+        }]);
+    }
+    let mut names = HashSet::new();
+    let mut ports = HashSet::new();
+    let mut profiles = Vec::new();
+    for (index, profile) in config.profiles.iter().enumerate() {
+        if !valid_name(&profile.name) {
+            return Err(ProfileError::InvalidName(profile.name.clone()));
+        }
+        if !names.insert(profile.name.clone()) {
+            return Err(ProfileError::DuplicateName(profile.name.clone()));
+        }
+        let port = profile
+            .proxy_port
+            .unwrap_or_else(|| DEFAULT_PROXY_PORT.saturating_add(index as u16));
+        if port != 0 && !ports.insert(port) {
+            return Err(ProfileError::DuplicatePort(port));
+        }
+        let source = profile.source.clone().unwrap_or_else(|| GATE_SOURCE.to_string());
+        if source_by_name(&source).is_none() {
+            return Err(ProfileError::UnknownSource(source));
+        }
+        let dir = data_dir.join(PROFILES_DIR).join(&profile.name);
+        profiles.push(Profile {
+            name: profile.name.clone(),
+            base_url: profile
+                .base_url
+                .clone()
+                .unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            source,
+            proxy_addr: local_addr(port),
+            fixed_port: profile.proxy_port.is_some_and(|p| p != 0),
+            webview_dir: Some(dir.join(WEBVIEW_DIR)),
+            data_dir: dir,
+        });
+    }
+    Ok(profiles)
+}
+
+/// Состояния прокси всех профилей по имени; уведомления профилей показывает
+/// общий `notifier`. Профиль, каталог которого не удалось создать,
+/// пропускается с ошибкой в логе.
+pub fn profile_states(
+    profiles: &[Profile],
+    config: &AppConfig,
+    notifier: &Notifier,
+) -> HashMap<String, ProxyState> {
+    let mut states = HashMap::new();
+    for (index, profile) in profiles.iter().enumerate() {
+        match profile.state(config) {
+            Ok(state) => {
+                // Реестр метрик общий: `/metrics` отдаёт только прокси первого профиля.
+                let state = state
+                    .with_notifier(notifier.for_profile(&profile.name))
+                    .with_metrics(index == 0);
+                states.insert(profile.name.clone(), state);
+            }
+            Err(e) => error!(profile = %profile.name, error = %e, "Failed to prepare profile directory"),
+        }
+    }
+    states
+}
+
+/// Останавливает задачи профилей и сохраняет их куки и историю.
+pub async fn stop_profiles(supervisor: &Supervisor, states: &HashMap<String, ProxyState>) {
+    if !supervisor.shutdown(SHUTDOWN_TIMEOUT).await {
+        warn!("Not all background tasks stopped in time");
+    }
+    for (profile, state) in states {
+        match state.flush() {
+            Ok(()) => info!(%profile, "Cookies and transaction history saved"),
+            Err(e) => error!(%profile, error = %e, "Failed to save data on shutdown"),
+        }
+    }
+}
+
+impl Profile {
+    /// Состояние прокси профиля: куки и история читаются из его каталога.
+    pub fn state(&self, config: &AppConfig) -> std::io::Result<ProxyState> {
+        std::fs::create_dir_all(&self.data_dir)?;
+        let mut state = ProxyState::new(self.base_url.clone(), self.data_dir.clone())
+            .with_recorder(TrafficRecorder::from_config(&config.recorder, &self.data_dir))
+            .with_config(config.clone())
+            .with_profile(&self.name);
+        // Имя проверено в `resolve_profiles`.
+        if let Some(source) = source_by_name(&self.source) {
+            state = state.with_source(source);
+        }
+        Ok(state)
+    }
+
+    /// Запускает фоновые задачи профиля под надзором `supervisor`: прокси,
+    /// опрос выплат, загрузку чеков, оповещения и поддержание сессии (последние
+    /// три — если включены). Порт прокси занимается сразу; если это не удалось,
+    /// остальные задачи всё равно запускаются, а ошибка возвращается.
+    pub fn spawn_tasks(
+        &self,
+        supervisor: &Supervisor,
+        proxy_state: &ProxyState,
+    ) -> Result<SocketAddr, BindError> {
+        let bound = bind_proxy(self.proxy_addr, self.fixed_port).and_then(|listener| {
+            let addr = listener.local_addr().map_err(|source| BindError {
+                addr: self.proxy_addr,
+                source,
+            })?;
+            Ok((listener, addr))
+        });
+        let bound = match bound {
+            Ok((listener, addr)) => {
+                // Адрес публикуется до запуска задачи: по нему строится окно IDEX.
+                *proxy_state.proxy_addr.lock().unwrap() = Some(addr);
+                let state = proxy_state.clone().with_tasks(supervisor.health_map());
+                supervisor.spawn(&self.task_name("proxy"), move |shutdown| {
+                    // Перезапуск после паники обслуживает тот же порт.
+                    let listener = listener.try_clone();
+                    let state = state.clone();
+                    async move {
+                        match listener {
+                            Ok(listener) => serve_proxy(state, listener, shutdown.cancelled_owned()).await,
+                            Err(e) => error!(error = %e, "Failed to reuse proxy listener"),
+                        }
+                    }
+                });
+                Ok(addr)
+            }
+            Err(e) => {
+                error!(profile = %self.name, error = %e, "Proxy is not running");
+                Err(e)
+            }
+        };
+
+        let state = proxy_state.clone();
+        supervisor.spawn(&self.task_name("idex"), move |shutdown| {
+            run_idex_until(state.clone(), shutdown)
+        });
+
+        if proxy_state.config.attachments.enabled {
+            let state = proxy_state.clone();
+            supervisor.spawn(&self.task_name("attachments"), move |shutdown| {
+                run_attachments_until(state.clone(), shutdown)
+            });
+        }
+
+        if proxy_state.config.alerts.enabled {
+            let state = proxy_state.clone();
+            supervisor.spawn(&self.task_name("alerts"), move |shutdown| {
+                run_alerts_until(state.clone(), shutdown)
+            });
+        }
+
+        if proxy_state.config.session.enabled {
+            let state = proxy_state.clone();
+            supervisor.spawn(&self.task_name("session"), move |shutdown| {
+                run_session_until(state.clone(), shutdown)
+            });
+        }
+        match &bound {
+            Ok(addr) => info!(profile = %self.name, proxy = %addr, "Profile started"),
+            Err(_) => warn!(profile = %self.name, "Profile started without proxy"),
+        }
+        bound
+    }
+
+    /// Имя фоновой задачи профиля для супервизора, например `idex:main`.
+    pub fn task_name(&self, task: &str) -> String {
+        format!("{}:{}", task, self.name)
+    }
+}
@@ -0,0 +1,574 @@
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    fmt, fs,
+    future::{self, Future},
+    io,
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hyper::{
+    header::{HeaderValue, SET_COOKIE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use reqwest::header::HeaderMap;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::api::{handle_api, is_api_path, publish_token};
+use crate::config::AppConfig;
+use crate::cookie_sync::{
+    apply_cookies, apply_document_cookies, merge_cookie_header, parse_set_cookie,
+    webview_set_cookie,
+};
+use crate::idex::{capture_payouts, load_transactions, save_transactions, PollStatus, Transaction};
+use crate::instance::new_secret;
+use crate::metrics::{metrics, METRICS_PATH};
+use crate::notify::Notifier;
+use crate::profiles::DEFAULT_PROFILE;
+use crate::recorder::{Exchange, TrafficRecorder};
+use crate::session::SessionHealth;
+use crate::source::{GateSource, PayoutSource};
+use crate::supervisor::HealthMap;
+
+// -----------------------------
+// Работа с куками для прокси
+// -----------------------------
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+pub struct CookieStore {
+    pub cookies: Vec<Cookie>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    pub expiration_date: Option<f64>,
+    pub host_only: Option<bool>,
+    pub http_only: Option<bool>,
+    pub same_site: Option<String>,
+    pub secure: Option<bool>,
+    pub session: Option<bool>,
+    pub store_id: Option<String>,
+}
+
+const COOKIES_FILE: &str = "cookies.json";
+
+pub fn save_cookies(data_dir: &Path, cookies: &CookieStore) -> std::io::Result<()> {
+    save_cookies_to(&data_dir.join(COOKIES_FILE), cookies)
+}
+
+pub fn load_cookies(data_dir: &Path) -> std::io::Result<CookieStore> {
+    load_cookies_from(&data_dir.join(COOKIES_FILE))
+}
+
+/// Сохраняет куки в произвольный файл (например, куки окна Telegram).
+pub fn save_cookies_to(path: &Path, cookies: &CookieStore) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(cookies)?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Хост из адреса панели; пустая строка, если адрес не разбирается.
+pub fn host_of(base_url: &str) -> String {
+    url::Url::parse(base_url)
+        .ok()
+        .and_then(|u| u.host_str().map(String::from))
+        .unwrap_or_default()
+}
+
+pub fn load_cookies_from(path: &Path) -> std::io::Result<CookieStore> {
+    if path.exists() {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    } else {
+        Ok(CookieStore::default())
+    }
+}
+
+// -----------------------------
+// Прокси-состояние
+// -----------------------------
+#[derive(Clone)]
+pub struct ProxyState {
+    pub cookies: Arc<Mutex<CookieStore>>,
+    /// Имена кук, которые окно IDEX уже показывало в `document.cookie`.
+    pub page_cookies: Arc<Mutex<HashSet<String>>>,
+    pub transactions: Arc<Mutex<Vec<Transaction>>>,
+    /// Когда пользователь последний раз загружал выплаты через прокси.
+    pub last_panel_activity: Arc<Mutex<Option<Instant>>>,
+    /// Итог последнего прохода `run_idex`.
+    pub poll_status: Arc<Mutex<PollStatus>>,
+    /// Срок и продление сессии панели, см. `session`.
+    pub session: Arc<Mutex<SessionHealth>>,
+    /// Адрес, на котором прокси действительно слушает; `None`, пока порт не занят.
+    pub proxy_addr: Arc<Mutex<Option<SocketAddr>>>,
+    /// Токен доступа к локальному API, см. `api`.
+    pub api_token: Arc<str>,
+    /// Состояние фоновых задач супервизора для `GET /__p2p/health`.
+    pub tasks: HealthMap,
+    /// Всплывающие уведомления профиля; по умолчанию отключены.
+    pub notifier: Notifier,
+    pub recorder: Option<Arc<TrafficRecorder>>,
+    pub config: Arc<AppConfig>,
+    /// Платформа, с которой работает аккаунт.
+    pub source: Arc<dyn PayoutSource>,
+    pub base_url: String,
+    /// Каталог, в котором лежат cookies.json, idex_history.json и прочие файлы.
+    pub data_dir: PathBuf,
+    /// Имя профиля: метка `profile` его метрик.
+    pub profile: Arc<str>,
+    /// Отдаёт ли этот прокси `/metrics`; реестр общий, поэтому только один.
+    pub serve_metrics: bool,
+}
+
+impl ProxyState {
+    pub fn new(base_url: String, data_dir: PathBuf) -> Self {
+        let store = load_cookies(&data_dir).unwrap_or_default();
+        Self {
+            cookies: Arc::new(Mutex::new(store)),
+            page_cookies: Arc::new(Mutex::new(HashSet::new())),
+            transactions: Arc::new(Mutex::new(load_transactions(&data_dir))),
+            last_panel_activity: Arc::new(Mutex::new(None)),
+            poll_status: Arc::new(Mutex::new(PollStatus::default())),
+            session: Arc::new(Mutex::new(SessionHealth::default())),
+            proxy_addr: Arc::new(Mutex::new(None)),
+            // Пустой токен закрывает API целиком.
+            api_token: new_secret()
+                .unwrap_or_else(|e| {
+                    error!(error = %e, "Failed to generate API token");
+                    String::new()
+                })
+                .into(),
+            tasks: HealthMap::default(),
+            notifier: Notifier::disabled(),
+            recorder: None,
+            config: Arc::new(AppConfig::default()),
+            source: Arc::new(GateSource::default()),
+            base_url,
+            data_dir,
+            profile: DEFAULT_PROFILE.into(),
+            serve_metrics: true,
+        }
+    }
+
+    /// Хост панели, на который проксируются запросы и к которому относятся куки.
+    pub fn upstream_host(&self) -> String {
+        host_of(&self.base_url)
+    }
+
+    pub fn with_recorder(mut self, recorder: Option<TrafficRecorder>) -> Self {
+        self.recorder = recorder.map(Arc::new);
+        self
+    }
+
+    /// Адрес, который открывает окно IDEX; `None`, если прокси не запущен.
+    pub fn webview_url(&self) -> Option<String> {
+        self.proxy_addr
+            .lock()
+            .unwrap()
+            .map(|addr| format!("http://{}/{}", addr, self.base_url))
+    }
+
+    pub fn with_tasks(mut self, tasks: HealthMap) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    pub fn with_config(mut self, config: AppConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    pub fn with_source(mut self, source: Arc<dyn PayoutSource>) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: &str) -> Self {
+        self.profile = profile.into();
+        self
+    }
+
+    pub fn with_metrics(mut self, serve_metrics: bool) -> Self {
+        self.serve_metrics = serve_metrics;
+        self
+    }
+
+    /// Значение заголовка `Cookie` для запросов к панели от имени пользователя.
+    pub fn cookie_header(&self) -> String {
+        let store = self.cookies.lock().unwrap();
+        store
+            .cookies
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Сбрасывает куки и историю транзакций на диск.
+    pub fn flush(&self) -> io::Result<()> {
+        save_cookies(&self.data_dir, &self.cookies.lock().unwrap())?;
+        save_transactions(&self.data_dir, &self.transactions.lock().unwrap())
+    }
+
+    pub fn mark_panel_activity(&self) {
+        *self.last_panel_activity.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn panel_active_within(&self, window: Duration) -> bool {
+        self.last_panel_activity
+            .lock()
+            .unwrap()
+            .is_some_and(|t| t.elapsed() < window)
+    }
+
+    /// Применяет `Set-Cookie` из ответа панели: новые куки сохраняются,
+    /// удалённые сервером (например, при выходе) убираются из хранилища.
+    pub fn update_from_headers(&self, headers: &HeaderMap<HeaderValue>, domain: &str) {
+        let now = chrono::Utc::now();
+        let new_cookies: Vec<Cookie> = headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .filter_map(|header| parse_set_cookie(header, domain, now))
+            .collect();
+
+        if new_cookies.is_empty() {
+            trace!("No new cookies found, keeping existing ones.");
+            return;
+        }
+        metrics()
+            .cookie_updates
+            .with_label_values(&[&self.profile])
+            .inc_by(new_cookies.len() as u64);
+        let mut store = self.cookies.lock().unwrap();
+        if apply_cookies(&mut store, new_cookies, now) {
+            self.save_cookie_store(&store);
+        }
+    }
+
+    /// Добавляет куки сессии, полученные извне (импорт, локальный API), заменяя
+    /// одноимённые. Истёкшие куки удаляются из хранилища.
+    pub fn merge_cookies(&self, cookies: Vec<Cookie>) -> bool {
+        let mut store = self.cookies.lock().unwrap();
+        let changed = apply_cookies(&mut store, cookies, chrono::Utc::now());
+        if changed {
+            self.save_cookie_store(&store);
+        }
+        changed
+    }
+
+    /// Переносит в хранилище `document.cookie`, присланный окном IDEX.
+    pub fn apply_document_cookies(&self, document_cookie: &str) {
+        let mut store = self.cookies.lock().unwrap();
+        let mut seen = self.page_cookies.lock().unwrap();
+        if apply_document_cookies(&mut store, &mut seen, document_cookie, &self.upstream_host()) {
+            self.save_cookie_store(&store);
+        }
+    }
+
+    fn save_cookie_store(&self, store: &CookieStore) {
+        if let Err(e) = save_cookies(&self.data_dir, store) {
+            warn!(error = %e, "Failed to save cookies");
+        } else {
+            // Значения кук не логируем никогда, только имена.
+            let names: Vec<&str> = store.cookies.iter().map(|c| c.name.as_str()).collect();
+            debug!(cookies = ?names, "Cookies saved");
+        }
+    }
+}
+
+// -----------------------------
+// Прокси-обработчик
+// -----------------------------
+pub async fn proxy_handler(
+    req: Request<Body>,
+    state: ProxyState,
+) -> Result<Response<Body>, Infallible> {
+    if req.method() == hyper::Method::GET && req.uri().path() == METRICS_PATH {
+        if !state.serve_metrics {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Metrics are served by the proxy of the first profile"))
+                .unwrap());
+        }
+        return Ok(Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics().encode()))
+            .unwrap());
+    }
+
+    if is_api_path(req.uri().path()) {
+        return Ok(handle_api(req, &state).await);
+    }
+
+    let method = req.method().clone();
+    let req_headers = req.headers().clone();
+    // Строка запроса нужна и для пагинации, и для фильтров панели.
+    let req_path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let req_path = req_path_and_query.trim_start_matches('/');
+
+    let target_url = if req.uri().path().contains("://") {
+        match url::Url::parse(req_path) {
+            Ok(url) => url,
+            Err(e) => {
+                return Ok(
+                    Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from(format!("Invalid URL: {}", e)))
+                        .unwrap(),
+                );
+            }
+        }
+    } else {
+        let base = url::Url::parse(&state.base_url).expect("Invalid base URL");
+        match base.join(req_path_and_query) {
+            Ok(url) => url,
+            Err(e) => {
+                return Ok(
+                    Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from(format!("URL join error: {}", e)))
+                        .unwrap(),
+                );
+            }
+        }
+    };
+
+    debug!(%method, url = %target_url, "Proxying request");
+
+    let upstream_host = state.upstream_host();
+    let is_upstream = target_url.host_str() == Some(upstream_host.as_str());
+
+    // Ответы API выплат разбираем сами, поэтому просим их без сжатия.
+    let capture_payouts_response = method == hyper::Method::GET
+        && is_upstream
+        && state.source.is_payouts_path(target_url.path());
+
+    let whole_body = hyper::body::to_bytes(req.into_body()).await.unwrap_or_default();
+    let client = reqwest::Client::new();
+    let mut request_builder = client.request(method.clone(), target_url.clone());
+    for (key, value) in req_headers.iter() {
+        // Куки собираются ниже: браузер видит только часть из них.
+        if key == hyper::header::COOKIE {
+            continue;
+        }
+        if key == hyper::header::HOST {
+            request_builder = request_builder.header(key, upstream_host.as_str());
+        } else if capture_payouts_response && key == hyper::header::ACCEPT_ENCODING {
+            continue;
+        } else if let Ok(val_str) = value.to_str() {
+            request_builder = request_builder.header(key, val_str);
+        }
+    }
+    // Куки уходят только панели: сторонние хосты не получают ни куки хранилища,
+    // ни куки браузера, которые тоже принадлежат панели.
+    if is_upstream {
+        let browser_cookies = req_headers
+            .get(hyper::header::COOKIE)
+            .and_then(|v| v.to_str().ok());
+        let store = state.cookies.lock().unwrap();
+        let cookie_str = merge_cookie_header(&store, browser_cookies, chrono::Utc::now());
+        if !cookie_str.is_empty() {
+            request_builder = request_builder.header(hyper::header::COOKIE, cookie_str);
+        }
+    }
+    if !whole_body.is_empty() {
+        request_builder = request_builder.body(whole_body.clone());
+    }
+    let started_at = chrono::Utc::now();
+    let started = Instant::now();
+    let response = match request_builder.send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            metrics()
+                .proxy_requests
+                .with_label_values(&[&state.profile, "error"])
+                .inc();
+            return Ok(
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("Request error: {}", err)))
+                    .unwrap(),
+            );
+        }
+    };
+    let status = response.status();
+    let headers = response.headers().clone();
+    let wait = started.elapsed();
+    metrics()
+        .proxy_requests
+        .with_label_values(&[&state.profile, status.as_str()])
+        .inc();
+    metrics()
+        .upstream_latency
+        .with_label_values(&[&state.profile])
+        .observe(wait.as_secs_f64());
+    let body_bytes = match response.bytes().await {
+        Ok(b) => b,
+        Err(err) => {
+            return Ok(
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from(format!("Body error: {}", err)))
+                    .unwrap(),
+            );
+        }
+    };
+
+    if let Some(recorder) = &state.recorder {
+        recorder.record(&Exchange {
+            started_at,
+            method: &method,
+            url: &target_url,
+            request_headers: &req_headers,
+            request_body: &whole_body,
+            status,
+            response_headers: &headers,
+            response_body: &body_bytes,
+            wait,
+            receive: started.elapsed() - wait,
+        });
+    }
+
+    if is_upstream {
+        state.update_from_headers(&headers, &upstream_host);
+    }
+
+    if capture_payouts_response && status.is_success() {
+        capture_payouts(&state, &body_bytes);
+    }
+
+    let mut builder = Response::builder().status(status);
+    for (key, value) in headers.iter() {
+        if key == hyper::header::TRANSFER_ENCODING {
+            continue;
+        }
+        if key == SET_COOKIE {
+            // Webview открыт на адресе прокси, поэтому куку нужно переписать под него.
+            if let Some(cookie) = value.to_str().ok().and_then(webview_set_cookie) {
+                builder = builder.header(key, cookie);
+            }
+            continue;
+        }
+        builder = builder.header(key, value);
+    }
+    let resp = builder.body(Body::from(body_bytes)).unwrap();
+    Ok(resp)
+}
+
+/// Сколько портов подряд после занятого пробуется, прежде чем взять любой свободный.
+const PORT_ATTEMPTS: u16 = 10;
+
+/// Не удалось занять порт прокси.
+#[derive(Debug)]
+pub struct BindError {
+    pub addr: SocketAddr,
+    pub source: io::Error,
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to bind proxy to {}: {}", self.addr, self.source)?;
+        if self.source.kind() == io::ErrorKind::AddrInUse {
+            write!(f, " (the port is used by another program; set another proxy_port in config.json)")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BindError {}
+
+/// Занимает порт прокси. Порт 0 — любой свободный. Если `preferred` занят и
+/// порт не `fixed`, пробуются следующие порты, а затем любой свободный.
+pub fn bind_proxy(preferred: SocketAddr, fixed: bool) -> Result<TcpListener, BindError> {
+    let error = |addr, source| BindError { addr, source };
+    match TcpListener::bind(preferred) {
+        Ok(listener) => return Ok(listener),
+        Err(e) if fixed || e.kind() != io::ErrorKind::AddrInUse => return Err(error(preferred, e)),
+        Err(e) => debug!(addr = %preferred, error = %e, "Preferred proxy port is busy"),
+    }
+    let next = (1..PORT_ATTEMPTS).filter_map(|i| preferred.port().checked_add(i));
+    for port in next.chain([0]) {
+        let addr = SocketAddr::new(preferred.ip(), port);
+        if let Ok(listener) = TcpListener::bind(addr) {
+            let bound = listener.local_addr().map_err(|e| error(addr, e))?;
+            warn!(preferred = %preferred, %bound, "Proxy port is busy, using another one");
+            return Ok(listener);
+        }
+    }
+    Err(error(
+        preferred,
+        io::Error::new(io::ErrorKind::AddrInUse, "no free port found"),
+    ))
+}
+
+pub async fn run_proxy(state: ProxyState, addr: SocketAddr) -> Result<(), BindError> {
+    run_proxy_until(state, addr, future::pending()).await
+}
+
+/// Занимает ровно `addr` и запускает прокси до срабатывания `shutdown`.
+pub async fn run_proxy_until(
+    state: ProxyState,
+    addr: SocketAddr,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), BindError> {
+    let listener = bind_proxy(addr, true)?;
+    serve_proxy(state, listener, shutdown).await;
+    Ok(())
+}
+
+/// Обслуживает прокси на уже занятом порту до срабатывания `shutdown`; после
+/// этого новые соединения не принимаются, а начатые запросы дорабатываются.
+pub async fn serve_proxy(state: ProxyState, listener: TcpListener, shutdown: impl Future<Output = ()>) {
+    let addr = match listener.local_addr() {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!(error = %e, "Proxy listener has no address");
+            return;
+        }
+    };
+    *state.proxy_addr.lock().unwrap() = Some(addr);
+    if let Err(e) = publish_token(&state.data_dir, &state.api_token) {
+        warn!(error = %e, "Failed to write API token file");
+    }
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                proxy_handler(req, state.clone())
+            }))
+        }
+    });
+
+    let server = match listener
+        .set_nonblocking(true)
+        .and_then(|()| Server::from_tcp(listener).map_err(io::Error::other))
+    {
+        Ok(builder) => builder.serve(make_service),
+        Err(e) => {
+            error!(%addr, error = %e, "Failed to start proxy server");
+            return;
+        }
+    };
+    info!("Proxy server listening on http://{}", addr);
+    if let Err(e) = server.with_graceful_shutdown(shutdown).await {
+        error!(error = %e, "Proxy server error");
+    }
+    info!("Proxy server stopped.");
+}
@@ -0,0 +1,421 @@
+//! Разбор PDF-чеков российских банков и сверка их с выплатой.
+//!
+//! Из текста чека извлекаются сумма, телефон или карта получателя, банк,
+//! дата и время операции и её идентификатор. Подписи полей у банков разные,
+//! а значение стоит либо в той же строке, что и подпись, либо в следующей.
+//! Сверка сравнивает чек с рублёвой суммой трейдера, `wallet`, `bank_label`
+//! и `approved_at` и отмечает чеки, которые панель пометила как поддельные.
+
+use std::{panic, str::FromStr, sync::OnceLock};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    idex::Transaction,
+    money::{Decimal, RUB},
+};
+
+/// Настройки сверки чеков (секция `receipts` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReceiptsConfig {
+    /// Смещение времени в чеках от UTC в часах.
+    pub utc_offset_hours: i32,
+    /// Допустимое расхождение суммы в рублях.
+    pub amount_tolerance: Decimal,
+    /// Допустимое расхождение времени чека и `approved_at` в минутах.
+    pub time_tolerance_minutes: i64,
+}
+
+impl Default for ReceiptsConfig {
+    fn default() -> Self {
+        Self {
+            utc_offset_hours: 3,
+            amount_tolerance: Decimal::ZERO,
+            time_tolerance_minutes: 30,
+        }
+    }
+}
+
+/// Поля, извлечённые из чека. Отсутствующее в чеке поле — `None`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Receipt {
+    pub amount: Option<Decimal>,
+    /// Телефон получателя в виде `7XXXXXXXXXX`.
+    pub phone: Option<String>,
+    /// Номер карты получателя; скрытые цифры заменены на `*`.
+    pub card: Option<String>,
+    pub bank: Option<String>,
+    /// Местное время операции, как оно напечатано в чеке.
+    pub datetime: Option<NaiveDateTime>,
+    pub operation_id: Option<String>,
+}
+
+/// Расхождение чека с выплатой.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReceiptIssue {
+    /// Текст из файла извлечь не удалось.
+    Unreadable { error: String },
+    /// Панель пометила чек как поддельный (`custom_properties.fake`).
+    MarkedFake,
+    AmountMissing,
+    AmountMismatch { expected: Decimal, found: Decimal },
+    RecipientMissing,
+    RecipientMismatch { expected: String, found: String },
+    BankMismatch { expected: String, found: String },
+    TimeMismatch { expected: DateTime<Utc>, found: DateTime<Utc> },
+}
+
+/// Текст PDF-документа.
+pub fn extract_text(data: &[u8]) -> Result<String, String> {
+    // pdf-extract паникует на части повреждённых файлов.
+    panic::catch_unwind(|| pdf_extract::extract_text_from_mem(data))
+        .map_err(|_| "PDF parser panicked".to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Извлекает текст PDF и разбирает его как чек.
+pub fn parse_pdf(data: &[u8]) -> Result<Receipt, String> {
+    let text = extract_text(data)?;
+    if text.trim().is_empty() {
+        return Err("PDF contains no text".to_string());
+    }
+    Ok(parse_receipt(&text))
+}
+
+const AMOUNT_LABELS: &[&str] = &[
+    "сумма перевода",
+    "сумма операции",
+    "сумма платежа",
+    "сумма в валюте карты",
+    "итого",
+    "сумма",
+];
+const PHONE_LABELS: &[&str] = &[
+    "номер телефона получателя",
+    "телефон получателя",
+];
+const CARD_LABELS: &[&str] = &[
+    "номер карты получателя",
+    "карта получателя",
+];
+const BANK_LABELS: &[&str] = &["банк получателя"];
+const DATETIME_LABELS: &[&str] = &[
+    "дата и время операции",
+    "дата и время перевода",
+    "дата и время",
+    "дата операции",
+];
+const OPERATION_LABELS: &[&str] = &[
+    "идентификатор операции в сбп",
+    "идентификатор операции",
+    "id операции",
+    "номер операции",
+    "номер документа",
+    "номер квитанции",
+    "квитанция №",
+    "код авторизации",
+];
+
+const MONTHS: [&str; 12] = [
+    "января", "февраля", "марта", "апреля", "мая", "июня", "июля", "августа", "сентября",
+    "октября", "ноября", "декабря",
+];
+
+fn amount_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\d{1,3}(?: \d{3})+(?:[.,]\d{1,2})?|\d+(?:[.,]\d{1,2})?").unwrap())
+}
+
+fn phone_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?:\+7|\b[78])[\s(-]*(\d{3})[\s)-]*(\d{3})[\s-]*(\d{2})[\s-]*(\d{2})\b").unwrap()
+    })
+}
+
+fn card_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"[\d*•·xX]{4}(?:[ ]?[\d*•·xX]{2,6}){0,4}").unwrap())
+}
+
+fn datetime_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)(\d{1,2})(?:\.(\d{2})\.|\s+([а-я]+)\s+)(\d{4})(?:\s*г\.?)?,?\s*(?:в\s*)?(\d{1,2}):(\d{2})(?::(\d{2}))?",
+        )
+        .unwrap()
+    })
+}
+
+fn operation_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"[A-Za-z0-9][A-Za-z0-9-]{5,}").unwrap())
+}
+
+/// Непустые строки текста без лишних пробелов.
+fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// Строки с этими словами относятся не к получателю и сумме перевода.
+const SKIPPED_LINES: &[&str] = &["отправител", "комисси"];
+
+/// Значение поля по первой найденной подписи: остаток строки с подписью,
+/// а если он не подходит — следующая строка.
+fn field<T>(lines: &[String], labels: &[&str], parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    for label in labels {
+        for (i, line) in lines.iter().enumerate() {
+            let lower = line.to_lowercase();
+            if SKIPPED_LINES.iter().any(|s| lower.contains(s)) {
+                continue;
+            }
+            let Some(pos) = lower.find(label) else {
+                continue;
+            };
+            let start = pos + label.len();
+            // Смещения совпадают, пока нижний регистр не меняет длину строки.
+            let rest = line
+                .get(start..)
+                .filter(|_| lower.len() == line.len())
+                .unwrap_or(&lower[start..])
+                .trim_start_matches([':', ' ', '—', '-', '№'])
+                .trim();
+            if let Some(value) = parse(rest) {
+                return Some(value);
+            }
+            if let Some(value) = lines.get(i + 1).and_then(|next| parse(next)) {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+fn parse_amount(value: &str) -> Option<Decimal> {
+    let m = amount_re().find(value)?;
+    let cleaned: String = m
+        .as_str()
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == ',' || *c == '.')
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect();
+    Decimal::from_str(&cleaned).ok()
+}
+
+fn parse_phone(value: &str) -> Option<String> {
+    let caps = phone_re().captures(value)?;
+    Some(format!("7{}{}{}{}", &caps[1], &caps[2], &caps[3], &caps[4]))
+}
+
+fn parse_card(value: &str) -> Option<String> {
+    card_re()
+        .find_iter(value)
+        .map(|m| {
+            m.as_str()
+                .chars()
+                .filter(|c| *c != ' ')
+                .map(|c| if c.is_ascii_digit() { c } else { '*' })
+                .collect::<String>()
+        })
+        .find(|card| card.len() >= 4 && card.chars().rev().take(4).all(|c| c.is_ascii_digit()))
+}
+
+fn parse_bank(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty() && value.chars().any(|c| c.is_alphabetic())).then(|| value.to_string())
+}
+
+fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    let caps = datetime_re().captures(value)?;
+    let day: u32 = caps[1].parse().ok()?;
+    let month: u32 = match (caps.get(2), caps.get(3)) {
+        (Some(m), _) => m.as_str().parse().ok()?,
+        (_, Some(name)) => {
+            let name = name.as_str().to_lowercase();
+            MONTHS.iter().position(|m| *m == name)? as u32 + 1
+        }
+        _ => return None,
+    };
+    let year: i32 = caps[4].parse().ok()?;
+    let hour: u32 = caps[5].parse().ok()?;
+    let minute: u32 = caps[6].parse().ok()?;
+    let second: u32 = caps.get(7).map_or(Some(0), |s| s.as_str().parse().ok())?;
+    Some(NaiveDateTime::new(
+        NaiveDate::from_ymd_opt(year, month, day)?,
+        NaiveTime::from_hms_opt(hour, minute, second)?,
+    ))
+}
+
+fn parse_operation_id(value: &str) -> Option<String> {
+    operation_re()
+        .find_iter(value)
+        .map(|m| m.as_str().to_string())
+        .find(|id| id.chars().any(|c| c.is_ascii_digit()))
+}
+
+/// Разбирает текст чека.
+pub fn parse_receipt(text: &str) -> Receipt {
+    let lines = lines(text);
+    Receipt {
+        amount: field(&lines, AMOUNT_LABELS, parse_amount),
+        phone: field(&lines, PHONE_LABELS, parse_phone),
+        card: field(&lines, CARD_LABELS, parse_card),
+        bank: field(&lines, BANK_LABELS, parse_bank),
+        datetime: field(&lines, DATETIME_LABELS, parse_datetime)
+            .or_else(|| lines.iter().find_map(|l| parse_datetime(l))),
+        operation_id: field(&lines, OPERATION_LABELS, parse_operation_id),
+    }
+}
+
+fn digits(value: &str) -> String {
+    value.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+/// Телефон из `wallet` в виде `7XXXXXXXXXX`, если это телефон.
+fn wallet_phone(wallet: &str) -> Option<String> {
+    let digits = digits(wallet);
+    match digits.len() {
+        10 => Some(format!("7{}", digits)),
+        11 if digits.starts_with('7') || digits.starts_with('8') => {
+            Some(format!("7{}", &digits[1..]))
+        }
+        _ => None,
+    }
+}
+
+/// Совпадают ли видимые цифры маскированной карты из чека с номером карты.
+fn card_matches(card: &str, masked: &str) -> bool {
+    let (card, masked): (Vec<char>, Vec<char>) = (card.chars().collect(), masked.chars().collect());
+    if masked.len() == card.len() {
+        return card
+            .iter()
+            .zip(&masked)
+            .all(|(c, m)| *m == '*' || c == m);
+    }
+    // Маска другой длины: сверяем только последние видимые цифры.
+    let tail: String = masked
+        .iter()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    !tail.is_empty() && card.iter().collect::<String>().ends_with(&tail)
+}
+
+/// Название банка без правовой формы, кавычек и слова «банк» для сравнения.
+fn normalize_bank(name: &str) -> String {
+    const ALIASES: &[(&str, &str)] = &[
+        ("тинькофф", "т"),
+        ("tinkoff", "т"),
+        ("т-банк", "т"),
+        ("tbank", "т"),
+        ("t-bank", "т"),
+        ("sberbank", "сбер"),
+        ("alfa", "альфа"),
+        ("vtb", "втб"),
+    ];
+    const SKIPPED_WORDS: &[&str] = &["пао", "ао", "оао", "зао", "ооо", "кб", "акб", "банк", "bank"];
+    let lower = name.to_lowercase();
+    if let Some((_, canonical)) = ALIASES.iter().find(|(alias, _)| lower.contains(alias)) {
+        return canonical.to_string();
+    }
+    lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty() && !SKIPPED_WORDS.contains(w))
+        .map(|w| w.strip_suffix("банк").filter(|s| !s.is_empty()).unwrap_or(w))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn banks_match(expected: &str, found: &str) -> bool {
+    let (expected, found) = (normalize_bank(expected), normalize_bank(found));
+    if expected.is_empty() || found.is_empty() || expected == found {
+        return true;
+    }
+    // «Сбер» и «Сбер Банк Онлайн»: одно название начинает другое.
+    let (short, long) = if expected.len() < found.len() {
+        (&expected, &found)
+    } else {
+        (&found, &expected)
+    };
+    short.chars().count() >= 3 && long.starts_with(short.as_str())
+}
+
+/// Сверяет чек с выплатой. Поля, которых нет в выплате, не проверяются.
+pub fn verify_receipt(
+    tx: &Transaction,
+    receipt: &Receipt,
+    fake: bool,
+    config: &ReceiptsConfig,
+) -> Vec<ReceiptIssue> {
+    let mut issues = Vec::new();
+    if fake {
+        issues.push(ReceiptIssue::MarkedFake);
+    }
+
+    if let Some(expected) = tx.trader_amount(RUB).map(|m| m.amount) {
+        match receipt.amount {
+            None => issues.push(ReceiptIssue::AmountMissing),
+            Some(found) if (found - expected).abs() > config.amount_tolerance => {
+                issues.push(ReceiptIssue::AmountMismatch { expected, found })
+            }
+            Some(_) => {}
+        }
+    }
+
+    if let Some(wallet) = tx.wallet.as_deref().filter(|w| !w.is_empty()) {
+        let found = match (wallet_phone(wallet), &receipt.phone, &receipt.card) {
+            (Some(expected), Some(phone), _) => (expected != *phone).then(|| phone.clone()),
+            (None, _, Some(card)) => (!card_matches(&digits(wallet), card)).then(|| card.clone()),
+            (_, Some(phone), _) => Some(phone.clone()),
+            (_, None, Some(card)) => Some(card.clone()),
+            (_, None, None) => {
+                issues.push(ReceiptIssue::RecipientMissing);
+                None
+            }
+        };
+        if let Some(found) = found {
+            issues.push(ReceiptIssue::RecipientMismatch {
+                expected: wallet.to_string(),
+                found,
+            });
+        }
+    }
+
+    if let (Some(expected), Some(found)) = (tx.bank_label.as_deref(), receipt.bank.as_deref()) {
+        if !banks_match(expected, found) {
+            issues.push(ReceiptIssue::BankMismatch {
+                expected: expected.to_string(),
+                found: found.to_string(),
+            });
+        }
+    }
+
+    let approved = tx
+        .approved_at
+        .as_deref()
+        .and_then(|a| DateTime::parse_from_rfc3339(a).ok())
+        .map(|a| a.with_timezone(&Utc));
+    let offset = FixedOffset::east_opt(config.utc_offset_hours * 3600)
+        .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    let printed = receipt
+        .datetime
+        .and_then(|dt| offset.from_local_datetime(&dt).single())
+        .map(|dt| dt.with_timezone(&Utc));
+    if let (Some(expected), Some(found)) = (approved, printed) {
+        if (found - expected).num_minutes().abs() > config.time_tolerance_minutes {
+            issues.push(ReceiptIssue::TimeMismatch { expected, found });
+        }
+    }
+    issues
+}
@@ -0,0 +1,383 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use hyper::{header::HeaderMap, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+/// Заголовки, значения которых никогда не попадают в запись.
+const REDACTED_HEADERS: [&str; 5] = [
+    "cookie",
+    "set-cookie",
+    "authorization",
+    "x-xsrf-token",
+    "x-csrf-token",
+];
+const REDACTED: &str = "[redacted]";
+
+const HAR_HEADER: &str = r#"{"log":{"version":"1.2","creator":{"name":"p2p_app","version":"0.1.0"},"entries":["#;
+const HAR_FOOTER: &str = "\n]}}";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordFormat {
+    #[default]
+    Jsonl,
+    Har,
+}
+
+/// Настройки записи трафика прокси (секция `recorder` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RecorderConfig {
+    pub enabled: bool,
+    pub format: RecordFormat,
+    /// Файл записи; по умолчанию traffic.jsonl или traffic.har.
+    pub path: Option<String>,
+    /// Тела запросов и ответов длиннее этого лимита обрезаются.
+    pub max_body_bytes: usize,
+    /// При превышении размера файл ротируется в `<path>.1`, `<path>.2`, ...
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+    /// Шаблоны URL с `*`; пустой список означает «записывать всё».
+    pub url_filter: Vec<String>,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: RecordFormat::Jsonl,
+            path: None,
+            max_body_bytes: 64 * 1024,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            url_filter: Vec::new(),
+        }
+    }
+}
+
+/// Одна пара запрос/ответ, прошедшая через прокси.
+pub struct Exchange<'a> {
+    pub started_at: DateTime<Utc>,
+    pub method: &'a Method,
+    pub url: &'a url::Url,
+    pub request_headers: &'a HeaderMap,
+    pub request_body: &'a [u8],
+    pub status: StatusCode,
+    pub response_headers: &'a HeaderMap,
+    pub response_body: &'a [u8],
+    /// Время до получения заголовков ответа.
+    pub wait: Duration,
+    /// Время чтения тела ответа.
+    pub receive: Duration,
+}
+
+struct OutputFile {
+    file: File,
+    size: u64,
+}
+
+pub struct TrafficRecorder {
+    config: RecorderConfig,
+    path: PathBuf,
+    output: Mutex<Option<OutputFile>>,
+}
+
+impl TrafficRecorder {
+    /// Создаёт рекордер, если запись включена в настройках.
+    /// Относительный путь файла отсчитывается от каталога данных.
+    pub fn from_config(config: &RecorderConfig, data_dir: &Path) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let path = data_dir.join(config.path.as_deref().unwrap_or(match config.format {
+            RecordFormat::Jsonl => "traffic.jsonl",
+            RecordFormat::Har => "traffic.har",
+        }));
+        info!(path = %path.display(), "Recording proxy traffic");
+        Some(Self {
+            config: config.clone(),
+            path,
+            output: Mutex::new(None),
+        })
+    }
+
+    pub fn matches(&self, url: &str) -> bool {
+        self.config.url_filter.is_empty()
+            || self.config.url_filter.iter().any(|p| glob_match(p, url))
+    }
+
+    pub fn record(&self, exchange: &Exchange) {
+        if !self.matches(exchange.url.as_str()) {
+            return;
+        }
+        let entry = match self.config.format {
+            RecordFormat::Jsonl => self.jsonl_entry(exchange),
+            RecordFormat::Har => self.har_entry(exchange),
+        };
+        if let Err(e) = self.write_entry(&entry.to_string()) {
+            warn!(path = %self.path.display(), error = %e, "Failed to record traffic");
+        }
+    }
+
+    fn jsonl_entry(&self, ex: &Exchange) -> Value {
+        json!({
+            "started_at": ex.started_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "method": ex.method.as_str(),
+            "url": ex.url.as_str(),
+            "status": ex.status.as_u16(),
+            "duration_ms": (ex.wait + ex.receive).as_millis() as u64,
+            "request": {
+                "headers": headers_json(ex.request_headers),
+                "body": self.body_json(ex.request_body),
+            },
+            "response": {
+                "headers": headers_json(ex.response_headers),
+                "body": self.body_json(ex.response_body),
+            },
+        })
+    }
+
+    fn har_entry(&self, ex: &Exchange) -> Value {
+        let wait_ms = ex.wait.as_secs_f64() * 1000.0;
+        let receive_ms = ex.receive.as_secs_f64() * 1000.0;
+        let query: Vec<Value> = ex
+            .url
+            .query_pairs()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect();
+
+        let mut request = json!({
+            "method": ex.method.as_str(),
+            "url": ex.url.as_str(),
+            "httpVersion": "HTTP/1.1",
+            "cookies": cookies_json(ex.request_headers, hyper::header::COOKIE),
+            "headers": headers_json(ex.request_headers),
+            "queryString": query,
+            "headersSize": -1,
+            "bodySize": ex.request_body.len(),
+        });
+        if !ex.request_body.is_empty() {
+            let mut post_data = self.body_json(ex.request_body);
+            post_data["mimeType"] = json!(content_type(ex.request_headers));
+            request["postData"] = post_data;
+        }
+
+        let mut content = self.body_json(ex.response_body);
+        content["mimeType"] = json!(content_type(ex.response_headers));
+
+        json!({
+            "startedDateTime": ex.started_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "time": wait_ms + receive_ms,
+            "request": request,
+            "response": {
+                "status": ex.status.as_u16(),
+                "statusText": ex.status.canonical_reason().unwrap_or(""),
+                "httpVersion": "HTTP/1.1",
+                "cookies": cookies_json(ex.response_headers, hyper::header::SET_COOKIE),
+                "headers": headers_json(ex.response_headers),
+                "content": content,
+                "redirectURL": ex.response_headers
+                    .get(hyper::header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or(""),
+                "headersSize": -1,
+                "bodySize": ex.response_body.len(),
+            },
+            "cache": {},
+            "timings": { "send": 0, "wait": wait_ms, "receive": receive_ms },
+        })
+    }
+
+    /// Тело в формате HAR `content`: текст, либо base64 для бинарных данных.
+    fn body_json(&self, body: &[u8]) -> Value {
+        let truncated = body.len() > self.config.max_body_bytes;
+        let mut kept = &body[..body.len().min(self.config.max_body_bytes)];
+        // Обрезка могла разрезать многобайтовый символ: отступаем к его началу,
+        // чтобы текст не превращался в base64.
+        if let Err(e) = std::str::from_utf8(kept) {
+            if truncated && e.error_len().is_none() {
+                kept = &kept[..e.valid_up_to()];
+            }
+        }
+        let mut value = match std::str::from_utf8(kept) {
+            Ok(text) => json!({ "size": body.len(), "text": text }),
+            Err(_) => json!({
+                "size": body.len(),
+                "text": BASE64.encode(kept),
+                "encoding": "base64",
+            }),
+        };
+        if truncated {
+            value["comment"] = json!(format!("truncated to {} bytes", kept.len()));
+        }
+        value
+    }
+
+    fn write_entry(&self, entry: &str) -> io::Result<()> {
+        let mut output = self.output.lock().unwrap();
+        let incoming = (entry.len() + HAR_FOOTER.len() + 2) as u64;
+        let needs_rotation = output
+            .as_ref()
+            .is_some_and(|o| o.size > 0 && o.size + incoming > self.config.max_file_bytes);
+        if needs_rotation {
+            *output = None;
+            rotate(&self.path, self.config.max_files)?;
+        }
+        if output.is_none() {
+            *output = Some(self.open()?);
+        }
+        let out = output.as_mut().unwrap();
+
+        match self.config.format {
+            RecordFormat::Jsonl => {
+                out.file.seek(SeekFrom::End(0))?;
+                out.file.write_all(entry.as_bytes())?;
+                out.file.write_all(b"\n")?;
+            }
+            RecordFormat::Har => {
+                // Дописываем запись перед закрывающим `]}}`, чтобы файл всегда оставался валидным HAR.
+                if out.size == 0 {
+                    out.file.write_all(HAR_HEADER.as_bytes())?;
+                    out.file.write_all(b"\n")?;
+                } else {
+                    out.file
+                        .seek(SeekFrom::End(-(HAR_FOOTER.len() as i64)))?;
+                    out.file.write_all(b",\n")?;
+                }
+                out.file.write_all(entry.as_bytes())?;
+                out.file.write_all(HAR_FOOTER.as_bytes())?;
+            }
+        }
+        out.file.flush()?;
+        out.size = out.file.stream_position()?;
+        Ok(())
+    }
+
+    fn open(&self) -> io::Result<OutputFile> {
+        if self.config.format == RecordFormat::Har && !har_is_appendable(&self.path)? {
+            rotate(&self.path, self.config.max_files)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(&self.path)?;
+        let size = file.metadata()?.len();
+        Ok(OutputFile { file, size })
+    }
+}
+
+/// Существующий HAR можно дописывать, только если он закончен нашим футером.
+fn har_is_appendable(path: &Path) -> io::Result<bool> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(true);
+    }
+    if len < HAR_FOOTER.len() as u64 {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::End(-(HAR_FOOTER.len() as i64)))?;
+    let mut tail = String::new();
+    file.read_to_string(&mut tail)?;
+    Ok(tail == HAR_FOOTER)
+}
+
+/// Сдвигает `<path>.N` → `<path>.N+1`, текущий файл становится `<path>.1`.
+fn rotate(path: &Path, max_files: usize) -> io::Result<()> {
+    let numbered = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    if max_files == 0 {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let _ = fs::remove_file(numbered(max_files));
+    for n in (1..max_files).rev() {
+        let from = numbered(n);
+        if from.exists() {
+            fs::rename(&from, numbered(n + 1))?;
+        }
+    }
+    if path.exists() {
+        fs::rename(path, numbered(1))?;
+    }
+    Ok(())
+}
+
+fn headers_json(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            json!({ "name": name.as_str(), "value": value })
+        })
+        .collect()
+}
+
+/// Имена кук без значений.
+fn cookies_json(headers: &HeaderMap, header: hyper::header::HeaderName) -> Vec<Value> {
+    headers
+        .get_all(&header)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| {
+            // В Cookie несколько пар через `;`, в Set-Cookie одна пара и атрибуты.
+            if header == hyper::header::COOKIE {
+                h.split(';').collect::<Vec<_>>()
+            } else {
+                h.split(';').take(1).collect()
+            }
+        })
+        .filter_map(|pair| pair.split('=').next())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| json!({ "name": name, "value": REDACTED }))
+        .collect()
+}
+
+fn content_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+}
+
+/// Сопоставление с шаблоном, где `*` означает любую подстроку.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
@@ -0,0 +1,370 @@
+//! Правила автоматического взятия выплат.
+//!
+//! Новые выплаты — найденные опросом `run_idex` или пришедшие через прокси —
+//! сверяются с правилами из секции `auto_claim` config.json: диапазон рублёвой
+//! суммы, разрешённые банки (`bank_code`), способы оплаты, минимальный курс,
+//! окна времени и дневные лимиты. Рассматриваются только свободные выплаты
+//! (статус `pending`) с неистёкшим сроком; выплаты, видимые при первом опросе,
+//! не берутся. Выплата забирается по первому подходящему правилу. В режиме
+//! `dry_run` приложение только пишет в лог, что взяло бы.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{
+    idex::{Transaction, STATUS_PENDING},
+    money::{Decimal, RUB},
+    proxy::ProxyState,
+};
+
+const USAGE_FILE: &str = "auto_claim.json";
+
+/// Окно времени `HH:MM`–`HH:MM` в местном времени; может переходить через полночь.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimeWindow {
+    pub start: String,
+    pub end: String,
+}
+
+impl TimeWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        let parse = |v: &str| NaiveTime::parse_from_str(v.trim(), "%H:%M").ok();
+        let (Some(start), Some(end)) = (parse(&self.start), parse(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            time >= start && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+/// Одно правило. Незаданное условие не ограничивает выбор.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Rule {
+    pub name: String,
+    /// Границы рублёвой суммы трейдера, включительно.
+    pub min_amount: Option<Decimal>,
+    pub max_amount: Option<Decimal>,
+    /// Разрешённые `bank_code`.
+    pub banks: Vec<String>,
+    /// Подстроки названия способа оплаты (`СБП`, `карта`), без учёта регистра.
+    pub payment_methods: Vec<String>,
+    pub min_course: Option<Decimal>,
+    pub time_windows: Vec<TimeWindow>,
+    /// Сколько выплат правило может взять за местные сутки.
+    pub daily_count: Option<u32>,
+    /// Сколько рублей правило может взять за местные сутки.
+    pub daily_amount: Option<Decimal>,
+}
+
+/// Настройки автоматического взятия (секция `auto_claim` в config.json).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AutoClaimConfig {
+    pub enabled: bool,
+    /// Только писать в лог, что было бы взято, без запросов к панели.
+    pub dry_run: bool,
+    /// Смещение местного времени от UTC в часах для окон и дневных лимитов.
+    pub utc_offset_hours: i32,
+    pub rules: Vec<Rule>,
+}
+
+impl Default for AutoClaimConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dry_run: true,
+            utc_offset_hours: 3,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Взято правилом за сутки.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub count: u32,
+    pub amount: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct DailyUsage {
+    date: Option<NaiveDate>,
+    rules: BTreeMap<String, Usage>,
+}
+
+/// Чем закончилась обработка подходящей выплаты.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClaimResult {
+    Claimed,
+    /// Режим `dry_run`: выплата подходит, но запрос не отправлялся.
+    DryRun,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimOutcome {
+    pub transaction_id: String,
+    pub rule: String,
+    pub result: ClaimResult,
+}
+
+/// Почему правило не подходит к выплате.
+enum Mismatch {
+    /// Выплата не подходит правилу и не подойдёт.
+    Never(String),
+    /// Правило пока не действует: вне окна времени или исчерпан дневной лимит.
+    NotNow(String),
+}
+
+/// Решение по выплате в момент `now`.
+enum Decision<'a> {
+    Claim(&'a Rule),
+    /// Ни одно правило не подходит сейчас, но какое-то может подойти позже.
+    Later,
+    Never,
+}
+
+/// Движок правил с учётом дневных лимитов. Расход лимитов по взятым выплатам
+/// хранится в `auto_claim.json` и переживает перезапуск; в `dry_run` он
+/// считается только в памяти.
+pub struct RuleEngine {
+    config: AutoClaimConfig,
+    path: PathBuf,
+    usage: DailyUsage,
+    /// Выплаты, решение по которым окончательное: взятые, взятые в `dry_run`
+    /// и не подходящие ни одному правилу.
+    seen: HashSet<String>,
+}
+
+/// Можно ли взять выплату в момент `now`: она свободна и её срок не истёк.
+pub fn is_claimable(tx: &Transaction, now: DateTime<Utc>) -> bool {
+    let expired = tx
+        .expired_at
+        .as_deref()
+        .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+        .is_some_and(|e| e <= now);
+    tx.status.as_deref() == Some(STATUS_PENDING) && !expired
+}
+
+impl RuleEngine {
+    pub fn new(config: &AutoClaimConfig, data_dir: &Path) -> Self {
+        let path = data_dir.join(USAGE_FILE);
+        let usage = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            config: config.clone(),
+            path,
+            usage,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn offset(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.config.utc_offset_hours * 3600)
+            .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap())
+    }
+
+    /// Расход лимита правила за текущие местные сутки.
+    pub fn usage(&self, rule: &str, now: DateTime<Utc>) -> Usage {
+        let today = now.with_timezone(&self.offset()).date_naive();
+        if self.usage.date != Some(today) {
+            return Usage::default();
+        }
+        self.usage.rules.get(rule).cloned().unwrap_or_default()
+    }
+
+    /// Почему правило не подходит к выплате, или `None`, если подходит.
+    fn mismatch(&self, rule: &Rule, tx: &Transaction, now: DateTime<Utc>) -> Option<Mismatch> {
+        let amount = tx.trader_amount(RUB).map(|m| m.amount);
+        let limited = rule.min_amount.is_some() || rule.max_amount.is_some();
+        if limited || rule.daily_amount.is_some() {
+            let Some(amount) = amount else {
+                return Some(Mismatch::Never("no RUB amount".to_string()));
+            };
+            if rule.min_amount.is_some_and(|min| amount < min) {
+                return Some(Mismatch::Never(format!("amount {} below minimum", amount)));
+            }
+            if rule.max_amount.is_some_and(|max| amount > max) {
+                return Some(Mismatch::Never(format!("amount {} above maximum", amount)));
+            }
+        }
+        if !rule.banks.is_empty()
+            && !tx
+                .bank_code
+                .as_ref()
+                .is_some_and(|code| rule.banks.contains(code))
+        {
+            return Some(Mismatch::Never(format!("bank {:?} not allowed", tx.bank_code)));
+        }
+        if !rule.payment_methods.is_empty() {
+            let method = tx.payment_method.as_deref().unwrap_or("").to_lowercase();
+            if !rule
+                .payment_methods
+                .iter()
+                .any(|m| method.contains(&m.to_lowercase()))
+            {
+                return Some(Mismatch::Never(format!(
+                    "payment method {:?} not allowed",
+                    tx.payment_method
+                )));
+            }
+        }
+        if let Some(min) = rule.min_course {
+            if tx.course.is_none_or(|course| course < min) {
+                return Some(Mismatch::Never(format!("course {:?} below minimum", tx.course)));
+            }
+        }
+        if !rule.time_windows.is_empty() {
+            let time = now.with_timezone(&self.offset()).time();
+            if !rule.time_windows.iter().any(|w| w.contains(time)) {
+                return Some(Mismatch::NotNow("outside time windows".to_string()));
+            }
+        }
+        let used = self.usage(&rule.name, now);
+        if rule.daily_count.is_some_and(|limit| used.count >= limit) {
+            return Some(Mismatch::NotNow("daily count limit reached".to_string()));
+        }
+        if let Some(limit) = rule.daily_amount {
+            if used.amount + amount.unwrap_or_default() > limit {
+                return Some(Mismatch::NotNow("daily amount limit reached".to_string()));
+            }
+        }
+        None
+    }
+
+    fn decide(&self, tx: &Transaction, now: DateTime<Utc>) -> Decision<'_> {
+        if !is_claimable(tx, now) {
+            debug!(transaction = %tx.transaction_id, status = ?tx.status, "Payout is not claimable");
+            return Decision::Never;
+        }
+        let mut later = false;
+        for rule in &self.config.rules {
+            let reason = match self.mismatch(rule, tx, now) {
+                None => return Decision::Claim(rule),
+                Some(Mismatch::Never(reason)) => reason,
+                Some(Mismatch::NotNow(reason)) => {
+                    later = true;
+                    reason
+                }
+            };
+            debug!(transaction = %tx.transaction_id, rule = %rule.name, %reason, "Rule does not match");
+        }
+        if later {
+            Decision::Later
+        } else {
+            Decision::Never
+        }
+    }
+
+    /// Первое правило, которое берёт выплату в момент `now`.
+    pub fn evaluate(&self, tx: &Transaction, now: DateTime<Utc>) -> Option<&Rule> {
+        match self.decide(tx, now) {
+            Decision::Claim(rule) => Some(rule),
+            Decision::Later | Decision::Never => None,
+        }
+    }
+
+    fn record(&mut self, rule: &str, tx: &Transaction, now: DateTime<Utc>) {
+        let today = now.with_timezone(&self.offset()).date_naive();
+        if self.usage.date != Some(today) {
+            self.usage = DailyUsage {
+                date: Some(today),
+                rules: BTreeMap::new(),
+            };
+        }
+        let usage = self.usage.rules.entry(rule.to_string()).or_default();
+        usage.count += 1;
+        usage.amount += tx.trader_amount(RUB).map(|m| m.amount).unwrap_or_default();
+        if self.config.dry_run {
+            return;
+        }
+        let saved = serde_json::to_string_pretty(&self.usage)
+            .map_err(std::io::Error::from)
+            .and_then(|json| fs::write(&self.path, json));
+        if let Err(e) = saved {
+            warn!(error = %e, "Failed to save auto-claim usage");
+        }
+    }
+
+    /// Принято ли по выплате окончательное решение.
+    pub fn has_seen(&self, transaction_id: &str) -> bool {
+        self.seen.contains(transaction_id)
+    }
+
+    /// Запоминает выплаты как рассмотренные, не применяя правил: так
+    /// пропускаются выплаты, уже видимые при первом опросе.
+    pub fn skip(&mut self, transactions: &[Transaction]) {
+        self.seen
+            .extend(transactions.iter().map(|tx| tx.transaction_id.clone()));
+    }
+
+    /// Применяет правила к ещё не рассмотренным выплатам и забирает подходящие
+    /// через источник выплат аккаунта `session`. Выплата, которую не удалось
+    /// взять или которой правила подойдут только позже (окно времени, дневной
+    /// лимит), рассматривается снова при следующем вызове.
+    pub async fn process(
+        &mut self,
+        session: &ProxyState,
+        transactions: &[Transaction],
+    ) -> Vec<ClaimOutcome> {
+        let mut outcomes = Vec::new();
+        for tx in transactions {
+            if self.seen.contains(&tx.transaction_id) {
+                continue;
+            }
+            let now = Utc::now();
+            let rule = match self.decide(tx, now) {
+                Decision::Claim(rule) => rule.name.clone(),
+                Decision::Later => continue,
+                Decision::Never => {
+                    self.seen.insert(tx.transaction_id.clone());
+                    continue;
+                }
+            };
+            let amount = tx.trader_amount(RUB);
+            let result = if self.config.dry_run {
+                info!(
+                    transaction = %tx.transaction_id,
+                    rule = %rule,
+                    amount = ?amount.map(|m| m.to_string()),
+                    "Dry run: payout would be claimed"
+                );
+                ClaimResult::DryRun
+            } else {
+                match session.source.claim(session, &tx.transaction_id).await {
+                    Ok(_) => {
+                        info!(transaction = %tx.transaction_id, rule = %rule, "Payout claimed");
+                        ClaimResult::Claimed
+                    }
+                    Err(e) => {
+                        warn!(transaction = %tx.transaction_id, rule = %rule, error = %e, "Failed to claim payout");
+                        ClaimResult::Failed(e.to_string())
+                    }
+                }
+            };
+            if !matches!(result, ClaimResult::Failed(_)) {
+                self.seen.insert(tx.transaction_id.clone());
+                self.record(&rule, tx, now);
+            }
+            outcomes.push(ClaimOutcome {
+                transaction_id: tx.transaction_id.clone(),
+                rule,
+                result,
+            });
+        }
+        outcomes
+    }
+}
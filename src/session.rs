@@ -0,0 +1,233 @@
+//! Сессия панели: срок куки `sid`, поддержание сессии и смена кук.
+//!
+//! Монитор читает срок куки сессии из хранилища и заранее предупреждает,
+//! если сессия скоро истечёт и продлить её запросами уже нельзя. Пока
+//! пользователь не работает с панелью, задача периодически делает лёгкий
+//! авторизованный запрос (`PayoutSource::keep_alive`), чтобы сессия не
+//! истекла по бездействию. Смена значений `sid` и `XSRF-TOKEN` считается
+//! в метриках и видна в `GET /__p2p/session`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::{
+    alerts::{default_sinks, deliver, Alert, AlertKind, AlertSink, AlertsConfig, Channel},
+    cookie_sync::is_expired,
+    metrics::metrics,
+    notify::NotifierSink,
+    panel::PanelError,
+    proxy::{CookieStore, ProxyState},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub enabled: bool,
+    /// Как часто продлевать сессию, пока пользователь не работает с панелью.
+    pub keepalive_interval_secs: u64,
+    pub check_interval_secs: u64,
+    /// За сколько секунд до истечения куки сессии предупреждать.
+    pub warn_before_secs: i64,
+    /// Кука, по сроку которой определяется срок сессии.
+    pub session_cookie: String,
+    /// Куки, смена значения которых записывается в метрики.
+    pub tracked_cookies: Vec<String>,
+    pub warn_channels: Vec<Channel>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            keepalive_interval_secs: 300,
+            check_interval_secs: 60,
+            warn_before_secs: 1800,
+            session_cookie: "sid".to_string(),
+            tracked_cookies: vec!["sid".to_string(), "XSRF-TOKEN".to_string()],
+            warn_channels: vec![Channel::Desktop],
+        }
+    }
+}
+
+/// Состояние сессии профиля.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SessionHealth {
+    /// Срок куки сессии; `None` — кука сессионная или её нет.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_keepalive: Option<DateTime<Utc>>,
+    /// Ошибка последнего запроса поддержания сессии.
+    pub keepalive_error: Option<String>,
+    /// Последний запрос поддержания сессии получил 401.
+    pub unauthorized: bool,
+    /// Когда последний раз сменилось значение отслеживаемой куки.
+    pub last_rotation: Option<DateTime<Utc>>,
+}
+
+/// Следит за куками сессии одного профиля.
+pub struct SessionMonitor {
+    config: SessionConfig,
+    sinks: BTreeMap<Channel, Arc<dyn AlertSink>>,
+    /// Последние увиденные значения отслеживаемых кук.
+    values: HashMap<String, String>,
+    /// Срок, о котором уже предупредили: после продления сессии он сменится.
+    warned: Option<DateTime<Utc>>,
+}
+
+impl SessionMonitor {
+    /// Монитор с каналами оповещений по умолчанию (см. `alerts::default_sinks`).
+    pub fn new(config: &SessionConfig, alerts: &AlertsConfig) -> Self {
+        Self {
+            config: config.clone(),
+            sinks: default_sinks(alerts),
+            values: HashMap::new(),
+            warned: None,
+        }
+    }
+
+    /// Заменяет способ доставки для канала.
+    pub fn with_sink(mut self, channel: Channel, sink: Arc<dyn AlertSink>) -> Self {
+        self.sinks.insert(channel, sink);
+        self
+    }
+
+    /// Срок куки сессии; `None`, если её нет, она истекла или сессионная.
+    pub fn forecast(&self, store: &CookieStore, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        store
+            .cookies
+            .iter()
+            .filter(|c| c.name == self.config.session_cookie && !is_expired(c, now))
+            .filter_map(|c| c.expiration_date)
+            .filter_map(|secs| Utc.timestamp_opt(secs as i64, 0).single())
+            .min()
+    }
+
+    /// Отслеживаемые куки, значение которых сменилось с прошлой проверки.
+    /// Первое появление куки сменой не считается.
+    pub fn rotations(&mut self, store: &CookieStore) -> Vec<String> {
+        let mut rotated = Vec::new();
+        for name in &self.config.tracked_cookies {
+            let Some(cookie) = store.cookies.iter().find(|c| &c.name == name) else {
+                continue;
+            };
+            match self.values.insert(name.clone(), cookie.value.clone()) {
+                Some(previous) if previous != cookie.value => rotated.push(name.clone()),
+                _ => {}
+            }
+        }
+        rotated
+    }
+
+    /// Предупреждение, если до `expires_at` осталось меньше `warn_before_secs`.
+    /// О каждом сроке предупреждает один раз.
+    pub fn due_warning(&mut self, expires_at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<Alert> {
+        let expires_at = expires_at?;
+        let remaining = (expires_at - now).num_seconds();
+        if remaining > self.config.warn_before_secs || self.warned == Some(expires_at) {
+            return None;
+        }
+        self.warned = Some(expires_at);
+        Some(Alert {
+            transaction_id: String::new(),
+            expired_at: expires_at,
+            kind: AlertKind::SessionExpiring {
+                remaining_secs: remaining,
+            },
+            channels: self.config.warn_channels.clone(),
+            title: "Сессия панели скоро истечёт".to_string(),
+            message: format!(
+                "Кука {} истекает через {} мин. Войдите в панель заново, чтобы опрос выплат не прервался.",
+                self.config.session_cookie,
+                remaining.max(0) / 60
+            ),
+        })
+    }
+
+    /// Проверяет куки профиля: записывает срок и смену кук в `state.session`
+    /// и доставляет предупреждение об истечении. Возвращает поднятые оповещения.
+    pub async fn check(&mut self, state: &ProxyState, now: DateTime<Utc>) -> Vec<Alert> {
+        let (rotated, expires_at) = {
+            let store = state.cookies.lock().unwrap();
+            (self.rotations(&store), self.forecast(&store, now))
+        };
+        {
+            let mut health = state.session.lock().unwrap();
+            health.expires_at = expires_at;
+            if !rotated.is_empty() {
+                health.last_rotation = Some(now);
+            }
+        }
+        for name in &rotated {
+            metrics()
+                .cookie_rotations
+                .with_label_values(&[&state.profile, name])
+                .inc();
+            debug!(cookie = %name, "Session cookie rotated");
+        }
+
+        let alerts: Vec<Alert> = self.due_warning(expires_at, now).into_iter().collect();
+        for alert in &alerts {
+            warn!(expires_at = %alert.expired_at, "Panel session is about to expire");
+            deliver(&self.sinks, alert, &state.profile).await;
+        }
+        alerts
+    }
+}
+
+/// Делает запрос поддержания сессии и записывает итог в `state.session`.
+pub async fn keep_alive(state: &ProxyState, now: DateTime<Utc>) -> Result<(), PanelError> {
+    let result = state.source.keep_alive(state).await;
+    let label = match &result {
+        Ok(()) => "ok",
+        Err(PanelError::Unauthorized) => "unauthorized",
+        Err(_) => "failed",
+    };
+    metrics()
+        .session_keepalives
+        .with_label_values(&[&state.profile, label])
+        .inc();
+
+    let mut health = state.session.lock().unwrap();
+    health.last_keepalive = Some(now);
+    health.unauthorized = matches!(result, Err(PanelError::Unauthorized));
+    health.keepalive_error = result.as_ref().err().map(|e| e.to_string());
+    match &result {
+        Ok(()) => debug!("Session keep-alive succeeded"),
+        Err(e) => warn!(error = %e, "Session keep-alive failed"),
+    }
+    result
+}
+
+/// Проверяет сессию каждые `check_interval_secs`, пока не сработает `shutdown`.
+/// Сессия продлевается, только если пользователь сам не обращался к панели
+/// за последние `keepalive_interval_secs`.
+pub async fn run_session_until(state: ProxyState, shutdown: CancellationToken) {
+    let config = state.config.session.clone();
+    let mut monitor = SessionMonitor::new(&config, &state.config.alerts).with_sink(
+        Channel::Desktop,
+        Arc::new(NotifierSink::new(state.notifier.clone())),
+    );
+    let check_interval = Duration::from_secs(config.check_interval_secs.max(1));
+    let keepalive_interval = Duration::from_secs(config.keepalive_interval_secs.max(1));
+    let mut last_keepalive: Option<tokio::time::Instant> = None;
+    while !shutdown.is_cancelled() {
+        let due = last_keepalive.is_none_or(|t| t.elapsed() >= keepalive_interval);
+        if due && state.source.has_session(&state) && !state.panel_active_within(keepalive_interval) {
+            last_keepalive = Some(tokio::time::Instant::now());
+            // Ошибка уже записана в `state.session` и в лог.
+            let _ = keep_alive(&state, Utc::now()).await;
+        }
+        monitor.check(&state, Utc::now()).await;
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(check_interval) => {}
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Источники выплат: P2P-платформы, с панелей которых приложение забирает выплаты.
+//!
+//! Опрос (`run_idex`), перехват в прокси, хранилище, правила и оповещения
+//! работают с `PayoutSource` и нормализованной `Transaction`, не зная формата
+//! конкретной панели. Первая реализация — `GateSource` для panel.gate.cx;
+//! новая платформа добавляется реализацией трейта и строкой в `source_by_name`.
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use reqwest::{
+    header::{COOKIE, USER_AGENT},
+    Client, StatusCode,
+};
+use serde_json::Value;
+
+use crate::{
+    idex::{extract_payouts, is_payouts_path, map_transaction, Transaction, PAYOUTS_PATH},
+    panel::{PanelClient, PanelError, BROWSER_USER_AGENT},
+    proxy::ProxyState,
+};
+
+/// Имя источника по умолчанию.
+pub const GATE_SOURCE: &str = "gate";
+
+/// Сколько страниц списка просматривает один проход опроса.
+const MAX_PAGES: u32 = 10;
+
+pub type SourceFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, PanelError>> + Send + 'a>>;
+
+/// Страница списка выплат.
+#[derive(Debug, Clone, Default)]
+pub struct PayoutPage {
+    pub transactions: Vec<Transaction>,
+    /// Номер последней страницы, если панель его сообщает.
+    pub last_page: Option<u32>,
+}
+
+/// Платформа с выплатами. Сессия (куки аккаунта) передаётся в каждый вызов,
+/// поэтому один источник обслуживает любые профили.
+pub trait PayoutSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Есть ли с чем идти к панели; без сессии опрос ждёт входа пользователя.
+    fn has_session(&self, session: &ProxyState) -> bool {
+        !session.cookies.lock().unwrap().cookies.is_empty()
+    }
+
+    /// Сколько страниц списка просматривать за один проход.
+    fn max_pages(&self) -> u32 {
+        MAX_PAGES
+    }
+
+    /// Лёгкий авторизованный запрос, который продлевает сессию. По умолчанию —
+    /// первая страница списка выплат.
+    fn keep_alive<'a>(&'a self, session: &'a ProxyState) -> SourceFuture<'a, ()> {
+        Box::pin(async move { self.fetch_page(session, 1).await.map(|_| ()) })
+    }
+
+    /// Относится ли путь запроса к выплатам: такие ответы разбирает прокси.
+    fn is_payouts_path(&self, path: &str) -> bool;
+
+    /// Выплаты из ответа API панели, уже в виде `Transaction`.
+    fn extract_payouts(&self, json: &Value) -> Vec<Transaction>;
+
+    /// Страница списка активных выплат, начиная с 1.
+    fn fetch_page<'a>(&'a self, session: &'a ProxyState, page: u32) -> SourceFuture<'a, PayoutPage>;
+
+    fn claim<'a>(&'a self, session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction>;
+
+    fn mark_paid<'a>(
+        &'a self,
+        session: &'a ProxyState,
+        id: &'a str,
+        file_name: &'a str,
+        receipt: Vec<u8>,
+    ) -> SourceFuture<'a, Transaction>;
+
+    fn cancel<'a>(&'a self, session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction>;
+
+    fn details<'a>(&'a self, session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction>;
+}
+
+/// Источник по имени из config.json.
+pub fn source_by_name(name: &str) -> Option<Arc<dyn PayoutSource>> {
+    match name {
+        GATE_SOURCE => Some(Arc::new(GateSource::default())),
+        _ => None,
+    }
+}
+
+/// Фильтр статусов, с которым опрашивается список выплат gate.cx.
+const GATE_PAYOUTS_QUERY: &str = "filters%5Bstatus%5D%5B%5D=2&filters%5Bstatus%5D%5B%5D=3&filters%5Bstatus%5D%5B%5D=7&filters%5Bstatus%5D%5B%5D=8&filters%5Bstatus%5D%5B%5D=9&page=";
+
+/// panel.gate.cx: список в `response.payouts` (или `data.transactions`),
+/// действия — через `PanelClient`.
+#[derive(Default)]
+pub struct GateSource {
+    client: Client,
+}
+
+impl PayoutSource for GateSource {
+    fn name(&self) -> &'static str {
+        GATE_SOURCE
+    }
+
+    fn is_payouts_path(&self, path: &str) -> bool {
+        is_payouts_path(path)
+    }
+
+    fn extract_payouts(&self, json: &Value) -> Vec<Transaction> {
+        extract_payouts(json)
+            .into_iter()
+            .filter_map(map_transaction)
+            .collect()
+    }
+
+    fn fetch_page<'a>(&'a self, session: &'a ProxyState, page: u32) -> SourceFuture<'a, PayoutPage> {
+        Box::pin(async move {
+            let url = format!(
+                "{}{}?{}{}",
+                session.base_url.trim_end_matches('/'),
+                PAYOUTS_PATH,
+                GATE_PAYOUTS_QUERY,
+                page
+            );
+            let response = self
+                .client
+                .get(&url)
+                .header(USER_AGENT, BROWSER_USER_AGENT)
+                .header(COOKIE, session.cookie_header())
+                .send()
+                .await?;
+            let status = response.status();
+            if status == StatusCode::UNAUTHORIZED {
+                return Err(PanelError::Unauthorized);
+            }
+            if !status.is_success() {
+                return Err(PanelError::Rejected {
+                    status: status.as_u16(),
+                    message: status.canonical_reason().unwrap_or("").to_string(),
+                });
+            }
+            let json: Value = serde_json::from_str(&response.text().await?)
+                .map_err(|e| PanelError::InvalidResponse(e.to_string()))?;
+            let last_page = json
+                .get("response")
+                .and_then(|r| r.get("payouts"))
+                .and_then(|p| p.get("last_page"))
+                .and_then(|l| l.as_u64())
+                .map(|l| l as u32);
+            Ok(PayoutPage {
+                transactions: self.extract_payouts(&json),
+                last_page,
+            })
+        })
+    }
+
+    /// Баланс трейдера: самый короткий ответ панели, требующий входа.
+    fn keep_alive<'a>(&'a self, session: &'a ProxyState) -> SourceFuture<'a, ()> {
+        Box::pin(async move { PanelClient::new(session.clone()).balance().await.map(|_| ()) })
+    }
+
+    fn claim<'a>(&'a self, session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction> {
+        Box::pin(async move { PanelClient::new(session.clone()).claim(id).await })
+    }
+
+    fn mark_paid<'a>(
+        &'a self,
+        session: &'a ProxyState,
+        id: &'a str,
+        file_name: &'a str,
+        receipt: Vec<u8>,
+    ) -> SourceFuture<'a, Transaction> {
+        Box::pin(async move {
+            PanelClient::new(session.clone())
+                .mark_paid(id, file_name, receipt)
+                .await
+        })
+    }
+
+    fn cancel<'a>(&'a self, session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction> {
+        Box::pin(async move { PanelClient::new(session.clone()).cancel(id).await })
+    }
+
+    fn details<'a>(&'a self, session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction> {
+        Box::pin(async move { PanelClient::new(session.clone()).details(id).await })
+    }
+}
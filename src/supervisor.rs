@@ -0,0 +1,217 @@
+//! Надзор за фоновыми задачами Tokio: перезапуск упавших задач с нарастающей
+//! паузой, состояние задач и согласованная остановка приложения.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Сколько ждём остановки фоновых задач при выходе.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Задача, проработавшая дольше, считается стабильной, и пауза сбрасывается.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Running,
+    Restarting,
+    Stopped,
+}
+
+/// Состояние одной задачи под надзором.
+#[derive(Serialize, Debug, Clone)]
+pub struct TaskHealth {
+    pub status: TaskStatus,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    pub last_started: Option<DateTime<Utc>>,
+}
+
+/// Состояние задач по именам; разделяется с локальным API (`GET /__p2p/health`).
+pub type HealthMap = Arc<Mutex<BTreeMap<String, TaskHealth>>>;
+
+/// Монитор задачи и её текущий запуск.
+struct Supervised {
+    name: String,
+    monitor: JoinHandle<()>,
+    running: Arc<Mutex<Option<AbortHandle>>>,
+}
+
+pub struct Supervisor {
+    shutdown: CancellationToken,
+    health: HealthMap,
+    handles: Mutex<Vec<Supervised>>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self {
+            shutdown: CancellationToken::new(),
+            health: Arc::new(Mutex::new(BTreeMap::new())),
+            handles: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Токен остановки, который получают все задачи.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Запускает задачу под надзором. `task` вызывается заново при каждом перезапуске;
+    /// задача должна завершиться сама, когда сработает переданный ей токен.
+    pub fn spawn<F, Fut>(&self, name: &str, task: F)
+    where
+        F: Fn(CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let name = name.to_string();
+        let shutdown = self.shutdown.clone();
+        let health = self.health.clone();
+        set_health(&health, &name, |h| h.status = TaskStatus::Running);
+
+        let running: Arc<Mutex<Option<AbortHandle>>> = Arc::default();
+        let monitor_running = running.clone();
+        let monitor_name = name.clone();
+        let monitor = tokio::spawn(async move {
+            let name = monitor_name;
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let started = Instant::now();
+                set_health(&health, &name, |h| {
+                    h.status = TaskStatus::Running;
+                    h.last_started = Some(Utc::now());
+                });
+
+                let run = tokio::spawn(task(shutdown.clone()));
+                *monitor_running.lock().unwrap() = Some(run.abort_handle());
+                let result = run.await;
+                if shutdown.is_cancelled() {
+                    break;
+                }
+
+                let reason = match result {
+                    Ok(()) => "task exited unexpectedly".to_string(),
+                    Err(e) if e.is_panic() => format!("task panicked: {}", panic_message(e)),
+                    Err(e) => format!("task failed: {}", e),
+                };
+                if started.elapsed() >= STABLE_AFTER {
+                    backoff = INITIAL_BACKOFF;
+                }
+                error!(task = %name, reason = %reason, retry_in = ?backoff, "Background task stopped, restarting");
+                set_health(&health, &name, |h| {
+                    h.status = TaskStatus::Restarting;
+                    h.restarts += 1;
+                    h.last_error = Some(reason.clone());
+                });
+
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            set_health(&health, &name, |h| h.status = TaskStatus::Stopped);
+        });
+        self.handles.lock().unwrap().push(Supervised {
+            name,
+            monitor,
+            running,
+        });
+    }
+
+    /// Снимок состояния всех задач.
+    pub fn health(&self) -> BTreeMap<String, TaskHealth> {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Общая карта состояния задач, которая обновляется по ходу работы.
+    pub fn health_map(&self) -> HealthMap {
+        self.health.clone()
+    }
+
+    /// Останавливает все задачи и ждёт их завершения не дольше `timeout`.
+    /// Не успевшие задачи прерываются вместе с их мониторами; возвращает
+    /// `false`, если кого-то пришлось прервать принудительно.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.shutdown.cancel();
+        let handles: Vec<_> = self.handles.lock().unwrap().drain(..).collect();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut clean = true;
+        for mut task in handles {
+            match tokio::time::timeout_at(deadline, &mut task.monitor).await {
+                Ok(_) => info!(task = %task.name, "Background task stopped"),
+                Err(_) => {
+                    warn!(task = %task.name, "Background task did not stop in time, aborting");
+                    task.monitor.abort();
+                    if let Some(run) = task.running.lock().unwrap().take() {
+                        run.abort();
+                    }
+                    clean = false;
+                }
+            }
+        }
+        clean
+    }
+}
+
+fn set_health(health: &HealthMap, name: &str, update: impl FnOnce(&mut TaskHealth)) {
+    let mut health = health.lock().unwrap();
+    let entry = health.entry(name.to_string()).or_insert(TaskHealth {
+        status: TaskStatus::Running,
+        restarts: 0,
+        last_error: None,
+        last_started: None,
+    });
+    update(entry);
+}
+
+fn panic_message(e: tokio::task::JoinError) -> String {
+    let payload = e.into_panic();
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Ждёт Ctrl+C, а на Unix также SIGTERM.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to listen for SIGTERM");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
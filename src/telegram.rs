@@ -1,80 +1,72 @@
-use std::{fs, path::PathBuf};
-use serde::{Deserialize, Serialize};
+//! Окно веб-версии Telegram. Открывается кнопкой оверлея окна IDEX;
+//! собирается только с фичей `gui`.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use tracing::{debug, warn};
 use wry::{
-    application::{
-        dpi::LogicalSize,
-        event_loop::EventLoopWindowTarget,
-        window::WindowBuilder,
-    },
+    application::{dpi::LogicalSize, event_loop::EventLoopWindowTarget, window::WindowBuilder},
     webview::{WebView, WebViewBuilder},
 };
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
-pub struct TelegramCookieStore {
-    pub cookies: Vec<Cookie>,
-}
+use p2p_app::{
+    cookie_sync::{apply_document_cookies, seed_script, CAPTURE_SCRIPT},
+    ipc::{parse_page_message, PageMessage, BRIDGE_SCRIPT},
+    proxy::{load_cookies_from, save_cookies_to, CookieStore},
+};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Cookie {
-    pub name: String,
-    pub value: String,
-    pub domain: String,
-    pub path: String,
-    pub expiration_date: Option<f64>,
-    pub host_only: Option<bool>,
-    pub http_only: Option<bool>,
-    pub same_site: Option<String>,
-    pub secure: Option<bool>,
-    pub session: Option<bool>,
-    pub store_id: Option<String>,
-}
+use crate::gui::Command;
 
-fn save_telegram_cookies(cookies: &TelegramCookieStore) -> std::io::Result<()> {
-    let path = PathBuf::from("telegram_cookie.json");
-    let json = serde_json::to_string_pretty(cookies)?;
-    fs::write(path, json)?;
-    Ok(())
-}
+const TELEGRAM_URL: &str = "https://web.telegram.org/";
+const TELEGRAM_HOST: &str = "web.telegram.org";
+const TELEGRAM_COOKIES_FILE: &str = "telegram_cookie.json";
 
-fn load_telegram_cookies() -> TelegramCookieStore {
-    let path = PathBuf::from("telegram_cookie.json");
-    if path.exists() {
-        if let Ok(content) = fs::read_to_string(&path) {
-            if let Ok(cookies) = serde_json::from_str::<TelegramCookieStore>(&content) {
-                return cookies;
-            }
-        }
-    }
-    TelegramCookieStore::default()
+fn cookies_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(TELEGRAM_COOKIES_FILE)
 }
 
 /// Создает и отображает окно с веб-версией Telegram, используя переданный target.
-/// Возвращает WebView или ошибку.
+/// Куки из `telegram_cookie.json` кладутся в страницу при открытии, а их
+/// изменения сохраняются обратно в файл.
 pub fn show_telegram(
-    target: &EventLoopWindowTarget<super::Command>,
+    target: &EventLoopWindowTarget<Command>,
+    data_dir: &Path,
 ) -> wry::Result<WebView> {
     let window = WindowBuilder::new()
         .with_title("Telegram")
         .with_inner_size(LogicalSize::new(1024.0, 768.0))
         .build(target)?;
-    let cookies = load_telegram_cookies();
-    let cookie_script = cookies
-        .cookies
-        .iter()
-        .map(|c| format!("document.cookie = '{}={}; path={}';", c.name, c.value, c.path))
-        .collect::<Vec<_>>()
-        .join("\n");
+    let path = cookies_path(data_dir);
+    let store: CookieStore = load_cookies_from(&path).unwrap_or_default();
+    let cookie_script = seed_script(&store.cookies, chrono::Utc::now());
+    let store = Mutex::new((store, HashSet::new()));
 
-    let telegram_url = "https://web.telegram.org/";
     let webview = WebViewBuilder::new(window)?
-        .with_url(telegram_url)?
+        .with_url(TELEGRAM_URL)?
+        .with_initialization_script(BRIDGE_SCRIPT)
         .with_initialization_script(&cookie_script)
+        .with_initialization_script(CAPTURE_SCRIPT)
         .with_initialization_script(
             r#"
             document.addEventListener('contextmenu', event => { event.preventDefault(); });
             "#,
         )
+        .with_ipc_handler(move |_, message| match parse_page_message(&message) {
+            Ok(PageMessage::Cookies { cookies }) => {
+                let (store, seen) = &mut *store.lock().unwrap();
+                if apply_document_cookies(store, seen, &cookies, TELEGRAM_HOST) {
+                    if let Err(e) = save_cookies_to(&path, store) {
+                        warn!(error = %e, "Не удалось сохранить куки Telegram");
+                    }
+                }
+            }
+            Ok(other) => debug!(?other, "Сообщение IPC пропущено"),
+            Err(e) => debug!(error = %e, "Invalid IPC message"),
+        })
         .build()?;
-    webview.evaluate_script(&cookie_script)?;
     Ok(webview)
 }
@@ -0,0 +1,36 @@
+use crate::metrics::metrics;
+
+/// Проверяет device token через API; `true`, только если сервер ответил `valid: true`.
+pub async fn verify_device_token(api_url: &str, device_token: &str) -> bool {
+    let result = request_verification(api_url, device_token).await;
+    let label = match result {
+        Some(true) => "valid",
+        Some(false) => "invalid",
+        None => "error",
+    };
+    metrics()
+        .token_verifications
+        .with_label_values(&[label])
+        .inc();
+    result == Some(true)
+}
+
+/// `None`, если сервер недоступен или ответил не JSON.
+async fn request_verification(api_url: &str, device_token: &str) -> Option<bool> {
+    let client = reqwest::Client::new();
+    let payload = serde_json::json!({ "deviceToken": device_token });
+    match client.post(api_url).json(&payload).send().await {
+        Ok(resp) => {
+            if let Ok(json) = resp.json::<serde_json::Value>().await {
+                // Если сервер вернул поле valid: true — токен валиден.
+                Some(json.get("valid").and_then(|v| v.as_bool()) == Some(true))
+            } else {
+                None
+            }
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, "Ошибка запроса проверки токена");
+            None
+        }
+    }
+}
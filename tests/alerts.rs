@@ -0,0 +1,199 @@
+//! Оповещения об истекающих выплатах: пороги, просрочки и доставка в Telegram.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use p2p_app::{
+    alerts::{
+        Alert, AlertKind, AlertSink, AlertsConfig, Channel, ExpiryScheduler, SinkFuture,
+        TelegramConfig,
+    },
+    idex::{load_transactions, map_transaction, status_name, Transaction},
+    mock::MockTelegram,
+};
+use serde_json::{json, Value};
+
+/// Запоминает доставленные оповещения вместо показа.
+#[derive(Default, Clone)]
+struct Recorder(Arc<Mutex<Vec<(Channel, String)>>>);
+
+struct RecordingSink {
+    channel: Channel,
+    recorder: Recorder,
+}
+
+impl AlertSink for RecordingSink {
+    fn deliver<'a>(&'a self, alert: &'a Alert) -> SinkFuture<'a> {
+        Box::pin(async move {
+            self.recorder
+                .0
+                .lock()
+                .unwrap()
+                .push((self.channel, alert.transaction_id.clone()));
+            Ok(())
+        })
+    }
+}
+
+fn scheduler(config: &AlertsConfig, dir: &std::path::Path, recorder: &Recorder) -> ExpiryScheduler {
+    let mut scheduler = ExpiryScheduler::new(config, dir);
+    for channel in [Channel::Desktop, Channel::Sound, Channel::Telegram] {
+        scheduler = scheduler.with_sink(
+            channel,
+            Arc::new(RecordingSink {
+                channel,
+                recorder: recorder.clone(),
+            }),
+        );
+    }
+    scheduler
+}
+
+fn payout(id: u64, expired_at: &str) -> Transaction {
+    map_transaction(&json!({
+        "id": id,
+        "wallet": "79000000001",
+        "amount": { "trader": { "643": 1500.0 } },
+        "status": "in_progress",
+        "expired_at": expired_at,
+        "created_at": "2025-02-05T03:34:01.000000Z",
+        "updated_at": "2025-02-05T03:44:05.000000Z",
+    }))
+    .unwrap()
+}
+
+fn at(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+}
+
+#[tokio::test]
+async fn escalates_through_thresholds() {
+    let dir = tempfile::tempdir().unwrap();
+    let recorder = Recorder::default();
+    let mut scheduler = scheduler(&AlertsConfig::default(), dir.path(), &recorder);
+    let txs = vec![payout(1, "2025-02-05T04:00:00Z")];
+
+    assert!(scheduler.check(&txs, at("2025-02-05T03:45:00Z")).await.is_empty());
+    let alerts = scheduler.check(&txs, at("2025-02-05T03:51:00Z")).await;
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].kind, AlertKind::Expiring { remaining_secs: 540 });
+    assert_eq!(alerts[0].channels, vec![Channel::Desktop]);
+    // Тот же порог повторно не срабатывает.
+    assert!(scheduler.check(&txs, at("2025-02-05T03:52:00Z")).await.is_empty());
+
+    let alerts = scheduler.check(&txs, at("2025-02-05T03:56:00Z")).await;
+    assert_eq!(alerts[0].channels, vec![Channel::Desktop, Channel::Sound]);
+    let alerts = scheduler.check(&txs, at("2025-02-05T03:59:00Z")).await;
+    assert_eq!(
+        alerts[0].channels,
+        vec![Channel::Desktop, Channel::Sound, Channel::Telegram]
+    );
+    assert!(alerts[0].message.contains("1500"));
+    assert_eq!(recorder.0.lock().unwrap().len(), 6);
+}
+
+#[test]
+fn skipped_thresholds_fire_only_the_most_urgent() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut scheduler = ExpiryScheduler::new(&AlertsConfig::default(), dir.path());
+    let mut paid = payout(2, "2025-02-05T04:00:00Z");
+    paid.approved_at = Some("2025-02-05T03:58:00Z".to_string());
+    let txs = vec![payout(1, "2025-02-05T04:00:00Z"), paid];
+
+    let alerts = scheduler.due(&txs, at("2025-02-05T03:59:30Z"));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].transaction_id, "1");
+    assert_eq!(alerts[0].channels.len(), 3);
+    assert!(scheduler.due(&txs, at("2025-02-05T03:59:40Z")).is_empty());
+}
+
+#[tokio::test]
+async fn records_missed_deadlines_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let recorder = Recorder::default();
+    let config = AlertsConfig::default();
+    let txs = vec![payout(7, "2025-02-05T04:00:00Z")];
+
+    let mut first = scheduler(&config, dir.path(), &recorder);
+    first.check(&txs, at("2025-02-05T03:30:00Z")).await;
+    let alerts = first.check(&txs, at("2025-02-05T04:00:05Z")).await;
+    assert_eq!(alerts[0].kind, AlertKind::Missed);
+    assert!(first.check(&txs, at("2025-02-05T04:01:00Z")).await.is_empty());
+
+    // После перезапуска просрочка не записывается повторно.
+    let mut second = scheduler(&config, dir.path(), &recorder);
+    assert!(second.check(&txs, at("2025-02-05T04:02:00Z")).await.is_empty());
+
+    let log = std::fs::read_to_string(dir.path().join("missed_deadlines.jsonl")).unwrap();
+    assert_eq!(log.lines().count(), 1);
+    assert!(log.contains("\"transaction_id\":\"7\""));
+}
+
+#[tokio::test]
+async fn sends_telegram_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let telegram = MockTelegram::start("123:secret");
+    let config = AlertsConfig {
+        thresholds: Vec::new(),
+        missed_channels: vec![Channel::Telegram],
+        telegram: Some(TelegramConfig {
+            bot_token: "123:secret".to_string(),
+            chat_id: "-100500".to_string(),
+            api_url: telegram.api_url(),
+        }),
+        ..Default::default()
+    };
+    let mut scheduler = ExpiryScheduler::new(&config, dir.path());
+    let txs = [payout(3, "2025-02-05T04:00:00Z")];
+    scheduler.check(&txs, at("2025-02-05T03:50:00Z")).await;
+    scheduler.check(&txs, at("2025-02-05T04:10:00Z")).await;
+
+    let messages = telegram.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].0, "-100500");
+    assert!(messages[0].1.contains("Выплата просрочена"));
+    assert!(messages[0].1.contains('3'));
+}
+
+#[tokio::test]
+async fn historic_payouts_raise_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let recorder = Recorder::default();
+    // История из репозитория: среди старых записей есть неоплаченные.
+    let history = load_transactions(Path::new(env!("CARGO_MANIFEST_DIR")));
+    assert!(history.iter().any(|tx| tx.approved_at.is_none()));
+    let mut scheduler = scheduler(&AlertsConfig::default(), dir.path(), &recorder);
+
+    assert!(scheduler.check(&history, Utc::now()).await.is_empty());
+    assert!(scheduler.check(&history, at("2025-02-05T04:00:00Z")).await.is_empty());
+    assert!(recorder.0.lock().unwrap().is_empty());
+    assert!(!dir.path().join("missed_deadlines.jsonl").exists());
+    assert!(!history.is_empty());
+}
+
+#[test]
+fn only_payouts_with_an_active_status_are_tracked() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut scheduler = ExpiryScheduler::new(&AlertsConfig::default(), dir.path());
+    let with = |id: u64, status: Value| {
+        let mut tx = payout(id, "2025-02-05T04:00:00Z");
+        tx.status = status_name(&status);
+        tx
+    };
+    // Числовой код панели остаётся как есть: его значение неизвестно.
+    let coded = map_transaction(&json!({ "id": 1, "status": 2 })).unwrap();
+    assert_eq!(coded.status.as_deref(), Some("2"));
+
+    let txs = vec![
+        with(1, json!(2)),
+        with(2, json!("completed")),
+        with(3, Value::Null),
+        with(4, json!("in_progress")),
+    ];
+    let alerts = scheduler.due(&txs, at("2025-02-05T03:59:00Z"));
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].transaction_id, "4");
+}
@@ -0,0 +1,156 @@
+//! Отчёт по обороту: группировка по дням, сменам и банкам, комиссия и курс.
+
+use p2p_app::{
+    analytics::{build_report, render_text, AnalyticsConfig},
+    export::parse_date,
+    idex::{load_transactions, Transaction},
+    money::{Decimal, PartyAmounts},
+};
+
+fn dec(value: &str) -> Decimal {
+    value.parse().unwrap()
+}
+
+fn trader(entries: &[(&str, &str)]) -> PartyAmounts {
+    let amounts = entries
+        .iter()
+        .map(|(code, value)| (code.to_string(), dec(value)))
+        .collect();
+    PartyAmounts::from([("trader".to_string(), amounts)])
+}
+
+fn transaction(
+    id: &str,
+    created_at: &str,
+    bank: &str,
+    amount: (&str, &str),
+    total: (&str, &str),
+    success_rate: Option<f64>,
+) -> Transaction {
+    Transaction {
+        user_id: None,
+        transaction_id: id.to_string(),
+        payment_method_id: None,
+        wallet: None,
+        amount: trader(&[("643", amount.0), ("000001", amount.1)]),
+        total: trader(&[("643", total.0), ("000001", total.1)]),
+        status: Some("completed".to_string()),
+        bank_name: None,
+        bank_code: None,
+        bank_label: Some(bank.to_string()),
+        payment_method: None,
+        course: Some(dec(amount.0) / dec(amount.1)),
+        success_count: None,
+        success_rate,
+        approved_at: None,
+        expired_at: None,
+        created_at: created_at.to_string(),
+        updated_at: created_at.to_string(),
+        trader_id: None,
+        trader_name: None,
+        attachments: None,
+        idex_id: None,
+    }
+}
+
+fn history() -> Vec<Transaction> {
+    vec![
+        // 10:00 МСК 5 февраля — дневная смена.
+        transaction("1", "2025-02-05T07:00:00Z", "Сбербанк", ("1000", "10"), ("1020", "10.2"), Some(90.0)),
+        // 23:00 МСК 5 февраля — ночная смена.
+        transaction("2", "2025-02-05T20:00:00Z", "Тинькофф", ("3500", "35"), ("3570", "35.7"), Some(80.0)),
+        // 01:00 МСК 6 февраля, хотя по UTC это ещё 5-е.
+        transaction("3", "2025-02-05T22:00:00Z", "Сбербанк", ("2000", "25"), ("2040", "25.5"), Some(95.0)),
+    ]
+}
+
+#[test]
+fn groups_by_local_day_shift_and_bank() {
+    let report = build_report(&history(), &AnalyticsConfig::default(), None, None);
+
+    assert_eq!(report.total.count, 3);
+    assert_eq!(report.total.amount["RUB"], dec("6500"));
+    assert_eq!(report.total.spread["RUB"], dec("130"));
+    // Десятичная арифметика без накопленной погрешности.
+    assert_eq!(report.total.spread["USDT"], dec("1.4"));
+    assert_eq!(report.total.effective_course["RUB/USDT"], dec("92.857143"));
+
+    let days: Vec<(&str, u64)> = report.by_day.iter().map(|d| (d.key.as_str(), d.count)).collect();
+    assert_eq!(days, vec![("2025-02-05", 2), ("2025-02-06", 1)]);
+    assert_eq!(report.by_day[0].avg_success_rate, Some(85.0));
+    assert_eq!(report.by_day[1].success_rate_change, Some(10.0));
+
+    let shifts: Vec<(&str, u64)> = report.by_shift.iter().map(|s| (s.key.as_str(), s.count)).collect();
+    assert_eq!(shifts, vec![("day", 1), ("night", 2)]);
+
+    // Банки отсортированы по числу выплат.
+    assert_eq!(report.by_bank[0].key, "Сбербанк");
+    assert_eq!(report.by_bank[0].amount["RUB"], dec("3000"));
+    assert_eq!(report.by_bank[1].key, "Тинькофф");
+}
+
+#[test]
+fn range_uses_local_dates() {
+    let day = parse_date("2025-02-06").unwrap();
+    let report = build_report(&history(), &AnalyticsConfig::default(), Some(day), Some(day));
+    assert_eq!(report.total.count, 1);
+    assert_eq!(report.by_bank[0].key, "Сбербанк");
+    assert!(render_text(&report).contains("2025-02-06"));
+}
+
+#[test]
+fn works_for_any_currency() {
+    let mut tx = history().remove(0);
+    tx.amount = trader(&[("398", "50000"), ("000001", "100")]);
+    tx.total = trader(&[("398", "51000")]);
+    let report = build_report(&[tx], &AnalyticsConfig::default(), None, None);
+
+    assert_eq!(report.total.amount["KZT"], dec("50000"));
+    assert_eq!(report.total.spread["KZT"], dec("1000"));
+    assert!(!report.total.spread.contains_key("USDT"));
+    assert_eq!(report.total.effective_course["KZT/USDT"], dec("500"));
+    assert!(render_text(&report).contains("50000.00 KZT"));
+}
+
+#[test]
+fn only_completed_payouts_count_towards_turnover() {
+    let mut history = history();
+    history[1].status = Some("cancelled".to_string());
+    history[2].status = Some("pending".to_string());
+    let mut unknown = history[0].clone();
+    unknown.transaction_id = "4".to_string();
+    unknown.status = None;
+    history.push(unknown);
+    let report = build_report(&history, &AnalyticsConfig::default(), None, None);
+
+    assert_eq!(report.total.count, 1);
+    assert_eq!(report.total.amount["RUB"], dec("1000"));
+    assert_eq!(report.by_bank.len(), 1);
+    let skipped: Vec<(&str, u64)> = report.not_completed.iter().map(|(s, c)| (s.as_str(), *c)).collect();
+    assert_eq!(skipped, vec![("cancelled", 1), ("pending", 1), ("unknown", 1)]);
+    assert!(render_text(&report).contains("Not completed (excluded): cancelled 1, pending 1, unknown 1"));
+}
+
+#[test]
+fn baseline_history_counts_approved_payouts_as_completed() {
+    let dir = tempfile::tempdir().unwrap();
+    // Формат исходной версии: суммы `f64`, статус не сохранялся.
+    let baseline = r#"[
+        {"transaction_id": "1", "amount_rub": 14438.0, "amount_usdt": 145.96,
+         "total_rub": 14755.636, "total_usdt": 149.17, "course": 98.92, "status": null,
+         "bank_label": "Озон Банк (Ozon)", "approved_at": "2025-02-05T03:43:34.000000Z",
+         "created_at": "2025-02-05T03:34:01.000000Z", "updated_at": "2025-02-05T03:44:05.000000Z"},
+        {"transaction_id": "2", "amount_rub": 5000.0, "amount_usdt": 50.0,
+         "total_rub": 5100.0, "total_usdt": 51.0, "course": 100.0, "status": null,
+         "approved_at": null,
+         "created_at": "2025-02-05T04:34:01.000000Z", "updated_at": "2025-02-05T04:44:05.000000Z"}
+    ]"#;
+    std::fs::write(dir.path().join("idex_history.json"), baseline).unwrap();
+
+    let history = load_transactions(dir.path());
+    assert_eq!(history[0].status.as_deref(), Some("completed"));
+    let report = build_report(&history, &AnalyticsConfig::default(), None, None);
+    assert_eq!(report.total.count, 1);
+    assert_eq!(report.total.amount["RUB"], dec("14438.0"));
+    assert_eq!(report.not_completed.get("unknown"), Some(&1));
+}
@@ -0,0 +1,198 @@
+//! Загрузка чеков из вложений выплат: хранение по хешу, докачка и проверки.
+
+use std::path::Path;
+
+use p2p_app::{
+    attachments::{attachment_refs, AttachmentStore, AttachmentsConfig, SyncStats},
+    idex::{load_transactions, map_transaction},
+    mock::{MockPanel, MockPanelConfig},
+    proxy::{Cookie, ProxyState},
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+fn pdf(marker: &str) -> Vec<u8> {
+    let mut content = b"%PDF-1.4\n".to_vec();
+    content.extend(format!("receipt {}\n", marker).repeat(200).into_bytes());
+    content.extend(b"%%EOF\n");
+    content
+}
+
+fn payout(id: u64, original_url: &str, size: usize) -> Value {
+    json!({
+        "id": id,
+        "wallet": "79000000001",
+        "amount": { "trader": { "643": 1000.0 } },
+        "status": "in_progress",
+        "created_at": "2025-02-05T03:34:01.000000Z",
+        "updated_at": "2025-02-05T03:44:05.000000Z",
+        "attachments": [{
+            "custom_properties": { "fake": true },
+            "extension": "pdf",
+            "file_name": Path::new(original_url).file_name().unwrap().to_str().unwrap(),
+            "original_url": original_url,
+            "size": size,
+        }],
+    })
+}
+
+fn state_with(panel: &MockPanel, data_dir: &Path, payouts: &[Value]) -> ProxyState {
+    let state = ProxyState::new(panel.base_url(), data_dir.to_path_buf());
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        name: "sid".to_string(),
+        value: "test-sid".to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: Some(true),
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    });
+    state
+        .transactions
+        .lock()
+        .unwrap()
+        .extend(payouts.iter().filter_map(map_transaction));
+    state
+}
+
+fn panel() -> MockPanel {
+    MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            ..Default::default()
+        },
+        Vec::new(),
+    )
+}
+
+fn config() -> AttachmentsConfig {
+    AttachmentsConfig {
+        retry_delay_secs: 0,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn downloads_and_links_receipts() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = panel();
+    let content = pdf("1");
+    panel.add_file("/storage/1724608/document.pdf", content.clone());
+    let state = state_with(&panel, dir.path(), &[payout(1, "1724608/document.pdf", content.len())]);
+
+    let refs = attachment_refs(&state.transactions.lock().unwrap()[0]);
+    assert_eq!(refs.len(), 1);
+    assert!(refs[0].fake);
+    assert_eq!(refs[0].size, Some(content.len() as u64));
+
+    let client = Client::new();
+    let mut store = AttachmentStore::open(dir.path(), &config());
+    let stats = store.sync(&state, &client).await;
+    assert_eq!(stats.downloaded, 1);
+    assert_eq!(stats.linked, 1);
+
+    let record = store.record("1724608/document.pdf").unwrap().clone();
+    let sha256 = record.sha256.clone().unwrap();
+    let local_path = record.local_path.clone().unwrap();
+    assert_eq!(local_path, format!("attachments/{}/{}.pdf", &sha256[..2], sha256));
+    assert_eq!(std::fs::read(dir.path().join(&local_path)).unwrap(), content);
+    assert!(panel.requests()[0]
+        .cookie
+        .as_deref()
+        .is_some_and(|c| c.contains("sid=test-sid")));
+
+    // Путь к файлу сохранён в истории рядом с метаданными вложения.
+    let saved = load_transactions(dir.path());
+    let attachment = &saved[0].attachments.as_ref().unwrap()[0];
+    assert_eq!(attachment["local_path"], json!(local_path));
+    assert_eq!(attachment["sha256"], json!(sha256));
+
+    // Повторный проход и новое хранилище с тем же индексом ничего не качают.
+    assert_eq!(store.sync(&state, &client).await, SyncStats::default());
+    let mut reopened = AttachmentStore::open(dir.path(), &config());
+    assert_eq!(reopened.sync(&state, &client).await, SyncStats::default());
+    assert_eq!(panel.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn identical_receipts_are_stored_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = panel();
+    let content = pdf("same");
+    panel.add_file("/storage/1/a.pdf", content.clone());
+    panel.add_file("/storage/2/b.pdf", content.clone());
+    let state = state_with(
+        &panel,
+        dir.path(),
+        &[payout(1, "1/a.pdf", content.len()), payout(2, "2/b.pdf", content.len())],
+    );
+
+    let mut store = AttachmentStore::open(dir.path(), &config());
+    let stats = store.sync(&state, &Client::new()).await;
+    assert_eq!((stats.downloaded, stats.deduplicated, stats.linked), (1, 1, 2));
+    assert_eq!(
+        store.record("1/a.pdf").unwrap().local_path,
+        store.record("2/b.pdf").unwrap().local_path
+    );
+}
+
+#[tokio::test]
+async fn resumes_interrupted_download() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = panel();
+    let content = pdf("resume");
+    panel.add_file("/storage/1/check.pdf", content.clone());
+    panel.interrupt_files(1);
+    let state = state_with(&panel, dir.path(), &[payout(1, "1/check.pdf", content.len())]);
+    let client = Client::new();
+
+    let mut store = AttachmentStore::open(dir.path(), &config());
+    let stats = store.sync(&state, &client).await;
+    assert_eq!(stats.failed, 1);
+    let record = store.record("1/check.pdf").unwrap();
+    assert_eq!(record.attempts, 1);
+    assert!(record.last_error.is_some());
+
+    // Докачка продолжается и после перезапуска: состояние берётся из индекса.
+    let mut store = AttachmentStore::open(dir.path(), &config());
+    let stats = store.sync(&state, &client).await;
+    assert_eq!(stats.downloaded, 1);
+    let requests = panel.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[1].range.as_deref(),
+        Some(format!("bytes={}-", content.len() / 2).as_str())
+    );
+    let local_path = store.record("1/check.pdf").unwrap().local_path.clone().unwrap();
+    assert_eq!(std::fs::read(dir.path().join(local_path)).unwrap(), content);
+}
+
+#[tokio::test]
+async fn rejects_wrong_size_and_gives_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = panel();
+    let content = pdf("short");
+    panel.add_file("/storage/1/check.pdf", content.clone());
+    let state = state_with(&panel, dir.path(), &[payout(1, "1/check.pdf", content.len() + 10)]);
+    let client = Client::new();
+
+    let mut store = AttachmentStore::open(
+        dir.path(),
+        &AttachmentsConfig {
+            max_attempts: 2,
+            ..config()
+        },
+    );
+    for _ in 0..3 {
+        store.sync(&state, &client).await;
+    }
+    let record = store.record("1/check.pdf").unwrap();
+    assert_eq!(record.attempts, 2);
+    assert!(record.local_path.is_none());
+    assert!(record.last_error.as_deref().unwrap().contains("size mismatch"));
+    assert_eq!(panel.requests().len(), 2);
+}
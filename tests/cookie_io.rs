@@ -0,0 +1,197 @@
+//! Импорт и экспорт кук: JSON расширений браузера, Netscape `cookies.txt`,
+//! строка заголовка, проверка домена и команды `cookies import/export`.
+
+use chrono::{DateTime, Utc};
+use p2p_app::{
+    cli::{parse_args, run, Invocation},
+    config::AppConfig,
+    cookie_io::{
+        domain_matches, export_cookies, filter_importable, import_into, parse_cookies,
+        CookieFormat, CookieIoError,
+    },
+    proxy::{load_cookies, save_cookies, Cookie, CookieStore, ProxyState},
+};
+use serde_json::Value;
+
+fn now() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2025-02-05T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+const EXTENSION_JSON: &str = r#"[
+  {
+    "domain": ".gate.cx",
+    "expirationDate": 2051222400.5,
+    "hostOnly": false,
+    "httpOnly": true,
+    "name": "sid",
+    "path": "/",
+    "sameSite": "no_restriction",
+    "secure": true,
+    "session": false,
+    "storeId": "0",
+    "value": "abc",
+    "id": 1
+  },
+  { "domain": "panel.gate.cx", "name": "XSRF-TOKEN", "value": "x" }
+]"#;
+
+const COOKIES_TXT: &str = "# Netscape HTTP Cookie File\n\
+# https://curl.se/docs/http-cookies.html\n\
+\n\
+#HttpOnly_.gate.cx\tTRUE\t/\tTRUE\t2051222400\tsid\tabc\n\
+panel.gate.cx\tFALSE\t/\tFALSE\t0\tlang\tru\r\n";
+
+#[test]
+fn parses_all_formats() {
+    let json = parse_cookies(EXTENSION_JSON, None, "panel.gate.cx").unwrap();
+    assert_eq!(json.len(), 2);
+    assert_eq!(json[0].expiration_date, Some(2051222400.5));
+    assert_eq!(json[0].host_only, Some(false));
+    assert_eq!(json[0].http_only, Some(true));
+    assert_eq!(json[0].same_site.as_deref(), Some("no_restriction"));
+    assert_eq!(json[0].store_id.as_deref(), Some("0"));
+    assert_eq!(json[1].path, "/");
+    assert_eq!(json[1].session, Some(true));
+
+    // cookies.json самого приложения тоже подходит.
+    let store = serde_json::to_string(&CookieStore {
+        cookies: json.clone(),
+    })
+    .unwrap();
+    assert_eq!(parse_cookies(&store, Some(CookieFormat::Json), "h").unwrap().len(), 2);
+
+    let txt = parse_cookies(COOKIES_TXT, None, "panel.gate.cx").unwrap();
+    assert_eq!(txt.len(), 2);
+    assert_eq!((txt[0].name.as_str(), txt[0].domain.as_str()), ("sid", ".gate.cx"));
+    assert_eq!(txt[0].http_only, Some(true));
+    assert_eq!(txt[0].secure, Some(true));
+    assert_eq!(txt[0].expiration_date, Some(2051222400.0));
+    assert_eq!(txt[1].value, "ru");
+    assert_eq!(txt[1].expiration_date, None);
+    assert_eq!(txt[1].session, Some(true));
+
+    let header = parse_cookies("Cookie: sid=abc; lang=ru", None, "panel.gate.cx").unwrap();
+    assert_eq!(header.len(), 2);
+    assert!(header.iter().all(|c| c.domain == "panel.gate.cx" && c.path == "/"));
+
+    assert_eq!(
+        parse_cookies("panel.gate.cx\tFALSE\t/\n", Some(CookieFormat::Netscape), "h").unwrap_err(),
+        CookieIoError::Netscape {
+            line: 1,
+            message: "expected 7 tab-separated fields".to_string()
+        }
+    );
+    assert!(matches!(parse_cookies("[{", None, "h"), Err(CookieIoError::Json(_))));
+    assert_eq!(parse_cookies("  ", None, "h").unwrap_err(), CookieIoError::Empty);
+    assert_eq!(
+        CookieFormat::parse("xml"),
+        Err(CookieIoError::UnknownFormat("xml".to_string()))
+    );
+}
+
+#[test]
+fn keeps_only_cookies_for_the_panel() {
+    assert!(domain_matches("panel.gate.cx", "panel.gate.cx"));
+    assert!(domain_matches(".gate.cx", "panel.gate.cx"));
+    assert!(domain_matches("GATE.cx", "panel.gate.cx"));
+    assert!(!domain_matches("evilgate.cx", "panel.gate.cx"));
+    assert!(!domain_matches("other.gate.cx", "panel.gate.cx"));
+    assert!(!domain_matches("", "panel.gate.cx"));
+
+    let mut cookies = parse_cookies(EXTENSION_JSON, None, "panel.gate.cx").unwrap();
+    cookies.push(Cookie {
+        domain: ".google.com".to_string(),
+        ..cookies[1].clone()
+    });
+    cookies.push(Cookie {
+        name: "old".to_string(),
+        expiration_date: Some(1.0),
+        ..cookies[1].clone()
+    });
+    let (accepted, report) = filter_importable(cookies, "panel.gate.cx", now());
+    assert_eq!(accepted.len(), 3);
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.expired, 1);
+    assert_eq!(report.rejected, vec!["XSRF-TOKEN (.google.com)".to_string()]);
+}
+
+#[test]
+fn exports_round_trip() {
+    let cookies = parse_cookies(COOKIES_TXT, None, "panel.gate.cx").unwrap();
+
+    let txt = export_cookies(&cookies, CookieFormat::Netscape, now());
+    assert!(txt.starts_with("# Netscape HTTP Cookie File\n"));
+    assert!(txt.contains("#HttpOnly_.gate.cx\tTRUE\t/\tTRUE\t2051222400\tsid\tabc\n"));
+    let back = parse_cookies(&txt, None, "panel.gate.cx").unwrap();
+    assert_eq!(
+        serde_json::to_value(&back).unwrap(),
+        serde_json::to_value(&cookies).unwrap()
+    );
+
+    let json: Value =
+        serde_json::from_str(&export_cookies(&cookies, CookieFormat::Json, now())).unwrap();
+    assert_eq!(json[0]["httpOnly"], true);
+    assert_eq!(json[0]["hostOnly"], false);
+    assert_eq!(json[1]["session"], true);
+    assert_eq!(json[1]["sameSite"], "unspecified");
+
+    assert_eq!(export_cookies(&cookies, CookieFormat::Header, now()), "sid=abc; lang=ru");
+}
+
+#[test]
+fn cookies_commands_merge_into_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = AppConfig::default();
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let command = |a: &[&str]| match parse_args(&args(a), &config).unwrap() {
+        Invocation::Command(command) => command,
+        Invocation::Launch(_) => panic!("expected command"),
+    };
+
+    // Уже сохранённая кука остаётся, одноимённая заменяется.
+    save_cookies(
+        dir.path(),
+        &CookieStore {
+            cookies: parse_cookies("lang=en; theme=dark", None, "panel.gate.cx").unwrap(),
+        },
+    )
+    .unwrap();
+    let input = dir.path().join("cookies.txt");
+    std::fs::write(&input, COOKIES_TXT).unwrap();
+    run(command(&["cookies", "import", input.to_str().unwrap()]), dir.path()).unwrap();
+
+    let store = load_cookies(dir.path()).unwrap();
+    let pairs: Vec<(&str, &str)> = store
+        .cookies
+        .iter()
+        .map(|c| (c.name.as_str(), c.value.as_str()))
+        .collect();
+    assert_eq!(pairs, vec![("lang", "ru"), ("theme", "dark"), ("sid", "abc")]);
+
+    let output = dir.path().join("export.txt");
+    run(
+        command(&["cookies", "export", "--format", "header", "--output", output.to_str().unwrap()]),
+        dir.path(),
+    )
+    .unwrap();
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "lang=ru; theme=dark; sid=abc");
+
+    assert!(parse_args(&args(&["cookies", "import"]), &config).is_err());
+    assert!(parse_args(&args(&["cookies", "import", "a", "--format", "xml"]), &config).is_err());
+    let unknown = command(&["cookies", "export", "--profile", "night"]);
+    assert_eq!(run(unknown, dir.path()).unwrap_err(), "unknown profile: night");
+}
+
+#[test]
+fn imports_cookies_txt_into_running_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = ProxyState::new("https://panel.gate.cx/".to_string(), dir.path().to_path_buf());
+
+    let text = format!("{}evil.com\tFALSE\t/\tFALSE\t0\tads\t1\n", COOKIES_TXT);
+    let report = import_into(&state, &text, Some(CookieFormat::Netscape), now()).unwrap();
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.rejected, vec!["ads (evil.com)".to_string()]);
+    assert_eq!(load_cookies(dir.path()).unwrap().cookies.len(), 2);
+}
@@ -0,0 +1,227 @@
+//! Синхронизация кук между webview и хранилищем: разбор `Set-Cookie`,
+//! экранирование скрипта, слияние и удаление при выходе.
+
+use std::{
+    collections::HashSet,
+    net::{SocketAddr, TcpListener},
+};
+
+use chrono::{DateTime, Utc};
+use p2p_app::{
+    cookie_sync::{
+        apply_cookies, apply_document_cookies, merge_cookie_header, parse_set_cookie, seed_script,
+        webview_set_cookie,
+    },
+    ipc::{parse_page_message, PageMessage},
+    mock::{MockPanel, MockPanelConfig},
+    proxy::{load_cookies, run_proxy, Cookie, CookieStore, ProxyState},
+};
+
+fn now() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2025-02-05T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+fn cookie(name: &str, value: &str, http_only: bool) -> Cookie {
+    Cookie {
+        name: name.to_string(),
+        value: value.to_string(),
+        domain: "panel.gate.cx".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: Some(true),
+        http_only: Some(http_only),
+        same_site: None,
+        secure: None,
+        session: Some(true),
+        store_id: None,
+    }
+}
+
+#[test]
+fn parses_set_cookie_attributes() {
+    let c = parse_set_cookie(
+        "sid=abc; Path=/api; Domain=.gate.cx; HttpOnly; Secure; SameSite=None; Max-Age=3600",
+        "panel.gate.cx",
+        now(),
+    )
+    .unwrap();
+    assert_eq!((c.name.as_str(), c.value.as_str()), ("sid", "abc"));
+    assert_eq!(c.path, "/api");
+    assert_eq!(c.domain, "gate.cx");
+    assert_eq!(c.host_only, Some(false));
+    assert_eq!(c.http_only, Some(true));
+    assert_eq!(c.secure, Some(true));
+    assert_eq!(c.same_site.as_deref(), Some("none"));
+    assert_eq!(c.expiration_date, Some(now().timestamp() as f64 + 3600.0));
+
+    let expires =
+        parse_set_cookie("a=1; expires=Thu, 06-Feb-2025 12:00:00 GMT", "h", now()).unwrap();
+    assert_eq!(expires.expiration_date, Some(now().timestamp() as f64 + 86400.0));
+    assert_eq!(expires.session, Some(false));
+    // Значение в кавычках и `=` внутри значения.
+    let quoted = parse_set_cookie("token=\"a=b\"; path=/", "h", now()).unwrap();
+    assert_eq!(quoted.value, "a=b");
+    assert!(parse_set_cookie("=nameless", "h", now()).is_none());
+}
+
+#[test]
+fn logout_removes_cookies_from_store() {
+    let mut store = CookieStore {
+        cookies: vec![cookie("sid", "abc", true), cookie("XSRF-TOKEN", "x", false)],
+    };
+    let deleted = [
+        "sid=deleted; expires=Thu, 01 Jan 1970 00:00:01 GMT; path=/; httponly",
+        "XSRF-TOKEN=; Max-Age=0; path=/",
+    ]
+    .iter()
+    .filter_map(|h| parse_set_cookie(h, "panel.gate.cx", now()))
+    .collect();
+    assert!(apply_cookies(&mut store, deleted, now()));
+    assert!(store.cookies.is_empty());
+    // Удаление отсутствующей куки ничего не меняет.
+    let again = vec![parse_set_cookie("sid=; Max-Age=0", "h", now()).unwrap()];
+    assert!(!apply_cookies(&mut store, again, now()));
+}
+
+#[test]
+fn seed_script_escapes_values_and_skips_http_only() {
+    let mut expiring = cookie("lang", "ru", false);
+    expiring.expiration_date = Some(now().timestamp() as f64 + 60.0);
+    let cookies = vec![
+        cookie("sid", "secret", true),
+        cookie("quote", r#"it's "x"\y"#, false),
+        cookie("broken", "a;b", false),
+        expiring,
+    ];
+    let script = seed_script(&cookies, now());
+    assert!(!script.contains("secret"));
+    assert!(!script.contains("broken"));
+    assert!(script.contains(r#"document.cookie = "quote=it's \"x\"\\y; path=/";"#));
+    assert!(script.contains("lang=ru; path=/; expires=Wed, 05 Feb 2025 12:01:00 GMT"));
+    assert_eq!(script.lines().count(), 2);
+}
+
+#[test]
+fn rewrites_set_cookie_for_webview() {
+    assert_eq!(
+        webview_set_cookie("XSRF-TOKEN=abc; Domain=.gate.cx; Path=/; Secure; SameSite=None").as_deref(),
+        Some("XSRF-TOKEN=abc; Path=/; SameSite=Lax")
+    );
+    assert_eq!(
+        webview_set_cookie("a=1; Max-Age=0; path=/").as_deref(),
+        Some("a=1; Max-Age=0; path=/")
+    );
+    assert_eq!(webview_set_cookie("sid=abc; path=/; HttpOnly"), None);
+}
+
+#[test]
+fn merges_browser_and_store_cookies() {
+    let store = CookieStore {
+        cookies: vec![cookie("sid", "store", true), cookie("XSRF-TOKEN", "new", false)],
+    };
+    let header = merge_cookie_header(&store, Some("XSRF-TOKEN=old; theme=dark"), now());
+    assert_eq!(header, "sid=store; XSRF-TOKEN=new; theme=dark");
+}
+
+#[test]
+fn document_cookies_flow_back_into_store() {
+    let mut store = CookieStore {
+        cookies: vec![
+            cookie("sid", "abc", true),
+            cookie("XSRF-TOKEN", "x", false),
+            cookie("unseen", "1", false),
+        ],
+    };
+    let values = |store: &CookieStore| -> HashSet<(String, String)> {
+        store
+            .cookies
+            .iter()
+            .map(|c| (c.name.clone(), c.value.clone()))
+            .collect()
+    };
+    let expected = |pairs: &[(&str, &str)]| -> HashSet<(String, String)> {
+        pairs
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    };
+    let mut seen = HashSet::new();
+    assert!(apply_document_cookies(
+        &mut store,
+        &mut seen,
+        "XSRF-TOKEN=y; theme=dark; sid=forged",
+        "panel.gate.cx"
+    ));
+    // Кука, которой страница ещё не показывала, остаётся в хранилище.
+    assert_eq!(
+        values(&store),
+        expected(&[("sid", "abc"), ("XSRF-TOKEN", "y"), ("theme", "dark"), ("unseen", "1")])
+    );
+    assert!(!apply_document_cookies(
+        &mut store,
+        &mut seen,
+        "XSRF-TOKEN=y; theme=dark; sid=forged",
+        "panel.gate.cx"
+    ));
+
+    // Выход: страница потеряла свои куки, HttpOnly-сессия не тронута.
+    assert!(apply_document_cookies(&mut store, &mut seen, "", "panel.gate.cx"));
+    assert_eq!(values(&store), expected(&[("sid", "abc"), ("unseen", "1")]));
+
+    let message = parse_page_message(r#"{"v":1,"type":"cookies","cookies":"a=1"}"#).unwrap();
+    assert_eq!(
+        message,
+        PageMessage::Cookies {
+            cookies: "a=1".to_string()
+        }
+    );
+}
+
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+#[tokio::test]
+async fn proxy_keeps_http_only_cookies_out_of_webview() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            ..Default::default()
+        },
+        Vec::new(),
+    );
+    let state = ProxyState::new(panel.base_url(), dir.path().to_path_buf());
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        domain: "127.0.0.1".to_string(),
+        ..cookie("sid", "test-sid", true)
+    });
+    let addr = free_addr();
+    let server = tokio::spawn(run_proxy(state.clone(), addr));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    // Браузер присылает устаревшую копию сессии — панель получает значение из хранилища.
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/api/v1/payments/payouts", addr))
+        .header("Cookie", "sid=stale; theme=dark")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let set_cookie: Vec<&str> = response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    assert!(set_cookie.iter().all(|c| c.starts_with("XSRF-TOKEN=")));
+
+    let request = panel.requests().pop().unwrap();
+    assert_eq!(request.cookie.as_deref(), Some("sid=test-sid; theme=dark"));
+    let saved = load_cookies(dir.path()).unwrap();
+    assert!(saved.cookies.iter().any(|c| c.name == "XSRF-TOKEN"));
+
+    server.abort();
+}
@@ -0,0 +1,170 @@
+//! Режим `--headless`: задачи профилей без окна, перечитывание config.json,
+//! куки из каталога профиля и PID-файл.
+
+use std::{net::TcpListener, path::Path, time::Duration};
+
+use p2p_app::{
+    api::{API_TOKEN_FILE, API_TOKEN_HEADER},
+    cli::{parse_args, Invocation, LaunchOptions},
+    config::{try_load_config, AppConfig},
+    daemon::{run_daemon, DaemonSignal, PidFile},
+    mock::{MockPanel, MockPanelConfig},
+    proxy::{save_cookies, Cookie, CookieStore},
+};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn write_config(dir: &Path, base_url: &str, port: u16) {
+    let config = json!({
+        "alerts": { "enabled": false },
+        "session": { "enabled": false },
+        "profiles": [{ "name": "main", "base_url": base_url, "proxy_port": port }],
+    });
+    std::fs::write(dir.join("config.json"), config.to_string()).unwrap();
+}
+
+/// `GET /__p2p/session` на порту профиля с токеном из его каталога; `None`,
+/// пока прокси не слушает.
+async fn session(profile_dir: &Path, port: u16) -> Option<Value> {
+    let token = std::fs::read_to_string(profile_dir.join(API_TOKEN_FILE)).ok()?;
+    let response = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:{}/__p2p/session", port))
+        .header(API_TOKEN_HEADER, token)
+        .send()
+        .await
+        .ok()?;
+    response.json().await.ok()
+}
+
+async fn wait_for(profile_dir: &Path, port: u16, up: bool) {
+    for _ in 0..100 {
+        if session(profile_dir, port).await.is_some() == up {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("proxy on port {} did not become {}", port, if up { "available" } else { "unavailable" });
+}
+
+#[test]
+fn parses_launch_options() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let config = AppConfig::default();
+    let launch = |a: &[&str]| match parse_args(&args(a), &config) {
+        Ok(Invocation::Launch(options)) => Ok(options),
+        Ok(Invocation::Command(_)) => panic!("expected launch"),
+        Err(e) => Err(e),
+    };
+
+    assert_eq!(launch(&[]).unwrap(), LaunchOptions::default());
+    assert_eq!(
+        launch(&["--headless", "--pid-file", "/run/p2p.pid"]).unwrap(),
+        LaunchOptions {
+            headless: true,
+            pid_file: Some("/run/p2p.pid".into()),
+        }
+    );
+    assert!(launch(&["--pid-file"]).is_err());
+    assert!(launch(&["--headless", "extra"]).is_err());
+    assert!(matches!(
+        parse_args(&args(&["report"]), &config),
+        Ok(Invocation::Command(_))
+    ));
+}
+
+#[test]
+fn pid_file_is_removed_only_if_still_ours() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("p2p.pid");
+
+    let pid_file = PidFile::create(&path).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap().trim(),
+        std::process::id().to_string()
+    );
+    drop(pid_file);
+    assert!(!path.exists());
+
+    // Файл перезаписал другой процесс — не трогаем.
+    let pid_file = PidFile::create(&path).unwrap();
+    std::fs::write(&path, "1\n").unwrap();
+    drop(pid_file);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "1\n");
+}
+
+#[tokio::test]
+async fn headless_daemon_reads_profile_cookies_and_reloads_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(MockPanelConfig::default(), Vec::new());
+    let (first_port, second_port) = (free_port(), free_port());
+    write_config(dir.path(), &panel.base_url(), first_port);
+    let profile_dir = dir.path().join("profiles").join("main");
+    std::fs::create_dir_all(&profile_dir).unwrap();
+    let cookies = CookieStore {
+        cookies: vec![Cookie {
+            name: "sid".to_string(),
+            value: "secret".to_string(),
+            domain: "127.0.0.1".to_string(),
+            path: "/".to_string(),
+            expiration_date: None,
+            host_only: None,
+            http_only: Some(true),
+            same_site: None,
+            secure: None,
+            session: None,
+            store_id: None,
+        }],
+    };
+    save_cookies(&profile_dir, &cookies).unwrap();
+
+    let (signals, receiver) = mpsc::unbounded_channel();
+    let data_dir = dir.path().to_path_buf();
+    let config = try_load_config(&data_dir).unwrap();
+    let daemon = tokio::spawn(async move { run_daemon(&data_dir, config, receiver, None).await });
+    wait_for(&profile_dir, first_port, true).await;
+
+    // Куки берутся из каталога профиля; значения наружу не отдаются.
+    let status = session(&profile_dir, first_port).await.unwrap();
+    assert_eq!(status["cookies"], json!(["sid"]));
+    assert!(!status.to_string().contains("secret"));
+
+    // Через локальный API куки не принимаются — только через `cookies import`.
+    let token = std::fs::read_to_string(profile_dir.join(API_TOKEN_FILE)).unwrap();
+    let rejected = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{}/__p2p/cookies", first_port))
+        .header(API_TOKEN_HEADER, token)
+        .json(&json!([{ "name": "sid", "value": "evil", "domain": "127.0.0.1", "path": "/" }]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rejected.status(), 404);
+
+    // SIGHUP: профиль переезжает на новый порт, куки остаются.
+    write_config(dir.path(), &panel.base_url(), second_port);
+    signals.send(DaemonSignal::Reload).unwrap();
+    wait_for(&profile_dir, second_port, true).await;
+    wait_for(&profile_dir, first_port, false).await;
+    assert_eq!(session(&profile_dir, second_port).await.unwrap()["cookies"], json!(["sid"]));
+
+    // Сломанный config.json не останавливает работающие профили.
+    std::fs::write(dir.path().join("config.json"), "{").unwrap();
+    signals.send(DaemonSignal::Reload).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(session(&profile_dir, second_port).await.is_some());
+
+    signals.send(DaemonSignal::Shutdown).unwrap();
+    let result = tokio::time::timeout(Duration::from_secs(20), daemon)
+        .await
+        .expect("daemon stops on shutdown")
+        .unwrap();
+    assert!(result.is_ok());
+    wait_for(&profile_dir, second_port, false).await;
+}
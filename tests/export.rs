@@ -0,0 +1,207 @@
+//! Выгрузка истории в CSV, XLSX и Parquet и её доступность через локальный API.
+
+use std::net::{SocketAddr, TcpListener};
+
+use bytes::Bytes;
+use p2p_app::{
+    api::API_TOKEN_HEADER,
+    export::{default_fields, export, parse_fields, ExportFormat, ExportOptions},
+    idex::Transaction,
+    money::{Decimal, PartyAmounts},
+    proxy::{run_proxy, ProxyState},
+};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+fn amounts(entries: &[(&str, &str, &str)]) -> PartyAmounts {
+    let mut map = PartyAmounts::new();
+    for (party, code, value) in entries {
+        map.entry(party.to_string())
+            .or_default()
+            .insert(code.to_string(), value.parse().unwrap());
+    }
+    map
+}
+
+fn transaction(id: &str, created_at: &str, amount_rub: &str, course: Option<&str>) -> Transaction {
+    let course: Option<Decimal> = course.map(|c| c.parse().unwrap());
+    Transaction {
+        user_id: None,
+        transaction_id: id.to_string(),
+        payment_method_id: None,
+        wallet: Some("79000000001".to_string()),
+        amount: amounts(&[("trader", "643", amount_rub), ("trader", "000001", "10.5")]),
+        total: amounts(&[("trader", "000001", "10.7")]),
+        status: Some("completed".to_string()),
+        bank_name: Some("sberbank".to_string()),
+        bank_code: None,
+        bank_label: Some("Сбербанк".to_string()),
+        payment_method: None,
+        course,
+        success_count: Some(3),
+        success_rate: None,
+        approved_at: None,
+        expired_at: None,
+        created_at: created_at.to_string(),
+        updated_at: created_at.to_string(),
+        trader_id: Some("1068".to_string()),
+        trader_name: None,
+        attachments: None,
+        idex_id: None,
+    }
+}
+
+fn history() -> Vec<Transaction> {
+    let mut first = transaction("1", "2025-02-04T23:59:59.000000Z", "1000.5", Some("95.5"));
+    first.amount.insert("merchant".to_string(), amounts(&[("m", "840", "12.34")])["m"].clone());
+    vec![
+        first,
+        transaction("2", "2025-02-05T03:34:01.000000Z", "14755.636", None),
+        transaction("3", "2025-02-06T00:00:00.000000Z", "3000.00", Some("96")),
+    ]
+}
+
+fn options(format: ExportFormat) -> ExportOptions {
+    ExportOptions {
+        format,
+        from: None,
+        to: None,
+        fields: Vec::new(),
+        delimiter: b',',
+        decimal_comma: false,
+    }
+}
+
+#[test]
+fn csv_respects_range_fields_and_decimal_comma() {
+    let mut opts = options(ExportFormat::Csv);
+    opts.set("from", "2025-02-05").unwrap();
+    opts.set("to", "2025-02-06").unwrap();
+    opts.set("fields", "transaction_id,amount_rub,course,created_at").unwrap();
+    opts.set("delimiter", ";").unwrap();
+    opts.set("decimal_comma", "true").unwrap();
+
+    let csv = String::from_utf8(export(&history(), &opts).unwrap()).unwrap();
+    assert_eq!(
+        csv,
+        "transaction_id;amount_rub;course;created_at\n\
+         2;14755,636;;2025-02-05T03:34:01.000000Z\n\
+         3;3000,00;96;2025-02-06T00:00:00.000000Z\n"
+    );
+}
+
+#[test]
+fn default_fields_cover_every_party_and_currency() {
+    let history = history();
+    let rows: Vec<&Transaction> = history.iter().collect();
+    let names: Vec<String> = default_fields(&rows).into_iter().map(|f| f.name).collect();
+    let wallet = names.iter().position(|n| n == "wallet").unwrap();
+    assert_eq!(
+        names[wallet + 1..wallet + 5],
+        ["amount_usdt", "amount_rub", "amount_merchant_usd", "total_usdt"]
+    );
+
+    let mut opts = options(ExportFormat::Csv);
+    opts.set("fields", "transaction_id,amount_merchant_usd").unwrap();
+    let csv = String::from_utf8(export(&history, &opts).unwrap()).unwrap();
+    assert_eq!(csv, "transaction_id,amount_merchant_usd\n1,12.34\n2,\n3,\n");
+}
+
+#[test]
+fn unknown_fields_and_formats_are_rejected() {
+    assert!(parse_fields("transaction_id,nope").is_err());
+    assert!(parse_fields("amount_xyz").is_err());
+    assert!("ods".parse::<ExportFormat>().is_err());
+    assert!(options(ExportFormat::Csv).set("from", "05.02.2025").is_err());
+}
+
+#[test]
+fn xlsx_is_a_workbook() {
+    let bytes = export(&history(), &options(ExportFormat::Xlsx)).unwrap();
+    assert_eq!(&bytes[..2], b"PK");
+}
+
+#[test]
+fn parquet_has_typed_columns_for_every_row() {
+    let history = history();
+    let bytes = export(&history, &options(ExportFormat::Parquet)).unwrap();
+    let reader = SerializedFileReader::new(Bytes::from(bytes)).unwrap();
+    let metadata = reader.metadata();
+    assert_eq!(metadata.file_metadata().num_rows(), 3);
+
+    let schema = metadata.file_metadata().schema_descr();
+    let rows: Vec<&Transaction> = history.iter().collect();
+    assert_eq!(schema.num_columns(), default_fields(&rows).len());
+    let column = |name: &str| {
+        (0..schema.num_columns())
+            .map(|i| schema.column(i))
+            .find(|c| c.name() == name)
+            .unwrap()
+    };
+    assert_eq!(
+        column("amount_rub").physical_type(),
+        parquet::basic::Type::FIXED_LEN_BYTE_ARRAY
+    );
+    assert_eq!(column("success_rate").physical_type(), parquet::basic::Type::DOUBLE);
+    assert_eq!(column("created_at").physical_type(), parquet::basic::Type::INT64);
+    assert_eq!(column("wallet").physical_type(), parquet::basic::Type::BYTE_ARRAY);
+}
+
+#[test]
+fn parquet_columns_are_unique_and_valid() {
+    let mut opts = options(ExportFormat::Parquet);
+    opts.set("fields", "transaction_id,amount_rub,transaction_id").unwrap();
+    let bytes = export(&history(), &opts).unwrap();
+    let reader = SerializedFileReader::new(Bytes::from(bytes)).unwrap();
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let names: Vec<String> = (0..schema.num_columns())
+        .map(|i| schema.column(i).name().to_string())
+        .collect();
+    assert_eq!(names, ["transaction_id", "amount_rub"]);
+
+    // Сторона сделки с пробелом попадает в имя колонки по умолчанию.
+    let mut history = history();
+    history[0].amount.insert("merchant x".to_string(), amounts(&[("m", "840", "1")])["m"].clone());
+    let err = export(&history, &options(ExportFormat::Parquet)).unwrap_err();
+    assert!(err.to_string().contains("amount_merchant x_usd"), "{}", err);
+    // CSV такие имена по-прежнему выгружает.
+    assert!(export(&history, &options(ExportFormat::Csv)).is_ok());
+}
+
+#[tokio::test]
+async fn export_is_served_by_local_api() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = ProxyState::new("http://127.0.0.1:9/".to_string(), dir.path().to_path_buf());
+    state.transactions.lock().unwrap().extend(history());
+    let token = state.api_token.to_string();
+    let addr: SocketAddr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let server = tokio::spawn(run_proxy(state, addr));
+    let get = |url: String| {
+        reqwest::Client::new()
+            .get(url)
+            .header(API_TOKEN_HEADER, token.as_str())
+            .send()
+    };
+
+    let url = format!(
+        "http://{}/__p2p/export?format=csv&from=2025-02-06&fields=transaction_id",
+        addr
+    );
+    let mut response = None;
+    for _ in 0..50 {
+        if let Ok(resp) = get(url.clone()).await {
+            response = Some(resp);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let response = response.expect("proxy did not start");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text().await.unwrap(), "transaction_id\n3\n");
+
+    let bad = get(format!("http://{}/__p2p/export?format=ods", addr))
+        .await
+        .unwrap();
+    assert_eq!(bad.status(), 400);
+
+    server.abort();
+}
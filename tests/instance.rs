@@ -0,0 +1,149 @@
+//! Один экземпляр на каталог данных: блокировка, файл упавшего процесса и
+//! передача команд повторного запуска работающему демону.
+
+use std::{io::Write, net::TcpListener, path::Path, sync::Arc, time::Duration};
+
+use p2p_app::{
+    config::try_load_config,
+    daemon::{run_daemon, DaemonSignal},
+    instance::{acquire, find_running, Acquired, HandOff, InstanceError, InstanceLock, INSTANCE_FILE},
+    mock::{MockPanel, MockPanelConfig},
+    proxy::load_cookies,
+};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+fn primary(dir: &Path) -> InstanceLock {
+    match acquire(dir).unwrap() {
+        Acquired::Primary(lock) => lock,
+        Acquired::Running(_) => panic!("expected to acquire the data directory"),
+    }
+}
+
+#[test]
+fn second_acquire_finds_running_instance() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = primary(dir.path());
+    assert!(dir.path().join(INSTANCE_FILE).exists());
+
+    match acquire(dir.path()).unwrap() {
+        Acquired::Running(running) => assert_eq!(running.pid(), std::process::id()),
+        Acquired::Primary(_) => panic!("data directory acquired twice"),
+    }
+    drop(lock);
+    assert!(!dir.path().join(INSTANCE_FILE).exists());
+    assert!(find_running(dir.path()).is_none());
+}
+
+#[test]
+fn stale_instance_file_is_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    // Порт, который никто не слушает: процесс упал, не удалив файл.
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let stale = json!({ "pid": 1, "port": port, "secret": "x" });
+    std::fs::write(dir.path().join(INSTANCE_FILE), stale.to_string()).unwrap();
+    assert!(find_running(dir.path()).is_none());
+
+    let lock = primary(dir.path());
+    assert_eq!(find_running(dir.path()).unwrap().pid(), std::process::id());
+    assert_ne!(lock.port(), port);
+}
+
+#[test]
+fn foreign_process_on_the_port_is_not_an_instance() {
+    let dir = tempfile::tempdir().unwrap();
+    // Порт упавшего экземпляра занял чужой сервер, который секрета не знает.
+    let foreign = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = foreign.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for mut stream in foreign.incoming().flatten() {
+            let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+        }
+    });
+    let stale = json!({ "pid": std::process::id(), "port": port, "secret": "x" });
+    std::fs::write(dir.path().join(INSTANCE_FILE), stale.to_string()).unwrap();
+    assert!(find_running(dir.path()).is_none());
+    primary(dir.path());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn instance_file_of_a_dead_process_is_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    // Порт кто-то слушает, но процесса из файла уже нет.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let stale = json!({ "pid": u32::MAX - 1, "port": port, "secret": "x" });
+    std::fs::write(dir.path().join(INSTANCE_FILE), stale.to_string()).unwrap();
+    assert!(find_running(dir.path()).is_none());
+    primary(dir.path());
+}
+
+#[tokio::test]
+async fn instance_answers_ping_with_its_pid() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = primary(dir.path());
+    let server = tokio::spawn(lock.serve(Arc::new(|_| Err("unexpected command".to_string()))));
+    let dir_path = dir.path().to_path_buf();
+    let running = tokio::task::spawn_blocking(move || find_running(&dir_path))
+        .await
+        .unwrap()
+        .expect("serving instance is alive");
+    assert_eq!(running.pid(), std::process::id());
+    server.abort();
+}
+
+#[tokio::test]
+async fn hands_cookies_over_to_running_daemon() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(MockPanelConfig::default(), Vec::new());
+    let config = json!({
+        "alerts": { "enabled": false },
+        "session": { "enabled": false },
+        "profiles": [{ "name": "main", "base_url": panel.base_url(), "proxy_port": 0 }],
+    });
+    std::fs::write(dir.path().join("config.json"), config.to_string()).unwrap();
+
+    let lock = primary(dir.path());
+    let (signals, receiver) = mpsc::unbounded_channel();
+    let data_dir = dir.path().to_path_buf();
+    let config = try_load_config(&data_dir).unwrap();
+    let daemon = tokio::spawn(async move { run_daemon(&data_dir, config, receiver, Some(&lock)).await });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let send = |hand_off: HandOff| {
+        let running = find_running(dir.path()).unwrap();
+        tokio::task::spawn_blocking(move || running.send(hand_off))
+    };
+    let reply = send(HandOff::ImportCookies {
+        profile: "main".to_string(),
+        text: "sid=handed-over".to_string(),
+        format: None,
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(reply.starts_with("Imported 1 cookies into profile main"), "{}", reply);
+    let profile_dir = dir.path().join("profiles").join("main");
+    assert_eq!(load_cookies(&profile_dir).unwrap().cookies[0].value, "handed-over");
+
+    // У демона нет окна, а неизвестный профиль — ошибка, а не молчание.
+    let show = send(HandOff::Show).await.unwrap();
+    assert!(matches!(show, Err(InstanceError::Rejected(_))));
+    let unknown = send(HandOff::ImportCookies {
+        profile: "night".to_string(),
+        text: "sid=x".to_string(),
+        format: None,
+    })
+    .await
+    .unwrap();
+    assert_eq!(unknown.unwrap_err().to_string(), "unknown profile: night");
+
+    signals.send(DaemonSignal::Shutdown).unwrap();
+    tokio::time::timeout(Duration::from_secs(20), daemon)
+        .await
+        .expect("daemon stops on shutdown")
+        .unwrap()
+        .unwrap();
+    assert!(find_running(dir.path()).is_none());
+}
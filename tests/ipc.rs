@@ -0,0 +1,152 @@
+//! IPC-мост окна IDEX: версии протокола, сериализация сообщений и состояние оверлея.
+
+use chrono::{DateTime, Utc};
+use p2p_app::{
+    idex::{map_transaction, PollStatus},
+    ipc::{
+        overlay_state, parse_page_message, payout_id_from_path, to_script, AppMessage, IpcError,
+        PageMessage, SessionStatus,
+    },
+    money::Decimal,
+    proxy::{Cookie, ProxyState},
+    supervisor::{TaskHealth, TaskStatus},
+};
+use serde_json::{json, Value};
+
+fn now() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2025-02-05T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+#[test]
+fn parses_versioned_page_messages() {
+    assert_eq!(
+        parse_page_message(r#"{"v":1,"type":"navigated","path":"/payouts/42"}"#).unwrap(),
+        PageMessage::Navigated {
+            path: "/payouts/42".to_string()
+        }
+    );
+    assert_eq!(
+        parse_page_message(r#"{"v":1,"type":"copied","field":"wallet"}"#).unwrap(),
+        PageMessage::Copied {
+            field: "wallet".to_string()
+        }
+    );
+    assert_eq!(
+        parse_page_message(r#"{"v":1,"type":"open_telegram"}"#).unwrap(),
+        PageMessage::OpenTelegram
+    );
+    assert_eq!(
+        parse_page_message(r#"{"v":2,"type":"navigated","path":"/"}"#),
+        Err(IpcError::UnsupportedVersion(2))
+    );
+    // Сообщения без версии (старые скрипты) и неизвестные типы отвергаются.
+    assert!(matches!(
+        parse_page_message(r#"{"type":"cookies","cookies":""}"#),
+        Err(IpcError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse_page_message(r#"{"v":1,"type":"unknown"}"#),
+        Err(IpcError::Malformed(_))
+    ));
+    assert!(matches!(parse_page_message("not json"), Err(IpcError::Malformed(_))));
+}
+
+#[test]
+fn extracts_payout_id_from_spa_path() {
+    assert_eq!(payout_id_from_path("/payouts/123").as_deref(), Some("123"));
+    assert_eq!(
+        payout_id_from_path("/https://panel.gate.cx/payments/payout/77/details").as_deref(),
+        Some("77")
+    );
+    assert_eq!(payout_id_from_path("/payouts"), None);
+    assert_eq!(payout_id_from_path("/payouts/new"), None);
+    assert_eq!(payout_id_from_path("/settings/12"), None);
+}
+
+#[test]
+fn overlay_reports_today_session_and_selected_payout() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = ProxyState::new("https://panel.gate.cx/".to_string(), dir.path().to_path_buf());
+    *state.transactions.lock().unwrap() = [
+        ("1", "2025-02-05T09:00:00+03:00", "1500.50"),
+        ("2", "2025-02-05T10:00:00+03:00", "500"),
+        ("3", "2025-02-04T10:00:00+03:00", "700"),
+    ]
+    .iter()
+    .map(|(id, created_at, amount)| {
+        map_transaction(&json!({
+            "id": id,
+            "wallet": format!("7900000000{}", id),
+            "amount": { "trader": { "643": amount } },
+            "bank": { "label": "Сбербанк" },
+            "status": "completed",
+            "created_at": created_at,
+        }))
+        .unwrap()
+    })
+    .collect();
+
+    let overlay = overlay_state("main", &state, None, Some("1"), now());
+    assert_eq!(overlay.profile, "main");
+    assert_eq!(overlay.today.count, 2);
+    assert_eq!(overlay.today.amount["RUB"], "2000.50".parse::<Decimal>().unwrap());
+    assert_eq!(overlay.session, SessionStatus::Missing);
+    assert_eq!(overlay.poller.status, None);
+    let selected = overlay.selected.unwrap();
+    assert_eq!(selected.wallet.as_deref(), Some("79000000001"));
+    assert_eq!(selected.amount.as_deref(), Some("1500.50"));
+    assert_eq!(selected.bank.as_deref(), Some("Сбербанк"));
+
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        name: "sid".to_string(),
+        value: "abc".to_string(),
+        domain: "panel.gate.cx".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: Some(true),
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    });
+    *state.poll_status.lock().unwrap() = PollStatus {
+        finished_at: Some(now()),
+        failed_pages: 1,
+        unauthorized: true,
+    };
+    let health = TaskHealth {
+        status: TaskStatus::Restarting,
+        restarts: 3,
+        last_error: None,
+        last_started: None,
+    };
+    let overlay = overlay_state("main", &state, Some(&health), Some("missing"), now());
+    assert_eq!(overlay.session, SessionStatus::Expired);
+    assert_eq!(overlay.poller.status, Some(TaskStatus::Restarting));
+    assert_eq!(overlay.poller.restarts, 3);
+    assert_eq!(overlay.poller.failed_pages, 1);
+    assert_eq!(overlay.poller.last_poll, Some(now()));
+    assert!(overlay.selected.is_none());
+}
+
+#[test]
+fn app_messages_become_safe_scripts() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = ProxyState::new("https://panel.gate.cx/".to_string(), dir.path().to_path_buf());
+    let overlay = overlay_state("</script><b>\"x\"", &state, None, None, now());
+    let script = to_script(&AppMessage::Overlay(overlay));
+
+    let json = script
+        .strip_prefix("window.__p2p && window.__p2p.receive(")
+        .and_then(|s| s.strip_suffix(");"))
+        .unwrap();
+    let message: Value = serde_json::from_str(json).unwrap();
+    assert_eq!(message["v"], 1);
+    assert_eq!(message["type"], "overlay");
+    assert_eq!(message["profile"], "</script><b>\"x\"");
+    assert_eq!(message["session"], "missing");
+    assert_eq!(message["selected"], Value::Null);
+}
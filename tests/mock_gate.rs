@@ -0,0 +1,305 @@
+//! Сквозные тесты прокси, опроса IDEX и проверки токена против офлайн-имитаций.
+
+use std::{
+    collections::HashSet,
+    net::{SocketAddr, TcpListener},
+    path::Path,
+    time::Duration,
+};
+
+use p2p_app::{
+    idex::{load_transactions, map_transaction, run_idex, upgrade_record, Transaction},
+    mock::{load_fixture, payout_from_transaction, MockPanel, MockPanelConfig, MockTokenApi},
+    proxy::{load_cookies, run_proxy, Cookie, ProxyState},
+    token::verify_device_token,
+};
+use serde_json::{json, Value};
+
+fn payout(id: u64) -> Value {
+    json!({
+        "id": id,
+        "wallet": format!("7900000{:04}", id),
+        "amount": { "trader": { "643": 1000.0 + id as f64, "000001": 10.5 } },
+        "total": { "trader": { "643": 1020.0 + id as f64, "000001": 10.7 } },
+        "bank": { "name": "sberbank", "code": "100000000111", "label": "Сбербанк" },
+        "method": { "label": "OUT: Система быстрых платежей (СБП)" },
+        "meta": { "courses": { "trader": 95.5 } },
+        "created_at": "2025-02-05T03:34:01.000000Z",
+        "updated_at": "2025-02-05T03:44:05.000000Z",
+        "trader": { "id": 1068, "name": "trader" },
+    })
+}
+
+fn payouts(count: u64) -> Vec<Value> {
+    (1..=count).map(payout).collect()
+}
+
+fn session_cookie(name: &str, value: &str) -> Cookie {
+    Cookie {
+        name: name.to_string(),
+        value: value.to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: Some(true),
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    }
+}
+
+fn state_with_session(panel: &MockPanel, data_dir: &Path) -> ProxyState {
+    let state = ProxyState::new(panel.base_url(), data_dir.to_path_buf());
+    state
+        .cookies
+        .lock()
+        .unwrap()
+        .cookies
+        .push(session_cookie("sid", "test-sid"));
+    state
+}
+
+fn session_config() -> MockPanelConfig {
+    MockPanelConfig {
+        session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+        ..Default::default()
+    }
+}
+
+fn free_addr() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap()
+}
+
+async fn wait_until(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while tokio::time::Instant::now() < deadline {
+        if done() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    done()
+}
+
+/// GET через прокси, который только что запущен и может ещё не слушать порт.
+async fn get_via_proxy(url: &str) -> reqwest::Response {
+    for _ in 0..50 {
+        if let Ok(resp) = reqwest::get(url).await {
+            return resp;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("proxy did not start");
+}
+
+fn stored_ids(state: &ProxyState) -> HashSet<String> {
+    state
+        .transactions
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|tx| tx.transaction_id.clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn token_verification_against_mock_api() {
+    let api = MockTokenApi::start(vec!["good-token".to_string()]);
+
+    assert!(verify_device_token(&api.url(), "good-token").await);
+    assert!(!verify_device_token(&api.url(), "bad-token").await);
+    assert_eq!(api.checked_tokens(), vec!["good-token", "bad-token"]);
+}
+
+#[tokio::test]
+async fn token_verification_fails_when_api_is_unreachable() {
+    let url = format!("http://{}/api/deviceToken", free_addr());
+    assert!(!verify_device_token(&url, "good-token").await);
+}
+
+#[tokio::test]
+async fn proxy_injects_session_and_captures_payouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(session_config(), payouts(15));
+    let state = state_with_session(&panel, dir.path());
+    let addr = free_addr();
+    let server = tokio::spawn(run_proxy(state.clone(), addr));
+
+    let url = format!("http://{}/api/v1/payments/payouts?page=2", addr);
+    let response = get_via_proxy(&url).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["response"]["payouts"]["current_page"], 2);
+
+    let request = panel.requests().pop().unwrap();
+    assert_eq!(request.path, "/api/v1/payments/payouts");
+    assert!(request.cookie.unwrap().contains("sid=test-sid"));
+
+    // Вторая страница — выплаты 11..=15, они попадают в хранилище без опроса.
+    let expected: HashSet<String> = (11..=15).map(|id: u64| id.to_string()).collect();
+    assert_eq!(stored_ids(&state), expected);
+    assert_eq!(load_transactions(dir.path()).len(), 5);
+
+    // Set-Cookie от панели сохраняется в хранилище и на диск.
+    let saved = load_cookies(dir.path()).unwrap();
+    assert!(saved.cookies.iter().any(|c| c.name == "sid"));
+    assert!(saved.cookies.iter().any(|c| c.name == "XSRF-TOKEN"));
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn proxy_captures_payout_details_as_updates() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(session_config(), payouts(3));
+    let state = state_with_session(&panel, dir.path());
+    let addr = free_addr();
+    let server = tokio::spawn(run_proxy(state.clone(), addr));
+
+    let list_url = format!("http://{}/api/v1/payments/payouts?page=1", addr);
+    get_via_proxy(&list_url).await;
+    assert_eq!(stored_ids(&state).len(), 3);
+
+    let mut changed = payout(2);
+    changed["status"] = json!("completed");
+    changed["updated_at"] = json!("2025-02-05T04:00:00.000000Z");
+    panel.replace_payout(changed);
+
+    let detail_url = format!("http://{}/api/v1/payments/payouts/2", addr);
+    reqwest::get(&detail_url).await.unwrap();
+
+    let store = state.transactions.lock().unwrap();
+    let tx = store.iter().find(|tx| tx.transaction_id == "2").unwrap();
+    assert_eq!(tx.status.as_deref(), Some("completed"));
+    assert_eq!(store.len(), 3);
+    drop(store);
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn proxy_serves_metrics() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(session_config(), payouts(3));
+    let state = state_with_session(&panel, dir.path()).with_profile("metrics");
+    let addr = free_addr();
+    let server = tokio::spawn(run_proxy(state.clone(), addr));
+
+    get_via_proxy(&format!("http://{}/api/v1/payments/payouts?page=1", addr)).await;
+    let response = reqwest::get(format!("http://{}/metrics", addr)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let text = response.text().await.unwrap();
+    assert!(text.contains("p2p_proxy_requests_total{profile=\"metrics\",status=\"200\"}"));
+    assert!(text.contains("p2p_proxy_upstream_latency_seconds_count{profile=\"metrics\"}"));
+    assert!(text.contains("p2p_transactions_total{kind=\"new\",profile=\"metrics\",source=\"proxy\"}"));
+    assert!(text.contains("p2p_cookie_updates_total{profile=\"metrics\"}"));
+    // Сам запрос метрик к панели не уходит.
+    assert!(panel.requests().iter().all(|r| r.path != "/metrics"));
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn only_one_proxy_serves_metrics() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(session_config(), payouts(1));
+    let state = state_with_session(&panel, dir.path()).with_metrics(false);
+    let addr = free_addr();
+    let server = tokio::spawn(run_proxy(state, addr));
+
+    get_via_proxy(&format!("http://{}/api/v1/payments/payouts?page=1", addr)).await;
+    let response = reqwest::get(format!("http://{}/metrics", addr)).await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert!(panel.requests().iter().all(|r| r.path != "/metrics"));
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn run_idex_collects_every_page() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(session_config(), payouts(25));
+    let state = state_with_session(&panel, dir.path());
+    let poller = tokio::spawn(run_idex(state.clone()));
+
+    assert!(wait_until(Duration::from_secs(10), || stored_ids(&state).len() == 25).await);
+    assert_eq!(load_transactions(dir.path()).len(), 25);
+
+    let requests = panel.requests();
+    assert!(requests
+        .iter()
+        .all(|r| r.cookie.as_deref().is_some_and(|c| c.contains("sid=test-sid"))));
+    assert!(requests
+        .iter()
+        .all(|r| r.query.as_deref().is_some_and(|q| q.contains("page="))));
+
+    poller.abort();
+}
+
+#[tokio::test]
+async fn run_idex_skips_failing_pages() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = MockPanelConfig {
+        page_size: 5,
+        failing_pages: HashSet::from([2]),
+        ..session_config()
+    };
+    let panel = MockPanel::start(config, payouts(15));
+    let state = state_with_session(&panel, dir.path());
+    let poller = tokio::spawn(run_idex(state.clone()));
+
+    // Страницы 1 и 3 сохранены, хотя 2-я отвечала 500.
+    assert!(wait_until(Duration::from_secs(10), || stored_ids(&state).len() == 10).await);
+    let ids = stored_ids(&state);
+    assert!(!ids.contains("6"));
+    assert!(ids.contains("11"));
+
+    poller.abort();
+}
+
+#[tokio::test]
+async fn run_idex_stores_nothing_after_session_expiry() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(session_config(), payouts(5));
+    panel.expire_session();
+    let state = state_with_session(&panel, dir.path());
+    let poller = tokio::spawn(run_idex(state.clone()));
+
+    // Дожидаемся полного прохода по 10 страницам.
+    assert!(wait_until(Duration::from_secs(10), || panel.requests().len() >= 10).await);
+    assert!(stored_ids(&state).is_empty());
+    assert!(!dir.path().join("idex_history.json").exists());
+
+    poller.abort();
+}
+
+#[test]
+fn history_fixture_round_trips_through_mapping() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("idex_history.json");
+    // Фикстура — история в исходном формате с суммами f64.
+    let records: Vec<Value> =
+        serde_json::from_str(&std::fs::read_to_string(&fixture).unwrap()).unwrap();
+    let history: Vec<Transaction> = records
+        .into_iter()
+        .map(|mut record| {
+            assert_eq!(upgrade_record(&mut record), Some(1));
+            serde_json::from_value(record).unwrap()
+        })
+        .collect();
+    let payouts = load_fixture(&fixture).unwrap();
+    assert_eq!(payouts.len(), history.len());
+
+    for (original, payout) in history.iter().zip(&payouts) {
+        let mapped = map_transaction(payout).unwrap();
+        assert_eq!(mapped.transaction_id, original.transaction_id);
+        assert_eq!(mapped.wallet, original.wallet);
+        assert_eq!(mapped.amount, original.amount);
+        assert_eq!(mapped.total, original.total);
+        assert_eq!(mapped.course, original.course);
+        assert_eq!(mapped.trader_id, original.trader_id);
+        assert_eq!(payout_from_transaction(&mapped), *payout);
+    }
+}
@@ -0,0 +1,131 @@
+//! Точный разбор сумм из ответов панели, справочник валют и миграция истории.
+
+use p2p_app::{
+    idex::{load_transactions, map_transaction, migrate_history},
+    money::{parse_decimal, Currency, Decimal, Money, RUB, USDT},
+};
+use serde_json::{json, Value};
+
+fn dec(value: &str) -> Decimal {
+    value.parse().unwrap()
+}
+
+#[test]
+fn parses_numbers_and_strings_without_loss() {
+    let payload: Value = serde_json::from_str(r#"{"a": 14755.636, "b": 0.1, "c": "1 234,56"}"#).unwrap();
+    assert_eq!(parse_decimal(&payload["a"]), Some(dec("14755.636")));
+    assert_eq!(parse_decimal(&payload["b"]), Some(dec("0.1")));
+    assert_eq!(parse_decimal(&payload["c"]), Some(dec("1234.56")));
+    assert_eq!(parse_decimal(&json!(null)), None);
+    assert_eq!(parse_decimal(&json!("n/a")), None);
+}
+
+#[test]
+fn commas_are_thousands_separators_unless_single_and_without_point() {
+    assert_eq!(parse_decimal(&json!("1,234.56")), Some(dec("1234.56")));
+    assert_eq!(parse_decimal(&json!("1,234,567")), Some(dec("1234567")));
+    assert_eq!(parse_decimal(&json!("1,234,567.8")), Some(dec("1234567.8")));
+    assert_eq!(parse_decimal(&json!("1234,5")), Some(dec("1234.5")));
+    assert_eq!(parse_decimal(&json!("12 345,67")), Some(dec("12345.67")));
+}
+
+#[test]
+fn mapping_keeps_every_party_and_currency() {
+    let payout: Value = serde_json::from_str(
+        r#"{"id": 1,
+            "amount": {"trader": {"643": 0, "000001": 145.96}, "merchant": {"398": "70000.5"}},
+            "total": {"trader": {"643": "14755.636"}},
+            "created_at": "", "updated_at": ""}"#,
+    )
+    .unwrap();
+    let tx = map_transaction(&payout).unwrap();
+    assert_eq!(tx.trader_amount(RUB), Some(Money::new(Decimal::ZERO, Currency::rub())));
+    assert_eq!(tx.trader_amount(USDT).unwrap().amount, dec("145.96"));
+    assert_eq!(tx.trader_total(USDT), None);
+    assert_eq!(tx.trader_total(RUB).unwrap().amount, dec("14755.636"));
+    assert_eq!(tx.amount["merchant"]["398"], dec("70000.5"));
+}
+
+#[test]
+fn registry_names_known_and_unknown_codes() {
+    assert_eq!(Currency::new("398").to_string(), "KZT");
+    assert!(Currency::usdt().is_crypto());
+    assert_eq!(Currency::parse("usdt"), Some(Currency::usdt()));
+    assert_eq!(Currency::parse("643"), Some(Currency::rub()));
+    // Неизвестный код показывается как есть.
+    assert_eq!(Currency::new("000042").to_string(), "000042");
+    assert_eq!(Currency::parse("XYZ"), None);
+}
+
+#[test]
+fn money_refuses_to_mix_currencies() {
+    let rub = Money::new(dec("100"), Currency::rub());
+    let usdt = Money::new(dec("1"), Currency::usdt());
+    assert_eq!(rub.checked_add(&rub).map(|m| m.amount), Some(dec("200")));
+    assert_eq!(rub.checked_add(&usdt), None);
+}
+
+#[test]
+fn legacy_float_history_is_migrated_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let legacy = r#"[{"transaction_id": "1", "amount_rub": 14438.0, "amount_usdt": 0.0,
+        "total_rub": 14755.636, "total_usdt": 149.17, "course": 98.92,
+        "created_at": "2025-02-05T03:34:01.000000Z", "updated_at": "2025-02-05T03:44:05.000000Z"}]"#;
+    let history = dir.path().join("idex_history.json");
+    std::fs::write(&history, legacy).unwrap();
+
+    // Загрузка переводит формат только в памяти.
+    let tx = load_transactions(dir.path()).remove(0);
+    assert_eq!(tx.trader_total(RUB).unwrap().amount, dec("14755.636"));
+    assert_eq!(tx.course, Some(dec("98.92")));
+    // Ноль в старом формате означал отсутствующую сумму.
+    assert_eq!(tx.trader_amount(USDT), None);
+    assert_eq!(std::fs::read_to_string(&history).unwrap(), legacy);
+    assert!(!dir.path().join("idex_history.v1.json").exists());
+
+    assert_eq!(migrate_history(dir.path()), Some(1));
+    let backup = std::fs::read_to_string(dir.path().join("idex_history.v1.json")).unwrap();
+    assert_eq!(backup, legacy);
+    let migrated: Value = serde_json::from_str(&std::fs::read_to_string(&history).unwrap()).unwrap();
+    assert_eq!(migrated[0]["total"]["trader"]["643"], json!("14755.636"));
+    assert!(migrated[0].get("amount_rub").is_none());
+
+    // Повторно переводить нечего.
+    assert_eq!(migrate_history(dir.path()), None);
+    let again = load_transactions(dir.path()).remove(0);
+    assert_eq!(again.total, tx.total);
+    assert!(!dir.path().join("idex_history.v2.json").exists());
+}
+
+#[test]
+fn single_currency_string_history_is_migrated() {
+    let dir = tempfile::tempdir().unwrap();
+    let v2 = r#"[{"transaction_id": "1", "amount_rub": "14438.0", "amount_usdt": null,
+        "total_rub": "0", "total_usdt": "149.17", "course": "98.92",
+        "created_at": "", "updated_at": ""}]"#;
+    std::fs::write(dir.path().join("idex_history.json"), v2).unwrap();
+
+    let tx = load_transactions(dir.path()).remove(0);
+    assert_eq!(migrate_history(dir.path()), Some(2));
+    assert_eq!(tx.trader_amount(RUB).unwrap().amount, dec("14438.0"));
+    assert_eq!(tx.trader_amount(USDT), None);
+    // В строковом формате ноль — настоящий ноль.
+    assert_eq!(tx.trader_total(RUB).unwrap().amount, Decimal::ZERO);
+    assert!(dir.path().join("idex_history.v2.json").exists());
+}
+
+#[test]
+fn zeros_in_the_current_format_are_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let current = r#"[{"transaction_id": "1", "amount": {"trader": {"643": "0"}},
+        "total": {"trader": {"643": 0, "000001": "0.0"}}, "course": "0",
+        "created_at": "", "updated_at": ""}]"#;
+    std::fs::write(dir.path().join("idex_history.json"), current).unwrap();
+
+    let tx = load_transactions(dir.path()).remove(0);
+    assert_eq!(tx.trader_amount(RUB).unwrap().amount, Decimal::ZERO);
+    assert_eq!(tx.trader_total(RUB).unwrap().amount, Decimal::ZERO);
+    assert_eq!(tx.trader_total(USDT).unwrap().amount, Decimal::ZERO);
+    assert_eq!(tx.course, Some(Decimal::ZERO));
+    assert_eq!(migrate_history(dir.path()), None);
+}
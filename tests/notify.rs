@@ -0,0 +1,264 @@
+//! Всплывающие уведомления: новые выплаты из опроса, звуки, часы «не беспокоить»
+//! и разбор ответов сервиса уведомлений D-Bus.
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use p2p_app::{
+    alerts::{Alert, AlertKind, AlertSink, Channel},
+    analytics::AnalyticsConfig,
+    idex::{map_transaction, run_idex, Transaction},
+    mock::{MockPanel, MockPanelConfig, RecordingNotifications},
+    notify::{
+        notify_args, parse_notify_reply, parse_signal, DbusSignal, Delivery, Notification,
+        NotificationClick, Notifier, NotifierSink, NotifyConfig, NotifyEvent, QuietHours,
+    },
+    proxy::{Cookie, ProxyState},
+};
+use serde_json::{json, Value};
+
+fn payout(id: u64) -> Value {
+    json!({
+        "id": id,
+        "wallet": format!("7900000{:04}", id),
+        "amount": { "trader": { "643": 1000.0 + id as f64 } },
+        "bank": { "name": "sberbank", "code": "100000000111", "label": "Сбербанк" },
+        "created_at": "2025-02-05T03:34:01.000000Z",
+        "updated_at": "2025-02-05T03:44:05.000000Z",
+    })
+}
+
+fn transactions(count: u64) -> Vec<Transaction> {
+    (1..=count).map(|id| map_transaction(&payout(id)).unwrap()).collect()
+}
+
+fn at(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+}
+
+fn notifier(config: &NotifyConfig, backend: &RecordingNotifications) -> Notifier {
+    Notifier::new(config, &AnalyticsConfig::default(), Arc::new(backend.clone())).for_profile("main")
+}
+
+fn notification(event: NotifyEvent) -> Notification {
+    Notification {
+        event,
+        title: "title".to_string(),
+        body: "body".to_string(),
+        click: None,
+    }
+}
+
+fn session_panel(latency: Duration) -> MockPanel {
+    MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            latency,
+            ..Default::default()
+        },
+        (1..=2).map(payout).collect(),
+    )
+}
+
+fn state_with_session(
+    panel: &MockPanel,
+    data_dir: &std::path::Path,
+    backend: &RecordingNotifications,
+) -> ProxyState {
+    let state = ProxyState::new(panel.base_url(), data_dir.to_path_buf())
+        .with_notifier(notifier(&NotifyConfig::default(), backend));
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        name: "sid".to_string(),
+        value: "test-sid".to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: Some(true),
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    });
+    state
+}
+
+#[tokio::test]
+async fn poller_notifies_about_new_payouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = session_panel(Duration::ZERO);
+    let backend = RecordingNotifications::default();
+    let state = state_with_session(&panel, dir.path(), &backend);
+    let poller = tokio::spawn(run_idex(state.clone()));
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    while backend.shown().len() < 2 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    poller.abort();
+
+    let mut shown = backend.shown();
+    shown.sort_by(|a, b| a.title.cmp(&b.title));
+    assert_eq!(shown.len(), 2);
+    assert_eq!(shown[0].event, NotifyEvent::NewPayout);
+    assert_eq!(shown[0].title, "main: новая выплата 1");
+    assert!(shown[0].body.contains("1001"), "{}", shown[0].body);
+    assert!(shown[0].body.contains("Сбербанк"));
+    assert!(shown[0].body.contains("79000000001"));
+    assert_eq!(
+        shown[0].click,
+        Some(NotificationClick {
+            profile: "main".to_string(),
+            transaction_id: Some("1".to_string()),
+        })
+    );
+    assert_eq!(backend.sounds(), vec!["beep".to_string(), "beep".to_string()]);
+}
+
+#[tokio::test]
+async fn payouts_captured_during_a_poll_are_not_announced_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = session_panel(Duration::from_millis(500));
+    let backend = RecordingNotifications::default();
+    let state = state_with_session(&panel, dir.path(), &backend);
+    let poller = tokio::spawn(run_idex(state.clone()));
+
+    // Пока панель отвечает на опрос, выплата 1 приходит через прокси.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    state
+        .transactions
+        .lock()
+        .unwrap()
+        .push(map_transaction(&payout(1)).unwrap());
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    while backend.shown().is_empty() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    poller.abort();
+
+    let shown = backend.shown();
+    assert_eq!(shown.len(), 1);
+    assert_eq!(shown[0].title, "main: новая выплата 2");
+    assert_eq!(state.transactions.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn many_new_payouts_are_summarised() {
+    let backend = RecordingNotifications::default();
+    let notifier = notifier(&NotifyConfig::default(), &backend);
+
+    assert_eq!(notifier.payout_notifications(&transactions(3)).len(), 3);
+    let summary = notifier.payout_notifications(&transactions(5));
+    assert_eq!(summary.len(), 1);
+    assert_eq!(summary[0].title, "main: новые выплаты: 5");
+    assert!(summary[0].body.contains("5015"), "{}", summary[0].body);
+    assert_eq!(summary[0].click.as_ref().unwrap().transaction_id, None);
+}
+
+#[tokio::test]
+async fn quiet_hours_mute_all_but_allowed_events() {
+    let backend = RecordingNotifications::default();
+    let config = NotifyConfig {
+        sounds: BTreeMap::from([
+            (NotifyEvent::NewPayout, "beep".to_string()),
+            (NotifyEvent::Missed, "/tmp/alarm.wav".to_string()),
+        ]),
+        quiet_hours: Some(QuietHours {
+            from: "23:00".to_string(),
+            to: "08:00".to_string(),
+            allow: vec![NotifyEvent::Missed],
+        }),
+        ..Default::default()
+    };
+    let quiet = notifier(&config, &backend);
+    // 01:00 по местному времени (UTC+3).
+    let night = at("2025-02-05T22:00:00Z");
+    assert!(quiet.is_quiet(night));
+    assert!(!quiet.is_quiet(at("2025-02-05T05:00:00Z")));
+
+    let muted = quiet.notify(&notification(NotifyEvent::NewPayout), night).await;
+    assert_eq!(muted, Delivery::default());
+    let allowed = quiet.notify(&notification(NotifyEvent::Missed), night).await;
+    assert_eq!(allowed, Delivery { shown: true, sound: false });
+    assert_eq!(backend.shown().len(), 1);
+    assert!(backend.sounds().is_empty());
+
+    // Днём — со звуком своего события; у `expiring` звука нет.
+    let day = at("2025-02-05T12:00:00Z");
+    quiet.notify(&notification(NotifyEvent::Missed), day).await;
+    quiet.notify(&notification(NotifyEvent::Expiring), day).await;
+    assert_eq!(backend.shown().len(), 3);
+    assert_eq!(backend.sounds(), vec!["/tmp/alarm.wav".to_string()]);
+
+    // Отключённые уведомления ничего не показывают.
+    let disabled = NotifyConfig {
+        enabled: false,
+        ..Default::default()
+    };
+    let delivery = notifier(&disabled, &backend).notify(&notification(NotifyEvent::Missed), day).await;
+    assert_eq!(delivery, Delivery::default());
+    assert_eq!(backend.shown().len(), 3);
+}
+
+#[tokio::test]
+async fn alert_sink_maps_alert_kinds_to_events() {
+    let backend = RecordingNotifications::default();
+    let sink = NotifierSink::new(notifier(&NotifyConfig::default(), &backend));
+    let alert = |transaction_id: &str, kind| Alert {
+        transaction_id: transaction_id.to_string(),
+        expired_at: at("2025-02-05T04:00:00Z"),
+        kind,
+        channels: vec![Channel::Desktop],
+        title: "Выплата".to_string(),
+        message: "Осталось 5 минут".to_string(),
+    };
+
+    sink.deliver(&alert("7", AlertKind::Expiring { remaining_secs: 300 }))
+        .await
+        .unwrap();
+    sink.deliver(&alert("", AlertKind::SessionExpiring { remaining_secs: 600 }))
+        .await
+        .unwrap();
+
+    let shown = backend.shown();
+    assert_eq!(shown[0].event, NotifyEvent::Expiring);
+    assert_eq!(shown[0].body, "Осталось 5 минут");
+    assert_eq!(shown[0].click.as_ref().unwrap().transaction_id.as_deref(), Some("7"));
+    assert_eq!(shown[1].event, NotifyEvent::Session);
+    assert_eq!(shown[1].click.as_ref().unwrap().transaction_id, None);
+    // Звук оповещений подаёт канал `sound`.
+    assert!(backend.sounds().is_empty());
+}
+
+#[test]
+fn dbus_arguments_and_replies() {
+    let mut n = notification(NotifyEvent::Missed);
+    n.title = "Trader's payout".to_string();
+    n.body = "1 500 RUB <b>\nСбербанк".to_string();
+    let args = notify_args(&n, true);
+    assert_eq!(args[0], "call");
+    assert!(args.contains(&r"'Trader\'s payout'".to_string()), "{:?}", args);
+    assert!(args.contains(&r"'1 500 RUB &lt;b&gt;\nСбербанк'".to_string()), "{:?}", args);
+    assert!(args.contains(&"['default', 'Открыть']".to_string()));
+    assert!(args.iter().any(|a| a.contains("<byte 2>")));
+    assert!(notify_args(&n, false).contains(&"@as []".to_string()));
+
+    assert_eq!(parse_notify_reply("(uint32 42,)\n"), Some(42));
+    assert_eq!(parse_notify_reply("Error: no service"), None);
+
+    let prefix = "/org/freedesktop/Notifications: org.freedesktop.Notifications.";
+    assert_eq!(
+        parse_signal(&format!("{}ActionInvoked (uint32 7, 'default')", prefix)),
+        Some(DbusSignal::ActionInvoked {
+            id: 7,
+            action: "default".to_string(),
+        })
+    );
+    assert_eq!(
+        parse_signal(&format!("{}NotificationClosed (uint32 7, uint32 2)", prefix)),
+        Some(DbusSignal::Closed { id: 7 })
+    );
+    assert_eq!(parse_signal("The name org.freedesktop.Notifications is owned"), None);
+}
@@ -0,0 +1,126 @@
+//! Действия трейдера на панели против имитации: взятие, оплата с чеком,
+//! отмена, подробности, баланс и ошибки.
+
+use p2p_app::{
+    mock::{MockPanel, MockPanelConfig},
+    panel::{PanelClient, PanelError},
+    proxy::{Cookie, ProxyState},
+};
+use serde_json::{json, Value};
+
+fn payout(id: u64) -> Value {
+    json!({
+        "id": id,
+        "wallet": "79000000001",
+        "amount": { "trader": { "643": 1000.0 } },
+        "created_at": "2025-02-05T03:34:01.000000Z",
+        "updated_at": "2025-02-05T03:44:05.000000Z",
+    })
+}
+
+fn start(dir: &std::path::Path) -> (MockPanel, PanelClient) {
+    let panel = MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            ..Default::default()
+        },
+        vec![payout(1), payout(2)],
+    );
+    let state = ProxyState::new(panel.base_url(), dir.to_path_buf());
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        name: "sid".to_string(),
+        value: "test-sid".to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: Some(true),
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    });
+    let client = PanelClient::new(state);
+    (panel, client)
+}
+
+#[tokio::test]
+async fn claims_pays_and_cancels_payouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let (panel, client) = start(dir.path());
+
+    let claimed = client.claim("1").await.unwrap();
+    assert_eq!(claimed.transaction_id, "1");
+    let receipt = b"%PDF-1.4 receipt".to_vec();
+    client.mark_paid("1", "check.pdf", receipt.clone()).await.unwrap();
+    assert_eq!(panel.paid(), vec!["1".to_string()]);
+
+    let upload = panel
+        .requests()
+        .into_iter()
+        .find(|r| r.path.ends_with("/1/approve"))
+        .unwrap();
+    assert!(upload
+        .content_type
+        .is_some_and(|t| t.starts_with("multipart/form-data")));
+    assert!(upload.body.windows(receipt.len()).any(|w| w == receipt.as_slice()));
+    assert!(upload.xsrf_token.is_some());
+
+    client.claim("2").await.unwrap();
+    client.cancel("2").await.unwrap();
+    assert_eq!(panel.cancelled(), vec!["2".to_string()]);
+    assert!(panel.claimed().is_empty());
+
+    let details = client.details("2").await.unwrap();
+    assert_eq!(details.wallet.as_deref(), Some("79000000001"));
+
+    panel.set_balance(json!({ "643": "15000.50", "000001": 120.25 }));
+    let balance = client.balance().await.unwrap();
+    assert_eq!(balance["643"], "15000.50".parse().unwrap());
+    assert_eq!(balance["000001"], "120.25".parse().unwrap());
+}
+
+#[tokio::test]
+async fn reports_typed_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let (panel, client) = start(dir.path());
+
+    client.claim("1").await.unwrap();
+    match client.claim("1").await {
+        Err(PanelError::Rejected { status, message }) => {
+            assert_eq!(status, 422);
+            assert_eq!(message, "Payout already taken.");
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(matches!(
+        client.mark_paid("2", "check.pdf", b"%PDF".to_vec()).await,
+        Err(PanelError::Rejected { .. })
+    ));
+    assert!(matches!(client.claim("404").await, Err(PanelError::NotFound)));
+
+    panel.expire_session();
+    assert!(matches!(client.balance().await, Err(PanelError::Unauthorized)));
+}
+
+#[tokio::test]
+async fn refreshes_stale_xsrf_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let (panel, client) = start(dir.path());
+
+    client.details("1").await.unwrap();
+    let stale = client.xsrf_token().unwrap();
+    panel.expire_xsrf();
+
+    client.claim("1").await.unwrap();
+    assert_eq!(panel.claimed(), vec!["1".to_string()]);
+    let attempts: Vec<Option<String>> = panel
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.ends_with("/take"))
+        .map(|r| r.xsrf_token)
+        .collect();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[0].as_deref(), Some(stale.as_str()));
+    assert_ne!(attempts[1].as_deref(), Some(stale.as_str()));
+}
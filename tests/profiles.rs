@@ -0,0 +1,266 @@
+//! Профили трейдеров: разбор настроек, изоляция кук и истории между аккаунтами
+//! и выбор порта прокси.
+
+use std::net::TcpListener;
+
+use p2p_app::{
+    cli::{parse_args, run, Invocation},
+    api::API_TOKEN_HEADER,
+    config::AppConfig,
+    idex::{load_transactions, map_transaction, save_transactions},
+    mock::{MockPanel, MockPanelConfig},
+    profiles::{resolve_profiles, ProfileConfig, ProfileError, DEFAULT_PROFILE},
+    proxy::{bind_proxy, load_cookies, run_proxy, save_cookies, Cookie, CookieStore},
+    supervisor::Supervisor,
+};
+use serde_json::json;
+
+fn profile(name: &str, base_url: Option<String>, proxy_port: Option<u16>) -> ProfileConfig {
+    ProfileConfig {
+        name: name.to_string(),
+        base_url,
+        source: None,
+        proxy_port,
+    }
+}
+
+fn config(profiles: Vec<ProfileConfig>) -> AppConfig {
+    AppConfig {
+        profiles,
+        ..Default::default()
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn session(value: &str) -> CookieStore {
+    CookieStore {
+        cookies: vec![Cookie {
+            name: "sid".to_string(),
+            value: value.to_string(),
+            domain: "127.0.0.1".to_string(),
+            path: "/".to_string(),
+            expiration_date: None,
+            host_only: None,
+            http_only: Some(true),
+            same_site: None,
+            secure: None,
+            session: None,
+            store_id: None,
+        }],
+    }
+}
+
+#[test]
+fn resolves_profiles_from_config() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // Без секции `profiles` — прежнее поведение: один профиль в каталоге данных.
+    let single = resolve_profiles(&AppConfig::default(), dir.path()).unwrap();
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].name, DEFAULT_PROFILE);
+    assert_eq!(single[0].data_dir, dir.path());
+    assert_eq!(single[0].proxy_addr.port(), 8080);
+    assert!(!single[0].fixed_port);
+    assert!(single[0].webview_dir.is_none());
+
+    let profiles = resolve_profiles(
+        &config(vec![
+            profile("main", None, None),
+            profile("night-shift", None, Some(9000)),
+            profile("spare", None, None),
+        ]),
+        dir.path(),
+    )
+    .unwrap();
+    let ports: Vec<u16> = profiles.iter().map(|p| p.proxy_addr.port()).collect();
+    assert_eq!(ports, vec![8080, 9000, 8082]);
+    let fixed: Vec<bool> = profiles.iter().map(|p| p.fixed_port).collect();
+    assert_eq!(fixed, vec![false, true, false]);
+    assert_eq!(profiles[1].data_dir, dir.path().join("profiles").join("night-shift"));
+    assert_eq!(
+        profiles[1].webview_dir.as_deref(),
+        Some(dir.path().join("profiles").join("night-shift").join("webview").as_path())
+    );
+    assert_eq!(profiles[2].task_name("idex"), "idex:spare");
+
+    let parsed: AppConfig =
+        serde_json::from_value(json!({ "profiles": [{ "name": "main", "proxy_port": 8181 }] }))
+            .unwrap();
+    assert_eq!(parsed.profiles[0].proxy_port, Some(8181));
+}
+
+#[test]
+fn rejects_invalid_profiles() {
+    let dir = tempfile::tempdir().unwrap();
+    let resolve = |profiles| resolve_profiles(&config(profiles), dir.path()).unwrap_err();
+
+    assert_eq!(
+        resolve(vec![profile("../main", None, None)]),
+        ProfileError::InvalidName("../main".to_string())
+    );
+    assert_eq!(
+        resolve(vec![profile("main", None, None), profile("main", None, Some(9000))]),
+        ProfileError::DuplicateName("main".to_string())
+    );
+    assert_eq!(
+        resolve(vec![profile("a", None, None), profile("b", None, Some(8080))]),
+        ProfileError::DuplicatePort(8080)
+    );
+    let mut unknown = profile("main", None, None);
+    unknown.source = Some("bybit".to_string());
+    assert_eq!(resolve(vec![unknown]), ProfileError::UnknownSource("bybit".to_string()));
+}
+
+#[tokio::test]
+async fn profiles_keep_cookies_and_history_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel_config = |sid: &str| MockPanelConfig {
+        session_cookie: Some(("sid".to_string(), sid.to_string())),
+        ..Default::default()
+    };
+    let first_panel = MockPanel::start(panel_config("first-sid"), vec![json!({ "id": 1 })]);
+    let second_panel = MockPanel::start(panel_config("second-sid"), vec![json!({ "id": 2 })]);
+    let profiles = resolve_profiles(
+        &config(vec![
+            profile("first", Some(first_panel.base_url()), Some(free_port())),
+            profile("second", Some(second_panel.base_url()), Some(free_port())),
+        ]),
+        dir.path(),
+    )
+    .unwrap();
+
+    let mut servers = Vec::new();
+    for (profile, sid) in profiles.iter().zip(["first-sid", "second-sid"]) {
+        std::fs::create_dir_all(&profile.data_dir).unwrap();
+        save_cookies(&profile.data_dir, &session(sid)).unwrap();
+        let state = profile.state(&AppConfig::default()).unwrap();
+        servers.push(tokio::spawn(run_proxy(state, profile.proxy_addr)));
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    for profile in &profiles {
+        let url = format!("http://{}/api/v1/payments/payouts?page=1", profile.proxy_addr);
+        assert_eq!(reqwest::get(url).await.unwrap().status(), 200);
+    }
+
+    // Каждая панель видит только сессию своего профиля.
+    for (panel, sid) in [(&first_panel, "first-sid"), (&second_panel, "second-sid")] {
+        let requests = panel.requests();
+        assert_eq!(requests.len(), 1);
+        let cookie = requests[0].cookie.clone().unwrap();
+        assert!(cookie.contains(sid));
+        assert!(!cookie.contains(if sid == "first-sid" { "second-sid" } else { "first-sid" }));
+    }
+
+    let ids = |i: usize| -> Vec<String> {
+        load_transactions(&profiles[i].data_dir)
+            .into_iter()
+            .map(|tx| tx.transaction_id)
+            .collect()
+    };
+    assert_eq!(ids(0), vec!["1".to_string()]);
+    assert_eq!(ids(1), vec!["2".to_string()]);
+    for profile in &profiles {
+        let cookies = load_cookies(&profile.data_dir).unwrap();
+        assert_eq!(cookies.cookies.len(), 2);
+        assert!(cookies.cookies.iter().any(|c| c.name == "XSRF-TOKEN"));
+    }
+    assert!(!dir.path().join("cookies.json").exists());
+
+    for server in servers {
+        server.abort();
+    }
+}
+
+#[test]
+fn export_reads_the_chosen_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = config(vec![profile("first", None, None), profile("second", None, None)]);
+    let profiles = resolve_profiles(&config, dir.path()).unwrap();
+    for (profile, id) in profiles.iter().zip([1, 2]) {
+        std::fs::create_dir_all(&profile.data_dir).unwrap();
+        let tx = map_transaction(&json!({ "id": id })).unwrap();
+        save_transactions(&profile.data_dir, &[tx]).unwrap();
+    }
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let export_of = |name: Option<&str>| {
+        let output = dir.path().join(format!("{}.csv", name.unwrap_or("default")));
+        let mut a = vec!["export", "--fields", "transaction_id", "--output", output.to_str().unwrap()];
+        if let Some(name) = name {
+            a.extend(["--profile", name]);
+        }
+        let Ok(Invocation::Command(command)) = parse_args(&args(&a), &config) else {
+            panic!("expected command");
+        };
+        run(command, dir.path()).map(|()| std::fs::read_to_string(&output).unwrap())
+    };
+
+    assert_eq!(export_of(None).unwrap(), "transaction_id\n1\n");
+    assert_eq!(export_of(Some("second")).unwrap(), "transaction_id\n2\n");
+    assert_eq!(export_of(Some("night")).unwrap_err(), "unknown profile: night");
+
+    let report = args(&["report", "--profile", "second", "--json"]);
+    let Ok(Invocation::Command(report)) = parse_args(&report, &config) else {
+        panic!("expected command");
+    };
+    assert!(run(report, dir.path()).is_ok());
+    assert!(parse_args(&args(&["report", "--profile"]), &config).is_err());
+}
+
+#[tokio::test]
+async fn busy_port_is_replaced_unless_fixed() {
+    let dir = tempfile::tempdir().unwrap();
+    let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+    let busy_addr = busy.local_addr().unwrap();
+
+    let err = bind_proxy(busy_addr, true).unwrap_err();
+    assert_eq!(err.addr, busy_addr);
+    assert_eq!(err.source.kind(), std::io::ErrorKind::AddrInUse);
+    assert!(err.to_string().contains("proxy_port"));
+    let other = bind_proxy(busy_addr, false).unwrap();
+    assert_ne!(other.local_addr().unwrap(), busy_addr);
+    drop(other);
+
+    let profiles = resolve_profiles(
+        &config(vec![
+            profile("fixed", None, Some(busy_addr.port())),
+            profile("any", None, Some(0)),
+        ]),
+        dir.path(),
+    )
+    .unwrap();
+    let supervisor = Supervisor::new();
+    let states: Vec<_> = profiles
+        .iter()
+        .map(|p| p.state(&AppConfig::default()).unwrap())
+        .collect();
+
+    // Занятый заданный порт — ошибка, и окну IDEX открывать нечего.
+    assert!(profiles[0].spawn_tasks(&supervisor, &states[0]).is_err());
+    assert_eq!(states[0].webview_url(), None);
+
+    // Фактический адрес публикуется до того, как прокси начнёт принимать запросы.
+    let addr = profiles[1].spawn_tasks(&supervisor, &states[1]).unwrap();
+    assert_ne!(addr.port(), 0);
+    assert_eq!(
+        states[1].webview_url(),
+        Some(format!("http://{}/https://panel.gate.cx/", addr))
+    );
+    let status = reqwest::Client::new()
+        .get(format!("http://{}/__p2p/session", addr))
+        .header(API_TOKEN_HEADER, states[1].api_token.as_ref())
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, 200);
+
+    supervisor.shutdown(std::time::Duration::from_secs(5)).await;
+}
@@ -0,0 +1,258 @@
+//! Разбор PDF-чеков и сверка их с выплатой.
+
+use chrono::NaiveDate;
+use p2p_app::{
+    attachments::{AttachmentStore, AttachmentsConfig},
+    idex::{map_transaction, Transaction},
+    mock::{MockPanel, MockPanelConfig},
+    proxy::{Cookie, ProxyState},
+    receipts::{parse_pdf, parse_receipt, verify_receipt, Receipt, ReceiptIssue, ReceiptsConfig},
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+const SBER: &str = "\
+ПАО Сбербанк
+Чек по операции
+05.02.2025 09:39:02 (МСК)
+Операция
+Перевод по СБП
+ФИО получателя
+Иван Иванович И.
+Телефон получателя
++7 (900) 000-00-01
+Банк получателя
+Т-Банк
+Сумма перевода
+14 438,00 ₽
+Комиссия
+0,00 ₽
+Номер документа
+1000000012345678
+";
+
+const TBANK: &str = "\
+Квитанция № 1-2-345-678-901
+5 февраля 2025 в 09:41
+Итого 14 438 ₽
+Перевод по номеру карты
+Отправитель Иван Петров
+Карта отправителя 5536 91** **** 0001
+Карта получателя 2200 70** **** 1234
+Банк получателя Сбербанк
+Идентификатор операции в СБП A50360339021820B0000040011580501
+";
+
+/// Минимальный PDF с кириллицей: Type1-шрифт с перекодировкой
+/// байтов 128–191 в глифы `afii100xx`.
+fn pdf(text: &str) -> Vec<u8> {
+    let glyphs = |base: u32| {
+        (0..32)
+            .map(|i| format!(" /afii{}", base + i + (i >= 6) as u32))
+            .collect::<String>()
+    };
+    let encode = |line: &str| -> Vec<u8> {
+        line.chars()
+            .map(|c| match c {
+                'А'..='Я' => 128 + (c as u32 - 'А' as u32) as u8,
+                'а'..='я' => 160 + (c as u32 - 'а' as u32) as u8,
+                '(' | ')' | '\\' => b' ',
+                c if c.is_ascii() => c as u8,
+                _ => b' ',
+            })
+            .collect()
+    };
+    let mut content = b"BT /F1 10 Tf 40 800 Td 14 TL\n".to_vec();
+    for line in text.lines() {
+        content.push(b'(');
+        content.extend(encode(line));
+        content.extend(b") Tj T*\n");
+    }
+    content.extend(b"ET");
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>".to_vec(),
+        format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding << /Type /Encoding /BaseEncoding /WinAnsiEncoding /Differences [128{} 160{}] >> >>",
+            glyphs(10017),
+            glyphs(10065)
+        )
+        .into_bytes(),
+        [
+            format!("<< /Length {} >>\nstream\n", content.len()).into_bytes(),
+            content,
+            b"\nendstream".to_vec(),
+        ]
+        .concat(),
+    ];
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend(format!("{} 0 obj\n", i + 1).into_bytes());
+        out.extend(object);
+        out.extend(b"\nendobj\n");
+    }
+    let xref = out.len();
+    out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        out.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+    }
+    out.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .into_bytes(),
+    );
+    out
+}
+
+fn payout(wallet: &str, amount: f64, bank: &str, attachments: Value) -> Value {
+    json!({
+        "id": 1,
+        "wallet": wallet,
+        "amount": { "trader": { "643": amount, "000001": 145.96 } },
+        "bank": { "name": "tbank", "label": bank },
+        "approved_at": "2025-02-05T06:40:10.000000Z",
+        "created_at": "2025-02-05T06:34:01.000000Z",
+        "updated_at": "2025-02-05T06:44:05.000000Z",
+        "attachments": attachments,
+    })
+}
+
+fn transaction(wallet: &str, amount: f64, bank: &str) -> Transaction {
+    map_transaction(&payout(wallet, amount, bank, Value::Null)).unwrap()
+}
+
+#[test]
+fn parses_receipt_layouts() {
+    let sber = parse_receipt(SBER);
+    assert_eq!(
+        sber,
+        Receipt {
+            amount: Some("14438.00".parse().unwrap()),
+            phone: Some("79000000001".to_string()),
+            card: None,
+            bank: Some("Т-Банк".to_string()),
+            datetime: NaiveDate::from_ymd_opt(2025, 2, 5).unwrap().and_hms_opt(9, 39, 2),
+            operation_id: Some("1000000012345678".to_string()),
+        }
+    );
+
+    let tbank = parse_receipt(TBANK);
+    assert_eq!(tbank.amount, Some("14438".parse().unwrap()));
+    assert_eq!(tbank.card.as_deref(), Some("220070******1234"));
+    assert_eq!(tbank.phone, None);
+    assert_eq!(tbank.bank.as_deref(), Some("Сбербанк"));
+    assert_eq!(
+        tbank.datetime,
+        NaiveDate::from_ymd_opt(2025, 2, 5).unwrap().and_hms_opt(9, 41, 0)
+    );
+    assert_eq!(
+        tbank.operation_id.as_deref(),
+        Some("A50360339021820B0000040011580501")
+    );
+}
+
+#[test]
+fn verifies_receipt_against_payout() {
+    let config = ReceiptsConfig::default();
+    let sber = parse_receipt(SBER);
+    let tbank = parse_receipt(TBANK);
+
+    let tx = transaction("79000000001", 14438.0, "Тинькофф");
+    assert_eq!(verify_receipt(&tx, &sber, false, &config), Vec::new());
+
+    let card_tx = transaction("2200700012341234", 14438.0, "Сбербанк");
+    assert_eq!(verify_receipt(&card_tx, &tbank, false, &config), Vec::new());
+
+    let wrong = transaction("79000000002", 14500.0, "ВТБ");
+    let issues = verify_receipt(&wrong, &sber, true, &config);
+    assert_eq!(
+        issues,
+        vec![
+            ReceiptIssue::MarkedFake,
+            ReceiptIssue::AmountMismatch {
+                expected: "14500".parse().unwrap(),
+                found: "14438.00".parse().unwrap(),
+            },
+            ReceiptIssue::RecipientMismatch {
+                expected: "79000000002".to_string(),
+                found: "79000000001".to_string(),
+            },
+            ReceiptIssue::BankMismatch {
+                expected: "ВТБ".to_string(),
+                found: "Т-Банк".to_string(),
+            },
+        ]
+    );
+
+    let mut late = tx.clone();
+    late.approved_at = Some("2025-02-05T09:40:10.000000Z".to_string());
+    assert!(matches!(
+        verify_receipt(&late, &sber, false, &config).as_slice(),
+        [ReceiptIssue::TimeMismatch { .. }]
+    ));
+
+    let other_card = transaction("2200700099995678", 14438.0, "Сбербанк");
+    assert!(matches!(
+        verify_receipt(&other_card, &tbank, false, &config).as_slice(),
+        [ReceiptIssue::RecipientMismatch { .. }]
+    ));
+}
+
+#[test]
+fn extracts_text_from_pdf() {
+    let receipt = parse_pdf(&pdf(SBER)).unwrap();
+    assert_eq!(receipt, parse_receipt(SBER));
+    assert!(parse_pdf(b"%PDF-1.4\nnot a document").is_err());
+}
+
+#[tokio::test]
+async fn downloaded_receipts_are_checked() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(MockPanelConfig::default(), Vec::new());
+    let content = pdf(SBER);
+    panel.add_file("/storage/1/check.pdf", content.clone());
+
+    let state = ProxyState::new(panel.base_url(), dir.path().to_path_buf());
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        name: "sid".to_string(),
+        value: "test-sid".to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: None,
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    });
+    let attachments = json!([{
+        "custom_properties": { "fake": false },
+        "extension": "pdf",
+        "file_name": "check.pdf",
+        "original_url": "1/check.pdf",
+        "size": content.len(),
+    }]);
+    let tx = map_transaction(&payout("79000000001", 15000.0, "Т-Банк", attachments)).unwrap();
+    state.transactions.lock().unwrap().push(tx);
+
+    let mut store = AttachmentStore::open(dir.path(), &AttachmentsConfig::default());
+    let stats = store.sync(&state, &Client::new()).await;
+    assert_eq!((stats.downloaded, stats.linked), (1, 1));
+    assert_eq!(
+        store.record("1/check.pdf").unwrap().receipt,
+        Some(parse_receipt(SBER))
+    );
+
+    let transactions = state.transactions.lock().unwrap();
+    let check = &transactions[0].attachments.as_ref().unwrap()[0]["receipt_check"];
+    assert_eq!(check["ok"], json!(false));
+    assert_eq!(check["issues"][0]["kind"], json!("amount_mismatch"));
+    assert_eq!(check["issues"].as_array().unwrap().len(), 1);
+}
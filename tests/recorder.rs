@@ -0,0 +1,202 @@
+//! Запись трафика прокси: JSONL и HAR, ротация файлов, фильтр URL, скрытие
+//! секретов и обрезка тел.
+
+use std::{path::Path, time::Duration};
+
+use chrono::{DateTime, Utc};
+use hyper::{
+    header::{HeaderMap, HeaderValue},
+    Method, StatusCode,
+};
+use p2p_app::recorder::{glob_match, Exchange, RecordFormat, RecorderConfig, TrafficRecorder};
+use serde_json::Value;
+
+fn recorder(dir: &Path, config: RecorderConfig) -> TrafficRecorder {
+    TrafficRecorder::from_config(
+        &RecorderConfig {
+            enabled: true,
+            ..config
+        },
+        dir,
+    )
+    .unwrap()
+}
+
+fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in pairs {
+        map.append(*name, HeaderValue::from_static(value));
+    }
+    map
+}
+
+/// Записывает один обмен с заданными телами.
+fn record(recorder: &TrafficRecorder, url: &str, request_body: &[u8], response_body: &[u8]) {
+    let url = url::Url::parse(url).unwrap();
+    let request_headers = headers(&[
+        ("cookie", "sid=abc123; XSRF-TOKEN=xsrf456"),
+        ("x-xsrf-token", "xsrf456"),
+        ("content-type", "application/json"),
+    ]);
+    let response_headers = headers(&[
+        ("set-cookie", "sid=new789; Path=/; HttpOnly"),
+        ("content-type", "application/json"),
+    ]);
+    recorder.record(&Exchange {
+        started_at: DateTime::parse_from_rfc3339("2025-02-05T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc),
+        method: &Method::POST,
+        url: &url,
+        request_headers: &request_headers,
+        request_body,
+        status: StatusCode::OK,
+        response_headers: &response_headers,
+        response_body,
+        wait: Duration::from_millis(120),
+        receive: Duration::from_millis(30),
+    });
+}
+
+fn jsonl_lines(path: &Path) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn jsonl_entries_hide_secrets() {
+    let dir = tempfile::tempdir().unwrap();
+    let rec = recorder(dir.path(), RecorderConfig::default());
+    record(&rec, "https://panel.gate.cx/api/payments?page=1", b"{\"id\":1}", b"{\"ok\":true}");
+    record(&rec, "https://panel.gate.cx/api/payments?page=2", b"", b"[]");
+
+    let lines = jsonl_lines(&dir.path().join("traffic.jsonl"));
+    assert_eq!(lines.len(), 2);
+    let entry = &lines[0];
+    assert_eq!(entry["method"], "POST");
+    assert_eq!(entry["status"], 200);
+    assert_eq!(entry["duration_ms"], 150);
+    assert_eq!(entry["request"]["body"]["text"], "{\"id\":1}");
+    assert_eq!(entry["response"]["body"]["text"], "{\"ok\":true}");
+
+    let text = std::fs::read_to_string(dir.path().join("traffic.jsonl")).unwrap();
+    for secret in ["abc123", "xsrf456", "new789"] {
+        assert!(!text.contains(secret), "{} leaked into {}", secret, text);
+    }
+    assert!(text.contains("[redacted]"));
+}
+
+#[test]
+fn har_file_stays_valid_after_each_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = RecorderConfig {
+        format: RecordFormat::Har,
+        ..Default::default()
+    };
+    let path = dir.path().join("traffic.har");
+
+    record(&recorder(dir.path(), config.clone()), "https://panel.gate.cx/a?x=1", b"{}", b"{}");
+    let har: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(har["log"]["version"], "1.2");
+    assert_eq!(har["log"]["entries"].as_array().unwrap().len(), 1);
+
+    // Новый рекордер дописывает существующий файл.
+    record(&recorder(dir.path(), config), "https://panel.gate.cx/b", b"", b"{}");
+    let text = std::fs::read_to_string(&path).unwrap();
+    let har: Value = serde_json::from_str(&text).unwrap();
+    let entries = har["log"]["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let first = &entries[0];
+    assert_eq!(first["request"]["queryString"][0]["name"], "x");
+    assert_eq!(first["request"]["cookies"][0]["name"], "sid");
+    assert_eq!(first["request"]["cookies"][1]["name"], "XSRF-TOKEN");
+    assert_eq!(first["response"]["cookies"][0]["value"], "[redacted]");
+    assert_eq!(first["response"]["content"]["mimeType"], "application/json");
+    assert_eq!(first["timings"]["wait"], 120.0);
+    assert!(entries[1]["request"].get("postData").is_none());
+    for secret in ["abc123", "xsrf456", "new789"] {
+        assert!(!text.contains(secret), "{} leaked", secret);
+    }
+}
+
+#[test]
+fn files_are_rotated_by_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let rec = recorder(
+        dir.path(),
+        RecorderConfig {
+            max_file_bytes: 1500,
+            max_files: 2,
+            ..Default::default()
+        },
+    );
+    for page in 0..8 {
+        record(&rec, &format!("https://panel.gate.cx/api?page={}", page), b"", &[b'x'; 500]);
+    }
+
+    let path = dir.path().join("traffic.jsonl");
+    let rotated = |n: usize| dir.path().join(format!("traffic.jsonl.{}", n));
+    assert!(rotated(1).exists());
+    assert!(rotated(2).exists());
+    assert!(!rotated(3).exists());
+    for file in [path.clone(), rotated(1), rotated(2)] {
+        assert!(std::fs::metadata(&file).unwrap().len() <= 1500, "{}", file.display());
+    }
+    // Самая свежая запись — в текущем файле.
+    let lines = jsonl_lines(&path);
+    assert!(lines.last().unwrap()["url"].as_str().unwrap().ends_with("page=7"));
+}
+
+#[test]
+fn url_filter_uses_globs() {
+    assert!(glob_match("*", "https://panel.gate.cx/"));
+    assert!(glob_match("https://panel.gate.cx/*", "https://panel.gate.cx/api/payments"));
+    assert!(glob_match("*/api/*/payments*", "https://panel.gate.cx/api/v1/payments?page=2"));
+    assert!(glob_match("exact", "exact"));
+    assert!(!glob_match("exact", "exactly"));
+    assert!(!glob_match("*/api/*", "https://panel.gate.cx/auth"));
+    assert!(!glob_match("https://*.gate.cx/*", "https://panel.gate.io/"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let rec = recorder(
+        dir.path(),
+        RecorderConfig {
+            url_filter: vec!["*/api/payments*".to_string()],
+            ..Default::default()
+        },
+    );
+    assert!(rec.matches("https://panel.gate.cx/api/payments?page=1"));
+    assert!(!rec.matches("https://panel.gate.cx/static/app.js"));
+    record(&rec, "https://panel.gate.cx/static/app.js", b"", b"");
+    record(&rec, "https://panel.gate.cx/api/payments", b"", b"");
+    assert_eq!(jsonl_lines(&dir.path().join("traffic.jsonl")).len(), 1);
+}
+
+#[test]
+fn truncated_bodies_stay_text() {
+    let dir = tempfile::tempdir().unwrap();
+    let rec = recorder(
+        dir.path(),
+        RecorderConfig {
+            max_body_bytes: 5,
+            ..Default::default()
+        },
+    );
+    // «пр» — 4 байта, лимит режет третий символ посередине.
+    record(&rec, "https://panel.gate.cx/a", "привет".as_bytes(), &[0xff, 0xfe, 0x00, 0x01]);
+
+    let entry = &jsonl_lines(&dir.path().join("traffic.jsonl"))[0];
+    let request = &entry["request"]["body"];
+    assert_eq!(request["text"], "пр");
+    assert_eq!(request["size"], 12);
+    assert!(request.get("encoding").is_none());
+    assert_eq!(request["comment"], "truncated to 4 bytes");
+    // Настоящие бинарные данные по-прежнему в base64.
+    let response = &entry["response"]["body"];
+    assert_eq!(response["encoding"], "base64");
+    assert_eq!(response["text"], "//4AAQ==");
+}
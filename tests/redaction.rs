@@ -0,0 +1,63 @@
+//! Маскирование секретов в логах.
+
+use p2p_app::logging::{init_logging, mask_wallet, redact, LoggingConfig};
+
+#[test]
+fn cookie_values_are_redacted() {
+    let line = "Cookie: sid=abc123; rsid=def456; XSRF-TOKEN=ghi789";
+    let out = redact(line);
+    assert!(!out.contains("abc123"));
+    assert!(!out.contains("def456"));
+    assert!(!out.contains("ghi789"));
+    assert!(out.contains("sid=[redacted]"));
+}
+
+#[test]
+fn json_fields_and_encrypted_payloads_are_redacted() {
+    let line = r#"{"deviceToken":"d3v1c3-t0k3n","payload":"eyJpdiI6Inl0MWsvUVNaaktWR08ySTdMU2VtakE9PSIs"}"#;
+    let out = redact(line);
+    assert!(!out.contains("d3v1c3-t0k3n"));
+    assert!(!out.contains("eyJpdiI6"));
+}
+
+#[test]
+fn wallet_numbers_keep_only_last_digits() {
+    assert_eq!(mask_wallet("79276006015"), "*******6015");
+    assert_eq!(mask_wallet("+7 927 600-60-15"), "+* *** ***-60-15");
+    let out = redact("wallet=79276006015 card 2200700112345678 id 1318978");
+    assert!(out.contains("*******6015"));
+    assert!(out.contains("************5678"));
+    // Идентификаторы транзакций и коды банков — не секреты.
+    assert!(out.contains("1318978"));
+}
+
+#[test]
+fn formatted_wallet_numbers_are_masked() {
+    let out = redact("wallet +7 927 600-60-15, phone 8 (927) 600-60-15, card 2200 7001 1234 5678");
+    assert!(out.contains("+* *** ***-60-15"), "{}", out);
+    assert!(out.contains("* (***) ***-60-15"), "{}", out);
+    assert!(out.contains("**** **** **** 5678"), "{}", out);
+    for digits in ["927 600", "2200 7001"] {
+        assert!(!out.contains(digits), "{}", out);
+    }
+    let line = "Payout 1318978 for bank 100000000111 at 2025-02-05 03:34:01";
+    assert_eq!(redact(line), line);
+}
+
+#[test]
+fn log_directory_is_created() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = LoggingConfig {
+        directory: "logs/app".to_string(),
+        ..Default::default()
+    };
+    let guard = init_logging(&config, dir.path());
+    assert!(guard.is_some());
+    assert!(dir.path().join("logs/app").is_dir());
+}
+
+#[test]
+fn plain_messages_are_untouched() {
+    let line = "Found 10 transactions on page 2";
+    assert_eq!(redact(line), line);
+}
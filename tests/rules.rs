@@ -0,0 +1,310 @@
+//! Правила автоматического взятия выплат и взятие через имитацию панели.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use p2p_app::{
+    config::AppConfig,
+    idex::{map_transaction, run_idex, Transaction},
+    mock::{MockPanel, MockPanelConfig},
+    proxy::{Cookie, ProxyState},
+    rules::{is_claimable, AutoClaimConfig, ClaimResult, Rule, RuleEngine, TimeWindow},
+};
+use serde_json::{json, Value};
+
+fn payout(id: u64, amount: f64, bank_code: &str, course: f64) -> Value {
+    json!({
+        "id": id,
+        "wallet": "79000000001",
+        "amount": { "trader": { "643": amount, "000001": 10.5 } },
+        "bank": { "name": "sberbank", "code": bank_code, "label": "Сбербанк" },
+        "method": { "label": "OUT: Система быстрых платежей (СБП)" },
+        "meta": { "courses": { "trader": course } },
+        "status": "pending",
+        "created_at": "2025-02-05T03:34:01.000000Z",
+        "updated_at": "2025-02-05T03:44:05.000000Z",
+    })
+}
+
+fn transaction(id: u64, amount: f64, bank_code: &str, course: f64) -> Transaction {
+    map_transaction(&payout(id, amount, bank_code, course)).unwrap()
+}
+
+fn at(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+}
+
+fn sbp_rule() -> Rule {
+    Rule {
+        name: "sbp".to_string(),
+        min_amount: Some("1000".parse().unwrap()),
+        max_amount: Some("20000".parse().unwrap()),
+        banks: vec!["100000000111".to_string()],
+        payment_methods: vec!["сбп".to_string()],
+        min_course: Some("95".parse().unwrap()),
+        daily_count: Some(1),
+        ..Default::default()
+    }
+}
+
+fn config(rules: Vec<Rule>, dry_run: bool) -> AutoClaimConfig {
+    AutoClaimConfig {
+        enabled: true,
+        dry_run,
+        rules,
+        ..Default::default()
+    }
+}
+
+fn state_with_session(panel: &MockPanel, data_dir: &std::path::Path) -> ProxyState {
+    let state = ProxyState::new(panel.base_url(), data_dir.to_path_buf());
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        name: "sid".to_string(),
+        value: "test-sid".to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: Some(true),
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    });
+    state
+}
+
+#[test]
+fn rules_filter_payouts() {
+    let dir = tempfile::tempdir().unwrap();
+    let night = Rule {
+        name: "night".to_string(),
+        time_windows: vec![TimeWindow {
+            start: "22:00".to_string(),
+            end: "06:00".to_string(),
+        }],
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(&config(vec![sbp_rule(), night], true), dir.path());
+    let noon = at("2025-02-05T09:00:00Z");
+    let midnight = at("2025-02-05T21:30:00Z");
+
+    let name = |tx: &Transaction, now| engine.evaluate(tx, now).map(|r| r.name.clone());
+    assert_eq!(name(&transaction(1, 5000.0, "100000000111", 96.0), noon).as_deref(), Some("sbp"));
+    // Мимо первого правила: сумма, банк и курс; второе действует только ночью (МСК).
+    for tx in [
+        transaction(2, 500.0, "100000000111", 96.0),
+        transaction(3, 25000.0, "100000000111", 96.0),
+        transaction(4, 5000.0, "100000000004", 96.0),
+        transaction(5, 5000.0, "100000000111", 94.0),
+    ] {
+        assert_eq!(name(&tx, noon), None);
+        assert_eq!(name(&tx, midnight).as_deref(), Some("night"));
+    }
+}
+
+#[tokio::test]
+async fn claims_matching_payouts_within_daily_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let payouts = vec![
+        payout(1, 5000.0, "100000000111", 96.0),
+        payout(2, 500.0, "100000000111", 96.0),
+        payout(3, 7000.0, "100000000111", 96.0),
+    ];
+    let panel = MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            ..Default::default()
+        },
+        payouts.clone(),
+    );
+    let state = state_with_session(&panel, dir.path());
+    let transactions: Vec<Transaction> = payouts.iter().filter_map(map_transaction).collect();
+
+    let mut engine = RuleEngine::new(&config(vec![sbp_rule()], false), dir.path());
+    let outcomes = engine.process(&state, &transactions).await;
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].transaction_id, "1");
+    assert_eq!(outcomes[0].result, ClaimResult::Claimed);
+    assert_eq!(panel.claimed(), vec!["1".to_string()]);
+
+    // Токен взят из куки XSRF-TOKEN, полученной до действия.
+    let claim = panel
+        .requests()
+        .into_iter()
+        .find(|r| r.path.ends_with("/take"))
+        .unwrap();
+    assert!(claim.xsrf_token.is_some_and(|t| t.starts_with("mock-xsrf-")));
+
+    // Дневной лимит сохраняется между перезапусками.
+    let mut engine = RuleEngine::new(&config(vec![sbp_rule()], false), dir.path());
+    assert_eq!(engine.usage("sbp", Utc::now()).count, 1);
+    assert!(engine.process(&state, &transactions[2..]).await.is_empty());
+}
+
+#[tokio::test]
+async fn dry_run_does_not_touch_panel() {
+    let dir = tempfile::tempdir().unwrap();
+    let payouts = vec![payout(1, 5000.0, "100000000111", 96.0)];
+    let panel = MockPanel::start(MockPanelConfig::default(), payouts.clone());
+    let state = state_with_session(&panel, dir.path());
+    let transactions: Vec<Transaction> = payouts.iter().filter_map(map_transaction).collect();
+
+    let mut engine = RuleEngine::new(&config(vec![sbp_rule()], true), dir.path());
+    let outcomes = engine.process(&state, &transactions).await;
+    assert_eq!(outcomes[0].result, ClaimResult::DryRun);
+    assert!(panel.requests().is_empty());
+    assert!(!dir.path().join("auto_claim.json").exists());
+}
+
+#[test]
+fn only_free_unexpired_payouts_are_claimable() {
+    let dir = tempfile::tempdir().unwrap();
+    let engine = RuleEngine::new(&config(vec![sbp_rule()], true), dir.path());
+    let noon = at("2025-02-05T09:00:00Z");
+    let with = |status: Value, expired_at: &str| {
+        let mut payout = payout(1, 5000.0, "100000000111", 96.0);
+        payout["status"] = status;
+        payout["expired_at"] = json!(expired_at);
+        map_transaction(&payout).unwrap()
+    };
+
+    let free = with(json!("pending"), "2025-02-05T09:10:00Z");
+    assert_eq!(free.status.as_deref(), Some("pending"));
+    assert!(is_claimable(&free, noon));
+    assert_eq!(engine.evaluate(&free, noon).map(|r| r.name.as_str()), Some("sbp"));
+
+    // Взятая, завершённая, без статуса и с числовым кодом — не берутся.
+    for status in [json!("in_progress"), json!("completed"), Value::Null, json!(1)] {
+        let tx = with(status, "2025-02-05T09:10:00Z");
+        assert!(!is_claimable(&tx, noon), "{:?}", tx.status);
+        assert!(engine.evaluate(&tx, noon).is_none());
+    }
+    let expired = with(json!("pending"), "2025-02-05T08:59:00Z");
+    assert!(!is_claimable(&expired, noon));
+    assert!(engine.evaluate(&expired, noon).is_none());
+}
+
+#[tokio::test]
+async fn process_skips_payouts_seen_before() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(MockPanelConfig::default(), Vec::new());
+    let state = state_with_session(&panel, dir.path());
+    let first = transaction(1, 5000.0, "100000000111", 96.0);
+    let second = transaction(2, 6000.0, "100000000111", 96.0);
+    let sbp = Rule {
+        daily_count: None,
+        ..sbp_rule()
+    };
+
+    let mut engine = RuleEngine::new(&config(vec![sbp], true), dir.path());
+    engine.skip(std::slice::from_ref(&first));
+    let outcomes = engine.process(&state, &[first.clone(), second.clone()]).await;
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].transaction_id, "2");
+    assert!(engine.process(&state, &[first, second]).await.is_empty());
+}
+
+#[tokio::test]
+async fn failed_claims_are_retried() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            ..Default::default()
+        },
+        Vec::new(),
+    );
+    let state = state_with_session(&panel, dir.path());
+    let tx = transaction(1, 5000.0, "100000000111", 96.0);
+
+    let mut engine = RuleEngine::new(&config(vec![sbp_rule()], false), dir.path());
+    // Панель ещё не знает выплату и отвечает 404.
+    let outcomes = engine.process(&state, std::slice::from_ref(&tx)).await;
+    assert!(matches!(outcomes[0].result, ClaimResult::Failed(_)));
+    assert!(!engine.has_seen("1"));
+    assert_eq!(engine.usage("sbp", Utc::now()).count, 0);
+
+    panel.push_payout(payout(1, 5000.0, "100000000111", 96.0));
+    let outcomes = engine.process(&state, std::slice::from_ref(&tx)).await;
+    assert_eq!(outcomes[0].result, ClaimResult::Claimed);
+    assert!(engine.has_seen("1"));
+    assert!(engine.process(&state, &[tx]).await.is_empty());
+}
+
+#[tokio::test]
+async fn payouts_rejected_for_now_are_reconsidered() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(MockPanelConfig::default(), Vec::new());
+    let state = state_with_session(&panel, dir.path());
+    let transactions = [
+        transaction(1, 5000.0, "100000000111", 96.0),
+        transaction(2, 6000.0, "100000000111", 96.0),
+        transaction(3, 500.0, "100000000111", 96.0),
+    ];
+
+    let mut engine = RuleEngine::new(&config(vec![sbp_rule()], true), dir.path());
+    let outcomes = engine.process(&state, &transactions).await;
+    assert_eq!(outcomes.len(), 1);
+    assert_eq!(outcomes[0].transaction_id, "1");
+    assert!(engine.has_seen("1"));
+    // Вторую не пустил дневной лимит — она подойдёт на следующие сутки;
+    // третья мала для правила и не подойдёт никогда.
+    assert!(!engine.has_seen("2"));
+    assert!(engine.has_seen("3"));
+}
+
+#[test]
+fn daily_amount_needs_rub_amount() {
+    let dir = tempfile::tempdir().unwrap();
+    let rule = Rule {
+        name: "budget".to_string(),
+        daily_amount: Some("10000".parse().unwrap()),
+        ..Default::default()
+    };
+    let engine = RuleEngine::new(&config(vec![rule], true), dir.path());
+    let noon = at("2025-02-05T09:00:00Z");
+
+    assert!(engine.evaluate(&transaction(1, 5000.0, "100000000111", 96.0), noon).is_some());
+    let mut no_rub = payout(2, 5000.0, "100000000111", 96.0);
+    no_rub["amount"]["trader"] = json!({ "000001": 10.5 });
+    let tx = map_transaction(&no_rub).unwrap();
+    assert!(tx.trader_amount("643").is_none());
+    assert!(engine.evaluate(&tx, noon).is_none());
+}
+
+#[tokio::test]
+async fn poller_claims_only_payouts_appearing_after_first_poll() {
+    let dir = tempfile::tempdir().unwrap();
+    let panel = MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            ..Default::default()
+        },
+        vec![payout(1, 5000.0, "100000000111", 96.0)],
+    );
+    let app_config = AppConfig {
+        auto_claim: config(vec![sbp_rule()], false),
+        ..Default::default()
+    };
+    let state = state_with_session(&panel, dir.path()).with_config(app_config);
+    let poller = tokio::spawn(run_idex(state.clone()));
+
+    // Выплата, видимая при первом опросе, не берётся.
+    wait(|| !state.transactions.lock().unwrap().is_empty()).await;
+    let mut taken = payout(3, 5000.0, "100000000111", 96.0);
+    taken["status"] = json!("in_progress");
+    panel.push_payout(taken);
+    panel.push_payout(payout(2, 5000.0, "100000000111", 96.0));
+    wait(|| !panel.claimed().is_empty()).await;
+    poller.abort();
+
+    assert_eq!(panel.claimed(), vec!["2".to_string()]);
+}
+
+async fn wait(mut done: impl FnMut() -> bool) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(15);
+    while !done() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
@@ -0,0 +1,129 @@
+//! Сессия панели: поддержание, смена кук и предупреждение об истечении `sid`.
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use p2p_app::{
+    alerts::{Alert, AlertKind, AlertSink, AlertsConfig, Channel, SinkFuture},
+    mock::{MockPanel, MockPanelConfig},
+    panel::{PanelError, BALANCE_PATH},
+    proxy::{Cookie, ProxyState},
+    session::{keep_alive, SessionConfig, SessionMonitor},
+};
+
+fn now() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2025-02-05T12:00:00Z")
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// Запоминает доставленные оповещения вместо показа.
+#[derive(Default, Clone)]
+struct RecordingSink(Arc<Mutex<Vec<Alert>>>);
+
+impl AlertSink for RecordingSink {
+    fn deliver<'a>(&'a self, alert: &'a Alert) -> SinkFuture<'a> {
+        Box::pin(async move {
+            self.0.lock().unwrap().push(alert.clone());
+            Ok(())
+        })
+    }
+}
+
+fn sid(expires_at: Option<DateTime<Utc>>) -> Cookie {
+    Cookie {
+        name: "sid".to_string(),
+        value: "test-sid".to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: expires_at.map(|e| e.timestamp() as f64),
+        host_only: None,
+        http_only: Some(true),
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    }
+}
+
+fn start(dir: &std::path::Path, config: MockPanelConfig) -> (MockPanel, ProxyState) {
+    let panel = MockPanel::start(
+        MockPanelConfig {
+            session_cookie: Some(("sid".to_string(), "test-sid".to_string())),
+            ..config
+        },
+        Vec::new(),
+    );
+    let state = ProxyState::new(panel.base_url(), dir.to_path_buf());
+    state.cookies.lock().unwrap().cookies.push(sid(None));
+    (panel, state)
+}
+
+#[tokio::test]
+async fn keep_alive_hits_balance_and_detects_rotation() {
+    let dir = tempfile::tempdir().unwrap();
+    let (panel, state) = start(dir.path(), MockPanelConfig::default());
+    let mut monitor = SessionMonitor::new(&SessionConfig::default(), &AlertsConfig::default());
+
+    keep_alive(&state, now()).await.unwrap();
+    assert_eq!(panel.requests().last().unwrap().path, BALANCE_PATH);
+    // Первое появление XSRF-TOKEN сменой не считается.
+    monitor.check(&state, now()).await;
+    assert_eq!(state.session.lock().unwrap().last_rotation, None);
+
+    // Имитация выдаёт новый XSRF-TOKEN на каждый ответ.
+    keep_alive(&state, now()).await.unwrap();
+    let rotated = monitor.rotations(&state.cookies.lock().unwrap());
+    assert_eq!(rotated, vec!["XSRF-TOKEN".to_string()]);
+
+    let health = state.session.lock().unwrap().clone();
+    assert_eq!(health.last_keepalive, Some(now()));
+    assert!(!health.unauthorized);
+    assert_eq!(health.keepalive_error, None);
+}
+
+#[tokio::test]
+async fn unauthorized_keep_alive_marks_session_expired() {
+    let dir = tempfile::tempdir().unwrap();
+    let (panel, state) = start(dir.path(), MockPanelConfig::default());
+    panel.expire_session();
+
+    let result = keep_alive(&state, now()).await;
+    assert!(matches!(result, Err(PanelError::Unauthorized)));
+    let health = state.session.lock().unwrap().clone();
+    assert!(health.unauthorized);
+    assert!(health.keepalive_error.is_some());
+}
+
+#[tokio::test]
+async fn warns_once_before_session_cookie_expires() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = ProxyState::new("https://panel.gate.cx/".to_string(), dir.path().to_path_buf());
+    let expires_at = now() + Duration::minutes(20);
+    state.cookies.lock().unwrap().cookies.push(sid(Some(now() + Duration::hours(2))));
+
+    let sink = RecordingSink::default();
+    let mut monitor = SessionMonitor::new(&SessionConfig::default(), &AlertsConfig::default())
+        .with_sink(Channel::Desktop, Arc::new(sink.clone()));
+
+    // До истечения больше получаса — только прогноз.
+    assert!(monitor.check(&state, now()).await.is_empty());
+    assert_eq!(
+        state.session.lock().unwrap().expires_at,
+        Some(now() + Duration::hours(2))
+    );
+
+    state.cookies.lock().unwrap().cookies[0] = sid(Some(expires_at));
+    let alerts = monitor.check(&state, now()).await;
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].kind, AlertKind::SessionExpiring { remaining_secs: 1200 });
+    assert_eq!(alerts[0].expired_at, expires_at);
+    assert!(monitor.check(&state, now() + Duration::minutes(5)).await.is_empty());
+    assert_eq!(sink.0.lock().unwrap().len(), 1);
+
+    // Сессионная кука срока не имеет, истёкшая в прогноз не попадает.
+    state.cookies.lock().unwrap().cookies[0] = sid(None);
+    assert_eq!(monitor.forecast(&state.cookies.lock().unwrap(), now()), None);
+    state.cookies.lock().unwrap().cookies[0] = sid(Some(now() - Duration::minutes(1)));
+    assert_eq!(monitor.forecast(&state.cookies.lock().unwrap(), now()), None);
+}
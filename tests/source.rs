@@ -0,0 +1,182 @@
+//! Источники выплат: опрос и правила работают с любой реализацией `PayoutSource`.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use p2p_app::{
+    idex::{map_transaction, run_idex, Transaction},
+    panel::PanelError,
+    proxy::{Cookie, ProxyState},
+    rules::{AutoClaimConfig, ClaimResult, Rule, RuleEngine},
+    source::{source_by_name, PayoutPage, PayoutSource, SourceFuture, GATE_SOURCE},
+};
+use serde_json::{json, Value};
+
+/// Платформа с собственным форматом ответа `{"orders": [{"order_id": ...}]}`.
+#[derive(Default)]
+struct OtherExchange {
+    pages: Vec<Vec<&'static str>>,
+    requested: Mutex<Vec<u32>>,
+    claimed: Mutex<Vec<String>>,
+}
+
+/// Нормализует свободную заявку другой платформы в `Transaction`.
+fn order(id: &str) -> Transaction {
+    map_transaction(&json!({
+        "id": id,
+        "wallet": "79000000001",
+        "amount": { "trader": { "643": 1000.0 } },
+        "status": "pending",
+    }))
+    .unwrap()
+}
+
+impl PayoutSource for OtherExchange {
+    fn name(&self) -> &'static str {
+        "other"
+    }
+
+    fn is_payouts_path(&self, path: &str) -> bool {
+        path == "/orders"
+    }
+
+    fn extract_payouts(&self, json: &Value) -> Vec<Transaction> {
+        json.get("orders")
+            .and_then(|o| o.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|o| o.get("order_id")?.as_str().map(order))
+            .collect()
+    }
+
+    fn fetch_page<'a>(&'a self, _session: &'a ProxyState, page: u32) -> SourceFuture<'a, PayoutPage> {
+        Box::pin(async move {
+            self.requested.lock().unwrap().push(page);
+            let ids = self.pages.get(page as usize - 1).cloned().unwrap_or_default();
+            Ok(PayoutPage {
+                transactions: self.extract_payouts(&json!({
+                    "orders": ids.iter().map(|id| json!({ "order_id": id })).collect::<Vec<_>>()
+                })),
+                last_page: Some(self.pages.len() as u32),
+            })
+        })
+    }
+
+    fn claim<'a>(&'a self, _session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction> {
+        Box::pin(async move {
+            self.claimed.lock().unwrap().push(id.to_string());
+            Ok(order(id))
+        })
+    }
+
+    fn mark_paid<'a>(
+        &'a self,
+        _session: &'a ProxyState,
+        _id: &'a str,
+        _file_name: &'a str,
+        _receipt: Vec<u8>,
+    ) -> SourceFuture<'a, Transaction> {
+        Box::pin(async { Err(PanelError::NotFound) })
+    }
+
+    fn cancel<'a>(&'a self, _session: &'a ProxyState, _id: &'a str) -> SourceFuture<'a, Transaction> {
+        Box::pin(async { Err(PanelError::NotFound) })
+    }
+
+    fn details<'a>(&'a self, _session: &'a ProxyState, id: &'a str) -> SourceFuture<'a, Transaction> {
+        Box::pin(async move { Ok(order(id)) })
+    }
+}
+
+fn state(dir: &std::path::Path, source: Arc<OtherExchange>) -> ProxyState {
+    let state = ProxyState::new("http://127.0.0.1:9/".to_string(), dir.to_path_buf())
+        .with_source(source);
+    state.cookies.lock().unwrap().cookies.push(Cookie {
+        name: "session".to_string(),
+        value: "other".to_string(),
+        domain: "127.0.0.1".to_string(),
+        path: "/".to_string(),
+        expiration_date: None,
+        host_only: None,
+        http_only: None,
+        same_site: None,
+        secure: None,
+        session: None,
+        store_id: None,
+    });
+    state
+}
+
+#[test]
+fn gate_is_the_default_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = ProxyState::new("https://panel.gate.cx/".to_string(), dir.path().to_path_buf());
+    assert_eq!(state.source.name(), GATE_SOURCE);
+    assert!(source_by_name(GATE_SOURCE).is_some());
+    assert!(source_by_name("unknown").is_none());
+
+    let found = state.source.extract_payouts(&json!({
+        "response": { "payouts": { "data": [{ "id": 5 }, { "id": "6" }] } }
+    }));
+    let ids: Vec<&str> = found.iter().map(|tx| tx.transaction_id.as_str()).collect();
+    assert_eq!(ids, vec!["5", "6"]);
+
+    // Карточка выплаты — только в `response.payout`; прочие ответы с `data` не выплаты.
+    let detail = state.source.extract_payouts(&json!({ "response": { "payout": { "id": 7 } } }));
+    assert_eq!(detail[0].transaction_id, "7");
+    for other in [json!({ "data": { "id": 8 } }), json!({ "data": { "transaction": { "id": 9 } } })] {
+        assert!(state.source.extract_payouts(&other).is_empty(), "{}", other);
+    }
+}
+
+#[tokio::test]
+async fn poller_stores_payouts_from_another_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = Arc::new(OtherExchange {
+        pages: vec![vec!["a1", "a2"], vec!["a3"]],
+        ..Default::default()
+    });
+    let state = state(dir.path(), source.clone());
+    let poller = tokio::spawn(run_idex(state.clone()));
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while state.transactions.lock().unwrap().len() < 3 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    poller.abort();
+
+    let ids: Vec<String> = state
+        .transactions
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|tx| tx.transaction_id.clone())
+        .collect();
+    assert_eq!(ids, vec!["a1", "a2", "a3"]);
+    // Последняя страница известна, поэтому дальше второй опрос не идёт.
+    assert_eq!(source.requested.lock().unwrap()[..2], [1, 2]);
+    assert!(!source.requested.lock().unwrap().contains(&3));
+}
+
+#[tokio::test]
+async fn rules_claim_through_the_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = Arc::new(OtherExchange::default());
+    let state = state(dir.path(), source.clone());
+    let config = AutoClaimConfig {
+        enabled: true,
+        dry_run: false,
+        rules: vec![Rule {
+            name: "any".to_string(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let mut engine = RuleEngine::new(&config, dir.path());
+    let outcomes = engine.process(&state, &[order("b1")]).await;
+    assert_eq!(outcomes[0].result, ClaimResult::Claimed);
+    assert_eq!(*source.claimed.lock().unwrap(), vec!["b1".to_string()]);
+}
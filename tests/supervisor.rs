@@ -0,0 +1,120 @@
+//! Перезапуск фоновых задач и согласованная остановка.
+
+use std::{
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+
+use p2p_app::{
+    api::API_TOKEN_HEADER,
+    idex::run_idex_until,
+    proxy::{run_proxy, ProxyState},
+    supervisor::{Supervisor, TaskStatus},
+};
+
+#[tokio::test]
+async fn panicked_task_is_restarted() {
+    let supervisor = Supervisor::new();
+    let runs = Arc::new(AtomicU32::new(0));
+    let counter = runs.clone();
+    supervisor.spawn("flaky", move |shutdown| {
+        let run = counter.fetch_add(1, Ordering::SeqCst);
+        async move {
+            if run == 0 {
+                panic!("boom");
+            }
+            shutdown.cancelled().await;
+        }
+    });
+
+    // Первый перезапуск происходит через секунду; ждём его с запасом.
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    let restarted = || {
+        runs.load(Ordering::SeqCst) == 2 && supervisor.health()["flaky"].status == TaskStatus::Running
+    };
+    while !restarted() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+    let health = supervisor.health()["flaky"].clone();
+    assert_eq!(health.status, TaskStatus::Running);
+    assert_eq!(health.restarts, 1);
+    assert!(health.last_error.unwrap().contains("boom"));
+
+    assert!(supervisor.shutdown(Duration::from_secs(1)).await);
+    assert_eq!(supervisor.health()["flaky"].status, TaskStatus::Stopped);
+}
+
+#[tokio::test]
+async fn stuck_task_is_aborted_after_timeout() {
+    let supervisor = Supervisor::new();
+    // Отправитель живёт, пока выполняется задача.
+    let (alive, stopped) = oneshot::channel::<()>();
+    let alive = Mutex::new(Some(alive));
+    supervisor.spawn("stuck", move |_| {
+        let alive = alive.lock().unwrap().take();
+        async move {
+            let _alive = alive;
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!supervisor.shutdown(Duration::from_millis(100)).await);
+    let dropped = tokio::time::timeout(Duration::from_secs(1), stopped)
+        .await
+        .expect("stuck task is still running");
+    assert!(dropped.is_err());
+}
+
+#[tokio::test]
+async fn idex_poller_stops_on_shutdown() {
+    let dir = tempfile::tempdir().unwrap();
+    // Недоступная панель: поллер уходит в паузу между попытками.
+    let state = ProxyState::new("http://127.0.0.1:9/".to_string(), dir.path().to_path_buf());
+    let supervisor = Supervisor::new();
+    supervisor.spawn("idex", move |shutdown| run_idex_until(state.clone(), shutdown));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(supervisor.shutdown(Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn task_health_is_served_by_local_api() {
+    let dir = tempfile::tempdir().unwrap();
+    let supervisor = Supervisor::new();
+    supervisor.spawn("broken", |_| async { panic!("boom") });
+    let state = ProxyState::new("http://127.0.0.1:9/".to_string(), dir.path().to_path_buf())
+        .with_tasks(supervisor.health_map());
+    let token = state.api_token.to_string();
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let server = tokio::spawn(run_proxy(state, addr));
+
+    let url = format!("http://{}/__p2p/health", addr);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    let body = loop {
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header(API_TOKEN_HEADER, token.as_str())
+            .send()
+            .await;
+        if let Ok(response) = response {
+            let body: serde_json::Value = response.json().await.unwrap();
+            if body["tasks"]["broken"]["restarts"] != 0 {
+                break body;
+            }
+        }
+        assert!(tokio::time::Instant::now() < deadline, "health was not reported");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(body["tasks"]["broken"]["status"], "restarting");
+    assert!(body["tasks"]["broken"]["last_error"].as_str().unwrap().contains("boom"));
+
+    server.abort();
+    supervisor.shutdown(Duration::from_secs(1)).await;
+}